@@ -0,0 +1,366 @@
+//! Uploading to AmeoTrack from programs that embed snapmeo, such as GUI applications that capture
+//! with `capture::capture_region_rgba` and encode the image themselves.
+//!
+//! `upload_async` is built on reqwest's async client so that uploading doesn't block an event
+//! loop. Like everything on reqwest 0.10, it has to be polled within a Tokio 0.2 runtime.
+//! `ameotrack_upload` blocks on it with a runtime of its own for programs without one, and
+//! `upload_in_background` runs that on a thread and calls back once it's done. The snapmeo
+//! command uploads to AmeoTrack through `ameotrack_upload` too.
+
+use std::io::Write;
+use std::thread::{self, JoinHandle};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use flate2::write::GzEncoder;
+use flate2::Compression;
+use reqwest::header::{ACCEPT_ENCODING, CONTENT_ENCODING, CONTENT_TYPE};
+use reqwest::multipart::{Form, Part};
+use reqwest::{Client, Method, Response, StatusCode, Url};
+
+use crate::error::SnapmeoError;
+
+pub const AMEOTRACK_UPLOAD_URL: &str = "https://ameo.link/u/upload";
+/// Files smaller than this aren't worth compressing before uploading
+pub const MIN_COMPRESSED_UPLOAD_BYTES: usize = 256 * 1024;
+/// How much of a response that isn't a URL is included in the error about it
+const RESPONSE_EXCERPT_CHARS: usize = 200;
+
+/// Where to upload to and how the upload is hosted
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct AmeoTrackOptions {
+    /// Endpoint that upload forms are posted to
+    pub upload_url: String,
+    pub password: String,
+    /// Whether to give the upload an obfuscated name
+    pub secret: bool,
+    /// Whether to delete the upload once it's been viewed
+    pub one_time: bool,
+    /// How many days the upload is hosted for, or `None` to keep it forever
+    pub expiry_days: Option<u32>,
+    /// Sent with the upload so that a server that remembers the keys it's seen can ignore a retry
+    /// of an upload that actually went through. Retries should reuse the same key.
+    pub idempotency_key: Option<String>,
+    /// Tags to file the upload under
+    pub tags: Vec<String>,
+    /// Whether to gzip the request if the file is at least `MIN_COMPRESSED_UPLOAD_BYTES` and the
+    /// server advertises that it accepts compressed requests. Otherwise, or if the compressed
+    /// upload fails for any reason other than the password, the file is uploaded uncompressed.
+    pub compress: bool,
+}
+
+impl AmeoTrackOptions {
+    /// Options for uploading to the main AmeoTrack server with `password`, which are kept forever
+    /// under their own names like the snapmeo command's defaults.
+    pub fn new(password: impl Into<String>) -> Self {
+        AmeoTrackOptions {
+            upload_url: AMEOTRACK_UPLOAD_URL.into(),
+            password: password.into(),
+            secret: false,
+            one_time: false,
+            expiry_days: None,
+            idempotency_key: None,
+            tags: Vec::new(),
+            compress: false,
+        }
+    }
+
+    /// The text fields of the upload form, which go along with the file.
+    pub fn fields(&self) -> Vec<(&'static str, String)> {
+        let flag = |enabled: bool| String::from(if enabled { "1" } else { "" });
+        let mut fields = vec![
+            ("secret", flag(self.secret)),
+            (
+                "expiry",
+                self.expiry_days
+                    .map_or_else(|| "-1".into(), |days| days.to_string()),
+            ),
+            ("password", self.password.clone()),
+            ("oneTime", flag(self.one_time)),
+        ];
+        if let Some(key) = &self.idempotency_key {
+            fields.push(("idempotencyKey", key.clone()));
+        }
+        if !self.tags.is_empty() {
+            fields.push(("tags", self.tags.join(",")));
+        }
+        fields
+    }
+}
+
+/// Encodes `fields` with `file` in the `file_field` field into a `multipart/form-data` body. The
+/// snapmeo command compresses uploads to its other form based backends with this too.
+pub fn multipart_body(
+    boundary: &str,
+    fields: &[(&str, String)],
+    file_field: &str,
+    file_name: &str,
+    content_type: &str,
+    file: &[u8],
+) -> Vec<u8> {
+    let mut body = Vec::with_capacity(file.len() + 1024);
+    for (name, value) in fields {
+        body.extend_from_slice(
+            format!(
+                "--{}\r\nContent-Disposition: form-data; name=\"{}\"\r\n\r\n{}\r\n",
+                boundary, name, value
+            )
+            .as_bytes(),
+        );
+    }
+    body.extend_from_slice(
+        format!(
+            "--{}\r\nContent-Disposition: form-data; name=\"{}\"; filename=\"{}\"\r\nContent-Type: {}\r\n\r\n",
+            boundary, file_field, file_name, content_type
+        )
+        .as_bytes(),
+    );
+    body.extend_from_slice(file);
+    body.extend_from_slice(format!("\r\n--{}--\r\n", boundary).as_bytes());
+
+    body
+}
+
+/// A boundary for a `multipart/form-data` body that won't turn up in a screenshot by chance
+pub fn multipart_boundary() -> Result<String, SnapmeoError> {
+    Ok(format!(
+        "snapmeo-{:x}",
+        SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_err(|err| SnapmeoError::Other(err.to_string()))?
+            .as_nanos()
+    ))
+}
+
+/// Gzips a request `body`, returning `None` if that wouldn't make it any smaller.
+pub fn gzip(body: &[u8]) -> Result<Option<Vec<u8>>, SnapmeoError> {
+    let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+    encoder.write_all(body)?;
+    let compressed = encoder.finish()?;
+    Ok(Some(compressed).filter(|compressed| compressed.len() < body.len()))
+}
+
+/// Returns the URL that a successful upload's response `body` consists of. Some hosts report
+/// errors with a success status, so a body that isn't an HTTP(S) URL is an error rather than
+/// something to copy to the clipboard.
+pub fn response_url(status: StatusCode, body: &str) -> Result<String, SnapmeoError> {
+    let body = body.trim();
+    match Url::parse(body) {
+        Ok(url) if matches!(url.scheme(), "http" | "https") && url.host().is_some() => {
+            Ok(body.to_owned())
+        }
+        _ => {
+            let mut excerpt: String = body.chars().take(RESPONSE_EXCERPT_CHARS).collect();
+            if excerpt.len() < body.len() {
+                excerpt.push_str("...");
+            }
+            Err(SnapmeoError::Upload {
+                status: Some(status.as_u16()),
+                message: format!("The response wasn't a URL: {:?}", excerpt),
+            })
+        }
+    }
+}
+
+/// Uploads the encoded image `file` of MIME type `content_type` under `file_name`, resolving to
+/// the URL at which it can be viewed.
+///
+/// Dropping the future cancels the upload wherever it's got to. If that's after the whole request
+/// was sent, the server may still have stored the image, so an upload that's tried again after
+/// being cancelled should reuse the `idempotency_key`.
+pub async fn upload_async(
+    file: Vec<u8>,
+    file_name: &str,
+    content_type: &str,
+    options: &AmeoTrackOptions,
+) -> Result<String, SnapmeoError> {
+    let client = Client::new();
+    if options.compress && file.len() >= MIN_COMPRESSED_UPLOAD_BYTES {
+        match upload_gzipped(&client, &file, file_name, content_type, options).await {
+            Ok(Some(url)) => return Ok(url),
+            // Retrying won't help if the password is wrong
+            Err(err @ SnapmeoError::Auth(_)) => return Err(err),
+            Ok(None) | Err(_) => (),
+        }
+    }
+
+    let file = Part::bytes(file)
+        .file_name(file_name.to_owned())
+        .mime_str(content_type)?;
+    let form = options
+        .fields()
+        .into_iter()
+        .fold(Form::new().part("file", file), |form, (name, value)| {
+            form.text(name, value)
+        });
+
+    let res = client
+        .post(&options.upload_url)
+        .multipart(form)
+        .send()
+        .await?;
+    uploaded_url(res).await
+}
+
+/// Uploads with a gzip-encoded request body. Returns `None` without uploading anything if the
+/// server doesn't advertise support for compressed requests or compression wouldn't make the
+/// request smaller.
+async fn upload_gzipped(
+    client: &Client,
+    file: &[u8],
+    file_name: &str,
+    content_type: &str,
+    options: &AmeoTrackOptions,
+) -> Result<Option<String>, SnapmeoError> {
+    if !accepts_gzip_requests(client, &options.upload_url).await? {
+        return Ok(None);
+    }
+
+    let boundary = multipart_boundary()?;
+    let body = multipart_body(
+        &boundary,
+        &options.fields(),
+        "file",
+        file_name,
+        content_type,
+        file,
+    );
+    let compressed = match gzip(&body)? {
+        Some(compressed) => compressed,
+        None => return Ok(None),
+    };
+
+    let res = client
+        .post(&options.upload_url)
+        .header(
+            CONTENT_TYPE,
+            format!("multipart/form-data; boundary={}", boundary),
+        )
+        .header(CONTENT_ENCODING, "gzip")
+        .body(compressed)
+        .send()
+        .await?;
+    uploaded_url(res).await.map(Some)
+}
+
+/// Returns whether the server at `url` advertises that it accepts gzip-encoded request bodies via
+/// the `Accept-Encoding` header of its response to an `OPTIONS` request (RFC 7694).
+async fn accepts_gzip_requests(client: &Client, url: &str) -> Result<bool, SnapmeoError> {
+    let res = client.request(Method::OPTIONS, url).send().await?;
+    let accepts_gzip = res
+        .headers()
+        .get_all(ACCEPT_ENCODING)
+        .iter()
+        .filter_map(|value| value.to_str().ok())
+        .flat_map(|value| value.split(','))
+        .any(|encoding| encoding.trim().starts_with("gzip"));
+
+    Ok(accepts_gzip)
+}
+
+/// Returns the URL that AmeoTrack's response to an upload gives, or the error it reports.
+async fn uploaded_url(res: Response) -> Result<String, SnapmeoError> {
+    let status = res.status();
+    let body = res.text().await?;
+    if status == StatusCode::UNAUTHORIZED || status == StatusCode::FORBIDDEN {
+        return Err(SnapmeoError::Auth(format!(
+            "AmeoTrack rejected the upload with {}: {:?}",
+            status, body
+        )));
+    }
+    if !status.is_success() {
+        return Err(SnapmeoError::Upload {
+            status: Some(status.as_u16()),
+            message: format!("{:?}", body),
+        });
+    }
+
+    response_url(status, &body)
+}
+
+/// Like `upload_async`, but blocks until the upload is done. This starts a Tokio runtime of its
+/// own, so it mustn't be called from within one.
+pub fn ameotrack_upload(
+    file: Vec<u8>,
+    file_name: &str,
+    content_type: &str,
+    options: &AmeoTrackOptions,
+) -> Result<String, SnapmeoError> {
+    let mut runtime = tokio::runtime::Builder::new()
+        .basic_scheduler()
+        .enable_all()
+        .build()?;
+    runtime.block_on(upload_async(file, file_name, content_type, options))
+}
+
+/// Uploads like `ameotrack_upload` on a thread of its own, calling `on_complete` on that thread
+/// with the URL or the error once it's done. The upload carries on even if the returned handle is
+/// dropped.
+pub fn upload_in_background(
+    file: Vec<u8>,
+    file_name: String,
+    content_type: String,
+    options: AmeoTrackOptions,
+    on_complete: impl FnOnce(Result<String, SnapmeoError>) + Send + 'static,
+) -> JoinHandle<()> {
+    thread::spawn(move || {
+        on_complete(ameotrack_upload(file, &file_name, &content_type, &options));
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn fields_use_ameotrack_names() {
+        let options = AmeoTrackOptions {
+            secret: true,
+            expiry_days: Some(7),
+            idempotency_key: Some("key".into()),
+            ..AmeoTrackOptions::new("hunter2")
+        };
+        assert_eq!(
+            options.fields(),
+            vec![
+                ("secret", "1".to_owned()),
+                ("expiry", "7".to_owned()),
+                ("password", "hunter2".to_owned()),
+                ("oneTime", String::new()),
+                ("idempotencyKey", "key".to_owned()),
+            ]
+        );
+        assert_eq!(AmeoTrackOptions::new("hunter2").fields()[1].1, "-1");
+    }
+
+    #[test]
+    fn response_url_accepts_only_http_urls() {
+        for body in [
+            "https://ameo.link/u/abc.png",
+            "  http://localhost:8080/abc.png\r\n",
+        ] {
+            assert_eq!(
+                response_url(StatusCode::OK, body).unwrap(),
+                body.trim(),
+                "{:?}",
+                body
+            );
+        }
+        for body in [
+            "",
+            "OK",
+            "<html><body>Upload failed</body></html>",
+            "/u/abc.png",
+            "file:///tmp/abc.png",
+            "javascript:alert(1)",
+        ] {
+            assert!(response_url(StatusCode::OK, body).is_err(), "{:?}", body);
+        }
+
+        let long_body = "x".repeat(RESPONSE_EXCERPT_CHARS * 2);
+        let message = response_url(StatusCode::OK, &long_body)
+            .unwrap_err()
+            .to_string();
+        assert!(message.contains(&format!("{}...", "x".repeat(RESPONSE_EXCERPT_CHARS))));
+        assert!(!message.contains(&long_body));
+    }
+}
@@ -0,0 +1,502 @@
+//! Drawing simple annotations onto a capture before it's saved.
+//!
+//! Annotations are kept as a list and re-rasterized onto the untouched capture whenever they
+//! change, which is what makes undoing and redoing them possible. The same list can be written out
+//! as SVG shapes so that annotations stay editable in SVG exports.
+
+use std::cmp;
+#[cfg(feature = "interactive")]
+use std::{thread, time::Duration};
+
+#[cfg(feature = "interactive")]
+use sdl2::{
+    event::Event,
+    keyboard::{Keycode, Mod},
+    mouse::MouseButton,
+    pixels::{Color, PixelFormatEnum},
+    render::{Canvas, Texture},
+    video::Window,
+    EventPump,
+};
+
+use crate::buffer::Region;
+#[cfg(feature = "interactive")]
+use crate::{buffer, font, selection};
+
+const ANNOTATION_COLOR: [u8; 4] = [255, 0, 0, 255];
+/// Translucent yellow, like a highlighter pen
+const HIGHLIGHT_COLOR: [u8; 4] = [255, 235, 0, 96];
+/// Half of the width of the lines that annotations are drawn with
+#[cfg(feature = "interactive")]
+const LINE_RADIUS: i32 = 1;
+#[cfg(feature = "interactive")]
+const ARROW_HEAD_LENGTH: f32 = 16.;
+/// Side length of the blocks that blurred areas are pixelated into
+const BLUR_BLOCK_SIZE: usize = 10;
+/// Annotations older than this are baked into the capture and can no longer be undone
+#[cfg(feature = "interactive")]
+const MAX_UNDO_DEPTH: usize = 100;
+#[cfg(feature = "interactive")]
+const HINT_SCALE: u32 = 2;
+
+#[cfg(feature = "interactive")]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Tool {
+    Rectangle,
+    Arrow,
+    /// A filled rectangle that's normally translucent so that what's under it stays visible
+    Highlight,
+    Blur,
+}
+
+#[cfg(feature = "interactive")]
+impl Tool {
+    fn name(self) -> &'static str {
+        match self {
+            Tool::Rectangle => "RECTANGLE",
+            Tool::Arrow => "ARROW",
+            Tool::Highlight => "HIGHLIGHT",
+            Tool::Blur => "BLUR",
+        }
+    }
+}
+
+/// The RGBA colors annotations are drawn in. Colors with an alpha below 255 are blended over the
+/// capture.
+#[derive(Clone, Copy, Debug)]
+pub struct AnnotationStyle {
+    /// Used for rectangles and arrows
+    pub color: [u8; 4],
+    pub highlight_color: [u8; 4],
+}
+
+impl Default for AnnotationStyle {
+    fn default() -> Self {
+        AnnotationStyle {
+            color: ANNOTATION_COLOR,
+            highlight_color: HIGHLIGHT_COLOR,
+        }
+    }
+}
+
+/// The result of annotating a capture
+#[cfg(feature = "interactive")]
+pub struct Annotated {
+    /// The capture with every annotation drawn onto it
+    pub buffer: Vec<u8>,
+    /// The capture with only the annotations that can't be vector shapes drawn onto it, which are
+    /// blurs and annotations too old to be undone
+    pub background: Vec<u8>,
+    annotations: Vec<Annotation>,
+    style: AnnotationStyle,
+}
+
+/// A shape drawn over the capture, in capture pixels
+#[cfg(feature = "interactive")]
+#[derive(Clone, Copy, Debug)]
+struct Annotation {
+    tool: Tool,
+    start: (i32, i32),
+    end: (i32, i32),
+}
+
+/// Shapes are first drawn into a mask of the pixels they cover and then composited in one go so
+/// that translucent colors don't get darker where strokes overlap.
+#[cfg(feature = "interactive")]
+fn fill_square(mask: &mut [bool], width: usize, height: usize, center: (i32, i32), radius: i32) {
+    for y in (center.1 - radius)..=(center.1 + radius) {
+        for x in (center.0 - radius)..=(center.0 + radius) {
+            if x < 0 || y < 0 || x as usize >= width || y as usize >= height {
+                continue;
+            }
+
+            mask[y as usize * width + x as usize] = true;
+        }
+    }
+}
+
+#[cfg(feature = "interactive")]
+fn draw_line(mask: &mut [bool], width: usize, height: usize, from: (i32, i32), to: (i32, i32)) {
+    let (dx, dy) = (to.0 - from.0, to.1 - from.1);
+    let steps = cmp::max(cmp::max(dx.abs(), dy.abs()), 1);
+    for step in 0..=steps {
+        let t = step as f32 / steps as f32;
+        let point = (
+            from.0 + (dx as f32 * t).round() as i32,
+            from.1 + (dy as f32 * t).round() as i32,
+        );
+        fill_square(mask, width, height, point, LINE_RADIUS);
+    }
+}
+
+/// Blends `color` over every pixel of the RGBA `buffer` that's set in `mask`.
+#[cfg(feature = "interactive")]
+fn composite(buffer: &mut [u8], mask: &[bool], color: [u8; 4]) {
+    for (pixel, _) in buffer
+        .chunks_mut(4)
+        .zip(mask)
+        .filter(|(_, &covered)| covered)
+    {
+        buffer::blend_over(pixel, &color);
+    }
+}
+
+/// Replaces every block of the area between the two corners with its average color.
+pub fn pixelate(
+    buffer: &mut [u8],
+    width: usize,
+    height: usize,
+    start: (i32, i32),
+    end: (i32, i32),
+) {
+    let area = Region::from_corners(start, end);
+    let (right, bottom) = (
+        cmp::min(area.x + area.width, width),
+        cmp::min(area.y + area.height, height),
+    );
+
+    for block_y in (area.y..bottom).step_by(BLUR_BLOCK_SIZE) {
+        for block_x in (area.x..right).step_by(BLUR_BLOCK_SIZE) {
+            let block_right = cmp::min(block_x + BLUR_BLOCK_SIZE, right);
+            let block_bottom = cmp::min(block_y + BLUR_BLOCK_SIZE, bottom);
+            let pixel_ixs = || {
+                (block_y..block_bottom)
+                    .flat_map(move |y| (block_x..block_right).map(move |x| (y * width + x) * 4))
+            };
+
+            let mut sums = [0u32; 4];
+            let mut count = 0;
+            for ix in pixel_ixs() {
+                for (sum, &channel) in sums.iter_mut().zip(&buffer[ix..ix + 4]) {
+                    *sum += channel as u32;
+                }
+                count += 1;
+            }
+            let average: Vec<u8> = sums.iter().map(|sum| (sum / count) as u8).collect();
+            for ix in pixel_ixs() {
+                buffer[ix..ix + 4].copy_from_slice(&average);
+            }
+        }
+    }
+}
+
+/// Returns the ends of the two lines making up the head of an arrow pointing from `start` to `end`.
+#[cfg(feature = "interactive")]
+fn arrow_head(start: (i32, i32), end: (i32, i32)) -> [(i32, i32); 2] {
+    let angle = ((start.1 - end.1) as f32).atan2((start.0 - end.0) as f32);
+    [angle - 0.5, angle + 0.5].map(|head_angle| {
+        (
+            end.0 + (head_angle.cos() * ARROW_HEAD_LENGTH).round() as i32,
+            end.1 + (head_angle.sin() * ARROW_HEAD_LENGTH).round() as i32,
+        )
+    })
+}
+
+/// Formats an RGBA color as SVG color and opacity attributes prefixed with `attribute`, such as
+/// `stroke="rgb(255,0,0)" stroke-opacity="1.000"`.
+#[cfg(feature = "interactive")]
+fn svg_paint(attribute: &str, color: [u8; 4]) -> String {
+    format!(
+        "{attribute}=\"rgb({},{},{})\" {attribute}-opacity=\"{:.3}\"",
+        color[0],
+        color[1],
+        color[2],
+        color[3] as f32 / 255.,
+        attribute = attribute
+    )
+}
+
+#[cfg(feature = "interactive")]
+impl Annotation {
+    /// Returns the annotation as an SVG element shifted by `offset`, or `None` for blurs, which
+    /// need the pixels under them.
+    fn svg_element(&self, offset: (i32, i32), style: &AnnotationStyle) -> Option<String> {
+        let shift = |point: (i32, i32)| (point.0 + offset.0, point.1 + offset.1);
+        let (start, end) = (shift(self.start), shift(self.end));
+        let stroke_width = LINE_RADIUS * 2 + 1;
+        let area = Region::from_corners(start, end);
+
+        match self.tool {
+            Tool::Rectangle => Some(format!(
+                "<rect x=\"{}\" y=\"{}\" width=\"{}\" height=\"{}\" fill=\"none\" {} stroke-width=\"{}\"/>",
+                area.x,
+                area.y,
+                area.width,
+                area.height,
+                svg_paint("stroke", style.color),
+                stroke_width
+            )),
+            Tool::Arrow => {
+                let [head_1, head_2] = arrow_head(start, end);
+                Some(format!(
+                    "<path d=\"M{} {} L{} {} M{} {} L{} {} L{} {}\" fill=\"none\" {} stroke-width=\"{}\" stroke-linecap=\"square\"/>",
+                    start.0,
+                    start.1,
+                    end.0,
+                    end.1,
+                    head_1.0,
+                    head_1.1,
+                    end.0,
+                    end.1,
+                    head_2.0,
+                    head_2.1,
+                    svg_paint("stroke", style.color),
+                    stroke_width
+                ))
+            }
+            Tool::Highlight => Some(format!(
+                "<rect x=\"{}\" y=\"{}\" width=\"{}\" height=\"{}\" {}/>",
+                area.x,
+                area.y,
+                area.width,
+                area.height,
+                svg_paint("fill", style.highlight_color)
+            )),
+            Tool::Blur => None,
+        }
+    }
+
+    fn draw(&self, buffer: &mut [u8], width: usize, height: usize, style: &AnnotationStyle) {
+        let (start, end) = (self.start, self.end);
+        let mut mask = vec![false; width * height];
+        match self.tool {
+            Tool::Rectangle => {
+                let corners = [start, (end.0, start.1), end, (start.0, end.1), start];
+                for edge in corners.windows(2) {
+                    draw_line(&mut mask, width, height, edge[0], edge[1]);
+                }
+                composite(buffer, &mask, style.color);
+            }
+            Tool::Arrow => {
+                draw_line(&mut mask, width, height, start, end);
+                for head_end in arrow_head(start, end) {
+                    draw_line(&mut mask, width, height, end, head_end);
+                }
+                composite(buffer, &mask, style.color);
+            }
+            Tool::Highlight => {
+                let area = Region::from_corners(start, end);
+                for y in area.y..cmp::min(area.y + area.height, height) {
+                    let row = y * width;
+                    let right = cmp::min(area.x + area.width, width);
+                    for covered in &mut mask[row + cmp::min(area.x, right)..row + right] {
+                        *covered = true;
+                    }
+                }
+                composite(buffer, &mask, style.highlight_color);
+            }
+            Tool::Blur => pixelate(buffer, width, height, start, end),
+        }
+    }
+}
+
+/// Draws `annotations` in order onto a copy of the RGBA `base`.
+#[cfg(feature = "interactive")]
+fn render(
+    base: &[u8],
+    width: usize,
+    height: usize,
+    annotations: &[Annotation],
+    style: &AnnotationStyle,
+) -> Vec<u8> {
+    let mut buffer = base.to_vec();
+    for annotation in annotations {
+        annotation.draw(&mut buffer, width, height, style);
+    }
+    buffer
+}
+
+#[cfg(feature = "interactive")]
+impl Annotated {
+    fn new(
+        base: &[u8],
+        width: usize,
+        height: usize,
+        annotations: Vec<Annotation>,
+        style: &AnnotationStyle,
+    ) -> Self {
+        let blurs: Vec<Annotation> = annotations
+            .iter()
+            .filter(|annotation| annotation.tool == Tool::Blur)
+            .cloned()
+            .collect();
+        Annotated {
+            buffer: render(base, width, height, &annotations, style),
+            background: render(base, width, height, &blurs, style),
+            annotations,
+            style: *style,
+        }
+    }
+
+    /// Returns SVG elements for every annotation that isn't already part of `background`, shifted
+    /// by `offset` to account for anything added around the capture.
+    pub fn svg_elements(&self, offset: (i32, i32)) -> String {
+        self.annotations
+            .iter()
+            .filter_map(|annotation| annotation.svg_element(offset, &self.style))
+            .collect::<Vec<_>>()
+            .join("\n")
+    }
+}
+
+/// Lets the user draw rectangles, arrows, highlights, and blurred areas over the RGBA `buffer`
+/// captured from `region`, shown in place over the frozen frame. Returns the annotated capture
+/// once they press enter or `None` if they cancel with escape.
+#[cfg(feature = "interactive")]
+pub fn annotate(
+    canvas: &mut Canvas<Window>,
+    frozen_frame: &Texture,
+    event_pump: &mut EventPump,
+    buffer: Vec<u8>,
+    region: &Region,
+    style: &AnnotationStyle,
+    one_frame: Duration,
+) -> Option<Annotated> {
+    let (width, height) = (region.width, region.height);
+    let texture_creator = canvas.texture_creator();
+    let mut preview = texture_creator
+        .create_texture_streaming(Some(PixelFormatEnum::RGBA32), width as u32, height as u32)
+        .expect("Unable to create texture!");
+
+    let mut base = buffer;
+    let mut annotations: Vec<Annotation> = Vec::new();
+    let mut undone: Vec<Annotation> = Vec::new();
+    let mut in_progress: Option<Annotation> = None;
+    let mut tool = Tool::Rectangle;
+    let mut dirty = true;
+    let to_local = |x: i32, y: i32| (x - region.x as i32, y - region.y as i32);
+    let scale_factor = selection::scale_factor(canvas);
+    let (canvas_width, canvas_height) = canvas.output_size().expect("Unable to get window size!");
+    let bounds = (canvas_width as usize, canvas_height as usize);
+
+    loop {
+        for event in event_pump.poll_iter() {
+            let event = selection::to_physical_event(event, scale_factor, bounds);
+            match event {
+                Event::Quit { .. }
+                | Event::KeyDown {
+                    keycode: Some(Keycode::Escape),
+                    ..
+                } => return None,
+                Event::KeyDown {
+                    keycode: Some(Keycode::Return),
+                    ..
+                }
+                | Event::KeyDown {
+                    keycode: Some(Keycode::KpEnter),
+                    ..
+                } => return Some(Annotated::new(&base, width, height, annotations, style)),
+                Event::KeyDown {
+                    keycode: Some(Keycode::Z),
+                    keymod,
+                    ..
+                } if keymod.intersects(Mod::LCTRLMOD | Mod::RCTRLMOD) => {
+                    let redo = keymod.intersects(Mod::LSHIFTMOD | Mod::RSHIFTMOD);
+                    let (from, to) = if redo {
+                        (&mut undone, &mut annotations)
+                    } else {
+                        (&mut annotations, &mut undone)
+                    };
+                    if let Some(annotation) = from.pop() {
+                        to.push(annotation);
+                        dirty = true;
+                    }
+                }
+                Event::KeyDown {
+                    keycode: Some(Keycode::R),
+                    ..
+                } => tool = Tool::Rectangle,
+                Event::KeyDown {
+                    keycode: Some(Keycode::A),
+                    ..
+                } => tool = Tool::Arrow,
+                Event::KeyDown {
+                    keycode: Some(Keycode::H),
+                    ..
+                } => tool = Tool::Highlight,
+                Event::KeyDown {
+                    keycode: Some(Keycode::B),
+                    ..
+                } => tool = Tool::Blur,
+                Event::MouseButtonDown {
+                    mouse_btn: MouseButton::Left,
+                    x,
+                    y,
+                    ..
+                } => {
+                    let point = to_local(x, y);
+                    in_progress = Some(Annotation {
+                        tool,
+                        start: point,
+                        end: point,
+                    });
+                }
+                Event::MouseMotion { x, y, .. } => {
+                    if let Some(annotation) = &mut in_progress {
+                        annotation.end = to_local(x, y);
+                        dirty = true;
+                    }
+                }
+                Event::MouseButtonUp {
+                    mouse_btn: MouseButton::Left,
+                    x,
+                    y,
+                    ..
+                } => {
+                    if let Some(mut annotation) = in_progress.take() {
+                        annotation.end = to_local(x, y);
+                        annotations.push(annotation);
+                        undone.clear();
+                        if annotations.len() > MAX_UNDO_DEPTH {
+                            let oldest = annotations.remove(0);
+                            oldest.draw(&mut base, width, height, style);
+                        }
+                        dirty = true;
+                    }
+                }
+                _ => {}
+            }
+        }
+
+        if dirty {
+            let mut shown = annotations.clone();
+            shown.extend(in_progress);
+            preview
+                .update(
+                    None,
+                    &render(&base, width, height, &shown, style),
+                    width * 4,
+                )
+                .expect("Error updating texture with image data!");
+            dirty = false;
+        }
+
+        canvas
+            .copy(frozen_frame, None, None)
+            .expect("Render failed");
+        let preview_rect = selection::region_rect(region);
+        canvas
+            .copy(&preview, None, preview_rect)
+            .expect("Render failed");
+        canvas.set_draw_color(Color::RGB(255, 255, 255));
+        canvas.draw_rect(preview_rect).expect("Render failed");
+
+        let hint = format!(
+            "TOOL: {}  R: RECTANGLE  A: ARROW  H: HIGHLIGHT  B: BLUR  CTRL+Z: UNDO  CTRL+SHIFT+Z: REDO  ENTER: DONE  ESC: CANCEL",
+            tool.name()
+        );
+        let (canvas_width, _) = canvas.output_size().expect("Unable to get window size!");
+        let (hint_width, hint_height) = font::text_size(&hint, HINT_SCALE);
+        font::draw_text(
+            canvas,
+            &hint,
+            (canvas_width as i32 - hint_width as i32) / 2,
+            hint_height as i32,
+            HINT_SCALE,
+        )
+        .expect("Render failed");
+        canvas.present();
+
+        thread::sleep(one_frame);
+    }
+}
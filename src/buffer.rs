@@ -0,0 +1,820 @@
+//! Helpers for working with captured pixel buffers.
+//!
+//! All positions and sizes are in physical pixels of the captured frame, which is also what
+//! `--region`, `--region-percent`, and window bounds are given in. The only input that isn't is
+//! SDL's mouse events, which are in logical points on HiDPI displays and have to go through
+//! `to_physical` first.
+
+use std::cmp;
+use std::str::FromStr;
+
+use crate::capture;
+use crate::font;
+
+/// A rectangular area of a captured frame, in frame pixels
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Region {
+    pub x: usize,
+    pub y: usize,
+    pub width: usize,
+    pub height: usize,
+}
+
+impl Region {
+    /// Builds the region spanned by two opposite corners, in any order.
+    pub fn from_corners(corner_1: (i32, i32), corner_2: (i32, i32)) -> Self {
+        Region {
+            x: cmp::max(cmp::min(corner_1.0, corner_2.0), 0) as usize,
+            y: cmp::max(cmp::min(corner_1.1, corner_2.1), 0) as usize,
+            width: (corner_1.0 - corner_2.0).unsigned_abs() as usize,
+            height: (corner_1.1 - corner_2.1).unsigned_abs() as usize,
+        }
+    }
+
+    /// Builds a region of `size` positioned relative to `point` according to `anchor`, shifting
+    /// and shrinking it as necessary to keep it within a display of size `bounds`.
+    #[cfg_attr(not(feature = "interactive"), allow(dead_code))]
+    pub fn anchored(
+        point: (i32, i32),
+        size: (usize, usize),
+        anchor: Anchor,
+        bounds: (usize, usize),
+    ) -> Self {
+        let width = cmp::min(size.0, bounds.0);
+        let height = cmp::min(size.1, bounds.1);
+        let clamp_start = |point: i32, length: usize, bound: usize| {
+            let offset = match anchor {
+                Anchor::TopLeft => 0,
+                Anchor::Center => length / 2,
+                Anchor::BottomRight => length,
+            };
+            let start = cmp::max(point as isize - offset as isize, 0) as usize;
+            cmp::min(start, bound - length)
+        };
+
+        Region {
+            x: clamp_start(point.0, width, bounds.0),
+            y: clamp_start(point.1, height, bounds.1),
+            width,
+            height,
+        }
+    }
+}
+
+impl Region {
+    /// Builds the part of the region at (`x`, `y`) of size `width` x `height` that lies within a
+    /// display of size `bounds`, or `None` if none of it does.
+    pub fn clamped(
+        x: i64,
+        y: i64,
+        width: usize,
+        height: usize,
+        bounds: (usize, usize),
+    ) -> Option<Self> {
+        let clamp = |value: i64, bound: usize| cmp::min(cmp::max(value, 0), bound as i64) as usize;
+        let (left, top) = (clamp(x, bounds.0), clamp(y, bounds.1));
+        let right = clamp(x + width as i64, bounds.0);
+        let bottom = clamp(y + height as i64, bounds.1);
+        if right <= left || bottom <= top {
+            return None;
+        }
+
+        Some(Region {
+            x: left,
+            y: top,
+            width: right - left,
+            height: bottom - top,
+        })
+    }
+
+    /// Returns the part of the region that lies within `area`, or `None` if none of it does.
+    pub fn within(&self, area: &Region) -> Option<Self> {
+        Region::clamped(
+            self.x as i64 - area.x as i64,
+            self.y as i64 - area.y as i64,
+            self.width,
+            self.height,
+            (area.width, area.height),
+        )
+        .map(|region| Region {
+            x: region.x + area.x,
+            y: region.y + area.y,
+            ..region
+        })
+    }
+
+    /// Returns the largest region with the `aspect` ratio of width to height that fits in the
+    /// middle of this one.
+    pub fn crop_to_aspect(&self, aspect: f64) -> Self {
+        if self.width as f64 > self.height as f64 * aspect {
+            let width = cmp::max((self.height as f64 * aspect).round() as usize, 1);
+            Region {
+                x: self.x + (self.width - width) / 2,
+                width,
+                ..*self
+            }
+        } else {
+            let height = cmp::max((self.width as f64 / aspect).round() as usize, 1);
+            Region {
+                y: self.y + (self.height - height) / 2,
+                height,
+                ..*self
+            }
+        }
+    }
+
+    /// Moves `point` to the nearest point within the region, counting its right and bottom edges
+    /// so that a corner dragged there still spans the whole region.
+    #[cfg_attr(not(feature = "interactive"), allow(dead_code))]
+    pub fn confine(&self, (x, y): (i32, i32)) -> (i32, i32) {
+        (
+            x.clamp(self.x as i32, (self.x + self.width) as i32),
+            y.clamp(self.y as i32, (self.y + self.height) as i32),
+        )
+    }
+}
+
+/// Which point of a fixed-size region is placed at the cursor
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Anchor {
+    TopLeft,
+    Center,
+    BottomRight,
+}
+
+impl FromStr for Anchor {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "top-left" => Ok(Anchor::TopLeft),
+            "center" => Ok(Anchor::Center),
+            "bottom-right" => Ok(Anchor::BottomRight),
+            _ => Err(format!(
+                "Invalid anchor `{}`; expected one of `top-left`, `center`, or `bottom-right`",
+                s
+            )),
+        }
+    }
+}
+
+/// Parses a size given as `WIDTHxHEIGHT`, such as `800x600`.
+pub fn parse_size(s: &str) -> Result<(usize, usize), String> {
+    let invalid = || format!("Invalid size `{}`; expected something like `800x600`", s);
+    let mut parts = s.trim().splitn(2, ['x', 'X']);
+    let width: usize = parts
+        .next()
+        .and_then(|width| width.parse().ok())
+        .ok_or_else(invalid)?;
+    let height: usize = parts
+        .next()
+        .and_then(|height| height.parse().ok())
+        .ok_or_else(invalid)?;
+    if width == 0 || height == 0 {
+        return Err(invalid());
+    }
+
+    Ok((width, height))
+}
+
+/// Parses an aspect ratio given as `WIDTH:HEIGHT`, such as `16:9` or `1.91:1`, into the ratio of
+/// width to height.
+pub fn parse_aspect(s: &str) -> Result<f64, String> {
+    let invalid = || {
+        format!(
+            "Invalid aspect ratio `{}`; expected something like `16:9`",
+            s.trim()
+        )
+    };
+    let (width, height) = s.trim().split_once(':').ok_or_else(invalid)?;
+    let width: f64 = width.trim().parse().map_err(|_| invalid())?;
+    let height: f64 = height.trim().parse().map_err(|_| invalid())?;
+    let aspect = width / height;
+    if !(aspect.is_finite() && aspect > 0.) {
+        return Err(invalid());
+    }
+
+    Ok(aspect)
+}
+
+/// Moves the `corner` being dragged away from `start` so that the region between them has the
+/// `aspect` ratio of width to height. The corner only ever moves towards `start`, so the region
+/// stays within what was dragged out and never goes off the display.
+#[cfg_attr(not(feature = "interactive"), allow(dead_code))]
+pub fn constrain_to_aspect(start: (i32, i32), corner: (i32, i32), aspect: f64) -> (i32, i32) {
+    let (dx, dy) = (corner.0 - start.0, corner.1 - start.1);
+    let (width, height) = (dx.abs() as f64, dy.abs() as f64);
+    let (width, height) = if width > height * aspect {
+        (height * aspect, height)
+    } else {
+        (width, width / aspect)
+    };
+    (
+        start.0 + width.round() as i32 * dx.signum(),
+        start.1 + height.round() as i32 * dy.signum(),
+    )
+}
+
+/// Parses a point given as `X,Y`.
+pub fn parse_point(s: &str) -> Result<(i64, i64), String> {
+    let invalid = || {
+        format!(
+            "Invalid corner `{}`; expected something like `100,200`",
+            s.trim()
+        )
+    };
+    let (x, y) = s.split_once(',').ok_or_else(invalid)?;
+    Ok((
+        x.trim().parse().map_err(|_| invalid())?,
+        y.trim().parse().map_err(|_| invalid())?,
+    ))
+}
+
+/// Returns the region between two opposite corners, in any order, as `(x, y, width, height)`, or
+/// an error if they don't span any area.
+pub fn region_between(
+    corner_1: (i64, i64),
+    corner_2: (i64, i64),
+) -> Result<(i64, i64, usize, usize), String> {
+    let width = (corner_1.0 - corner_2.0).unsigned_abs() as usize;
+    let height = (corner_1.1 - corner_2.1).unsigned_abs() as usize;
+    if width == 0 || height == 0 {
+        return Err("The corners have to be apart both horizontally and vertically".into());
+    }
+    Ok((
+        cmp::min(corner_1.0, corner_2.0),
+        cmp::min(corner_1.1, corner_2.1),
+        width,
+        height,
+    ))
+}
+
+/// Parses a region given as `X,Y,WIDTH,HEIGHT`. The position and size may also be given as
+/// `X,Y WIDTHxHEIGHT`, which is what region pickers such as `slurp` print by default. Returns the
+/// position and size separately since the position may be off screen.
+pub fn parse_region(s: &str) -> Result<(i64, i64, usize, usize), String> {
+    let invalid = || {
+        format!(
+            "Invalid region `{}`; expected something like `100,200,800,600`",
+            s.trim()
+        )
+    };
+    let parts: Vec<&str> = s
+        .split([',', ' ', 'x', 'X'])
+        .filter(|part| !part.trim().is_empty())
+        .map(str::trim)
+        .collect();
+    if parts.len() != 4 {
+        return Err(invalid());
+    }
+
+    let x: i64 = parts[0].parse().map_err(|_| invalid())?;
+    let y: i64 = parts[1].parse().map_err(|_| invalid())?;
+    let width: usize = parts[2].parse().map_err(|_| invalid())?;
+    let height: usize = parts[3].parse().map_err(|_| invalid())?;
+    if width == 0 || height == 0 {
+        return Err(invalid());
+    }
+
+    Ok((x, y, width, height))
+}
+
+/// Parses a region given as percentages of the display's size like `X,Y,WIDTH,HEIGHT`, such as
+/// `25,25,50,50` for the middle quarter of the screen.
+pub fn parse_region_percent(s: &str) -> Result<[f64; 4], String> {
+    let invalid = || {
+        format!(
+            "Invalid region `{}`; expected percentages of the screen like `25,25,50,50`",
+            s.trim()
+        )
+    };
+    let parts: Vec<f64> = s
+        .split(',')
+        .map(|part| part.trim().trim_end_matches('%').parse())
+        .collect::<Result<_, _>>()
+        .map_err(|_| invalid())?;
+    if parts.len() != 4 || parts.iter().any(|&part| !(0. ..=100.).contains(&part)) {
+        return Err(invalid());
+    }
+    if parts[2] == 0. || parts[3] == 0. {
+        return Err(invalid());
+    }
+
+    Ok([parts[0], parts[1], parts[2], parts[3]])
+}
+
+/// Converts a region given in percentages of a display of size `bounds` into pixels.
+pub fn region_from_percent(percent: [f64; 4], bounds: (usize, usize)) -> (i64, i64, usize, usize) {
+    let to_pixels = |percent: f64, bound: usize| (percent / 100. * bound as f64).round();
+    (
+        to_pixels(percent[0], bounds.0) as i64,
+        to_pixels(percent[1], bounds.1) as i64,
+        cmp::max(to_pixels(percent[2], bounds.0) as usize, 1),
+        cmp::max(to_pixels(percent[3], bounds.1) as usize, 1),
+    )
+}
+
+/// Converts a point from SDL window coordinates into physical pixels, given the window's
+/// `scale_factor` of physical pixels per point. The result is clamped to between `(0, 0)` and
+/// `bounds` inclusive, since a point is used as a corner of a selection that extends up to but
+/// not including it.
+#[cfg_attr(not(feature = "interactive"), allow(dead_code))]
+pub fn to_physical(point: (i32, i32), scale_factor: f64, bounds: (usize, usize)) -> (usize, usize) {
+    let scale = |coordinate: i32, bound: usize| {
+        let physical = (coordinate as f64 * scale_factor).round();
+        (physical.max(0.) as usize).min(bound)
+    };
+    (scale(point.0, bounds.0), scale(point.1, bounds.1))
+}
+
+/// Copies `region` out of a captured BGRA `frame` with rows `stride` bytes apart, producing a
+/// tightly packed RGBA buffer.
+pub fn crop(frame: &[u8], stride: usize, region: &Region) -> Vec<u8> {
+    capture::crop_frame(
+        frame,
+        stride,
+        (region.x, region.y, region.width, region.height),
+    )
+}
+
+/// Returns the color of `region` of a captured BGRA `frame` as RGB if every pixel in it is that
+/// color, ignoring alpha. It gives up at the first pixel that differs, so it's quick for anything
+/// that isn't a solid color.
+pub fn solid_color(frame: &[u8], stride: usize, region: &Region) -> Option<[u8; 3]> {
+    let start = region.y * stride + region.x * 4;
+    let first = frame.get(start..start + 3)?;
+    let solid = (region.y..region.y + region.height).all(|y| {
+        let row = y * stride + region.x * 4;
+        frame[row..row + region.width * 4]
+            .chunks_exact(4)
+            .all(|pixel| pixel[..3] == *first)
+    });
+    solid.then(|| [first[2], first[1], first[0]])
+}
+
+/// Like `crop`, but for an RGBA `buffer` that's `width` pixels wide, which it doesn't reorder.
+pub fn crop_rgba(buffer: &[u8], width: usize, region: &Region) -> Vec<u8> {
+    let mut cropped = Vec::with_capacity(region.width * region.height * 4);
+    for y in region.y..region.y + region.height {
+        let start = (y * width + region.x) * 4;
+        cropped.extend_from_slice(&buffer[start..start + region.width * 4]);
+    }
+    cropped
+}
+
+/// Parses a color given as hex `rrggbb` or `rrggbbaa`, optionally prefixed with `#`, into RGBA.
+pub fn parse_color(s: &str) -> Result<[u8; 4], String> {
+    let hex = s.trim().trim_start_matches('#');
+    let invalid = || {
+        format!(
+            "Invalid color `{}`; expected a hex color like `#ffffff` or `#00000080`",
+            s
+        )
+    };
+    if (hex.len() != 6 && hex.len() != 8) || !hex.is_ascii() {
+        return Err(invalid());
+    }
+
+    let mut color = [0, 0, 0, 255];
+    for (i, channel) in color.iter_mut().enumerate().take(hex.len() / 2) {
+        *channel = u8::from_str_radix(&hex[i * 2..i * 2 + 2], 16).map_err(|_| invalid())?;
+    }
+
+    Ok(color)
+}
+
+/// Composites the RGBA pixel `src` over the RGBA pixel `dst` in place.
+pub fn blend_over(dst: &mut [u8], src: &[u8]) {
+    let src_alpha = src[3] as u32;
+    let dst_alpha = dst[3] as u32;
+    let out_alpha = src_alpha + dst_alpha * (255 - src_alpha) / 255;
+    if out_alpha == 0 {
+        dst.copy_from_slice(&[0, 0, 0, 0]);
+        return;
+    }
+
+    for i in 0..3 {
+        let blended = (src[i] as u32 * src_alpha
+            + dst[i] as u32 * dst_alpha * (255 - src_alpha) / 255)
+            / out_alpha;
+        dst[i] = blended as u8;
+    }
+    dst[3] = out_alpha as u8;
+}
+
+/// Surrounds an RGBA buffer with `padding` pixels on every side, compositing the original image
+/// over `background`. Returns the new buffer along with its width and height.
+pub fn pad(
+    buffer: &[u8],
+    width: usize,
+    height: usize,
+    padding: usize,
+    background: [u8; 4],
+) -> (Vec<u8>, usize, usize) {
+    let padded_width = width + padding * 2;
+    let padded_height = height + padding * 2;
+    let mut padded: Vec<u8> = background
+        .iter()
+        .cycle()
+        .take(padded_width * padded_height * 4)
+        .cloned()
+        .collect();
+    if width == 0 {
+        return (padded, padded_width, padded_height);
+    }
+
+    for (y, row) in buffer.chunks(width * 4).enumerate() {
+        let row_start = ((y + padding) * padded_width + padding) * 4;
+        for (dst, src) in padded[row_start..row_start + width * 4]
+            .chunks_mut(4)
+            .zip(row.chunks(4))
+        {
+            blend_over(dst, src);
+        }
+    }
+
+    (padded, padded_width, padded_height)
+}
+
+/// Places two RGBA images that are both `width` x `height` next to each other, with `gap` pixels of
+/// `background` between them. Returns the new buffer along with its width and height.
+pub fn side_by_side(
+    left: &[u8],
+    right: &[u8],
+    width: usize,
+    height: usize,
+    gap: usize,
+    background: [u8; 4],
+) -> (Vec<u8>, usize, usize) {
+    let combined_width = width * 2 + gap;
+    let mut combined = Vec::with_capacity(combined_width * height * 4);
+    let gap_row: Vec<u8> = background.iter().cycle().take(gap * 4).cloned().collect();
+    for y in 0..height {
+        let row = y * width * 4..(y + 1) * width * 4;
+        combined.extend_from_slice(&left[row.clone()]);
+        combined.extend_from_slice(&gap_row);
+        combined.extend_from_slice(&right[row]);
+    }
+    (combined, combined_width, height)
+}
+
+/// How much any channel of a pixel has to change by to count as different in `highlight_diff`
+const DIFF_THRESHOLD: u8 = 8;
+
+/// Returns the smallest region containing every pixel that differs between `before` and `after`,
+/// RGBA images of the same size, or `None` if they're the same.
+pub fn changed_bounds(before: &[u8], after: &[u8], width: usize) -> Option<Region> {
+    let mut bounds: Option<(usize, usize, usize, usize)> = None;
+    for (i, (after, before)) in after.chunks(4).zip(before.chunks(4)).enumerate() {
+        let changed = after
+            .iter()
+            .zip(before)
+            .any(|(&after, &before)| after.abs_diff(before) > DIFF_THRESHOLD);
+        if !changed {
+            continue;
+        }
+        let (x, y) = (i % width, i / width);
+        bounds = Some(match bounds {
+            Some((left, top, right, bottom)) => (
+                cmp::min(left, x),
+                cmp::min(top, y),
+                cmp::max(right, x),
+                cmp::max(bottom, y),
+            ),
+            None => (x, y, x, y),
+        });
+    }
+    bounds.map(|(left, top, right, bottom)| Region {
+        x: left,
+        y: top,
+        width: right - left + 1,
+        height: bottom - top + 1,
+    })
+}
+
+/// Returns `after` with the pixels that differ from `before` tinted with `highlight` and the rest
+/// washed out, so that what changed between two RGBA images of the same size stands out.
+pub fn highlight_diff(before: &[u8], after: &[u8], highlight: [u8; 4]) -> Vec<u8> {
+    let mut diff = after.to_vec();
+    for (pixel, before) in diff.chunks_mut(4).zip(before.chunks(4)) {
+        let changed = pixel
+            .iter()
+            .zip(before)
+            .any(|(&after, &before)| after.abs_diff(before) > DIFF_THRESHOLD);
+        if changed {
+            blend_over(pixel, &highlight);
+        } else {
+            blend_over(pixel, &[255, 255, 255, 176]);
+        }
+    }
+    diff
+}
+
+/// What shows through the transparent parts of a window captured by `--transparent-window-capture`
+#[derive(Clone, Copy, Debug)]
+pub enum Backdrop {
+    Checkerboard,
+    /// A color, which keeps the transparency in the image if it's transparent itself
+    Color([u8; 4]),
+}
+
+impl FromStr for Backdrop {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if s.eq_ignore_ascii_case("checkerboard") {
+            return Ok(Backdrop::Checkerboard);
+        }
+        parse_color(s)
+            .map(Backdrop::Color)
+            .map_err(|err| format!("{}, or `checkerboard`", err))
+    }
+}
+
+/// Side length in pixels of the squares in `Backdrop::Checkerboard`
+const CHECKER_SIZE: usize = 8;
+
+impl Backdrop {
+    fn color_at(self, x: usize, y: usize) -> [u8; 4] {
+        match self {
+            Backdrop::Checkerboard if (x / CHECKER_SIZE + y / CHECKER_SIZE).is_multiple_of(2) => {
+                [255, 255, 255, 255]
+            }
+            Backdrop::Checkerboard => [204, 204, 204, 255],
+            Backdrop::Color(color) => color,
+        }
+    }
+}
+
+/// Composites an RGBA image with premultiplied alpha, as transparent windows are drawn, over
+/// `backdrop`, returning a straight alpha RGBA image.
+pub fn composite_over(premultiplied: &[u8], width: usize, backdrop: Backdrop) -> Vec<u8> {
+    let mut composited = Vec::with_capacity(premultiplied.len());
+    for (i, pixel) in premultiplied.chunks(4).enumerate() {
+        let under = backdrop.color_at(i % width, i / width);
+        let coverage = 255 - pixel[3] as u32;
+        let under_alpha = under[3] as u32 * coverage / 255;
+        let alpha = pixel[3] as u32 + under_alpha;
+        for channel in 0..3 {
+            let value = pixel[channel] as u32 + under[channel] as u32 * under_alpha / 255;
+            let straight = (value * 255).checked_div(alpha).unwrap_or(0);
+            composited.push(cmp::min(straight, 255) as u8);
+        }
+        composited.push(alpha as u8);
+    }
+    composited
+}
+
+/// How the frame drawn around captures by `--border` and `--shadow` looks
+#[derive(Clone, Copy, Debug)]
+pub struct Border {
+    /// Width in pixels of the solid frame, which can be zero for just a shadow
+    pub width: usize,
+    pub color: [u8; 4],
+    /// Blur radius of the drop shadow in pixels, or zero for no shadow
+    pub shadow_blur: usize,
+}
+
+/// Opacity of the darkest part of drop shadows
+const SHADOW_OPACITY: f32 = 0.5;
+
+impl Border {
+    /// Room left around the frame for the shadow to fade out in
+    fn shadow_margin(&self) -> usize {
+        self.shadow_blur * 2
+    }
+
+    /// Distance from the edges of a bordered image to the original image inside it
+    pub fn inset(&self) -> usize {
+        self.width + self.shadow_margin()
+    }
+}
+
+/// Blurs a single channel `width` x `height` image in place by averaging each value with the
+/// `radius` values on either side of it, first along rows and then along columns. Values past the
+/// edges count as zero.
+fn box_blur(values: &mut [f32], width: usize, height: usize, radius: usize) {
+    let mut blur_line = |len: usize, index: &dyn Fn(usize) -> usize| {
+        let line: Vec<f32> = (0..len).map(|i| values[index(i)]).collect();
+        let mut sum: f32 = line.iter().take(radius).sum();
+        for i in 0..len {
+            if i + radius < len {
+                sum += line[i + radius];
+            }
+            values[index(i)] = sum / (radius * 2 + 1) as f32;
+            if i >= radius {
+                sum -= line[i - radius];
+            }
+        }
+    };
+
+    for y in 0..height {
+        blur_line(width, &|x| y * width + x);
+    }
+    for x in 0..width {
+        blur_line(height, &|y| y * width + x);
+    }
+}
+
+/// Draws `border` around an RGBA buffer: a solid frame of its color, then a drop shadow falling
+/// down from behind that. Returns the new buffer along with its width and height, which grow by
+/// `border.inset()` on every side.
+pub fn add_border(
+    buffer: &[u8],
+    width: usize,
+    height: usize,
+    border: &Border,
+) -> (Vec<u8>, usize, usize) {
+    let (mut framed, framed_width, framed_height) =
+        pad(buffer, width, height, border.width, [0, 0, 0, 0]);
+    for y in 0..framed_height {
+        for x in 0..framed_width {
+            let inside = (border.width..border.width + width).contains(&x)
+                && (border.width..border.width + height).contains(&y);
+            if !inside {
+                let i = (y * framed_width + x) * 4;
+                framed[i..i + 4].copy_from_slice(&border.color);
+            }
+        }
+    }
+    if border.shadow_blur == 0 || framed_width == 0 {
+        return (framed, framed_width, framed_height);
+    }
+
+    let margin = border.shadow_margin();
+    let (out_width, out_height) = (framed_width + margin * 2, framed_height + margin * 2);
+    // The shadow is cast by light from slightly above
+    let shadow_offset = border.shadow_blur / 2;
+    let mut shadow = vec![0.; out_width * out_height];
+    for y in margin + shadow_offset..(margin + shadow_offset + framed_height).min(out_height) {
+        for x in margin..margin + framed_width {
+            shadow[y * out_width + x] = 1.;
+        }
+    }
+    // Repeated box blurs approximate a Gaussian blur
+    let pass_radius = (border.shadow_blur / 3).max(1);
+    for _ in 0..3 {
+        box_blur(&mut shadow, out_width, out_height, pass_radius);
+    }
+
+    let mut out: Vec<u8> = shadow
+        .iter()
+        .flat_map(|&coverage| {
+            let alpha = (coverage * SHADOW_OPACITY * 255.).round() as u8;
+            vec![0, 0, 0, alpha]
+        })
+        .collect();
+    for (y, row) in framed.chunks(framed_width * 4).enumerate() {
+        let row_start = ((y + margin) * out_width + margin) * 4;
+        for (dst, src) in out[row_start..row_start + framed_width * 4]
+            .chunks_mut(4)
+            .zip(row.chunks(4))
+        {
+            blend_over(dst, src);
+        }
+    }
+
+    (out, out_width, out_height)
+}
+
+/// Extends an RGBA buffer downwards with a bar of `background` containing a single line of `text`
+/// drawn in `foreground` with the built-in font at `scale`. Text that doesn't fit is cut off.
+pub fn append_caption(
+    buffer: &[u8],
+    width: usize,
+    height: usize,
+    text: &str,
+    scale: u32,
+    background: [u8; 4],
+    foreground: [u8; 4],
+) -> (Vec<u8>, usize, usize) {
+    let margin = (2 * scale) as usize;
+    let (_, text_height) = font::text_size(text, scale);
+    let bar_height = text_height as usize + 2 * margin;
+
+    let mut bar: Vec<u8> = background
+        .iter()
+        .cycle()
+        .take(width * bar_height * 4)
+        .cloned()
+        .collect();
+    for (left, top) in font::text_pixels(text, margin as i32, margin as i32, scale) {
+        for y in top..top + scale as i32 {
+            for x in left..cmp::min(left + scale as i32, width as i32) {
+                let ix = (y as usize * width + x as usize) * 4;
+                blend_over(&mut bar[ix..ix + 4], &foreground);
+            }
+        }
+    }
+
+    let mut captioned = buffer.to_vec();
+    captioned.extend_from_slice(&bar);
+    (captioned, width, height + bar_height)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn aspect_ratios_are_kept_during_drags_and_crops() {
+        assert_eq!(parse_aspect("16:9"), Ok(16. / 9.));
+        assert_eq!(parse_aspect(" 1.91 : 1 "), Ok(1.91));
+        assert!(parse_aspect("16x9").is_err());
+        assert!(parse_aspect("1:0").is_err());
+
+        // Dragging up and to the left of the start of a wide drag
+        assert_eq!(constrain_to_aspect((500, 500), (100, 400), 1.), (400, 400));
+        assert_eq!(
+            Region {
+                x: 0,
+                y: 10,
+                width: 100,
+                height: 300,
+            }
+            .crop_to_aspect(2.),
+            Region {
+                x: 0,
+                y: 135,
+                width: 100,
+                height: 50,
+            }
+        );
+    }
+
+    #[test]
+    fn within_keeps_the_part_inside_the_area() {
+        // A display with a 40 pixel panel along its bottom
+        let area = Region {
+            x: 0,
+            y: 0,
+            width: 1920,
+            height: 1040,
+        };
+        let region = Region {
+            x: 100,
+            y: 1000,
+            width: 200,
+            height: 80,
+        };
+        assert_eq!(
+            region.within(&area),
+            Some(Region {
+                height: 40,
+                ..region
+            })
+        );
+        assert_eq!(area.confine((2000, 1079)), (1920, 1040));
+        assert_eq!(Region { y: 1050, ..region }.within(&area), None);
+    }
+
+    #[test]
+    fn solid_color_ignores_pixels_outside_the_region() {
+        // 3x2 BGRA frame with one red pixel in the top right corner
+        let mut frame = [10, 20, 30, 255].repeat(6);
+        frame[8..12].copy_from_slice(&[0, 0, 255, 255]);
+        let region = |x, width| Region {
+            x,
+            y: 0,
+            width,
+            height: 2,
+        };
+        assert_eq!(solid_color(&frame, 12, &region(0, 2)), Some([30, 20, 10]));
+        assert_eq!(solid_color(&frame, 12, &region(0, 3)), None);
+    }
+
+    #[test]
+    fn to_physical_is_identity_at_1x() {
+        assert_eq!(to_physical((0, 0), 1., (1920, 1080)), (0, 0));
+        assert_eq!(to_physical((100, 200), 1., (1920, 1080)), (100, 200));
+        assert_eq!(to_physical((1919, 1079), 1., (1920, 1080)), (1919, 1079));
+    }
+
+    #[test]
+    fn to_physical_scales_at_2x() {
+        assert_eq!(to_physical((0, 0), 2., (2880, 1800)), (0, 0));
+        assert_eq!(to_physical((100, 200), 2., (2880, 1800)), (200, 400));
+        assert_eq!(to_physical((1439, 899), 2., (2880, 1800)), (2878, 1798));
+    }
+
+    #[test]
+    fn to_physical_clamps_to_bounds() {
+        assert_eq!(to_physical((-5, -10), 1., (1920, 1080)), (0, 0));
+        assert_eq!(to_physical((1920, 1080), 1., (1920, 1080)), (1920, 1080));
+        assert_eq!(to_physical((3000, 1200), 1., (1920, 1080)), (1920, 1080));
+        assert_eq!(to_physical((1440, 900), 2., (2880, 1800)), (2880, 1800));
+        assert_eq!(to_physical((1500, -1), 2., (2880, 1800)), (2880, 0));
+    }
+
+    #[test]
+    fn region_between_accepts_corners_in_any_order() {
+        let region = Ok((100, 200, 300, 400));
+        assert_eq!(region_between((100, 200), (400, 600)), region);
+        assert_eq!(region_between((400, 600), (100, 200)), region);
+        assert_eq!(region_between((100, 600), (400, 200)), region);
+        assert_eq!(region_between((-50, 0), (50, 10)), Ok((-50, 0, 100, 10)));
+        assert!(region_between((100, 200), (100, 600)).is_err());
+        assert_eq!(parse_point(" 12, -3 "), Ok((12, -3)));
+        assert!(parse_point("12").is_err());
+    }
+}
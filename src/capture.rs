@@ -0,0 +1,614 @@
+//! Pulling frames out of the screen capturer.
+//!
+//! `scrap` hands out frames that borrow the capturer and are only valid until the next read, and
+//! some of its backends report `WouldBlock` intermittently even after frames have started
+//! arriving. Frames are copied into owned buffers here and not-ready reads are retried so that
+//! the rest of the code can grab frames repeatedly without worrying about either.
+//!
+//! Everything outside of `open_display` works with the `ScreenCapture` trait rather than a
+//! `scrap::Capturer` directly, so that tests can supply fake capturers with synthetic frames.
+
+use std::env;
+use std::io::{self, ErrorKind};
+use std::process::Command;
+use std::thread;
+use std::time::{Duration, Instant};
+
+use scrap::{Capturer, Display};
+
+use crate::error::SnapmeoError;
+
+/// How long to keep retrying for a frame before giving up on the capturer
+pub const FRAME_TIMEOUT: Duration = Duration::from_secs(5);
+/// How many frames to throw away when capturing starts unless configured otherwise
+pub const SETTLE_FRAMES: u32 = 1;
+/// How long to wait for transient UI such as closing animations to get out of the way before the
+/// captured frame is grabbed unless configured otherwise
+pub const SETTLE_DELAY: Duration = Duration::from_millis(100);
+/// How long to wait between checks for a frame by default, which is a frame at 60Hz
+const POLL_INTERVAL: Duration = Duration::from_micros(16_667);
+/// Platforms pad rows out by less than this many bytes for alignment, so rows that are any longer
+/// than that come from a display that's wider than the capturer thinks
+const MAX_ROW_PADDING: usize = 256;
+
+/// Something frames of the screen can be read from
+pub trait ScreenCapture {
+    /// Width of the display in pixels
+    fn width(&self) -> usize;
+    /// Height of the display in pixels
+    fn height(&self) -> usize;
+    /// Returns a copy of the current BGRA frame, or an error of kind `WouldBlock` if there isn't a
+    /// new one ready yet. Its rows may be padded out past `width * 4` bytes.
+    fn frame(&mut self) -> io::Result<Vec<u8>>;
+}
+
+impl ScreenCapture for Capturer {
+    fn width(&self) -> usize {
+        Capturer::width(self)
+    }
+
+    fn height(&self) -> usize {
+        Capturer::height(self)
+    }
+
+    fn frame(&mut self) -> io::Result<Vec<u8>> {
+        Capturer::frame(self).map(|frame| frame.to_vec())
+    }
+}
+
+impl<C: ScreenCapture + ?Sized> ScreenCapture for Box<C> {
+    fn width(&self) -> usize {
+        (**self).width()
+    }
+
+    fn height(&self) -> usize {
+        (**self).height()
+    }
+
+    fn frame(&mut self) -> io::Result<Vec<u8>> {
+        (**self).frame()
+    }
+}
+
+/// Returns a `Capture` error for `problem` that suggests the usual reason for displays being
+/// unavailable on this platform, since that's what most people hit when first running snapmeo.
+fn display_unavailable(problem: String) -> SnapmeoError {
+    let hint = if cfg!(target_os = "macos") {
+        "Check that snapmeo, or the terminal running it, is allowed to record the screen under \
+         System Settings > Privacy & Security > Screen Recording."
+            .to_owned()
+    } else if cfg!(windows) {
+        "Check that snapmeo is running in a desktop session rather than as a service.".to_owned()
+    } else {
+        match env::var("DISPLAY").ok().filter(|display| !display.is_empty()) {
+            Some(display) => format!(
+                "Check that the X server at `DISPLAY={}` is running and can be connected to.",
+                display
+            ),
+            None if env::var_os("WAYLAND_DISPLAY").is_some() => {
+                "`DISPLAY` isn't set; under Wayland, snapmeo captures through XWayland, which needs \
+                 it to be."
+                    .to_owned()
+            }
+            None => "`DISPLAY` isn't set, so there's no X server to capture from.".to_owned(),
+        }
+    };
+    SnapmeoError::Capture(format!("{}. {}", problem, hint))
+}
+
+fn open(display: Display) -> Result<Box<dyn ScreenCapture>, SnapmeoError> {
+    let capturer = Capturer::new(display)
+        .map_err(|err| display_unavailable(format!("Couldn't begin capture: {}", err)))?;
+    Ok(Box::new(capturer))
+}
+
+/// Starts capturing the primary display.
+pub fn open_primary() -> Result<Box<dyn ScreenCapture>, SnapmeoError> {
+    let display = Display::primary().map_err(|err| {
+        display_unavailable(format!("Couldn't find a display to capture: {}", err))
+    })?;
+    open(display)
+}
+
+/// The position and size of a display on the desktop, in pixels: `(x, y, width, height)`
+pub type DisplayRect = (i64, i64, usize, usize);
+
+/// Returns where each display is on the desktop, in the order that `open_display` numbers them.
+/// Only the X11 capturer says where its displays are, so this is `None` on other platforms.
+pub fn display_rects() -> Option<Vec<DisplayRect>> {
+    #[cfg(all(unix, not(target_os = "macos")))]
+    {
+        use std::rc::Rc;
+
+        use scrap::x11::Server;
+
+        let server = Rc::new(Server::default().ok()?);
+        Some(
+            Server::displays(server)
+                .map(|display| {
+                    let rect = display.rect();
+                    (
+                        i64::from(rect.x),
+                        i64::from(rect.y),
+                        usize::from(rect.w),
+                        usize::from(rect.h),
+                    )
+                })
+                .collect(),
+        )
+    }
+    #[cfg(not(all(unix, not(target_os = "macos"))))]
+    {
+        None
+    }
+}
+
+/// Returns the index among `displays` of the only one at `rect`, or `None` if there isn't exactly
+/// one, in which case there's no telling which display is meant.
+pub fn find_display(displays: &[DisplayRect], rect: DisplayRect) -> Option<usize> {
+    let mut matches = displays
+        .iter()
+        .enumerate()
+        .filter(|(_, display)| **display == rect)
+        .map(|(index, _)| index);
+    match (matches.next(), matches.next()) {
+        (Some(index), None) => Some(index),
+        _ => None,
+    }
+}
+
+/// Starts capturing the display at `index` in the order the platform lists them.
+pub fn open_display(index: usize) -> Result<Box<dyn ScreenCapture>, SnapmeoError> {
+    let displays = Display::all()
+        .map_err(|err| display_unavailable(format!("Couldn't list the displays: {}", err)))?;
+    if displays.is_empty() {
+        return Err(display_unavailable("No displays were found".into()));
+    }
+    let display_count = displays.len();
+    let display = displays.into_iter().nth(index).ok_or_else(|| {
+        SnapmeoError::BadArguments(format!(
+            "There's no display {}; there are only {}",
+            index, display_count
+        ))
+    })?;
+    open(display)
+}
+
+/// Reads a frame from `source`, waiting `poll_interval` between attempts while it's not ready.
+/// Returns an error if no frame is ready within `timeout` or reading fails for any other reason.
+pub fn next_frame<S: ScreenCapture + ?Sized>(
+    source: &mut S,
+    poll_interval: Duration,
+    timeout: Duration,
+) -> Result<Vec<u8>, SnapmeoError> {
+    let start = Instant::now();
+
+    loop {
+        match source.frame() {
+            // An empty frame is as good as no frame at all
+            Ok(frame) if !frame.is_empty() => return Ok(frame),
+            Ok(_) => {}
+            Err(err) if matches!(err.kind(), ErrorKind::WouldBlock | ErrorKind::Interrupted) => {}
+            Err(err) => return Err(SnapmeoError::Capture(err.to_string())),
+        }
+
+        if start.elapsed() >= timeout {
+            return Err(SnapmeoError::Capture(format!(
+                "No frame was ready after waiting {:.1} seconds",
+                timeout.as_secs_f64()
+            )));
+        }
+        thread::sleep(poll_interval);
+    }
+}
+
+/// Reads and discards `frames` frames from `source`, for backends whose first frames after
+/// capturing starts can be stale or blank.
+pub fn settle<S: ScreenCapture + ?Sized>(
+    source: &mut S,
+    frames: u32,
+    poll_interval: Duration,
+    timeout: Duration,
+) -> Result<(), SnapmeoError> {
+    for _ in 0..frames {
+        next_frame(source, poll_interval, timeout)?;
+    }
+    Ok(())
+}
+
+/// Returns whether every pixel of the BGRA `frame` is black, ignoring alpha.
+pub fn is_blank(frame: &[u8]) -> bool {
+    frame
+        .chunks(4)
+        .all(|pixel| pixel[..3].iter().all(|&c| c == 0))
+}
+
+/// Returns an error if `frame` looks like what macOS hands out until snapmeo is allowed to record
+/// the screen, which is an entirely black frame rather than an error. Does nothing on other
+/// platforms, where a black screen is just a black screen.
+pub fn check_permission(frame: &[u8]) -> Result<(), SnapmeoError> {
+    if !cfg!(target_os = "macos") || !is_blank(frame) {
+        return Ok(());
+    }
+    Err(SnapmeoError::Capture(
+        "The screen came back entirely black, which is what macOS captures until snapmeo, or the \
+         terminal running it, is allowed to record the screen. Allow it under System Settings > \
+         Privacy & Security > Screen Recording, then restart the terminal and try again."
+            .into(),
+    ))
+}
+
+/// Opens the Screen Recording pane of macOS's System Settings, where snapmeo can be allowed to
+/// record the screen.
+pub fn open_permission_settings() {
+    let _ = Command::new("open")
+        .arg("x-apple.systempreferences:com.apple.preference.security?Privacy_ScreenCapture")
+        .status();
+}
+
+/// Returns the rows of a `width` x `height` BGRA `frame` packed tightly together, or `None` if the
+/// frame's size doesn't fit those dimensions with rows of `width` pixels plus some padding.
+fn pack_frame(frame: Vec<u8>, width: usize, height: usize) -> Option<Vec<u8>> {
+    let row_len = width * 4;
+    if height == 0 || !frame.len().is_multiple_of(height) {
+        return None;
+    }
+    let stride = frame.len() / height;
+    if stride < row_len || !stride.is_multiple_of(4) || stride - row_len >= MAX_ROW_PADDING {
+        return None;
+    }
+
+    if stride == row_len {
+        return Some(frame);
+    }
+    // Some platforms pad rows out for alignment
+    Some(
+        frame
+            .chunks(stride)
+            .flat_map(|row| &row[..row_len])
+            .cloned()
+            .collect(),
+    )
+}
+
+/// Captures a frame from the screen, checking every `poll_interval` until the capturer has one
+/// ready or `timeout` passes. The frame's rows are `width * 4` bytes apart.
+///
+/// The display can change size after the capturer was created, such as when a laptop is docked,
+/// in which case its frames no longer match its dimensions. The capturer is then replaced with one
+/// from `reopen`, and an error is returned if the display's size really has changed, since the
+/// selection overlay and any region being captured were based on the old size.
+pub fn grab_frame<C: ScreenCapture>(
+    capturer: &mut C,
+    reopen: impl FnOnce() -> Result<C, SnapmeoError>,
+    poll_interval: Duration,
+    timeout: Duration,
+) -> Result<Vec<u8>, SnapmeoError> {
+    let (width, height) = (capturer.width(), capturer.height());
+    let frame = next_frame(capturer, poll_interval, timeout)?;
+    if let Some(frame) = pack_frame(frame, width, height) {
+        return Ok(frame);
+    }
+
+    *capturer = reopen()?;
+    let (new_width, new_height) = (capturer.width(), capturer.height());
+    if (new_width, new_height) != (width, height) {
+        return Err(SnapmeoError::Capture(format!(
+            "The display's resolution changed from {}x{} to {}x{} during capture; try again",
+            width, height, new_width, new_height
+        )));
+    }
+
+    let frame = next_frame(capturer, poll_interval, timeout)?;
+    let frame_len = frame.len();
+    pack_frame(frame, width, height).ok_or_else(|| {
+        SnapmeoError::Capture(format!(
+            "Captured a frame of {} bytes, which doesn't match the display's size of {}x{}",
+            frame_len, width, height
+        ))
+    })
+}
+
+/// Copies the `width` x `height` area at (`x`, `y`) out of a BGRA `frame` whose rows are `stride`
+/// bytes apart, converting it to RGBA.
+pub fn crop_frame(
+    frame: &[u8],
+    stride: usize,
+    (x, y, width, height): (usize, usize, usize, usize),
+) -> Vec<u8> {
+    let mut buffer: Vec<u8> = Vec::with_capacity(width * height * 4);
+
+    for row in y..y + height {
+        for column in x..x + width {
+            let i = (stride * row) + (4 * column);
+            buffer.extend_from_slice(&[frame[i + 2], frame[i + 1], frame[i], 255]);
+        }
+    }
+
+    buffer
+}
+
+/// Captures `region`, given as `(x, y, width, height)` in pixels, of the display at
+/// `display_index` in the order the platform lists them, without showing anything or encoding the
+/// result.
+///
+/// Returns the pixels along with the region's width and height. They're RGBA with 8 bits per
+/// channel and alpha always 255, in rows from top to bottom with no padding between them, so each
+/// row is `width * 4` bytes long.
+pub fn capture_region_rgba(
+    display_index: usize,
+    region: (usize, usize, usize, usize),
+) -> Result<(Vec<u8>, u32, u32), SnapmeoError> {
+    let mut capturer = open_display(display_index)?;
+    settle(&mut capturer, SETTLE_FRAMES, POLL_INTERVAL, FRAME_TIMEOUT)?;
+    region_rgba(
+        &mut capturer,
+        || open_display(display_index),
+        region,
+        POLL_INTERVAL,
+        FRAME_TIMEOUT,
+    )
+}
+
+fn region_rgba<C: ScreenCapture>(
+    capturer: &mut C,
+    reopen: impl FnOnce() -> Result<C, SnapmeoError>,
+    region: (usize, usize, usize, usize),
+    poll_interval: Duration,
+    timeout: Duration,
+) -> Result<(Vec<u8>, u32, u32), SnapmeoError> {
+    let (x, y, width, height) = region;
+    let (display_width, display_height) = (capturer.width(), capturer.height());
+    if width == 0 || height == 0 || x + width > display_width || y + height > display_height {
+        return Err(SnapmeoError::BadArguments(format!(
+            "The region {}x{} at ({}, {}) isn't within the {}x{} display",
+            width, height, x, y, display_width, display_height
+        )));
+    }
+
+    let frame = grab_frame(capturer, reopen, poll_interval, timeout)?;
+    Ok((
+        crop_frame(&frame, display_width * 4, region),
+        width as u32,
+        height as u32,
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Returns frames where each pixel's blue and green channels are its x and y coordinates, with
+    /// each row padded out by `padding` bytes. The frames are `frame_width` pixels wide, which can
+    /// differ from the `width` reported.
+    struct FakeCapturer {
+        width: usize,
+        height: usize,
+        padding: usize,
+        frame_width: usize,
+    }
+
+    impl ScreenCapture for FakeCapturer {
+        fn width(&self) -> usize {
+            self.width
+        }
+
+        fn height(&self) -> usize {
+            self.height
+        }
+
+        fn frame(&mut self) -> io::Result<Vec<u8>> {
+            let mut frame = Vec::new();
+            for y in 0..self.height {
+                for x in 0..self.frame_width {
+                    frame.extend_from_slice(&[x as u8, y as u8, 0, 255]);
+                }
+                frame.extend(std::iter::repeat_n(0, self.padding));
+            }
+            Ok(frame)
+        }
+    }
+
+    fn fake(width: usize, height: usize, padding: usize) -> FakeCapturer {
+        FakeCapturer {
+            width,
+            height,
+            padding,
+            frame_width: width,
+        }
+    }
+
+    fn grab(capturer: &mut FakeCapturer, reopened: FakeCapturer) -> Result<Vec<u8>, SnapmeoError> {
+        grab_frame(
+            capturer,
+            || Ok(reopened),
+            Duration::from_millis(1),
+            FRAME_TIMEOUT,
+        )
+    }
+
+    #[test]
+    fn grab_frame_strips_row_padding() {
+        let mut capturer = fake(3, 2, 8);
+        let frame = grab(&mut capturer, fake(3, 2, 8)).unwrap();
+        assert_eq!(frame, fake(3, 2, 0).frame().unwrap());
+    }
+
+    #[test]
+    fn grab_frame_reopens_when_frames_stop_matching() {
+        // Still claims to be 3x2 but produces 2x2 frames, like a capturer for a display that was
+        // resized after it was created
+        let mut capturer = fake(3, 2, 0);
+        capturer.frame_width = 2;
+        let frame = grab(&mut capturer, fake(3, 2, 0)).unwrap();
+        assert_eq!(frame, fake(3, 2, 0).frame().unwrap());
+
+        let mut capturer = fake(3, 2, 0);
+        capturer.frame_width = 2;
+        let result = grab(&mut capturer, fake(2, 2, 0));
+        assert!(matches!(result, Err(SnapmeoError::Capture(_))));
+    }
+
+    #[test]
+    fn grab_frame_doesnt_crop_frames_from_a_wider_display() {
+        // Rows this much longer than expected aren't padding
+        let mut capturer = fake(3, 2, 0);
+        capturer.frame_width = 3 + MAX_ROW_PADDING / 4;
+        let frame = grab(&mut capturer, fake(3, 2, 0)).unwrap();
+        assert_eq!(frame, fake(3, 2, 0).frame().unwrap());
+
+        let mut capturer = fake(3, 2, 0);
+        capturer.frame_width = 3 + MAX_ROW_PADDING / 4;
+        let result = grab(&mut capturer, fake(3 + MAX_ROW_PADDING / 4, 2, 0));
+        assert!(matches!(result, Err(SnapmeoError::Capture(_))));
+
+        assert_eq!(pack_frame(vec![0; 2 * (12 + 6)], 3, 2), None);
+    }
+
+    #[test]
+    fn find_display_needs_a_single_match() {
+        let displays = [
+            (0, 0, 1920, 1080),
+            (1920, 0, 2560, 1440),
+            (0, 0, 1920, 1080),
+        ];
+        assert_eq!(find_display(&displays[..2], (1920, 0, 2560, 1440)), Some(1));
+        assert_eq!(find_display(&displays[..2], (1920, 0, 1280, 720)), None);
+        assert_eq!(find_display(&displays[..2], (0, 1080, 2560, 1440)), None);
+        // Mirrored displays
+        assert_eq!(find_display(&displays, (0, 0, 1920, 1080)), None);
+    }
+
+    #[test]
+    fn is_blank_only_accepts_all_black_frames() {
+        assert!(is_blank(&[0, 0, 0, 255, 0, 0, 0, 0]));
+        assert!(!is_blank(&[0, 0, 0, 255, 0, 1, 0, 255]));
+        assert!(!is_blank(&fake(8, 6, 0).frame().unwrap()));
+    }
+
+    #[test]
+    fn grabbed_frames_crop_to_the_region() {
+        let mut capturer = fake(8, 6, 0);
+        let frame = grab(&mut capturer, fake(8, 6, 0)).unwrap();
+        // Cropping converts the pixels from BGRA to RGBA
+        assert_eq!(
+            crop_frame(&frame, 8 * 4, (2, 3, 2, 2)),
+            vec![0, 3, 2, 255, 0, 3, 3, 255, 0, 4, 2, 255, 0, 4, 3, 255]
+        );
+    }
+
+    #[test]
+    fn region_rgba_returns_packed_rgba_pixels() {
+        let mut capturer = fake(8, 6, 12);
+        let (pixels, width, height) = region_rgba(
+            &mut capturer,
+            || Ok(fake(8, 6, 12)),
+            (5, 1, 3, 2),
+            Duration::from_millis(1),
+            FRAME_TIMEOUT,
+        )
+        .unwrap();
+        assert_eq!((width, height), (3, 2));
+        assert_eq!(
+            pixels,
+            vec![
+                0, 1, 5, 255, 0, 1, 6, 255, 0, 1, 7, 255, //
+                0, 2, 5, 255, 0, 2, 6, 255, 0, 2, 7, 255,
+            ]
+        );
+    }
+
+    #[test]
+    fn region_rgba_rejects_regions_outside_the_display() {
+        for region in [(6, 0, 3, 2), (0, 5, 2, 2), (0, 0, 0, 2)] {
+            let mut capturer = fake(8, 6, 0);
+            let result = region_rgba(
+                &mut capturer,
+                || Ok(fake(8, 6, 0)),
+                region,
+                Duration::from_millis(1),
+                FRAME_TIMEOUT,
+            );
+            assert!(matches!(result, Err(SnapmeoError::BadArguments(_))));
+        }
+    }
+
+    /// Reports `WouldBlock` for the first `not_ready` reads and then returns frames after that
+    struct FlakySource {
+        not_ready: usize,
+        reads: usize,
+    }
+
+    impl ScreenCapture for FlakySource {
+        fn width(&self) -> usize {
+            1
+        }
+
+        fn height(&self) -> usize {
+            1
+        }
+
+        fn frame(&mut self) -> io::Result<Vec<u8>> {
+            self.reads += 1;
+            if self.reads <= self.not_ready {
+                return Err(ErrorKind::WouldBlock.into());
+            }
+            Ok(vec![self.reads as u8; 4])
+        }
+    }
+
+    #[test]
+    fn next_frame_retries_until_a_frame_is_ready() {
+        let mut source = FlakySource {
+            not_ready: 3,
+            reads: 0,
+        };
+        let frame = next_frame(&mut source, Duration::from_millis(1), FRAME_TIMEOUT).unwrap();
+        assert_eq!(frame, vec![4; 4]);
+        assert_eq!(source.reads, 4);
+
+        // Frames can stop being ready again after the first one
+        source.not_ready = 6;
+        let frame = next_frame(&mut source, Duration::from_millis(1), FRAME_TIMEOUT).unwrap();
+        assert_eq!(frame, vec![7; 4]);
+    }
+
+    #[test]
+    fn next_frame_gives_up_after_the_timeout() {
+        let mut source = FlakySource {
+            not_ready: usize::MAX,
+            reads: 0,
+        };
+        let result = next_frame(
+            &mut source,
+            Duration::from_millis(1),
+            Duration::from_millis(20),
+        );
+        assert!(matches!(result, Err(SnapmeoError::Capture(_))));
+        assert!(source.reads > 1);
+    }
+
+    #[test]
+    fn next_frame_does_not_retry_other_errors() {
+        struct BrokenSource(usize);
+        impl ScreenCapture for BrokenSource {
+            fn width(&self) -> usize {
+                1
+            }
+
+            fn height(&self) -> usize {
+                1
+            }
+
+            fn frame(&mut self) -> io::Result<Vec<u8>> {
+                self.0 += 1;
+                Err(ErrorKind::PermissionDenied.into())
+            }
+        }
+
+        let mut source = BrokenSource(0);
+        let result = next_frame(&mut source, Duration::from_millis(1), FRAME_TIMEOUT);
+        assert!(matches!(result, Err(SnapmeoError::Capture(_))));
+        assert_eq!(source.0, 1);
+    }
+}
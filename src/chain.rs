@@ -0,0 +1,82 @@
+//! `--chain`, which takes one screenshot after another, each selected interactively, until the
+//! selection is cancelled with escape, then lists everything that was uploaded.
+//!
+//! Like `--listen`, each capture is run by a fresh snapmeo process with the same flags, so no state
+//! from one capture can leak into the next. Captures keep the terminal so that they can prompt,
+//! and tell the chain where they were uploaded to through a file named by `RESULT_FILE_VAR`.
+
+use std::env;
+use std::fs;
+use std::io;
+use std::process::{self, Command};
+
+use colored::*;
+
+use crate::error::{SnapmeoError, EXIT_CANCELLED};
+
+/// Environment variable holding the path that a capture run by `run` writes its upload's URL to
+pub const RESULT_FILE_VAR: &str = "SNAPMEO_CHAIN_RESULT_FILE";
+
+/// Tells the chain that started this capture, if any, the URL that it was uploaded to.
+pub fn report_url(url: &str) -> Result<(), SnapmeoError> {
+    if let Some(path) = env::var_os(RESULT_FILE_VAR) {
+        fs::write(path, url)?;
+    }
+    Ok(())
+}
+
+/// Runs captures with the flags snapmeo was started with until one is cancelled, listing the URLs
+/// of everything they uploaded once done. Each capture prints its own output as it happens.
+pub fn run(json: bool) -> Result<(), SnapmeoError> {
+    let exe = env::current_exe()?;
+    let args: Vec<_> = env::args_os()
+        .skip(1)
+        .filter(|arg| arg != "--chain")
+        .collect();
+    let result_path = env::temp_dir().join(format!("snapmeo-chain-{}", process::id()));
+
+    let mut urls = Vec::new();
+    loop {
+        match fs::remove_file(&result_path) {
+            Err(err) if err.kind() != io::ErrorKind::NotFound => return Err(err.into()),
+            _ => {}
+        }
+        let status = Command::new(&exe)
+            .args(&args)
+            .env(RESULT_FILE_VAR, &result_path)
+            .status()?;
+        if status.code() == Some(EXIT_CANCELLED) {
+            break;
+        }
+        if !status.success() {
+            // Its error has already been printed to stderr
+            eprintln!(
+                "{} Stopping after {} capture(s) since the last one failed",
+                "Warning:".yellow(),
+                urls.len()
+            );
+            break;
+        }
+
+        // Captures that weren't uploaded don't write anything
+        if let Some(url) = fs::read_to_string(&result_path)
+            .ok()
+            .filter(|url| !url.is_empty())
+        {
+            urls.push(url);
+        }
+    }
+    let _ = fs::remove_file(&result_path);
+
+    if json {
+        println!("{}", serde_json::json!({ "urls": urls }));
+    } else if urls.is_empty() {
+        println!("Nothing was uploaded.");
+    } else {
+        println!("\n{}", "Uploaded in this chain:".green());
+        for url in &urls {
+            println!("{}", url);
+        }
+    }
+    Ok(())
+}
@@ -0,0 +1,79 @@
+//! `--only-changed-region`, which crops a capture down to the part of it that changed since the
+//! previous capture of the same region, so that repeated captures of a mostly still screen upload
+//! far less.
+//!
+//! Captures are usually taken by separate runs, so the previous capture is kept as
+//! `previous-capture.png` in the same directory as the upload queue, along with the region it was
+//! taken of in `previous-capture.json`.
+
+use std::fs;
+use std::path::PathBuf;
+
+use crate::buffer::{self, Region};
+use crate::error::SnapmeoError;
+use crate::upload;
+
+/// Changed regions bigger than this fraction of the capture are uploaded as the whole capture,
+/// since cropping wouldn't save much and the context is more useful
+const MAX_CHANGED_FRACTION: f64 = 0.5;
+
+fn previous_capture_paths() -> Result<(PathBuf, PathBuf), SnapmeoError> {
+    let dir = upload::data_dir().ok_or_else(|| {
+        SnapmeoError::Other("Unable to find a directory to remember the previous capture in".into())
+    })?;
+    Ok((
+        dir.join("previous-capture.png"),
+        dir.join("previous-capture.json"),
+    ))
+}
+
+/// Returns the previous capture if it was of the same `region`.
+fn load_previous(region: &Region) -> Result<Option<Vec<u8>>, SnapmeoError> {
+    let (image_path, region_path) = previous_capture_paths()?;
+    let previous_region = match fs::read_to_string(&region_path) {
+        Ok(contents) => serde_json::from_str::<[usize; 4]>(&contents).ok(),
+        Err(_) => None,
+    };
+    if previous_region != Some([region.x, region.y, region.width, region.height]) {
+        return Ok(None);
+    }
+    // This is only an optimization, so a damaged file just means uploading everything
+    Ok(image::open(&image_path)
+        .ok()
+        .map(|image| image.to_rgba8().into_raw())
+        .filter(|previous| previous.len() == region.width * region.height * 4))
+}
+
+fn save(region: &Region, capture: &[u8]) -> Result<(), SnapmeoError> {
+    let (image_path, region_path) = previous_capture_paths()?;
+    if let Some(dir) = image_path.parent() {
+        fs::create_dir_all(dir)?;
+    }
+    repng::encode(
+        fs::File::create(&image_path)?,
+        region.width as u32,
+        region.height as u32,
+        capture,
+    )?;
+    fs::write(
+        region_path,
+        serde_json::json!([region.x, region.y, region.width, region.height]).to_string(),
+    )?;
+    Ok(())
+}
+
+/// Remembers `capture`, the RGBA capture of `region` of the screen, for next time and returns the
+/// part of it that changed since the previous capture of the same region, relative to the
+/// capture. Returns `None` if all of it should be uploaded instead, such as when there's no
+/// previous capture, nothing changed, or too much did.
+pub fn changed_part(region: &Region, capture: &[u8]) -> Result<Option<Region>, SnapmeoError> {
+    let previous = load_previous(region)?;
+    save(region, capture)?;
+
+    Ok(previous
+        .and_then(|previous| buffer::changed_bounds(&previous, capture, region.width))
+        .filter(|changed| {
+            (changed.width * changed.height) as f64
+                <= (region.width * region.height) as f64 * MAX_CHANGED_FRACTION
+        }))
+}
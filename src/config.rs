@@ -0,0 +1,479 @@
+//! Settings read from snapmeo's config file, which provide defaults for things that would
+//! otherwise have to be passed on the command line every time.
+//!
+//! The file is TOML and is read from `--config` if given, otherwise from
+//! `$XDG_CONFIG_HOME/snapmeo/config.toml` (`~/.config/snapmeo/config.toml` if that's unset), or
+//! `%APPDATA%\snapmeo\config.toml` on Windows. It's fine for the default file not to exist.
+//!
+//! Named profiles under `[profiles]` can override the settings at the top level of the file when
+//! selected with `--profile`, so that one file can hold the settings for several contexts.
+
+use std::collections::BTreeMap;
+use std::env;
+use std::fs;
+use std::io::ErrorKind;
+use std::iter;
+use std::path::{Path, PathBuf};
+
+use serde::Deserialize;
+
+use crate::annotate::AnnotationStyle;
+use crate::buffer;
+use crate::encode::Format;
+use crate::error::SnapmeoError;
+
+/// Aspect ratios that `--aspect` knows by name without them being in the config file, for the
+/// images that sites ask for most often
+const BUILT_IN_ASPECTS: [(&str, &str); 4] = [
+    ("instagram-portrait", "4:5"),
+    ("story", "9:16"),
+    // The large image summary card, which is 1200x628
+    ("twitter-card", "1.91:1"),
+    ("youtube-thumbnail", "16:9"),
+];
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+    /// Directory screenshots are saved into when `--output_dir` isn't given
+    pub output_dir: Option<PathBuf>,
+    /// Formats to save screenshots in, like `--format` takes
+    pub format: Option<String>,
+    /// Backend to upload to, like `--uploader` takes
+    pub uploader: Option<String>,
+    /// URL of the AmeoTrack upload endpoint, like `--upload-url` takes
+    pub upload_url: Option<String>,
+    /// How long uploads last, like `--expiry` takes
+    pub expiry: Option<String>,
+    /// What to copy to the clipboard after uploading, like `--clipboard-template` takes
+    pub clipboard_template: Option<String>,
+    /// What to name saved screenshots, like `--filename-template` takes
+    pub filename_template: Option<String>,
+    /// Other backends that `--broadcast` also uploads to when it isn't given a list of its own
+    pub broadcast: Option<Vec<String>>,
+    /// Default quality of JPEG images from 0 to 100
+    pub jpeg_quality: Option<i64>,
+    /// Default quality of WebP images from 0 to 100. Accepted for configs shared with builds that
+    /// can write WebP; this build can't, so it has no effect.
+    pub webp_quality: Option<i64>,
+    /// Default quality of AVIF images from 0 to 100
+    pub avif_quality: Option<i64>,
+    /// Hex color of annotated rectangles and arrows, such as `#ff0000`. Colors with an alpha such
+    /// as `#ff000080` are drawn translucently.
+    pub annotation_color: Option<String>,
+    /// Hex color of annotated highlights, which defaults to a translucent yellow
+    pub highlight_color: Option<String>,
+    /// Hex color of the bar added by `--timestamp-bar`, which defaults to black
+    pub timestamp_bar_color: Option<String>,
+    /// Hex color of the text in the timestamp bar, which defaults to white
+    pub timestamp_bar_text_color: Option<String>,
+    /// Size of the timestamp bar's text as a multiple of the built-in font's 7 pixel height
+    pub timestamp_bar_font_scale: Option<u32>,
+    /// Whether to include the computer's hostname in the timestamp bar
+    pub timestamp_bar_hostname: bool,
+    /// How many milliseconds to wait between attempts to read a frame while the capturer doesn't
+    /// have one ready, which defaults to one display frame at 60 Hz
+    pub frame_poll_interval_ms: Option<u64>,
+    /// How many milliseconds to keep trying to read a frame for before giving up, which defaults
+    /// to 5 seconds
+    pub frame_timeout_ms: Option<u64>,
+    /// How many frames to read and throw away when capturing starts, since some capture backends
+    /// hand out a stale or black frame first. The default of 1 is enough on Windows, where the
+    /// first frame from DXGI is often blank, and harmless elsewhere; X11 works with 0, while
+    /// macOS can need 2 or 3 right after screen recording permission has been granted.
+    pub settle_frames: Option<u32>,
+    /// How many milliseconds to wait before grabbing the frame that's captured, so that things
+    /// like the fade-out of the launcher snapmeo was started from are gone by then. Defaults to
+    /// 100; 0 turns it off.
+    pub settle_delay_ms: Option<u64>,
+    /// How the frozen frame is scaled when it's shown on a display whose size differs from the
+    /// window's, such as a HiDPI one: `nearest`, `linear`, or `best`. Defaults to `linear`, which
+    /// is smoother than `nearest`; captures themselves aren't affected either way.
+    pub preview_scale_quality: Option<String>,
+    /// Named regions that can be captured with `--preset`, such as `[presets.left-half]`
+    pub presets: BTreeMap<String, Preset>,
+    /// Named aspect ratios that `--aspect` can be given along with the built in ones, such as
+    /// `banner = "4:1"` under `[aspects]`
+    pub aspects: BTreeMap<String, String>,
+    /// Areas of the screen that are pixelated in every capture that overlaps them unless
+    /// `--no-masks` is given, such as `[masks.clock]`. They're written like presets.
+    pub masks: BTreeMap<String, Preset>,
+    /// Named sets of overrides that can be selected with `--profile`, such as `[profiles.work]`
+    pub profiles: BTreeMap<String, Profile>,
+}
+
+/// Settings that override those at the top level of the config file when the profile is selected.
+/// Any that aren't set are left as they are.
+#[derive(Clone, Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Profile {
+    pub output_dir: Option<PathBuf>,
+    pub format: Option<String>,
+    pub uploader: Option<String>,
+    pub upload_url: Option<String>,
+    pub expiry: Option<String>,
+    pub clipboard_template: Option<String>,
+    pub filename_template: Option<String>,
+    pub broadcast: Option<Vec<String>>,
+    pub jpeg_quality: Option<i64>,
+    pub webp_quality: Option<i64>,
+    pub avif_quality: Option<i64>,
+    pub annotation_color: Option<String>,
+    pub highlight_color: Option<String>,
+    pub timestamp_bar_color: Option<String>,
+    pub timestamp_bar_text_color: Option<String>,
+    pub timestamp_bar_font_scale: Option<u32>,
+    pub timestamp_bar_hostname: Option<bool>,
+}
+
+/// How the bar added by `--timestamp-bar` looks
+#[derive(Clone, Copy, Debug)]
+pub struct TimestampBar {
+    pub background: [u8; 4],
+    pub foreground: [u8; 4],
+    pub font_scale: u32,
+    pub hostname: bool,
+}
+
+/// A named region of the screen. Exactly one of the fields must be set.
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Preset {
+    /// `X,Y,WIDTH,HEIGHT` in pixels, like `--region` takes
+    pub region: Option<String>,
+    /// Position and size as percentages of the screen's size, like `--region-percent` takes
+    pub region_percent: Option<String>,
+}
+
+/// A preset's region, which can only be resolved to pixels once the display's size is known
+#[derive(Clone, Copy, Debug)]
+pub enum PresetRegion {
+    Pixels((i64, i64, usize, usize)),
+    Percent([f64; 4]),
+}
+
+impl Preset {
+    fn parse(&self) -> Result<PresetRegion, String> {
+        match (&self.region, &self.region_percent) {
+            (Some(region), None) => buffer::parse_region(region).map(PresetRegion::Pixels),
+            (None, Some(percent)) => {
+                buffer::parse_region_percent(percent).map(PresetRegion::Percent)
+            }
+            _ => Err("exactly one of `region` or `region_percent` must be set".into()),
+        }
+    }
+}
+
+/// Returns where the config file is read from when `--config` isn't given.
+pub fn default_path() -> Option<PathBuf> {
+    if cfg!(windows) {
+        return env::var_os("APPDATA").map(|dir| Path::new(&dir).join("snapmeo\\config.toml"));
+    }
+
+    env::var_os("XDG_CONFIG_HOME")
+        .filter(|dir| !dir.is_empty())
+        .map(PathBuf::from)
+        .or_else(|| env::var_os("HOME").map(|home| Path::new(&home).join(".config")))
+        .map(|dir| dir.join("snapmeo").join("config.toml"))
+}
+
+/// Parses a quality from 0 to 100, as given to `--quality`.
+pub fn parse_quality(s: &str) -> Result<u8, String> {
+    s.parse()
+        .ok()
+        .filter(|quality| *quality <= 100)
+        .ok_or_else(|| format!("Invalid quality `{}`; expected a number from 0 to 100", s))
+}
+
+impl Config {
+    /// Reads the config file at `path`, or at the default location if `path` is `None`.
+    pub fn load(path: Option<&Path>) -> Result<Config, SnapmeoError> {
+        let (path, explicit) = match path {
+            Some(path) => (path.to_owned(), true),
+            None => match default_path() {
+                Some(path) => (path, false),
+                None => return Ok(Config::default()),
+            },
+        };
+
+        let contents = match fs::read_to_string(&path) {
+            Ok(contents) => contents,
+            Err(err) if err.kind() == ErrorKind::NotFound && !explicit => {
+                return Ok(Config::default())
+            }
+            Err(err) => {
+                return Err(SnapmeoError::BadArguments(format!(
+                    "Unable to read the config file `{}`: {}",
+                    path.display(),
+                    err
+                )))
+            }
+        };
+        let config: Config = toml::from_str(&contents).map_err(|err| {
+            SnapmeoError::BadArguments(format!("Invalid config file `{}`: {}", path.display(), err))
+        })?;
+
+        let qualities = iter::once((
+            String::new(),
+            [
+                config.jpeg_quality,
+                config.webp_quality,
+                config.avif_quality,
+            ],
+        ))
+        .chain(config.profiles.iter().map(|(name, profile)| {
+            (
+                format!("profiles.{}.", name),
+                [
+                    profile.jpeg_quality,
+                    profile.webp_quality,
+                    profile.avif_quality,
+                ],
+            )
+        }));
+        for (prefix, values) in qualities {
+            let keys = ["jpeg_quality", "webp_quality", "avif_quality"];
+            for (key, quality) in keys.iter().zip(values) {
+                if let Some(quality) = quality.filter(|quality| !(0..=100).contains(quality)) {
+                    return Err(SnapmeoError::BadArguments(format!(
+                        "`{}{}` in `{}` is {}, but it must be from 0 to 100",
+                        prefix,
+                        key,
+                        path.display(),
+                        quality
+                    )));
+                }
+            }
+        }
+
+        for (name, preset) in &config.presets {
+            preset.parse().map_err(|err| {
+                SnapmeoError::BadArguments(format!(
+                    "Invalid preset `{}` in `{}`: {}",
+                    name,
+                    path.display(),
+                    err
+                ))
+            })?;
+        }
+        for (name, aspect) in &config.aspects {
+            buffer::parse_aspect(aspect).map_err(|err| {
+                SnapmeoError::BadArguments(format!(
+                    "Invalid aspect ratio `{}` in `{}`: {}",
+                    name,
+                    path.display(),
+                    err
+                ))
+            })?;
+        }
+        for (name, mask) in &config.masks {
+            mask.parse().map_err(|err| {
+                SnapmeoError::BadArguments(format!(
+                    "Invalid mask `{}` in `{}`: {}",
+                    name,
+                    path.display(),
+                    err
+                ))
+            })?;
+        }
+
+        Ok(config)
+    }
+
+    /// Returns the areas of every mask in the config file.
+    pub fn masks(&self) -> Vec<PresetRegion> {
+        self.masks
+            .values()
+            // Masks were checked when the config was loaded
+            .filter_map(|mask| mask.parse().ok())
+            .collect()
+    }
+
+    /// Applies the overrides of the profile called `name` on top of the top level settings.
+    pub fn apply_profile(&mut self, name: &str) -> Result<(), SnapmeoError> {
+        let profile = match self.profiles.get(name) {
+            Some(profile) => profile.clone(),
+            None if self.profiles.is_empty() => {
+                return Err(SnapmeoError::BadArguments(format!(
+                    "Unknown profile `{}`; no profiles are defined in the config file",
+                    name
+                )))
+            }
+            None => {
+                return Err(SnapmeoError::BadArguments(format!(
+                    "Unknown profile `{}`; the available profiles are: {}",
+                    name,
+                    self.profiles
+                        .keys()
+                        .map(|name| format!("`{}`", name))
+                        .collect::<Vec<_>>()
+                        .join(", ")
+                )))
+            }
+        };
+
+        fn merge<T>(setting: &mut Option<T>, value: Option<T>) {
+            if value.is_some() {
+                *setting = value;
+            }
+        }
+        merge(&mut self.output_dir, profile.output_dir);
+        merge(&mut self.format, profile.format);
+        merge(&mut self.uploader, profile.uploader);
+        merge(&mut self.upload_url, profile.upload_url);
+        merge(&mut self.expiry, profile.expiry);
+        merge(&mut self.clipboard_template, profile.clipboard_template);
+        merge(&mut self.filename_template, profile.filename_template);
+        merge(&mut self.broadcast, profile.broadcast);
+        merge(&mut self.jpeg_quality, profile.jpeg_quality);
+        merge(&mut self.webp_quality, profile.webp_quality);
+        merge(&mut self.avif_quality, profile.avif_quality);
+        merge(&mut self.annotation_color, profile.annotation_color);
+        merge(&mut self.highlight_color, profile.highlight_color);
+        merge(&mut self.timestamp_bar_color, profile.timestamp_bar_color);
+        merge(
+            &mut self.timestamp_bar_text_color,
+            profile.timestamp_bar_text_color,
+        );
+        merge(
+            &mut self.timestamp_bar_font_scale,
+            profile.timestamp_bar_font_scale,
+        );
+        if let Some(hostname) = profile.timestamp_bar_hostname {
+            self.timestamp_bar_hostname = hostname;
+        }
+        Ok(())
+    }
+
+    /// Returns the colors to annotate with, falling back to the defaults for any that aren't set.
+    pub fn annotation_style(&self) -> Result<AnnotationStyle, SnapmeoError> {
+        let parse = |key: &str, color: &Option<String>, default: [u8; 4]| match color {
+            Some(color) => buffer::parse_color(color)
+                .map_err(|err| SnapmeoError::BadArguments(format!("`{}`: {}", key, err))),
+            None => Ok(default),
+        };
+        let defaults = AnnotationStyle::default();
+
+        Ok(AnnotationStyle {
+            color: parse("annotation_color", &self.annotation_color, defaults.color)?,
+            highlight_color: parse(
+                "highlight_color",
+                &self.highlight_color,
+                defaults.highlight_color,
+            )?,
+        })
+    }
+
+    /// Returns how the timestamp bar should look, falling back to the defaults for anything that
+    /// isn't set.
+    pub fn timestamp_bar(&self) -> Result<TimestampBar, SnapmeoError> {
+        let parse = |key: &str, color: &Option<String>, default: [u8; 4]| match color {
+            Some(color) => buffer::parse_color(color)
+                .map_err(|err| SnapmeoError::BadArguments(format!("`{}`: {}", key, err))),
+            None => Ok(default),
+        };
+        let font_scale = self.timestamp_bar_font_scale.unwrap_or(2);
+        if font_scale == 0 {
+            return Err(SnapmeoError::BadArguments(
+                "`timestamp_bar_font_scale` must be at least 1".into(),
+            ));
+        }
+
+        Ok(TimestampBar {
+            background: parse(
+                "timestamp_bar_color",
+                &self.timestamp_bar_color,
+                [0, 0, 0, 255],
+            )?,
+            foreground: parse(
+                "timestamp_bar_text_color",
+                &self.timestamp_bar_text_color,
+                [255, 255, 255, 255],
+            )?,
+            font_scale,
+            hostname: self.timestamp_bar_hostname,
+        })
+    }
+
+    /// Returns the value of SDL's render scale quality hint that the frozen frame should be shown
+    /// with.
+    pub fn preview_scale_quality(&self) -> Result<&'static str, SnapmeoError> {
+        match self.preview_scale_quality.as_deref() {
+            None | Some("linear") => Ok("linear"),
+            Some("nearest") => Ok("nearest"),
+            Some("best") => Ok("best"),
+            Some(quality) => Err(SnapmeoError::BadArguments(format!(
+                "Invalid `preview_scale_quality` `{}`; expected `nearest`, `linear`, or `best`",
+                quality
+            ))),
+        }
+    }
+
+    /// Returns the region of the preset called `name`.
+    pub fn preset(&self, name: &str) -> Result<PresetRegion, SnapmeoError> {
+        match self.presets.get(name) {
+            // Presets were checked when the config was loaded
+            Some(preset) => preset.parse().map_err(SnapmeoError::BadArguments),
+            None if self.presets.is_empty() => Err(SnapmeoError::BadArguments(format!(
+                "Unknown preset `{}`; no presets are defined in the config file",
+                name
+            ))),
+            None => Err(SnapmeoError::BadArguments(format!(
+                "Unknown preset `{}`; the available presets are: {}",
+                name,
+                self.presets
+                    .keys()
+                    .map(|name| format!("`{}`", name))
+                    .collect::<Vec<_>>()
+                    .join(", ")
+            ))),
+        }
+    }
+
+    /// Returns the ratio of width to height that `--aspect` was given, which is either a ratio
+    /// like `16:9` or the name of one from the config file or `BUILT_IN_ASPECTS`.
+    pub fn aspect(&self, aspect: &str) -> Result<f64, SnapmeoError> {
+        if let Ok(ratio) = buffer::parse_aspect(aspect) {
+            return Ok(ratio);
+        }
+        let named = self.aspects.get(aspect).map(String::as_str).or_else(|| {
+            BUILT_IN_ASPECTS
+                .iter()
+                .find(|(name, _)| *name == aspect)
+                .map(|(_, ratio)| *ratio)
+        });
+        match named {
+            // Ratios in the config file were checked when it was loaded
+            Some(ratio) => buffer::parse_aspect(ratio).map_err(SnapmeoError::BadArguments),
+            None => {
+                let mut names: Vec<_> = BUILT_IN_ASPECTS
+                    .iter()
+                    .map(|(name, _)| *name)
+                    .chain(self.aspects.keys().map(String::as_str))
+                    .collect();
+                names.sort_unstable();
+                names.dedup();
+                Err(SnapmeoError::BadArguments(format!(
+                    "Unknown aspect ratio `{}`; give one like `16:9` or one of: {}",
+                    aspect,
+                    names
+                        .iter()
+                        .map(|name| format!("`{}`", name))
+                        .collect::<Vec<_>>()
+                        .join(", ")
+                )))
+            }
+        }
+    }
+
+    /// Returns the configured default quality for `format`, if it's a lossy format that has one.
+    pub fn quality(&self, format: Format) -> Option<u8> {
+        let quality = match format {
+            Format::Jpeg => self.jpeg_quality,
+            Format::Avif => self.avif_quality,
+            Format::Png | Format::Bmp | Format::Ico | Format::Svg => None,
+        };
+        // Ranges were checked when the config was loaded
+        quality.map(|quality| quality as u8)
+    }
+}
@@ -0,0 +1,224 @@
+//! `--cursor`, which draws the mouse cursor into captures since the screen capture APIs leave it
+//! out.
+//!
+//! The cursor is drawn from the image given with `--cursor-image` if there is one, so that
+//! tutorial screenshots can all show the same cursor. Otherwise the system's current cursor is
+//! used where it can be read, which is only X11, and a built in arrow everywhere else.
+
+use std::error::Error;
+use std::path::Path;
+
+/// A cursor image to draw into captures
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct CursorImage {
+    pub width: usize,
+    pub height: usize,
+    /// The pixel within the image that's at the cursor's position, which is the tip of an arrow
+    pub hotspot: (usize, usize),
+    /// Premultiplied BGRA pixels, to match captured frames
+    pub pixels: Vec<u8>,
+}
+
+/// The built in arrow, with `#` for its outline and `.` for its fill
+const ARROW: [&str; 19] = [
+    "#           ",
+    "##          ",
+    "#.#         ",
+    "#..#        ",
+    "#...#       ",
+    "#....#      ",
+    "#.....#     ",
+    "#......#    ",
+    "#.......#   ",
+    "#........#  ",
+    "#.........# ",
+    "#......#####",
+    "#...#..#    ",
+    "#..# #..#   ",
+    "#.#  #..#   ",
+    "##    #..#  ",
+    "#     #..#  ",
+    "       #..# ",
+    "        ##  ",
+];
+
+impl CursorImage {
+    /// The arrow drawn when neither an image nor the system cursor is available
+    pub fn arrow() -> Self {
+        let pixels = ARROW
+            .iter()
+            .flat_map(|row| row.chars())
+            .flat_map(|c| match c {
+                '#' => [0, 0, 0, 255],
+                '.' => [255, 255, 255, 255],
+                _ => [0, 0, 0, 0],
+            })
+            .collect();
+        CursorImage {
+            width: ARROW[0].len(),
+            height: ARROW.len(),
+            hotspot: (0, 0),
+            pixels,
+        }
+    }
+
+    /// Loads the image at `path` to draw as the cursor, with its top left corner as the hotspot.
+    pub fn load(path: &Path) -> Result<Self, String> {
+        let image = image::open(path)
+            .map_err(|err| {
+                format!(
+                    "Unable to load the cursor image `{}`: {}",
+                    path.display(),
+                    err
+                )
+            })?
+            .into_rgba8();
+        let pixels = image
+            .pixels()
+            .flat_map(|pixel| {
+                let [r, g, b, a] = pixel.0;
+                let premultiply = |c: u8| (c as u16 * a as u16 / 255) as u8;
+                [premultiply(b), premultiply(g), premultiply(r), a]
+            })
+            .collect();
+        Ok(CursorImage {
+            width: image.width() as usize,
+            height: image.height() as usize,
+            hotspot: (0, 0),
+            pixels,
+        })
+    }
+
+    /// Returns the image scaled up by `factor`, such as for a HiDPI display, without smoothing so
+    /// that its edges stay sharp.
+    pub fn scaled(&self, factor: f64) -> Self {
+        if (factor - 1.).abs() < 0.01 {
+            return self.clone();
+        }
+        let scale = |n: usize| ((n as f64 * factor).round() as usize).max(1);
+        let (width, height) = (scale(self.width), scale(self.height));
+        let mut pixels = Vec::with_capacity(width * height * 4);
+        for y in 0..height {
+            let source_y = (y * self.height / height).min(self.height - 1);
+            for x in 0..width {
+                let source_x = (x * self.width / width).min(self.width - 1);
+                let ix = (source_y * self.width + source_x) * 4;
+                pixels.extend_from_slice(&self.pixels[ix..ix + 4]);
+            }
+        }
+        CursorImage {
+            width,
+            height,
+            hotspot: (
+                (self.hotspot.0 as f64 * factor) as usize,
+                (self.hotspot.1 as f64 * factor) as usize,
+            ),
+            pixels,
+        }
+    }
+
+    /// Draws the cursor over a `width` x `height` BGRA `frame` with its hotspot at `position`,
+    /// leaving out whatever part of it is off the edges.
+    pub fn draw(&self, frame: &mut [u8], width: usize, height: usize, position: (i64, i64)) {
+        let left = position.0 - self.hotspot.0 as i64;
+        let top = position.1 - self.hotspot.1 as i64;
+        for y in 0..self.height {
+            let frame_y = top + y as i64;
+            if frame_y < 0 || frame_y >= height as i64 {
+                continue;
+            }
+            for x in 0..self.width {
+                let frame_x = left + x as i64;
+                if frame_x < 0 || frame_x >= width as i64 {
+                    continue;
+                }
+                let source = &self.pixels[(y * self.width + x) * 4..][..4];
+                let destination =
+                    &mut frame[(frame_y as usize * width + frame_x as usize) * 4..][..4];
+                let transparency = 255 - source[3] as u16;
+                for (d, &s) in destination[..3].iter_mut().zip(&source[..3]) {
+                    *d = (s as u16 + *d as u16 * transparency / 255).min(255) as u8;
+                }
+            }
+        }
+    }
+}
+
+/// Returns the system's current cursor image.
+pub fn system_cursor() -> Result<CursorImage, Box<dyn Error>> {
+    platform::system_cursor()
+}
+
+#[cfg(target_os = "linux")]
+mod platform {
+    use std::error::Error;
+
+    use x11rb::protocol::xfixes::ConnectionExt;
+
+    use super::CursorImage;
+
+    pub fn system_cursor() -> Result<CursorImage, Box<dyn Error>> {
+        let (conn, _) = x11rb::connect(None)?;
+        // XFixes requests are only answered once the version has been agreed on
+        conn.xfixes_query_version(4, 0)?.reply()?;
+        let cursor = conn.xfixes_get_cursor_image()?.reply()?;
+        Ok(CursorImage {
+            width: cursor.width as usize,
+            height: cursor.height as usize,
+            hotspot: (cursor.xhot as usize, cursor.yhot as usize),
+            // Each pixel is premultiplied ARGB packed into a word, which is BGRA in memory
+            pixels: cursor
+                .cursor_image
+                .iter()
+                .flat_map(|pixel| pixel.to_le_bytes())
+                .collect(),
+        })
+    }
+}
+
+#[cfg(not(target_os = "linux"))]
+mod platform {
+    use std::error::Error;
+
+    use super::CursorImage;
+
+    pub fn system_cursor() -> Result<CursorImage, Box<dyn Error>> {
+        Err("Reading the system cursor isn't supported on this platform".into())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn draw_blends_and_clips_to_the_frame() {
+        let cursor = CursorImage {
+            width: 2,
+            height: 1,
+            hotspot: (1, 0),
+            // An opaque white pixel then a half transparent black one
+            pixels: vec![255, 255, 255, 255, 0, 0, 0, 128],
+        };
+        let mut frame = [200, 200, 200, 255].repeat(2);
+        cursor.draw(&mut frame, 2, 1, (0, 0));
+        // The white pixel is off the left edge
+        assert_eq!(frame, [99, 99, 99, 255, 200, 200, 200, 255]);
+    }
+
+    #[test]
+    fn scaled_scales_the_hotspot_too() {
+        let cursor = CursorImage::arrow();
+        let scaled = CursorImage {
+            hotspot: (2, 3),
+            ..cursor.clone()
+        }
+        .scaled(2.);
+        assert_eq!(
+            (scaled.width, scaled.height),
+            (cursor.width * 2, cursor.height * 2)
+        );
+        assert_eq!(scaled.hotspot, (4, 6));
+        assert_eq!(scaled.pixels.len(), scaled.width * scaled.height * 4);
+    }
+}
@@ -0,0 +1,133 @@
+//! `--env-file`, which loads environment variables such as `AMEOTRACK_PASSWORD` from a dotenv
+//! file so that they don't have to be exported globally. A `.env` in the current directory is
+//! loaded automatically when no file is given.
+//!
+//! Each line is `KEY=VALUE`, optionally preceded by `export`. Values can be wrapped in single
+//! quotes to be taken literally or double quotes to allow `\n`, `\"`, and `\\` escapes, and
+//! lines starting with `#` are comments. Variables that are already set in the environment win
+//! over the file.
+
+use std::env;
+use std::fs;
+use std::io::ErrorKind;
+use std::path::Path;
+
+use crate::error::SnapmeoError;
+
+/// The file that's loaded when `--env-file` isn't given, if it exists
+pub const DEFAULT_PATH: &str = ".env";
+
+/// Parses the contents of a dotenv file into its variables, in order.
+fn parse(contents: &str) -> Result<Vec<(String, String)>, String> {
+    let mut vars = Vec::new();
+    for (ix, line) in contents.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let line = line.strip_prefix("export ").unwrap_or(line);
+        let (key, value) = line
+            .split_once('=')
+            .ok_or_else(|| format!("Line {} isn't of the form `KEY=VALUE`", ix + 1))?;
+        let key = key.trim();
+        if key.is_empty() || key.contains(char::is_whitespace) {
+            return Err(format!("Line {} has an invalid name `{}`", ix + 1, key));
+        }
+        let value = value.trim();
+        let value = if let Some(quoted) = value.strip_prefix('\'') {
+            quoted
+                .strip_suffix('\'')
+                .ok_or_else(|| format!("Line {} has an unclosed `'`", ix + 1))?
+                .to_owned()
+        } else if let Some(quoted) = value.strip_prefix('"') {
+            let quoted = quoted
+                .strip_suffix('"')
+                .ok_or_else(|| format!("Line {} has an unclosed `\"`", ix + 1))?;
+            let mut unescaped = String::with_capacity(quoted.len());
+            let mut chars = quoted.chars();
+            while let Some(c) = chars.next() {
+                match (c, chars.clone().next()) {
+                    ('\\', Some('n')) => unescaped.push('\n'),
+                    ('\\', Some(escaped @ ('"' | '\\'))) => unescaped.push(escaped),
+                    _ => {
+                        unescaped.push(c);
+                        continue;
+                    }
+                }
+                chars.next();
+            }
+            unescaped
+        } else {
+            // Unquoted values can have a trailing comment
+            match value.find(" #") {
+                Some(ix) => value[..ix].trim_end().to_owned(),
+                None => value.to_owned(),
+            }
+        };
+        vars.push((key.to_owned(), value));
+    }
+    Ok(vars)
+}
+
+/// Sets the variables in the dotenv file at `path` that aren't already set. A missing file is an
+/// error only if it was `required`, as it is when it's given explicitly.
+pub fn load(path: &Path, required: bool) -> Result<(), SnapmeoError> {
+    let contents = match fs::read_to_string(path) {
+        Ok(contents) => contents,
+        Err(err) if err.kind() == ErrorKind::NotFound && !required => return Ok(()),
+        Err(err) => {
+            return Err(SnapmeoError::BadArguments(format!(
+                "Unable to read the env file `{}`: {}",
+                path.display(),
+                err
+            )))
+        }
+    };
+    let vars = parse(&contents).map_err(|err| {
+        SnapmeoError::BadArguments(format!("Invalid env file `{}`: {}", path.display(), err))
+    })?;
+    for (key, value) in vars {
+        if env::var_os(&key).is_none() {
+            env::set_var(key, value);
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn pairs(vars: &[(&str, &str)]) -> Vec<(String, String)> {
+        vars.iter()
+            .map(|&(key, value)| (key.to_owned(), value.to_owned()))
+            .collect()
+    }
+
+    #[test]
+    fn parse_reads_plain_and_quoted_values() {
+        let contents = "# Upload settings\n\
+                        AMEOTRACK_PASSWORD=hunter2\n\
+                        export S3_BUCKET = shots # the bucket\n\
+                        \n\
+                        LITERAL='a \\n # b'\n\
+                        ESCAPED=\"line\\none \\\"quoted\\\"\"\n";
+        assert_eq!(
+            parse(contents).unwrap(),
+            pairs(&[
+                ("AMEOTRACK_PASSWORD", "hunter2"),
+                ("S3_BUCKET", "shots"),
+                ("LITERAL", "a \\n # b"),
+                ("ESCAPED", "line\none \"quoted\""),
+            ])
+        );
+    }
+
+    #[test]
+    fn parse_rejects_malformed_lines() {
+        assert!(parse("NO_EQUALS_SIGN").is_err());
+        assert!(parse("=value").is_err());
+        assert!(parse("TWO WORDS=value").is_err());
+        assert!(parse("UNCLOSED=\"value").is_err());
+    }
+}
@@ -0,0 +1,588 @@
+//! Encoding captured RGBA buffers into the supported output formats.
+
+use std::cmp;
+use std::collections::HashSet;
+use std::fs::{self, File};
+use std::io::{BufWriter, ErrorKind, Write};
+use std::path::Path;
+use std::process::Command;
+use std::str::FromStr;
+use std::time::Duration;
+
+use image::codecs::bmp::BmpEncoder;
+use image::codecs::ico::IcoEncoder;
+use image::imageops::{self, FilterType};
+use image::{ColorType, ImageEncoder, RgbaImage};
+use jpeg_encoder::{Encoder as JpegEncoder, SamplingFactor};
+
+use crate::capture;
+use crate::error::SnapmeoError;
+use crate::record::Recording;
+
+/// Icon sizes that are generally expected to be present in ICO files
+const ICO_SIZES: [u32; 7] = [16, 24, 32, 48, 64, 128, 256];
+const JPEG_QUALITY: u8 = 90;
+/// Size in bytes of the RGBA buffer that a capture would need above which it's encoded straight
+/// from the captured frame when possible, rather than being copied out into a buffer first
+pub const STREAMING_THRESHOLD: usize = 64 << 20;
+/// Text and UI edges survive AVIF compression well down to fairly low qualities
+const AVIF_QUALITY: u8 = 80;
+/// From 1 (slowest, smallest) to 10 (fastest)
+#[cfg(feature = "avif")]
+const AVIF_SPEED: u8 = 6;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Format {
+    Png,
+    Bmp,
+    Ico,
+    Jpeg,
+    /// Only available when built with the `avif` feature
+    Avif,
+    /// A PNG embedded in an SVG, which annotations can be layered over as vector shapes
+    Svg,
+}
+
+impl FromStr for Format {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "png" => Ok(Format::Png),
+            "bmp" => Ok(Format::Bmp),
+            "ico" => Ok(Format::Ico),
+            "jpg" | "jpeg" => Ok(Format::Jpeg),
+            "svg" => Ok(Format::Svg),
+            "avif" if cfg!(feature = "avif") => Ok(Format::Avif),
+            "avif" => Err(
+                "This build of snapmeo doesn't support AVIF; rebuild it with `--features avif`"
+                    .into(),
+            ),
+            _ => Err(format!(
+                "Unsupported format `{}`; expected one of `png`, `bmp`, `ico`, `jpeg`, `avif`, or `svg`",
+                s
+            )),
+        }
+    }
+}
+
+impl Format {
+    pub fn extension(self) -> &'static str {
+        match self {
+            Format::Png => "png",
+            Format::Bmp => "bmp",
+            Format::Ico => "ico",
+            Format::Jpeg => "jpg",
+            Format::Avif => "avif",
+            Format::Svg => "svg",
+        }
+    }
+
+    /// Returns the quality the format is encoded with if none is given, if it's a lossy format.
+    pub fn default_quality(self) -> Option<u8> {
+        match self {
+            Format::Jpeg => Some(JPEG_QUALITY),
+            Format::Avif => Some(AVIF_QUALITY),
+            Format::Png | Format::Bmp | Format::Ico | Format::Svg => None,
+        }
+    }
+
+    pub fn content_type(self) -> &'static str {
+        match self {
+            Format::Png => "image/png",
+            Format::Bmp => "image/bmp",
+            Format::Ico => "image/vnd.microsoft.icon",
+            Format::Jpeg => "image/jpeg",
+            Format::Avif => "image/avif",
+            Format::Svg => "image/svg+xml",
+        }
+    }
+}
+
+/// How much JPEG images' color information is reduced relative to their brightness
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Subsampling {
+    /// Full color resolution
+    Yuv444,
+    /// Half horizontal color resolution
+    Yuv422,
+    /// Half horizontal and vertical color resolution
+    Yuv420,
+}
+
+impl FromStr for Subsampling {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "4:4:4" | "444" => Ok(Subsampling::Yuv444),
+            "4:2:2" | "422" => Ok(Subsampling::Yuv422),
+            "4:2:0" | "420" => Ok(Subsampling::Yuv420),
+            _ => Err(format!(
+                "Invalid subsampling `{}`; expected one of `4:4:4`, `4:2:2`, or `4:2:0`",
+                s
+            )),
+        }
+    }
+}
+
+impl Subsampling {
+    fn sampling_factor(self) -> SamplingFactor {
+        match self {
+            Subsampling::Yuv444 => SamplingFactor::R_4_4_4,
+            Subsampling::Yuv422 => SamplingFactor::R_4_2_2,
+            Subsampling::Yuv420 => SamplingFactor::R_4_2_0,
+        }
+    }
+}
+
+/// Settings controlling how captures are encoded
+#[derive(Clone, Copy, Debug)]
+pub struct EncodeOptions {
+    pub format: Format,
+    /// Chroma subsampling of JPEG images
+    pub subsampling: Subsampling,
+    /// Quality of lossy formats from 0 to 100, or `None` for the format's default
+    pub quality: Option<u8>,
+}
+
+/// Scales an RGBA buffer down (or up, if it's tiny) to fit within the largest standard icon size
+/// that its larger side can fill, centering it on a transparent square. Returns the icon's pixels
+/// along with its side length.
+fn to_icon(buffer: &[u8], width: u32, height: u32) -> (Vec<u8>, u32) {
+    let largest_side = cmp::max(width, height);
+    let size = ICO_SIZES
+        .iter()
+        .rev()
+        .find(|&&size| size <= largest_side)
+        .cloned()
+        .unwrap_or(ICO_SIZES[0]);
+
+    let image = RgbaImage::from_raw(width, height, buffer.to_vec())
+        .expect("Buffer size doesn't match image dimensions!");
+    let scale = size as f32 / largest_side as f32;
+    let scaled_width = cmp::max((width as f32 * scale).round() as u32, 1);
+    let scaled_height = cmp::max((height as f32 * scale).round() as u32, 1);
+    let scaled = imageops::resize(&image, scaled_width, scaled_height, FilterType::Lanczos3);
+
+    let mut icon = RgbaImage::new(size, size);
+    imageops::overlay(
+        &mut icon,
+        &scaled,
+        ((size - scaled_width) / 2) as i64,
+        ((size - scaled_height) / 2) as i64,
+    );
+
+    (icon.into_raw(), size)
+}
+
+#[cfg(feature = "avif")]
+fn save_avif(
+    buffer: &[u8],
+    width: u32,
+    height: u32,
+    quality: Option<u8>,
+    mut file: File,
+) -> Result<(), SnapmeoError> {
+    use ravif::{Encoder, Img, RGBA8};
+
+    let pixels: Vec<RGBA8> = buffer
+        .chunks(4)
+        .map(|pixel| RGBA8::new(pixel[0], pixel[1], pixel[2], pixel[3]))
+        .collect();
+    let encoded = Encoder::new()
+        // The encoder rejects a quality of 0
+        .with_quality(quality.unwrap_or(AVIF_QUALITY).max(1) as f32)
+        .with_speed(AVIF_SPEED)
+        .encode_rgba(Img::new(pixels.as_slice(), width as usize, height as usize))
+        .map_err(|err| SnapmeoError::Encode(err.to_string()))?;
+    file.write_all(&encoded.avif_file)?;
+
+    Ok(())
+}
+
+#[cfg(not(feature = "avif"))]
+fn save_avif(
+    _buffer: &[u8],
+    _width: u32,
+    _height: u32,
+    _quality: Option<u8>,
+    _file: File,
+) -> Result<(), SnapmeoError> {
+    Err(SnapmeoError::Encode(
+        "This build of snapmeo doesn't support AVIF".into(),
+    ))
+}
+
+/// Writes an SVG the size of the RGBA `buffer` showing it as an embedded PNG, with the SVG markup in
+/// `elements` layered on top.
+fn write_svg(
+    buffer: &[u8],
+    width: u32,
+    height: u32,
+    elements: &str,
+    file: File,
+) -> Result<(), SnapmeoError> {
+    let mut png = Vec::new();
+    repng::encode(&mut png, width, height, buffer)?;
+
+    let mut writer = BufWriter::new(file);
+    write!(
+        writer,
+        "<svg xmlns=\"http://www.w3.org/2000/svg\" width=\"{width}\" height=\"{height}\" viewBox=\"0 0 {width} {height}\">\n\
+         <image width=\"{width}\" height=\"{height}\" href=\"data:image/png;base64,{}\"/>\n\
+         {}\n\
+         </svg>\n",
+        base64::encode(&png),
+        elements,
+        width = width,
+        height = height
+    )?;
+    writer.flush()?;
+    Ok(())
+}
+
+/// Saves an RGBA buffer as an SVG at `path` with the SVG markup in `elements`, such as
+/// annotations, layered over it.
+pub fn save_svg(
+    buffer: &[u8],
+    width: usize,
+    height: usize,
+    elements: &str,
+    path: &Path,
+) -> Result<(), SnapmeoError> {
+    if width == 0 || height == 0 {
+        return Err(SnapmeoError::Encode("The captured image is empty".into()));
+    }
+
+    write_svg(
+        buffer,
+        width as u32,
+        height as u32,
+        elements,
+        File::create(path)?,
+    )
+}
+
+/// Returns `delay` as the fraction of a second that APNG frame delays are given as, which is only
+/// as precise as it can be while fitting in 16 bits.
+fn frame_delay(delay: Duration) -> (u16, u16) {
+    [1000, 100, 10, 1]
+        .iter()
+        .map(|&denominator| {
+            let numerator = (delay.as_secs_f64() * denominator as f64).round();
+            (numerator, denominator)
+        })
+        .find(|&(numerator, _)| numerator <= u16::MAX as f64)
+        .map(|(numerator, denominator)| (numerator as u16, denominator))
+        .unwrap_or((u16::MAX, 1))
+}
+
+/// Saves `recording` as an animated PNG at `path` that loops forever.
+pub fn save_apng(recording: &Recording, path: &Path) -> Result<(), SnapmeoError> {
+    if recording.width == 0 || recording.height == 0 || recording.frames.is_empty() {
+        return Err(SnapmeoError::Encode("The recording is empty".into()));
+    }
+
+    let mut encoder = png::Encoder::new(
+        BufWriter::new(File::create(path)?),
+        recording.width as u32,
+        recording.height as u32,
+    );
+    encoder.set_color(png::ColorType::Rgba);
+    encoder.set_depth(png::BitDepth::Eight);
+    encoder.set_animated(recording.frames.len() as u32, 0)?;
+    let mut writer = encoder.write_header()?;
+    for (frame, delay) in &recording.frames {
+        let (numerator, denominator) = frame_delay(*delay);
+        writer.set_frame_delay(numerator, denominator)?;
+        writer.write_image_data(frame)?;
+    }
+    writer.finish()?;
+    Ok(())
+}
+
+/// Returns whether the RGBA `buffer` looks like a photo, which compresses far better as a JPEG,
+/// rather than flat UI content such as text and windows, which is better off as a PNG. Photos have
+/// lots of distinct colors that rarely repeat from one pixel to the next, while UI content is
+/// mostly runs of a few colors. Only a sample of the pixels is looked at, so that it's quick for
+/// even the largest captures.
+pub fn looks_photographic(buffer: &[u8]) -> bool {
+    const MAX_SAMPLES: usize = 1 << 16;
+    let pixels: Vec<&[u8]> = buffer.chunks_exact(4).collect();
+    if pixels.len() < 2 {
+        return false;
+    }
+    let step = cmp::max(pixels.len() / MAX_SAMPLES, 1);
+    let mut colors = HashSet::new();
+    let mut repeats = 0;
+    let mut samples = 0;
+    for ix in (1..pixels.len()).step_by(step) {
+        colors.insert(&pixels[ix][..3]);
+        if pixels[ix][..3] == pixels[ix - 1][..3] {
+            repeats += 1;
+        }
+        samples += 1;
+    }
+    colors.len() * 4 > samples && repeats * 2 < samples
+}
+
+/// Saves the `(x, y, width, height)` region of the BGRA `frame`, whose rows are `stride` bytes
+/// apart, as a PNG at `path`. It's converted and compressed a row at a time, so unlike `save` the
+/// whole region never has to be held in memory as RGBA.
+pub fn save_png_from_frame(
+    frame: &[u8],
+    stride: usize,
+    (x, y, width, height): (usize, usize, usize, usize),
+    path: &Path,
+) -> Result<(), SnapmeoError> {
+    if width == 0 || height == 0 {
+        return Err(SnapmeoError::Encode("The captured image is empty".into()));
+    }
+
+    let file = BufWriter::new(File::create(path)?);
+    let mut encoder = repng::Options::smallest(width as u32, height as u32).build(file)?;
+    for row in y..y + height {
+        encoder.write(&capture::crop_frame(frame, stride, (x, row, width, 1)))?;
+    }
+    encoder.finish()?;
+    Ok(())
+}
+
+/// Recompresses the PNG at `path` in place with `oxipng` for `--optimize-png`, returning its size
+/// in bytes before and after. The image itself is left unchanged. This runs the `oxipng` command
+/// rather than linking against it so that snapmeo builds without it, like `--ocr` does with
+/// Tesseract.
+pub fn optimize_png(path: &Path) -> Result<(u64, u64), SnapmeoError> {
+    let before = fs::metadata(path)?.len();
+    let output = Command::new("oxipng")
+        .args(["--opt", "2", "--strip", "safe", "--quiet"])
+        .arg(path)
+        .output()
+        .map_err(|err| match err.kind() {
+            ErrorKind::NotFound => SnapmeoError::Encode(
+                "`--optimize-png` needs the `oxipng` command, which isn't installed or isn't on the PATH"
+                    .into(),
+            ),
+            _ => SnapmeoError::Encode(format!("Unable to run `oxipng`: {}", err)),
+        })?;
+    if !output.status.success() {
+        return Err(SnapmeoError::Encode(format!(
+            "`oxipng` failed: {}",
+            String::from_utf8_lossy(&output.stderr).trim()
+        )));
+    }
+    Ok((before, fs::metadata(path)?.len()))
+}
+
+/// Encodes an RGBA buffer according to `options` and writes it to a new file at `path`.
+pub fn save(
+    buffer: &[u8],
+    width: usize,
+    height: usize,
+    options: &EncodeOptions,
+    path: &Path,
+) -> Result<(), SnapmeoError> {
+    if width == 0 || height == 0 {
+        return Err(SnapmeoError::Encode("The captured image is empty".into()));
+    }
+
+    let (width, height) = (width as u32, height as u32);
+    let file = File::create(path)?;
+    match options.format {
+        Format::Png => repng::encode(file, width, height, buffer)?,
+        Format::Bmp => BmpEncoder::new(&mut BufWriter::new(file)).write_image(
+            buffer,
+            width,
+            height,
+            ColorType::Rgba8,
+        )?,
+        Format::Ico => {
+            let (icon, size) = to_icon(buffer, width, height);
+            IcoEncoder::new(BufWriter::new(file)).write_image(
+                &icon,
+                size,
+                size,
+                ColorType::Rgba8,
+            )?
+        }
+        Format::Jpeg => {
+            if width > u16::MAX as u32 || height > u16::MAX as u32 {
+                return Err(SnapmeoError::Encode(format!(
+                    "JPEG images can be at most {} pixels on each side",
+                    u16::MAX
+                )));
+            }
+
+            // The encoder treats qualities below 1 as 1 anyway
+            let quality = options.quality.unwrap_or(JPEG_QUALITY).max(1);
+            let mut encoder = JpegEncoder::new(BufWriter::new(file), quality);
+            encoder.set_sampling_factor(options.subsampling.sampling_factor());
+            // JPEG has no alpha channel, so it's dropped
+            encoder.encode(
+                buffer,
+                width as u16,
+                height as u16,
+                jpeg_encoder::ColorType::Rgba,
+            )?
+        }
+        Format::Avif => save_avif(buffer, width, height, options.quality, file)?,
+        Format::Svg => write_svg(buffer, width, height, "", file)?,
+    }
+
+    Ok(())
+}
+
+/// Resizes an RGBA buffer to `new_width` x `new_height`.
+pub fn resize(
+    buffer: &[u8],
+    width: usize,
+    height: usize,
+    new_width: usize,
+    new_height: usize,
+) -> Vec<u8> {
+    let image = RgbaImage::from_raw(width as u32, height as u32, buffer.to_vec())
+        .expect("Buffer size doesn't match image dimensions!");
+    imageops::resize(
+        &image,
+        new_width as u32,
+        new_height as u32,
+        FilterType::Lanczos3,
+    )
+    .into_raw()
+}
+
+/// Returns the size of the largest image with the aspect ratio of `width` x `height` that fits
+/// within `bounds`. Images that already fit aren't scaled up.
+pub fn fit_within(width: usize, height: usize, bounds: (usize, usize)) -> (usize, usize) {
+    let scale = (bounds.0 as f64 / width as f64)
+        .min(bounds.1 as f64 / height as f64)
+        .min(1.);
+    (
+        cmp::max((width as f64 * scale).round() as usize, 1),
+        cmp::max((height as f64 * scale).round() as usize, 1),
+    )
+}
+
+/// Repeatedly scales an RGBA buffer down and re-encodes it to `path` until the file is no larger
+/// than `max_bytes`, returning the final dimensions of the image.
+pub fn shrink_to_fit(
+    buffer: &[u8],
+    width: usize,
+    height: usize,
+    options: &EncodeOptions,
+    path: &Path,
+    max_bytes: u64,
+) -> Result<(usize, usize), SnapmeoError> {
+    let (mut scaled_width, mut scaled_height) = (width, height);
+    let mut size = fs::metadata(path)?.len();
+
+    while size > max_bytes {
+        // Encoded size grows roughly in proportion to the pixel count, so aim a little under the
+        // limit to avoid needing many passes
+        let scale = ((max_bytes as f64 / size as f64).sqrt() * 0.95).min(0.95);
+        scaled_width = (scaled_width as f64 * scale) as usize;
+        scaled_height = (scaled_height as f64 * scale) as usize;
+        if scaled_width == 0 || scaled_height == 0 {
+            return Err(SnapmeoError::Encode(format!(
+                "Unable to shrink the image below {} bytes",
+                max_bytes
+            )));
+        }
+
+        let scaled = resize(buffer, width, height, scaled_width, scaled_height);
+        save(&scaled, scaled_width, scaled_height, options, path)?;
+        size = fs::metadata(path)?.len();
+    }
+
+    Ok((scaled_width, scaled_height))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::env;
+
+    use super::*;
+
+    /// An RGBA buffer of pseudo-random pixels, which PNG can't compress much
+    fn noise(width: usize, height: usize) -> Vec<u8> {
+        let mut state: u32 = 0x1234_5678;
+        (0..width * height * 4)
+            .map(|i| {
+                state = state.wrapping_mul(1_664_525).wrapping_add(1_013_904_223);
+                if i % 4 == 3 {
+                    255
+                } else {
+                    (state >> 24) as u8
+                }
+            })
+            .collect()
+    }
+
+    #[test]
+    fn flat_content_isnt_photographic() {
+        // A white window with a grey title bar and a line of black "text"
+        let (width, height) = (320, 240);
+        let buffer: Vec<u8> = (0..width * height)
+            .flat_map(|ix| {
+                let (x, y) = (ix % width, ix / width);
+                let shade = match y {
+                    0..=23 => 200,
+                    100..=109 if x % 8 < 5 => 0,
+                    _ => 255,
+                };
+                [shade, shade, shade, 255]
+            })
+            .collect();
+        assert!(!looks_photographic(&buffer));
+        assert!(!looks_photographic(&[0, 0, 0, 255]));
+        assert!(!looks_photographic(&[]));
+    }
+
+    #[test]
+    fn noisy_content_is_photographic() {
+        assert!(looks_photographic(&noise(320, 240)));
+    }
+
+    #[test]
+    fn frame_delay_keeps_millisecond_precision_while_it_fits() {
+        assert_eq!(frame_delay(Duration::from_millis(33)), (33, 1000));
+        assert_eq!(frame_delay(Duration::from_millis(0)), (0, 1000));
+        assert_eq!(frame_delay(Duration::from_millis(65_535)), (65_535, 1000));
+        assert_eq!(frame_delay(Duration::from_secs(100)), (10_000, 100));
+        assert_eq!(frame_delay(Duration::from_secs(1000)), (10_000, 10));
+        assert_eq!(frame_delay(Duration::from_secs(60_000)), (60_000, 1));
+        assert_eq!(frame_delay(Duration::from_secs(100_000)), (u16::MAX, 1));
+    }
+
+    #[test]
+    fn fit_within_keeps_the_aspect_ratio() {
+        assert_eq!(fit_within(1920, 1080, (320, 320)), (320, 180));
+        assert_eq!(fit_within(1080, 1920, (320, 320)), (180, 320));
+        // Images that already fit aren't scaled up
+        assert_eq!(fit_within(100, 50, (320, 320)), (100, 50));
+        // Neither side is scaled down to nothing
+        assert_eq!(fit_within(10_000, 1, (100, 100)), (100, 1));
+    }
+
+    #[test]
+    fn shrink_to_fit_gets_under_the_limit() {
+        let (width, height) = (200, 150);
+        let buffer = noise(width, height);
+        let options = EncodeOptions {
+            format: Format::Png,
+            subsampling: Subsampling::Yuv420,
+            quality: None,
+        };
+        let path = env::temp_dir().join(format!("snapmeo-test-{}-shrink.png", std::process::id()));
+        save(&buffer, width, height, &options, &path).unwrap();
+        let max_bytes = fs::metadata(&path).unwrap().len() / 3;
+
+        let (scaled_width, scaled_height) =
+            shrink_to_fit(&buffer, width, height, &options, &path, max_bytes).unwrap();
+        let size = fs::metadata(&path).unwrap().len();
+        fs::remove_file(&path).unwrap();
+        assert!(size <= max_bytes, "{} bytes is over {}", size, max_bytes);
+        assert!(scaled_width < width && scaled_height < height);
+    }
+}
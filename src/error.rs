@@ -0,0 +1,134 @@
+//! The ways that taking a screenshot can fail and the exit codes they're reported with, so that
+//! scripts and hotkey daemons can react to them.
+
+use std::error::Error;
+use std::fmt;
+use std::io;
+use std::time::Duration;
+
+pub const EXIT_SUCCESS: i32 = 0;
+pub const EXIT_FAILURE: i32 = 1;
+pub const EXIT_BAD_ARGUMENTS: i32 = 2;
+pub const EXIT_CAPTURE_FAILED: i32 = 3;
+pub const EXIT_UPLOAD_FAILED: i32 = 4;
+/// Matches the conventional exit code of a process interrupted with Ctrl+C
+pub const EXIT_CANCELLED: i32 = 130;
+
+#[derive(Debug)]
+pub enum SnapmeoError {
+    /// The command line arguments were invalid
+    BadArguments(String),
+    /// The screen couldn't be captured
+    Capture(String),
+    /// The captured image couldn't be encoded
+    Encode(String),
+    /// The image couldn't be uploaded. `status` is the HTTP status the server responded with, if
+    /// it got that far.
+    Upload {
+        status: Option<u16>,
+        message: String,
+    },
+    /// The upload backend rejected or was missing credentials
+    Auth(String),
+    /// The clipboard couldn't be read or written
+    Clipboard(String),
+    Io(io::Error),
+    /// The user backed out before anything was captured or uploaded
+    Cancelled,
+    /// Nothing was selected within the `--select-timeout`
+    SelectionTimedOut(Duration),
+    Other(String),
+}
+
+impl SnapmeoError {
+    pub fn exit_code(&self) -> i32 {
+        match self {
+            SnapmeoError::BadArguments(_) => EXIT_BAD_ARGUMENTS,
+            SnapmeoError::Capture(_) => EXIT_CAPTURE_FAILED,
+            SnapmeoError::Upload { .. } | SnapmeoError::Auth(_) => EXIT_UPLOAD_FAILED,
+            SnapmeoError::Cancelled | SnapmeoError::SelectionTimedOut(_) => EXIT_CANCELLED,
+            SnapmeoError::Encode(_)
+            | SnapmeoError::Clipboard(_)
+            | SnapmeoError::Io(_)
+            | SnapmeoError::Other(_) => EXIT_FAILURE,
+        }
+    }
+}
+
+impl fmt::Display for SnapmeoError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            SnapmeoError::BadArguments(message) => write!(f, "{}", message),
+            SnapmeoError::Capture(message) => {
+                write!(f, "Unable to capture the screen: {}", message)
+            }
+            SnapmeoError::Encode(message) => write!(f, "Unable to encode the image: {}", message),
+            SnapmeoError::Upload {
+                status: Some(status),
+                message,
+            } => write!(
+                f,
+                "Unable to upload the image; the server responded with {}: {}",
+                status, message
+            ),
+            SnapmeoError::Upload {
+                status: None,
+                message,
+            } => write!(f, "Unable to upload the image: {}", message),
+            SnapmeoError::Auth(message) => write!(f, "Authentication failed: {}", message),
+            SnapmeoError::Clipboard(message) => {
+                write!(f, "Unable to access the clipboard: {}", message)
+            }
+            SnapmeoError::Io(err) => write!(f, "I/O error: {}", err),
+            SnapmeoError::Cancelled => write!(f, "Capture cancelled."),
+            SnapmeoError::SelectionTimedOut(timeout) => write!(
+                f,
+                "Nothing was selected within {} seconds; capture cancelled.",
+                timeout.as_secs()
+            ),
+            SnapmeoError::Other(message) => write!(f, "{}", message),
+        }
+    }
+}
+
+impl Error for SnapmeoError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            SnapmeoError::Io(err) => Some(err),
+            _ => None,
+        }
+    }
+}
+
+impl From<io::Error> for SnapmeoError {
+    fn from(err: io::Error) -> Self {
+        SnapmeoError::Io(err)
+    }
+}
+
+impl From<reqwest::Error> for SnapmeoError {
+    fn from(err: reqwest::Error) -> Self {
+        SnapmeoError::Upload {
+            status: err.status().map(|status| status.as_u16()),
+            message: err.to_string(),
+        }
+    }
+}
+
+impl From<image::ImageError> for SnapmeoError {
+    fn from(err: image::ImageError) -> Self {
+        SnapmeoError::Encode(err.to_string())
+    }
+}
+
+impl From<png::EncodingError> for SnapmeoError {
+    fn from(err: png::EncodingError) -> Self {
+        SnapmeoError::Encode(err.to_string())
+    }
+}
+
+impl From<jpeg_encoder::EncodingError> for SnapmeoError {
+    fn from(err: jpeg_encoder::EncodingError) -> Self {
+        SnapmeoError::Encode(err.to_string())
+    }
+}
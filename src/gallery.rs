@@ -0,0 +1,192 @@
+//! `snapmeo gallery`, which shows recent uploads from the history as a grid of thumbnails so that
+//! clicking one copies its URL again.
+//!
+//! Images are only loaded once their row is scrolled into view, using the small copy made by
+//! `--thumbnail` when there is one. Captures whose files have since been moved or deleted are shown
+//! as empty cells, which can still be clicked since their URLs usually still work.
+
+use std::thread;
+use std::time::Duration;
+
+use sdl2::event::Event;
+use sdl2::image::LoadTexture;
+use sdl2::keyboard::Keycode;
+use sdl2::mouse::MouseButton;
+use sdl2::pixels::Color;
+use sdl2::rect::Rect;
+use sdl2::render::Texture;
+use sdl2::{EventPump, VideoSubsystem};
+
+use crate::encode;
+use crate::error::SnapmeoError;
+use crate::history::Entry;
+
+const COLUMNS: usize = 4;
+/// Most rows shown at once; the rest are scrolled to
+const MAX_VISIBLE_ROWS: usize = 3;
+/// Width and height of each cell in pixels
+const CELL_SIZE: u32 = 200;
+/// Space between cells and around the edges of the window in pixels
+const PADDING: u32 = 8;
+
+const BACKGROUND_COLOR: Color = Color::RGB(32, 32, 32);
+const EMPTY_CELL_COLOR: Color = Color::RGB(64, 64, 64);
+const HOVER_COLOR: Color = Color::RGB(66, 135, 245);
+
+/// Returns the cell at window position `(x, y)` when scrolled down by `scroll_rows`, if any.
+fn cell_at(x: i32, y: i32, scroll_rows: usize) -> Option<usize> {
+    let stride = (CELL_SIZE + PADDING) as i32;
+    let (x, y) = (x - PADDING as i32, y - PADDING as i32);
+    // Clicks between cells don't pick either of them
+    if x < 0 || y < 0 || x % stride >= CELL_SIZE as i32 || y % stride >= CELL_SIZE as i32 {
+        return None;
+    }
+    let column = (x / stride) as usize;
+    (column < COLUMNS).then(|| (scroll_rows + (y / stride) as usize) * COLUMNS + column)
+}
+
+/// The bounds of the cell at column `column` and visible row `row`
+fn cell_rect(column: usize, row: usize) -> Rect {
+    let stride = CELL_SIZE + PADDING;
+    Rect::new(
+        (PADDING + column as u32 * stride) as i32,
+        (PADDING + row as u32 * stride) as i32,
+        CELL_SIZE,
+        CELL_SIZE,
+    )
+}
+
+/// Shows `entries` as a grid of thumbnails until one is clicked, returning it, or the window is
+/// closed with escape, returning `None`.
+pub fn pick<'a>(
+    video_subsystem: &VideoSubsystem,
+    event_pump: &mut EventPump,
+    entries: &'a [Entry],
+    one_frame: Duration,
+) -> Result<Option<&'a Entry>, SnapmeoError> {
+    let rows = entries.len().div_ceil(COLUMNS);
+    let visible_rows = rows.clamp(1, MAX_VISIBLE_ROWS);
+    let window = video_subsystem
+        .window(
+            "Snapmeo gallery",
+            PADDING + COLUMNS as u32 * (CELL_SIZE + PADDING),
+            PADDING + visible_rows as u32 * (CELL_SIZE + PADDING),
+        )
+        .position_centered()
+        .build()
+        .map_err(|err| SnapmeoError::Other(err.to_string()))?;
+    let mut canvas = window
+        .into_canvas()
+        .build()
+        .map_err(|err| SnapmeoError::Other(err.to_string()))?;
+    let texture_creator = canvas.texture_creator();
+    // `None` until the image has been loaded, then `Some(None)` if it couldn't be
+    let mut textures: Vec<Option<Option<Texture>>> = entries.iter().map(|_| None).collect();
+
+    let mut scroll_rows = 0;
+    let mut hovered = None;
+    loop {
+        for event in event_pump.poll_iter() {
+            match event {
+                Event::Quit { .. }
+                | Event::KeyDown {
+                    keycode: Some(Keycode::Escape),
+                    ..
+                } => return Ok(None),
+                Event::MouseWheel { y, .. } => {
+                    let max_scroll = rows.saturating_sub(visible_rows);
+                    scroll_rows =
+                        (scroll_rows as i64 - y as i64).clamp(0, max_scroll as i64) as usize;
+                    hovered = None;
+                }
+                Event::MouseMotion { x, y, .. } => hovered = cell_at(x, y, scroll_rows),
+                Event::MouseButtonUp {
+                    mouse_btn: MouseButton::Left,
+                    x,
+                    y,
+                    ..
+                } => {
+                    if let Some(entry) = cell_at(x, y, scroll_rows).and_then(|ix| entries.get(ix)) {
+                        return Ok(Some(entry));
+                    }
+                }
+                _ => {}
+            }
+        }
+
+        canvas.set_draw_color(BACKGROUND_COLOR);
+        canvas.clear();
+        for row in 0..visible_rows {
+            for column in 0..COLUMNS {
+                let ix = (scroll_rows + row) * COLUMNS + column;
+                let entry = match entries.get(ix) {
+                    Some(entry) => entry,
+                    None => break,
+                };
+                let cell = cell_rect(column, row);
+                let texture = textures[ix].get_or_insert_with(|| {
+                    let thumbnail = entry.thumbnail.as_ref().filter(|path| path.exists());
+                    texture_creator
+                        .load_texture(thumbnail.unwrap_or(&entry.path))
+                        .ok()
+                });
+                match texture {
+                    Some(texture) => {
+                        let query = texture.query();
+                        let (width, height) = encode::fit_within(
+                            query.width as usize,
+                            query.height as usize,
+                            (CELL_SIZE as usize, CELL_SIZE as usize),
+                        );
+                        let mut destination = Rect::new(0, 0, width as u32, height as u32);
+                        destination.center_on(cell.center());
+                        canvas
+                            .copy(texture, None, destination)
+                            .map_err(SnapmeoError::Other)?;
+                    }
+                    None => {
+                        canvas.set_draw_color(EMPTY_CELL_COLOR);
+                        canvas.fill_rect(cell).map_err(SnapmeoError::Other)?;
+                    }
+                }
+                if hovered == Some(ix) {
+                    canvas.set_draw_color(HOVER_COLOR);
+                    canvas.draw_rect(cell).map_err(SnapmeoError::Other)?;
+                }
+            }
+        }
+        canvas.present();
+
+        thread::sleep(one_frame);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn cell_at_finds_the_clicked_cell() {
+        let stride = (CELL_SIZE + PADDING) as i32;
+        assert_eq!(cell_at(PADDING as i32, PADDING as i32, 0), Some(0));
+        assert_eq!(cell_at(PADDING as i32 + stride, PADDING as i32, 0), Some(1));
+        assert_eq!(
+            cell_at(PADDING as i32, PADDING as i32 + stride, 0),
+            Some(COLUMNS)
+        );
+        assert_eq!(
+            cell_at(PADDING as i32, PADDING as i32, 2),
+            Some(2 * COLUMNS)
+        );
+    }
+
+    #[test]
+    fn cell_at_ignores_the_gaps_between_cells() {
+        assert_eq!(cell_at(0, 0, 0), None);
+        let gap = (PADDING + CELL_SIZE) as i32 + 1;
+        assert_eq!(cell_at(gap, PADDING as i32, 0), None);
+        assert_eq!(cell_at(PADDING as i32, gap, 0), None);
+        let past_last_column = (PADDING + COLUMNS as u32 * (CELL_SIZE + PADDING)) as i32 + 1;
+        assert_eq!(cell_at(past_last_column, PADDING as i32, 0), None);
+    }
+}
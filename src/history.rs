@@ -0,0 +1,116 @@
+//! The history of uploaded captures, which `snapmeo gallery` shows so that a recent capture's URL
+//! can be copied again without digging through old output.
+//!
+//! It's kept in `history.json` next to the upload queue, newest first, and only the most recent
+//! `MAX_ENTRIES` uploads are remembered. Captures can be given `--tag`s so that `snapmeo history
+//! --tag` can list just the ones about a particular project.
+
+use std::fs;
+use std::io::ErrorKind;
+use std::path::PathBuf;
+
+use serde::{Deserialize, Serialize};
+
+use crate::error::SnapmeoError;
+use crate::upload::data_dir;
+
+/// How many uploads are remembered
+const MAX_ENTRIES: usize = 200;
+
+/// An uploaded capture
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct Entry {
+    pub url: String,
+    /// Where the capture was saved, which may have since been moved or deleted
+    pub path: PathBuf,
+    /// The smaller copy made by `--thumbnail`, if there was one
+    #[serde(default)]
+    pub thumbnail: Option<PathBuf>,
+    /// When it was uploaded, in RFC 3339 format
+    pub uploaded_at: String,
+    #[serde(default)]
+    pub tags: Vec<String>,
+}
+
+impl Entry {
+    /// Whether the capture was given every one of `tags`
+    pub fn has_tags(&self, tags: &[String]) -> bool {
+        tags.iter().all(|tag| self.tags.contains(tag))
+    }
+}
+
+/// Checks and tidies up the labels given with `--tag`, dropping duplicates.
+pub fn parse_tags<'a>(tags: impl Iterator<Item = &'a str>) -> Result<Vec<String>, String> {
+    let mut parsed: Vec<String> = Vec::new();
+    for tag in tags.map(str::trim) {
+        if tag.is_empty() {
+            return Err("Tags can't be empty".into());
+        }
+        if !parsed.iter().any(|seen| seen == tag) {
+            parsed.push(tag.to_owned());
+        }
+    }
+    Ok(parsed)
+}
+
+fn history_path() -> Result<PathBuf, SnapmeoError> {
+    data_dir()
+        .map(|dir| dir.join("history.json"))
+        .ok_or_else(|| {
+            SnapmeoError::Other("Unable to find a directory to keep the history in".into())
+        })
+}
+
+/// Returns the remembered uploads, newest first.
+pub fn load() -> Result<Vec<Entry>, SnapmeoError> {
+    let path = history_path()?;
+    let contents = match fs::read_to_string(&path) {
+        Ok(contents) => contents,
+        Err(err) if err.kind() == ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(err) => return Err(err.into()),
+    };
+    serde_json::from_str(&contents).map_err(|err| {
+        SnapmeoError::Other(format!(
+            "The history at {} is damaged: {}",
+            path.display(),
+            err
+        ))
+    })
+}
+
+/// Adds `entry` to the front of the history, forgetting the oldest uploads if there are too many.
+pub fn record(mut entry: Entry) -> Result<(), SnapmeoError> {
+    // Relative paths would stop working as soon as the gallery is opened from elsewhere
+    entry.path = fs::canonicalize(&entry.path).unwrap_or(entry.path);
+    entry.thumbnail = entry
+        .thumbnail
+        .map(|thumbnail| fs::canonicalize(&thumbnail).unwrap_or(thumbnail));
+
+    // A damaged history shouldn't stop new uploads from going through
+    let mut entries = load().unwrap_or_default();
+    entries.insert(0, entry);
+    entries.truncate(MAX_ENTRIES);
+
+    let path = history_path()?;
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir)?;
+    }
+    let contents = serde_json::to_string_pretty(&entries)
+        .map_err(|err| SnapmeoError::Other(err.to_string()))?;
+    fs::write(path, contents)?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_tags_trims_and_drops_duplicates() {
+        assert_eq!(
+            parse_tags(vec![" work ", "bugs", "work"].into_iter()).unwrap(),
+            vec!["work".to_owned(), "bugs".to_owned()]
+        );
+        assert!(parse_tags(vec!["work", " "].into_iter()).is_err());
+    }
+}
@@ -0,0 +1,138 @@
+//! `--hold-to-select`, where the selection overlay only stays up while a modifier key is held, so
+//! that a capture is a single gesture: hold the key, drag out the region, and let go of the mouse.
+//!
+//! On X11 the key is read from the X server, which knows about it whichever window has focus;
+//! that's what lets the overlay wait for the key before it appears. Elsewhere only SDL's view of
+//! the keyboard is available, which only covers keys pressed while the overlay has focus.
+
+use std::str::FromStr;
+
+#[cfg(feature = "interactive")]
+use sdl2::{keyboard::Scancode, EventPump};
+
+/// The modifier key that has to be held
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum HoldKey {
+    Ctrl,
+    Shift,
+    Alt,
+    Super,
+}
+
+impl FromStr for HoldKey {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "ctrl" | "control" => Ok(HoldKey::Ctrl),
+            "shift" => Ok(HoldKey::Shift),
+            "alt" => Ok(HoldKey::Alt),
+            "super" | "win" | "cmd" => Ok(HoldKey::Super),
+            _ => Err(format!(
+                "Invalid hold key `{}`; expected one of `ctrl`, `shift`, `alt`, or `super`",
+                s
+            )),
+        }
+    }
+}
+
+#[cfg(feature = "interactive")]
+impl HoldKey {
+    fn scancodes(self) -> [Scancode; 2] {
+        match self {
+            HoldKey::Ctrl => [Scancode::LCtrl, Scancode::RCtrl],
+            HoldKey::Shift => [Scancode::LShift, Scancode::RShift],
+            HoldKey::Alt => [Scancode::LAlt, Scancode::RAlt],
+            HoldKey::Super => [Scancode::LGui, Scancode::RGui],
+        }
+    }
+}
+
+/// Keeps track of whether a `HoldKey` is held.
+#[cfg(feature = "interactive")]
+pub struct HoldWatcher {
+    key: HoldKey,
+    global: Option<platform::GlobalModifiers>,
+}
+
+#[cfg(feature = "interactive")]
+impl HoldWatcher {
+    pub fn new(key: HoldKey) -> Self {
+        HoldWatcher {
+            key,
+            global: platform::GlobalModifiers::connect(),
+        }
+    }
+
+    /// Returns whether the key's state can be read while another window has focus.
+    pub fn is_global(&self) -> bool {
+        self.global.is_some()
+    }
+
+    /// Returns whether the key is held down right now.
+    pub fn is_held(&self, event_pump: &EventPump) -> bool {
+        if let Some(held) = self
+            .global
+            .as_ref()
+            .and_then(|global| global.is_held(self.key))
+        {
+            return held;
+        }
+        let keyboard = event_pump.keyboard_state();
+        self.key
+            .scancodes()
+            .iter()
+            .any(|&scancode| keyboard.is_scancode_pressed(scancode))
+    }
+}
+
+#[cfg(all(feature = "interactive", target_os = "linux"))]
+mod platform {
+    use x11rb::connection::Connection;
+    use x11rb::protocol::xproto::{ConnectionExt, KeyButMask, Window};
+    use x11rb::rust_connection::RustConnection;
+
+    use super::HoldKey;
+
+    pub struct GlobalModifiers {
+        conn: RustConnection,
+        root: Window,
+    }
+
+    impl GlobalModifiers {
+        pub fn connect() -> Option<Self> {
+            let (conn, screen_num) = x11rb::connect(None).ok()?;
+            let root = conn.setup().roots[screen_num].root;
+            Some(GlobalModifiers { conn, root })
+        }
+
+        /// Returns whether `key` is held, or `None` if the X server couldn't be asked.
+        pub fn is_held(&self, key: HoldKey) -> Option<bool> {
+            let mask = self.conn.query_pointer(self.root).ok()?.reply().ok()?.mask;
+            let key_mask = match key {
+                HoldKey::Ctrl => KeyButMask::CONTROL,
+                HoldKey::Shift => KeyButMask::SHIFT,
+                HoldKey::Alt => KeyButMask::MOD1,
+                HoldKey::Super => KeyButMask::MOD4,
+            };
+            Some(mask.contains(key_mask))
+        }
+    }
+}
+
+#[cfg(all(feature = "interactive", not(target_os = "linux")))]
+mod platform {
+    use super::HoldKey;
+
+    pub struct GlobalModifiers;
+
+    impl GlobalModifiers {
+        pub fn connect() -> Option<Self> {
+            None
+        }
+
+        pub fn is_held(&self, _key: HoldKey) -> Option<bool> {
+            None
+        }
+    }
+}
@@ -0,0 +1,394 @@
+//! Placing images on and reading them from the system clipboard.
+//!
+//! The `clipboard` crate only handles text, so images are handed off to and read back from the
+//! platform's own clipboard tools. On X11 and Wayland these also keep serving the clipboard contents after
+//! snapmeo exits, which an in-process clipboard owner wouldn't.
+
+use std::env;
+use std::fs::{self, File};
+use std::path::Path;
+use std::process::{self, Command, Stdio};
+use std::str::FromStr;
+
+use crate::error::SnapmeoError;
+
+/// What's put on the clipboard once a capture is uploaded, for `--clipboard-flavor`
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Flavor {
+    /// The image itself, for pasting into image editors and chat apps
+    Image,
+    /// A reference to the saved file, for pasting into file managers and upload fields
+    FileUri,
+    /// The link, or whatever `--clipboard-template` makes of it
+    Url,
+    /// All of them at once where the platform allows it, so that the app being pasted into picks
+    Auto,
+}
+
+impl FromStr for Flavor {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "png" | "image" => Ok(Flavor::Image),
+            "file-uri" | "file" => Ok(Flavor::FileUri),
+            "url" | "link" => Ok(Flavor::Url),
+            "auto" => Ok(Flavor::Auto),
+            _ => Err(format!(
+                "Invalid clipboard flavor `{}`; expected `png`, `file-uri`, `url`, or `auto`",
+                s
+            )),
+        }
+    }
+}
+
+/// Returns the `file://` URI of `path`, percent-encoding anything that isn't allowed in one.
+#[cfg(target_os = "linux")]
+fn file_uri(path: &Path) -> Result<String, SnapmeoError> {
+    let mut uri = String::from("file://");
+    for byte in fs::canonicalize(path)?.to_string_lossy().bytes() {
+        match byte {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'/' | b'-' | b'_' | b'.' | b'~' | b':' => {
+                uri.push(byte as char)
+            }
+            _ => uri.push_str(&format!("%{:02X}", byte)),
+        }
+    }
+    Ok(uri)
+}
+
+/// Puts a reference to the file at `path` on the clipboard, which file managers paste as a copy of
+/// the file.
+pub fn copy_file(path: &Path) -> Result<(), SnapmeoError> {
+    let status = file_command(path)?.status().map_err(|err| {
+        SnapmeoError::Clipboard(format!(
+            "Unable to run the clipboard tool: {}. On Linux, `xclip` or `wl-clipboard` must be installed.",
+            err
+        ))
+    })?;
+
+    if !status.success() {
+        return Err(SnapmeoError::Clipboard(format!(
+            "The clipboard tool exited with {}",
+            status
+        )));
+    }
+    Ok(())
+}
+
+#[cfg(target_os = "linux")]
+fn file_command(path: &Path) -> Result<Command, SnapmeoError> {
+    let uri = file_uri(path)?;
+    // File managers look for a `text/uri-list`, which has to be fed to the tools through a file
+    let list_path = env::temp_dir().join(format!("snapmeo-uri-list-{}", process::id()));
+    fs::write(&list_path, format!("{}\r\n", uri))?;
+    let mut command = if env::var_os("WAYLAND_DISPLAY").is_some() {
+        let mut command = Command::new("wl-copy");
+        command.args(["--type", "text/uri-list"]);
+        command
+    } else {
+        let mut command = Command::new("xclip");
+        command.args(["-selection", "clipboard", "-target", "text/uri-list"]);
+        command
+    };
+    command.stdin(File::open(&list_path)?).stdout(Stdio::null());
+    // The tools have their own handle on it by now
+    let _ = fs::remove_file(&list_path);
+
+    Ok(command)
+}
+
+#[cfg(target_os = "macos")]
+fn file_command(path: &Path) -> Result<Command, SnapmeoError> {
+    let path = fs::canonicalize(path)?;
+
+    let mut command = Command::new("osascript");
+    command
+        .arg("-e")
+        .arg(format!(
+            "set the clipboard to (POSIX file \"{}\")",
+            path.display()
+                .to_string()
+                .replace('\\', "\\\\")
+                .replace('"', "\\\"")
+        ))
+        .stdout(Stdio::null());
+
+    Ok(command)
+}
+
+#[cfg(target_os = "windows")]
+fn file_command(path: &Path) -> Result<Command, SnapmeoError> {
+    File::open(path)?;
+
+    let mut command = Command::new("powershell");
+    command
+        .arg("-NoProfile")
+        .arg("-Command")
+        .arg(format!(
+            "Add-Type -AssemblyName System.Windows.Forms; $files = New-Object System.Collections.Specialized.StringCollection; $files.Add('{}'); [System.Windows.Forms.Clipboard]::SetFileDropList($files)",
+            windows_path(path).replace('\'', "''")
+        ))
+        .stdout(Stdio::null());
+
+    Ok(command)
+}
+
+/// Returns the absolute form of `path` without the `\\?\` prefix `canonicalize` adds, which the
+/// clipboard doesn't understand.
+#[cfg(target_os = "windows")]
+fn windows_path(path: &Path) -> String {
+    let path = fs::canonicalize(path).unwrap_or_else(|_| path.to_owned());
+    let path = path.display().to_string();
+    path.strip_prefix(r"\\?\").unwrap_or(&path).to_owned()
+}
+
+/// Copies the image at `path` of MIME type `content_type` to the clipboard.
+pub fn copy_image(path: &Path, content_type: &str) -> Result<(), SnapmeoError> {
+    let status = clipboard_command(path, content_type)?.status().map_err(|err| {
+        SnapmeoError::Clipboard(format!(
+            "Unable to run the clipboard tool: {}. On Linux, `xclip` or `wl-clipboard` must be installed.",
+            err
+        ))
+    })?;
+
+    if !status.success() {
+        return Err(SnapmeoError::Clipboard(format!(
+            "The clipboard tool exited with {}",
+            status
+        )));
+    }
+    Ok(())
+}
+
+/// Reads the image on the clipboard, returning it as an RGBA buffer along with its width and
+/// height.
+pub fn paste_image() -> Result<(Vec<u8>, usize, usize), SnapmeoError> {
+    // The clipboard tools are all made to write the image out as a PNG file, since that's the one
+    // format every platform can provide
+    let path = env::temp_dir().join(format!("snapmeo-clipboard-{}.png", process::id()));
+    let status = paste_command(&path)?.status().map_err(|err| {
+        SnapmeoError::Clipboard(format!(
+            "Unable to run the clipboard tool: {}. On Linux, `xclip` or `wl-clipboard` must be installed.",
+            err
+        ))
+    });
+    let contents = fs::read(&path);
+    let _ = fs::remove_file(&path);
+
+    let no_image = || SnapmeoError::Clipboard("The clipboard doesn't hold an image".into());
+    let contents = match (status?, contents) {
+        (status, Ok(contents)) if status.success() && !contents.is_empty() => contents,
+        _ => return Err(no_image()),
+    };
+    let image = image::load_from_memory(&contents)
+        .map_err(|_| no_image())?
+        .into_rgba8();
+    let (width, height) = (image.width() as usize, image.height() as usize);
+
+    Ok((image.into_raw(), width, height))
+}
+
+#[cfg(target_os = "linux")]
+fn paste_command(path: &Path) -> Result<Command, SnapmeoError> {
+    let mut command = if env::var_os("WAYLAND_DISPLAY").is_some() {
+        let mut command = Command::new("wl-paste");
+        command.args(["--no-newline", "--type", "image/png"]);
+        command
+    } else {
+        let mut command = Command::new("xclip");
+        command.args(["-selection", "clipboard", "-target", "image/png", "-out"]);
+        command
+    };
+    command.stdout(File::create(path)?).stderr(Stdio::null());
+
+    Ok(command)
+}
+
+#[cfg(target_os = "macos")]
+fn paste_command(path: &Path) -> Result<Command, SnapmeoError> {
+    let mut command = Command::new("osascript");
+    command
+        .arg("-e")
+        .arg(format!(
+            "set png to (the clipboard as «class PNGf»)\n\
+             set output to open for access (POSIX file \"{}\") with write permission\n\
+             write png to output\n\
+             close access output",
+            path.display()
+        ))
+        .stdout(Stdio::null())
+        .stderr(Stdio::null());
+
+    Ok(command)
+}
+
+#[cfg(target_os = "windows")]
+fn paste_command(path: &Path) -> Result<Command, SnapmeoError> {
+    let mut command = Command::new("powershell");
+    command
+        .arg("-NoProfile")
+        .arg("-Command")
+        .arg(format!(
+            "Add-Type -AssemblyName System.Windows.Forms; $image = [System.Windows.Forms.Clipboard]::GetImage(); if (!$image) {{ exit 1 }}; $image.Save('{}', [System.Drawing.Imaging.ImageFormat]::Png)",
+            path.display()
+        ))
+        .stdout(Stdio::null());
+
+    Ok(command)
+}
+
+#[cfg(target_os = "linux")]
+fn clipboard_command(path: &Path, content_type: &str) -> Result<Command, SnapmeoError> {
+    let mut command = if std::env::var_os("WAYLAND_DISPLAY").is_some() {
+        let mut command = Command::new("wl-copy");
+        command.args(["--type", content_type]);
+        command
+    } else {
+        let mut command = Command::new("xclip");
+        command.args(["-selection", "clipboard", "-target", content_type]);
+        command
+    };
+    command.stdin(File::open(path)?).stdout(Stdio::null());
+
+    Ok(command)
+}
+
+#[cfg(target_os = "macos")]
+fn clipboard_command(path: &Path, content_type: &str) -> Result<Command, SnapmeoError> {
+    let class = match content_type {
+        "image/png" => "PNGf",
+        "image/bmp" => "BMPf",
+        "image/jpeg" => "JPEG",
+        _ => {
+            return Err(SnapmeoError::Clipboard(format!(
+                "Images of type `{}` can't be copied to the clipboard on macOS",
+                content_type
+            )))
+        }
+    };
+    // Make sure the file exists so that a missing file doesn't surface as an AppleScript error
+    File::open(path)?;
+
+    let mut command = Command::new("osascript");
+    command
+        .arg("-e")
+        .arg(format!(
+            "set the clipboard to (read (POSIX file \"{}\") as «class {}»)",
+            path.display(),
+            class
+        ))
+        .stdout(Stdio::null());
+
+    Ok(command)
+}
+
+#[cfg(target_os = "windows")]
+fn clipboard_command(path: &Path, _content_type: &str) -> Result<Command, SnapmeoError> {
+    File::open(path)?;
+
+    let mut command = Command::new("powershell");
+    command
+        .arg("-NoProfile")
+        .arg("-Command")
+        .arg(format!(
+            "Add-Type -AssemblyName System.Windows.Forms; [System.Windows.Forms.Clipboard]::SetImage([System.Drawing.Image]::FromFile('{}'))",
+            path.display()
+        ))
+        .stdout(Stdio::null());
+
+    Ok(command)
+}
+
+/// Puts the image at `path` of MIME type `content_type`, a reference to the file, and `text` on
+/// the clipboard all at once, so that pasting into an image editor gives the image, pasting into a
+/// file manager gives the file, and pasting into a text field gives the text. Returns an error if
+/// the platform's clipboard tools can't hold them all at once, in which case nothing is copied.
+pub fn copy_image_and_text(
+    path: &Path,
+    content_type: &str,
+    text: &str,
+) -> Result<(), SnapmeoError> {
+    let status = image_and_text_command(path, content_type, text)?
+        .status()
+        .map_err(|err| {
+            SnapmeoError::Clipboard(format!("Unable to run the clipboard tool: {}", err))
+        })?;
+
+    if !status.success() {
+        return Err(SnapmeoError::Clipboard(format!(
+            "The clipboard tool exited with {}",
+            status
+        )));
+    }
+    Ok(())
+}
+
+#[cfg(target_os = "linux")]
+fn image_and_text_command(
+    _path: &Path,
+    _content_type: &str,
+    _text: &str,
+) -> Result<Command, SnapmeoError> {
+    // `xclip` and `wl-copy` both serve a single type, and a second run replaces the first
+    Err(SnapmeoError::Clipboard(
+        "The clipboard can only hold one format at a time with `xclip` and `wl-clipboard`".into(),
+    ))
+}
+
+#[cfg(target_os = "macos")]
+fn image_and_text_command(
+    path: &Path,
+    content_type: &str,
+    text: &str,
+) -> Result<Command, SnapmeoError> {
+    let class = match content_type {
+        "image/png" => "PNGf",
+        "image/bmp" => "BMPf",
+        "image/jpeg" => "JPEG",
+        _ => {
+            return Err(SnapmeoError::Clipboard(format!(
+                "Images of type `{}` can't be copied to the clipboard on macOS",
+                content_type
+            )))
+        }
+    };
+    File::open(path)?;
+
+    // Setting the clipboard to a record gives it one representation per field
+    let mut command = Command::new("osascript");
+    command
+        .arg("-e")
+        .arg(format!(
+            "set the clipboard to {{«class {class}»:(read (POSIX file \"{path}\") as «class {class}»), \
+             «class furl»:(POSIX file \"{path}\"), string:\"{}\"}}",
+            text.replace('\\', "\\\\").replace('"', "\\\""),
+            path = fs::canonicalize(path)?.display(),
+            class = class
+        ))
+        .stdout(Stdio::null());
+
+    Ok(command)
+}
+
+#[cfg(target_os = "windows")]
+fn image_and_text_command(
+    path: &Path,
+    _content_type: &str,
+    text: &str,
+) -> Result<Command, SnapmeoError> {
+    File::open(path)?;
+
+    let mut command = Command::new("powershell");
+    command
+        .arg("-NoProfile")
+        .arg("-Command")
+        .arg(format!(
+            "Add-Type -AssemblyName System.Windows.Forms; $data = New-Object System.Windows.Forms.DataObject; $data.SetImage([System.Drawing.Image]::FromFile('{path}')); $files = New-Object System.Collections.Specialized.StringCollection; $files.Add('{path}'); $data.SetFileDropList($files); $data.SetText('{}'); [System.Windows.Forms.Clipboard]::SetDataObject($data, $true)",
+            text.replace('\'', "''"),
+            path = windows_path(path).replace('\'', "''")
+        ))
+        .stdout(Stdio::null());
+
+    Ok(command)
+}
@@ -0,0 +1,7 @@
+//! The parts of snapmeo that other programs can use without its selection UI, which so far is
+//! capturing the screen as raw pixels with `capture::capture_region_rgba` and uploading to
+//! AmeoTrack without blocking with `ameotrack::upload_async`.
+
+pub mod ameotrack;
+pub mod capture;
+pub mod error;
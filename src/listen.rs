@@ -0,0 +1,418 @@
+//! `--listen` mode, where snapmeo waits on a local TCP socket for other programs such as editor
+//! plugins to ask it to take screenshots.
+//!
+//! The protocol is one JSON object per line in each direction. A request looks like
+//! `{"token": "...", "args": ["--region", "0,0,800,600"]}`, where `args` are the same flags
+//! snapmeo takes on the command line. Each capture is run by a fresh snapmeo process with `--json` added to those
+//! flags, and once it's done the connection gets back either `{"ok": true, "result": ...}` with
+//! the JSON it printed, such as the uploaded URL, or `{"ok": false, "exit_code": ..., "error":
+//! ...}`. A connection can send any number of requests, which are handled one at a time.
+//!
+//! Requests that arrive within the cooldown after the previous capture finished are answered with
+//! `{"ok": false, "ignored": true, ...}` without capturing, so that a hotkey that gets stuck or
+//! repeats doesn't flood the upload host.
+//!
+//! Only loopback addresses can be listened on, and even then every local process and web page can
+//! connect, so requests are locked down three ways:
+//!
+//! - Each one has to carry the token kept in `listen-token` next to the upload queue, which only
+//!   the user can read. It's made the first time `--listen` is run.
+//! - Only the flags in `ALLOWED_FLAGS` can be given, which choose what to capture and how, but
+//!   not where it's uploaded to, what's run afterwards, or which files are read.
+//! - Connections that start like an HTTP request are dropped, since that's all a browser can send.
+
+use std::env;
+use std::fs::{self, OpenOptions};
+use std::io::{BufRead, BufReader, ErrorKind, Write};
+use std::net::{SocketAddr, TcpListener, TcpStream};
+#[cfg(unix)]
+use std::os::unix::fs::{OpenOptionsExt, PermissionsExt};
+use std::path::PathBuf;
+use std::process::Command;
+use std::time::{Duration, Instant};
+
+use colored::*;
+use serde::Deserialize;
+use serde_json::json;
+
+use crate::error::SnapmeoError;
+use crate::upload;
+
+/// Flags given to `--listen` itself that are passed along to every capture. Requests can't give
+/// them themselves.
+pub const INHERITED_FLAGS: [&str; 4] = ["output_dir", "config", "profile", "uploader"];
+
+/// Whether a flag takes a value
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum FlagValue {
+    None,
+    Required,
+    /// Taken only if there is one, so it has to be given as `--flag=value` to be told apart from
+    /// the arguments that follow
+    Optional,
+}
+
+/// The flags that requests can give, and whether each one takes a value
+const ALLOWED_FLAGS: [(&str, FlagValue); 67] = [
+    ("active-window", FlagValue::None),
+    ("anchor", FlagValue::Required),
+    ("annotate", FlagValue::None),
+    ("aspect", FlagValue::Required),
+    ("auto-format", FlagValue::None),
+    ("auto-reduce", FlagValue::None),
+    ("background", FlagValue::Required),
+    ("border", FlagValue::Required),
+    ("border-color", FlagValue::Required),
+    ("clipboard-flavor", FlagValue::Required),
+    ("clipboard-image", FlagValue::None),
+    ("clipboard-template", FlagValue::Required),
+    ("compress-upload", FlagValue::None),
+    ("confirm", FlagValue::None),
+    ("copy-region", FlagValue::None),
+    ("cursor", FlagValue::None),
+    ("decorations", FlagValue::Required),
+    ("delay", FlagValue::Required),
+    ("expiry", FlagValue::Required),
+    ("fast-paste", FlagValue::None),
+    ("filename-template", FlagValue::Required),
+    ("force", FlagValue::None),
+    ("format", FlagValue::Required),
+    ("from-clipboard", FlagValue::None),
+    ("hold-to-select", FlagValue::Optional),
+    ("live", FlagValue::None),
+    ("max-upload-bytes", FlagValue::Required),
+    ("monitor-capture-fps", FlagValue::Required),
+    ("name", FlagValue::Required),
+    ("no-masks", FlagValue::None),
+    ("notify", FlagValue::None),
+    ("ocr", FlagValue::Optional),
+    ("on-click", FlagValue::None),
+    ("one-time", FlagValue::None),
+    ("only-changed-region", FlagValue::None),
+    ("optimize-png", FlagValue::None),
+    ("padding", FlagValue::Required),
+    ("pin", FlagValue::None),
+    ("playback-fps", FlagValue::Required),
+    ("preset", FlagValue::Required),
+    ("quality", FlagValue::Required),
+    ("recapture", FlagValue::None),
+    ("record", FlagValue::Required),
+    ("region", FlagValue::Required),
+    ("region-percent", FlagValue::Required),
+    ("restore-clipboard-after", FlagValue::Required),
+    ("retina-downscale", FlagValue::None),
+    ("scroll", FlagValue::None),
+    ("secret", FlagValue::None),
+    ("select-timeout", FlagValue::Required),
+    ("send-tags", FlagValue::None),
+    ("shadow", FlagValue::Required),
+    ("since-last", FlagValue::None),
+    ("size", FlagValue::Required),
+    ("skip-unchanged", FlagValue::None),
+    ("sound", FlagValue::None),
+    ("subsampling", FlagValue::Required),
+    ("tag", FlagValue::Required),
+    ("thumbnail", FlagValue::Required),
+    ("timestamp-bar", FlagValue::None),
+    ("transparent-window-capture", FlagValue::Optional),
+    ("type-corners", FlagValue::None),
+    ("upload-format", FlagValue::Required),
+    ("verbose", FlagValue::None),
+    ("window", FlagValue::Required),
+    ("window-under-cursor", FlagValue::None),
+    ("work-area", FlagValue::None),
+];
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct CaptureRequest {
+    #[serde(default)]
+    token: String,
+    #[serde(default)]
+    args: Vec<String>,
+}
+
+fn token_path() -> Result<PathBuf, SnapmeoError> {
+    upload::data_dir()
+        .map(|dir| dir.join("listen-token"))
+        .ok_or_else(|| {
+            SnapmeoError::Other("Unable to find a directory to keep the listen token in".into())
+        })
+}
+
+/// Returns the token that requests have to carry, making one the first time. Returns an error if
+/// the file it's kept in can be read by anyone other than the user.
+pub fn load_token() -> Result<(String, PathBuf), SnapmeoError> {
+    let path = token_path()?;
+    match fs::read_to_string(&path) {
+        Ok(token) => {
+            #[cfg(unix)]
+            if fs::metadata(&path)?.permissions().mode() & 0o077 != 0 {
+                return Err(SnapmeoError::Other(format!(
+                    "The listen token at {} can be read by other users; run `chmod 600` on it",
+                    path.display()
+                )));
+            }
+            return Ok((token.trim().to_owned(), path));
+        }
+        Err(err) if err.kind() == ErrorKind::NotFound => {}
+        Err(err) => return Err(err.into()),
+    }
+
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir)?;
+    }
+    // Two keys' worth of randomness, without the dashes
+    let token: String = format!("{}{}", upload::idempotency_key(), upload::idempotency_key())
+        .chars()
+        .filter(|&c| c != '-')
+        .collect();
+    let mut options = OpenOptions::new();
+    options.write(true).create_new(true);
+    #[cfg(unix)]
+    options.mode(0o600);
+    writeln!(options.open(&path)?, "{}", token)?;
+    Ok((token, path))
+}
+
+/// Compares tokens without stopping at the first difference, so that how long it takes doesn't
+/// give away how much of a guess was right.
+fn tokens_match(given: &str, token: &str) -> bool {
+    given.len() == token.len()
+        && given
+            .bytes()
+            .zip(token.bytes())
+            .fold(0, |difference, (a, b)| difference | (a ^ b))
+            == 0
+}
+
+/// Checks that `args` only use `ALLOWED_FLAGS`, each written out in full like `--region`. Values
+/// given as separate arguments can't start with `-`, so that anything that could be taken for a
+/// flag of its own is checked as one.
+fn check_args(args: &[String]) -> Result<(), String> {
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        let flag = arg.strip_prefix("--").ok_or_else(|| {
+            format!(
+                "Unexpected argument `{}`; only long flags can be given",
+                arg
+            )
+        })?;
+        let (name, inline_value) = match flag.split_once('=') {
+            Some((name, _)) => (name, true),
+            None => (flag, false),
+        };
+        let value = ALLOWED_FLAGS
+            .iter()
+            .find(|(allowed, _)| *allowed == name)
+            .map(|&(_, value)| value)
+            .ok_or_else(|| format!("`--{}` can't be given in capture requests", name))?;
+        match value {
+            FlagValue::None if inline_value => {
+                return Err(format!("`--{}` doesn't take a value", name));
+            }
+            FlagValue::Required if !inline_value => match args.next() {
+                None => return Err(format!("`--{}` needs a value", name)),
+                Some(value) if value.starts_with('-') => {
+                    return Err(format!(
+                        "`--{}`'s value `{}` has to be given as `--{}={}`",
+                        name, value, name, value
+                    ))
+                }
+                Some(_) => {}
+            },
+            // Optional values without `=` are left for the loop to check as arguments of their own
+            _ => {}
+        }
+    }
+    Ok(())
+}
+
+/// Whether `line` is the first line of an HTTP request, such as a web page posting to the
+/// listener
+fn looks_like_http(line: &str) -> bool {
+    let mut words = line.split_whitespace();
+    let method = words.next().unwrap_or_default();
+    !method.is_empty()
+        && method.chars().all(|c| c.is_ascii_uppercase())
+        && words
+            .last()
+            .is_some_and(|version| version.starts_with("HTTP/"))
+}
+
+/// Parses `addr` as an `IP:PORT` socket address, refusing anything that isn't a loopback address.
+pub fn parse_addr(addr: &str) -> Result<SocketAddr, SnapmeoError> {
+    let socket_addr: SocketAddr = addr.parse().map_err(|_| {
+        SnapmeoError::BadArguments(format!(
+            "Invalid listen address `{}`; expected something like `127.0.0.1:7332`",
+            addr
+        ))
+    })?;
+    if !socket_addr.ip().is_loopback() {
+        return Err(SnapmeoError::BadArguments(format!(
+            "Refusing to listen on `{}`; only loopback addresses like `127.0.0.1` are allowed",
+            addr
+        )));
+    }
+    Ok(socket_addr)
+}
+
+/// Runs one capture with `request_args` on top of `inherited_args` and returns the response to
+/// send back.
+fn run_capture(request_args: &[String], inherited_args: &[(String, String)]) -> serde_json::Value {
+    if let Err(err) = check_args(request_args) {
+        return json!({ "ok": false, "error": err });
+    }
+
+    let exe = match env::current_exe() {
+        Ok(exe) => exe,
+        Err(err) => return json!({ "ok": false, "error": err.to_string() }),
+    };
+
+    let mut command = Command::new(exe);
+    for (flag, value) in inherited_args {
+        command.arg(format!("--{}", flag)).arg(value);
+    }
+    command.args(request_args).arg("--json");
+
+    let output = match command.output() {
+        Ok(output) => output,
+        Err(err) => return json!({ "ok": false, "error": err.to_string() }),
+    };
+    if !output.status.success() {
+        return json!({
+            "ok": false,
+            "exit_code": output.status.code(),
+            "error": String::from_utf8_lossy(&output.stderr).trim(),
+        });
+    }
+
+    // Progress messages can come before the JSON, which is always the last line
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    let result = stdout
+        .lines()
+        .rev()
+        .find_map(|line| serde_json::from_str::<serde_json::Value>(line).ok())
+        .unwrap_or(serde_json::Value::Null);
+    json!({ "ok": true, "result": result })
+}
+
+fn handle_connection(
+    stream: TcpStream,
+    token: &str,
+    inherited_args: &[(String, String)],
+    cooldown: Duration,
+    last_capture: &mut Option<Instant>,
+) -> Result<(), SnapmeoError> {
+    let mut writer = stream.try_clone()?;
+    for line in BufReader::new(stream).lines() {
+        let line = line?;
+        if line.trim().is_empty() {
+            continue;
+        }
+        if looks_like_http(&line) {
+            eprintln!(
+                "{} Dropped a connection that sent an HTTP request",
+                "Warning:".yellow()
+            );
+            return Ok(());
+        }
+
+        let response = match serde_json::from_str::<CaptureRequest>(&line) {
+            Ok(request) if !tokens_match(&request.token, token) => {
+                json!({ "ok": false, "error": "Missing or wrong token" })
+            }
+            Ok(_) if last_capture.is_some_and(|last| last.elapsed() < cooldown) => {
+                println!(
+                    "Ignoring a capture request that came within {}ms of the last capture",
+                    cooldown.as_millis()
+                );
+                json!({ "ok": false, "ignored": true, "error": "Too soon after the last capture" })
+            }
+            Ok(request) => {
+                let response = run_capture(&request.args, inherited_args);
+                *last_capture = Some(Instant::now());
+                response
+            }
+            Err(err) => json!({ "ok": false, "error": format!("Invalid request: {}", err) }),
+        };
+        writeln!(writer, "{}", response)?;
+    }
+    Ok(())
+}
+
+/// Listens on `addr` forever, taking a screenshot for every request with the right token other
+/// than those within `cooldown` of the last capture. The flags in `inherited_args` are passed to
+/// every capture.
+pub fn serve(
+    addr: SocketAddr,
+    inherited_args: Vec<(String, String)>,
+    cooldown: Duration,
+) -> Result<(), SnapmeoError> {
+    let (token, token_path) = load_token()?;
+    let listener = TcpListener::bind(addr)?;
+    println!(
+        "Listening for capture requests on {} with the token in {}",
+        addr,
+        token_path.display()
+    );
+
+    let mut last_capture = None;
+    for stream in listener.incoming() {
+        let result = stream.map_err(SnapmeoError::from).and_then(|stream| {
+            handle_connection(stream, &token, &inherited_args, cooldown, &mut last_capture)
+        });
+        // One client going away mid-request shouldn't take the service down for everyone else
+        if let Err(err) = result {
+            eprintln!("{} {}", "Connection error:".yellow(), err);
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn args(args: &[&str]) -> Vec<String> {
+        args.iter().map(|&arg| arg.to_owned()).collect()
+    }
+
+    #[test]
+    fn check_args_only_allows_capture_flags() {
+        assert!(check_args(&args(&[
+            "--region",
+            "0,0,800,600",
+            "--secret",
+            "--tag=work",
+            "--ocr",
+            "--transparent-window-capture=white",
+            "--region=-10,0,800,600",
+        ]))
+        .is_ok());
+        for rejected in [
+            &["--upload-url", "https://example.com"][..],
+            &["--post-command=rm -rf ~"],
+            &["--config", "other.toml"],
+            &["-e", "7d"],
+            &["config"],
+            &["--secret=1"],
+            &["--region"],
+            &["--region", "--post-command=sh -c true"],
+            &["--ocr", "--post-command=sh -c true"],
+            &["--ocr", "--upload-url=http://example.com"],
+            &["--hold-to-select", "--upload-url", "http://example.com"],
+            &["--ocr", "text"],
+        ] {
+            assert!(check_args(&args(rejected)).is_err(), "{:?}", rejected);
+        }
+    }
+
+    #[test]
+    fn looks_like_http_spots_request_lines() {
+        assert!(looks_like_http("POST / HTTP/1.1"));
+        assert!(looks_like_http("GET /capture?x=1 HTTP/1.0"));
+        assert!(!looks_like_http(r#"{"token": "abc", "args": []}"#));
+        assert!(!looks_like_http("POST"));
+    }
+}
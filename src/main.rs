@@ -1,70 +1,984 @@
+extern crate base64;
 extern crate chrono;
 extern crate clap;
 extern crate clipboard;
 extern crate colored;
+extern crate flate2;
+extern crate image;
 extern crate repng;
 extern crate reqwest;
 extern crate scrap;
+#[cfg(feature = "interactive")]
 extern crate sdl2;
+extern crate serde;
+extern crate serde_json;
+extern crate toml;
+
+mod annotate;
+mod buffer;
+mod chain;
+mod changes;
+mod config;
+mod cursor;
+mod dotenv;
+mod encode;
+mod font;
+#[cfg(feature = "interactive")]
+mod gallery;
+mod history;
+mod hold;
+mod image_clipboard;
+mod listen;
+mod notify;
+mod ocr;
+#[cfg(feature = "interactive")]
+mod pin;
+mod prompt;
+mod record;
+mod schedule;
+#[cfg(feature = "interactive")]
+mod scroll;
+#[cfg(feature = "interactive")]
+mod selection;
+#[cfg(feature = "interactive")]
+mod settings;
+mod since_last;
+#[cfg(feature = "interactive")]
+mod snap;
+#[cfg(feature = "interactive")]
+mod sound;
+mod template;
+mod upload;
+mod window;
 
 use std::cmp;
 use std::env;
-use std::error::Error;
-use std::fs::File;
-use std::io::ErrorKind;
-use std::path::Path;
-use std::process::exit;
-use std::thread;
-use std::time::Duration;
+use std::fs;
+use std::io::{self, ErrorKind, IsTerminal, Read};
+use std::iter;
+use std::path::{Path, PathBuf};
+use std::process::{exit, Command};
+use std::str::FromStr;
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant, SystemTime};
 
+use chrono::format::{Item, StrftimeItems};
 use chrono::prelude::*;
-use clap::{App, Arg};
+use clap::{App, Arg, ArgMatches, SubCommand};
 use clipboard::{ClipboardContext, ClipboardProvider};
 use colored::*;
-use scrap::{Capturer, Display, Frame};
-use sdl2::event::Event;
-use sdl2::keyboard::Keycode;
-use sdl2::pixels::PixelFormatEnum;
+#[cfg(feature = "interactive")]
+use sdl2::{
+    event::Event,
+    keyboard::Keycode,
+    pixels::{Color, PixelFormatEnum},
+    rect::Rect,
+    render::{Canvas, Texture},
+    video::Window,
+    EventPump, Sdl, VideoSubsystem,
+};
+
+use snapmeo::{capture, error};
+
+use crate::buffer::{Anchor, Backdrop, Border, Region};
+use crate::config::{Config, PresetRegion, TimestampBar};
+use crate::cursor::CursorImage;
+use crate::encode::{EncodeOptions, Format, Subsampling};
+use crate::error::{SnapmeoError, EXIT_SUCCESS};
+use crate::hold::HoldKey;
+#[cfg(feature = "interactive")]
+use crate::hold::HoldWatcher;
+use crate::image_clipboard::Flavor;
+use crate::ocr::OcrMode;
+use crate::record::Recording;
+use crate::upload::queue::{self, QueuedUpload};
+use crate::upload::s3::S3Uploader;
+use crate::upload::sftp::SftpUploader;
+use crate::upload::{AmeoTrack, Expiry, UploadOptions, Uploader};
+use crate::window::{Decorations, WindowInfo};
+
+/// How many times the password is asked for again when the backend rejects it
+const PASSWORD_ATTEMPTS: usize = 3;
+
+/// What to do with a capture once the user has confirmed it
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum ConfirmAction {
+    Upload,
+    CopyImage,
+    Cancel,
+}
+
+/// Opacity of the capture window while counting down so that the live screen shows through it
+#[cfg(feature = "interactive")]
+const COUNTDOWN_OVERLAY_OPACITY: f32 = 0.6;
+
+/// Draws the number of seconds remaining until capture in large text over a dimmed view of the
+/// live screen, re-rendering every frame until `delay` has elapsed. Returns `false` if the user
+/// cancelled the capture during the countdown.
+///
+/// The window is made translucent rather than showing captured frames since the capture window
+/// would otherwise end up capturing itself.
+#[cfg(feature = "interactive")]
+fn count_down(
+    canvas: &mut Canvas<Window>,
+    event_pump: &mut EventPump,
+    delay: Duration,
+    one_frame: Duration,
+) -> bool {
+    canvas.window_mut().show();
+    if let Err(err) = canvas.window_mut().set_opacity(COUNTDOWN_OVERLAY_OPACITY) {
+        // Without a compositor the overlay would just be an opaque black screen, so fall back to
+        // counting down on the console instead.
+        eprintln!("Unable to render countdown overlay: {}", err);
+        canvas.window_mut().hide();
+    }
+
+    let (width, height) = canvas.output_size().expect("Unable to get window size!");
+    let scale = cmp::max(height / 4 / font::GLYPH_HEIGHT, 1);
+    let start = Instant::now();
+    let mut last_printed_secs = 0;
+
+    while let Some(remaining) = delay.checked_sub(start.elapsed()) {
+        for event in event_pump.poll_iter() {
+            match event {
+                Event::Quit { .. }
+                | Event::KeyDown {
+                    keycode: Some(Keycode::Escape),
+                    ..
+                } => return false,
+                _ => {}
+            }
+        }
+
+        let remaining_secs = (remaining.as_millis() as u64).div_ceil(1000);
+        if remaining_secs != last_printed_secs {
+            eprintln!("Capturing in {}...", remaining_secs);
+            last_printed_secs = remaining_secs;
+        }
+
+        let text = remaining_secs.to_string();
+        let (text_width, text_height) = font::text_size(&text, scale);
+        canvas.set_draw_color(Color::RGB(0, 0, 0));
+        canvas.clear();
+        canvas.set_draw_color(Color::RGB(255, 255, 255));
+        font::draw_text(
+            canvas,
+            &text,
+            (width as i32 - text_width as i32) / 2,
+            (height as i32 - text_height as i32) / 2,
+            scale,
+        )
+        .expect("Render failed");
+        canvas.present();
+
+        thread::sleep(one_frame);
+    }
+
+    // Get the overlay out of the way before the frame that will be frozen is captured
+    canvas.window_mut().hide();
+    let _ = canvas.window_mut().set_opacity(1.0);
+    thread::sleep(one_frame * 4);
+
+    true
+}
+
+/// What to do when a file already exists where a screenshot is going to be saved
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum OnExists {
+    Error,
+    Overwrite,
+    Rename,
+}
+
+impl FromStr for OnExists {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "error" => Ok(OnExists::Error),
+            "overwrite" => Ok(OnExists::Overwrite),
+            "rename" => Ok(OnExists::Rename),
+            _ => Err(format!(
+                "Invalid `--on-exists` policy `{}`; expected one of `error`, `overwrite`, or `rename`",
+                s
+            )),
+        }
+    }
+}
+
+/// How `--compare` shows the before and after captures
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum CompareMode {
+    SideBySide,
+    /// The after capture with what changed highlighted
+    Diff,
+}
+
+impl FromStr for CompareMode {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "side-by-side" => Ok(CompareMode::SideBySide),
+            "diff" => Ok(CompareMode::Diff),
+            _ => Err(format!(
+                "Invalid `--compare` mode `{}`; expected `side-by-side` or `diff`",
+                s
+            )),
+        }
+    }
+}
+
+/// Pixels between the before and after captures of `--compare side-by-side`
+const COMPARE_GAP: usize = 8;
+/// Color that `--compare diff` highlights changed pixels with
+const COMPARE_HIGHLIGHT: [u8; 4] = [255, 0, 0, 128];
+
+/// Returns the path to save to instead of `path` according to `on_exists`, or an error if a file
+/// is already there and that's not allowed.
+fn output_path(path: &Path, on_exists: OnExists) -> Result<PathBuf, SnapmeoError> {
+    match on_exists {
+        OnExists::Rename => Ok(unique_path(path)),
+        OnExists::Overwrite => Ok(path.to_owned()),
+        OnExists::Error if path.exists() => Err(SnapmeoError::Io(io::Error::new(
+            ErrorKind::AlreadyExists,
+            format!(
+                "`{}` already exists; pass `--on-exists overwrite` or `--on-exists rename` to save anyway",
+                path.display()
+            ),
+        ))),
+        OnExists::Error => Ok(path.to_owned()),
+    }
+}
+
+/// Returns `path` if nothing exists there yet, otherwise the first of `name (2).ext`,
+/// `name (3).ext`, ... that's free. Screenshot names only have a resolution of one second, so
+/// rapid captures would otherwise overwrite each other.
+fn unique_path(path: &Path) -> PathBuf {
+    if !path.exists() {
+        return path.to_owned();
+    }
+
+    let stem = path
+        .file_stem()
+        .map(|stem| stem.to_string_lossy().into_owned())
+        .unwrap_or_default();
+    let extension = path
+        .extension()
+        .map(|extension| format!(".{}", extension.to_string_lossy()))
+        .unwrap_or_default();
+    (2..)
+        .map(|i| path.with_file_name(format!("{} ({}){}", stem, i, extension)))
+        .find(|candidate| !candidate.exists())
+        .unwrap()
+}
+
+/// Returns the path next to `path` that its thumbnail is saved at.
+fn thumbnail_path(path: &Path) -> PathBuf {
+    let stem = path
+        .file_stem()
+        .map(|stem| stem.to_string_lossy().into_owned())
+        .unwrap_or_default();
+    let extension = path
+        .extension()
+        .map(|extension| format!(".{}", extension.to_string_lossy()))
+        .unwrap_or_default();
+    path.with_file_name(format!("{} thumbnail{}", stem, extension))
+}
+
+/// Reads a region in the format accepted by `buffer::parse_region` from the file at `path`, or
+/// from stdin if `path` is `-`.
+fn read_selection_file(path: &str) -> Result<(i64, i64, usize, usize), SnapmeoError> {
+    let contents = if path == "-" {
+        let mut contents = String::new();
+        io::stdin().read_to_string(&mut contents).map(|_| contents)
+    } else {
+        fs::read_to_string(path)
+    }
+    .map_err(|err| {
+        SnapmeoError::BadArguments(format!(
+            "Unable to read the selection from `{}`: {}",
+            path, err
+        ))
+    })?;
+
+    buffer::parse_region(&contents).map_err(SnapmeoError::BadArguments)
+}
+
+/// Returns the index and bounds of the display the mouse cursor is on, so that the selection
+/// overlay can follow whichever display is being used, or `None` if it can't be found.
+#[cfg(feature = "interactive")]
+fn cursor_display(video_subsystem: &VideoSubsystem) -> Option<(usize, Rect)> {
+    let (x, y) = window::cursor_position().ok()?;
+    let display_count = video_subsystem.num_video_displays().ok()?;
+    (0..display_count).find_map(|index| {
+        let bounds = video_subsystem.display_bounds(index).ok()?;
+        bounds
+            .contains_point((x as i32, y as i32))
+            .then_some((index as usize, bounds))
+    })
+}
+
+/// Waits for the image that `--fast-paste` is copying to be on the clipboard, returning whether it
+/// made it there.
+fn finish_fast_paste(copy: &mut Option<JoinHandle<Result<(), SnapmeoError>>>) -> bool {
+    match copy.take().map(JoinHandle::join) {
+        Some(Ok(Ok(()))) => true,
+        Some(Ok(Err(err))) => {
+            eprintln!(
+                "{} Unable to copy the image before uploading: {}",
+                "Warning:".yellow(),
+                err
+            );
+            false
+        }
+        Some(Err(_)) | None => false,
+    }
+}
+
+/// Returns the work area of the display at `index`, or of the primary display if that's `None`,
+/// which leaves out panels and taskbars, in the pixels of a capture of it of size `bounds`. Returns
+/// `None` if the platform doesn't say what it is.
+#[cfg(feature = "interactive")]
+fn work_area(
+    video_subsystem: &VideoSubsystem,
+    index: Option<i32>,
+    bounds: (usize, usize),
+) -> Option<Region> {
+    let index = match index {
+        Some(index) => index,
+        // The primary display is the one at the origin, as long as it's what was captured rather
+        // than one of several displays that the capture spans
+        None => (0..video_subsystem.num_video_displays().ok()?).find(|&index| {
+            video_subsystem.display_bounds(index).is_ok_and(|display| {
+                display.contains_point((0, 0))
+                    && display.width() as u64 * bounds.1 as u64
+                        == display.height() as u64 * bounds.0 as u64
+            })
+        })?,
+    };
+    let display = video_subsystem.display_bounds(index).ok()?;
+    let usable = video_subsystem.display_usable_bounds(index).ok()?;
+    if display.width() == 0 || display.height() == 0 {
+        return None;
+    }
+    // SDL gives both in points, which are larger than pixels on HiDPI displays
+    let (x_factor, y_factor) = (
+        bounds.0 as f64 / display.width() as f64,
+        bounds.1 as f64 / display.height() as f64,
+    );
+    Region::clamped(
+        ((usable.x() - display.x()) as f64 * x_factor).round() as i64,
+        ((usable.y() - display.y()) as f64 * y_factor).round() as i64,
+        (usable.width() as f64 * x_factor).round() as usize,
+        (usable.height() as f64 * y_factor).round() as usize,
+        bounds,
+    )
+}
+
+/// Shows the frozen frame in the capture window.
+#[cfg(feature = "interactive")]
+fn show_frozen_frame(canvas: &mut Canvas<Window>, texture: &Texture) {
+    canvas.clear();
+    canvas.copy(texture, None, None).expect("Render failed");
+    canvas.window_mut().show();
+    canvas.present();
+}
+
+/// How often the capturer is polled for a new frame while benchmarking. This is much shorter than
+/// a frame so that the time it takes for a frame to be ready is measured closely.
+const BENCHMARK_POLL_INTERVAL: Duration = Duration::from_millis(1);
+
+/// Runs `snapmeo gallery`, copying the URL of the upload that's picked.
+#[cfg(feature = "interactive")]
+fn gallery(json: bool) -> Result<(), SnapmeoError> {
+    let entries = history::load()?;
+    if entries.is_empty() {
+        return Err(SnapmeoError::Other(
+            "Nothing has been uploaded yet, so there's nothing to show".into(),
+        ));
+    }
+
+    let sdl_context = sdl2::init().map_err(sdl_error)?;
+    let video_subsystem = sdl_context.video().map_err(sdl_error)?;
+    let mut event_pump = sdl_context.event_pump().map_err(sdl_error)?;
+    let entry = gallery::pick(
+        &video_subsystem,
+        &mut event_pump,
+        &entries,
+        Duration::new(1, 0) / 60,
+    )?
+    .ok_or(SnapmeoError::Cancelled)?;
+
+    let mut ctx: ClipboardContext =
+        ClipboardProvider::new().map_err(|err| SnapmeoError::Clipboard(err.to_string()))?;
+    ctx.set_contents(entry.url.clone())
+        .map_err(|err| SnapmeoError::Clipboard(err.to_string()))?;
+    if json {
+        println!("{}", serde_json::json!({ "url": entry.url }));
+    } else {
+        println!("{} has been copied to the clipboard", entry.url);
+    }
+    Ok(())
+}
+
+/// Runs `snapmeo history`, listing the uploads with all of the given tags.
+fn list_history(matches: &ArgMatches, json: bool) -> Result<(), SnapmeoError> {
+    let tags = history::parse_tags(matches.values_of("tag").into_iter().flatten())
+        .map_err(SnapmeoError::BadArguments)?;
+    let entries: Vec<_> = history::load()?
+        .into_iter()
+        .filter(|entry| entry.has_tags(&tags))
+        .collect();
+    if json {
+        println!("{}", serde_json::json!(entries));
+        return Ok(());
+    }
+    if entries.is_empty() {
+        println!("No uploads match.");
+    }
+    for entry in &entries {
+        print!(
+            "{}  {}  {}",
+            entry.uploaded_at,
+            entry.url,
+            entry.path.display()
+        );
+        if !entry.tags.is_empty() {
+            print!("  [{}]", entry.tags.join(", "));
+        }
+        println!();
+    }
+    Ok(())
+}
+
+/// Runs the `benchmark` subcommand: grabs full frames back-to-back for a fixed duration or number
+/// of frames, then reports the frame rate achieved and how long each grab took, without encoding
+/// or uploading anything.
+fn benchmark(matches: &ArgMatches, json: bool) -> Result<(), SnapmeoError> {
+    let frame_limit: Option<u64> = matches
+        .value_of("frames")
+        .map(str::parse)
+        .transpose()
+        .map_err(|_| {
+            SnapmeoError::BadArguments("The number of frames must be a whole number".into())
+        })?;
+    let duration = matches
+        .value_of("duration")
+        .map(str::parse)
+        .transpose()
+        .map_err(|_| {
+            SnapmeoError::BadArguments("The duration must be a whole number of seconds".into())
+        })?
+        .map(Duration::from_secs)
+        .unwrap_or(Duration::from_secs(5));
+
+    let mut capturer = capture::open_primary()?;
+    let (width, height) = (capturer.width(), capturer.height());
+    if !json {
+        match frame_limit {
+            Some(frames) => println!("Capturing {} frames at {}x{}...", frames, width, height),
+            None => println!(
+                "Capturing frames at {}x{} for {} seconds...",
+                width,
+                height,
+                duration.as_secs()
+            ),
+        }
+    }
+
+    let mut latencies: Vec<Duration> = Vec::new();
+    let start = Instant::now();
+    loop {
+        let done = match frame_limit {
+            Some(frames) => latencies.len() as u64 >= frames,
+            None => start.elapsed() >= duration,
+        };
+        if done {
+            break;
+        }
+
+        let grab_start = Instant::now();
+        capture::grab_frame(
+            &mut capturer,
+            capture::open_primary,
+            BENCHMARK_POLL_INTERVAL,
+            capture::FRAME_TIMEOUT,
+        )?;
+        latencies.push(grab_start.elapsed());
+    }
+    let elapsed = start.elapsed();
+
+    let frame_count = latencies.len();
+    let fps = frame_count as f64 / elapsed.as_secs_f64();
+    let millis = |duration: Duration| duration.as_secs_f64() * 1000.;
+    let average_ms = if frame_count == 0 {
+        0.
+    } else {
+        millis(latencies.iter().sum::<Duration>()) / frame_count as f64
+    };
+    let min_ms = latencies
+        .iter()
+        .min()
+        .map_or(0., |&latency| millis(latency));
+    let max_ms = latencies
+        .iter()
+        .max()
+        .map_or(0., |&latency| millis(latency));
+
+    if json {
+        println!(
+            "{}",
+            serde_json::json!({
+                "width": width,
+                "height": height,
+                "frames": frame_count,
+                "seconds": elapsed.as_secs_f64(),
+                "fps": fps,
+                "average_grab_ms": average_ms,
+                "min_grab_ms": min_ms,
+                "max_grab_ms": max_ms,
+            })
+        );
+    } else {
+        println!(
+            "Captured {} frames in {:.2}s: {:.1} FPS",
+            frame_count,
+            elapsed.as_secs_f64(),
+            fps
+        );
+        println!(
+            "Frame grab latency: {:.2}ms average, {:.2}ms min, {:.2}ms max",
+            average_ms, min_ms, max_ms
+        );
+    }
+    Ok(())
+}
+
+/// Makes sure the encoded image at `path` is no larger than `max_bytes` before it's uploaded,
+/// either scaling it down to fit if `auto_reduce` is set or refusing to upload it. With `force`
+/// and no `auto_reduce`, it's only warned about.
+#[allow(clippy::too_many_arguments)]
+fn check_upload_size(
+    buffer: &[u8],
+    width: usize,
+    height: usize,
+    options: &EncodeOptions,
+    path: &Path,
+    max_bytes: u64,
+    auto_reduce: bool,
+    force: bool,
+) -> Result<(), SnapmeoError> {
+    let size = fs::metadata(path)?.len();
+    if size <= max_bytes {
+        return Ok(());
+    }
+    if force && !auto_reduce {
+        warn_oversized(size, max_bytes);
+        return Ok(());
+    }
+    if !auto_reduce {
+        return Err(SnapmeoError::Upload {
+            status: None,
+            message: format!(
+                "The encoded image is {} bytes, which is over the limit of {} bytes; pass `--auto-reduce` to scale it down to fit",
+                size, max_bytes
+            ),
+        });
+    }
+
+    let (scaled_width, scaled_height) =
+        encode::shrink_to_fit(buffer, width, height, options, path, max_bytes)?;
+    eprintln!(
+        "Scaled the image down from {}x{} to {}x{} to fit within {} bytes",
+        width, height, scaled_width, scaled_height, max_bytes
+    );
+
+    Ok(())
+}
+
+fn warn_oversized(size: u64, max_bytes: u64) {
+    eprintln!(
+        "{} The image is {} bytes, which is over the limit of {} bytes; uploading it anyway because of `--force`",
+        "Warning:".yellow(),
+        size,
+        max_bytes
+    );
+}
+
+/// Replaces the pixels of a `buffer` captured from `region` of the screen that lie within `window`
+/// with the window's own `pixels` from `window::capture_with_alpha`, composited over `backdrop`.
+fn apply_window_alpha(
+    buffer: &mut [u8],
+    region: &Region,
+    window: &WindowInfo,
+    pixels: &[u8],
+    backdrop: Backdrop,
+) {
+    let composited = buffer::composite_over(pixels, window.width, backdrop);
+    for y in 0..region.height {
+        let window_y = (region.y + y) as i64 - window.y;
+        if window_y < 0 || window_y >= window.height as i64 {
+            continue;
+        }
+        for x in 0..region.width {
+            let window_x = (region.x + x) as i64 - window.x;
+            if window_x < 0 || window_x >= window.width as i64 {
+                continue;
+            }
+            let from = (window_y as usize * window.width + window_x as usize) * 4;
+            let to = (y * region.width + x) * 4;
+            buffer[to..to + 4].copy_from_slice(&composited[from..from + 4]);
+        }
+    }
+}
+
+/// Pixelates the parts of a `buffer` captured from `region` of the screen that overlap any of the
+/// `masks`, which are `(x, y, width, height)` in screen pixels.
+fn apply_masks(buffer: &mut [u8], region: &Region, masks: &[(i64, i64, usize, usize)]) {
+    for &(x, y, width, height) in masks {
+        let left = cmp::max(x, region.x as i64);
+        let top = cmp::max(y, region.y as i64);
+        let right = cmp::min(x + width as i64, (region.x + region.width) as i64);
+        let bottom = cmp::min(y + height as i64, (region.y + region.height) as i64);
+        if left >= right || top >= bottom {
+            continue;
+        }
+
+        let to_buffer = |screen_x: i64, screen_y: i64| {
+            (
+                (screen_x - region.x as i64) as i32,
+                (screen_y - region.y as i64) as i32,
+            )
+        };
+        annotate::pixelate(
+            buffer,
+            region.width,
+            region.height,
+            to_buffer(left, top),
+            to_buffer(right, bottom),
+        );
+    }
+}
+
+/// Returns the name of this computer, if it can be found.
+fn hostname() -> Option<String> {
+    let output = Command::new("hostname").output().ok()?;
+    let hostname = String::from_utf8_lossy(&output.stdout).trim().to_owned();
+    Some(hostname).filter(|hostname| output.status.success() && !hostname.is_empty())
+}
+
+/// Runs the `--post-command` `command` through the shell with `path` added as its last argument,
+/// returning an error if it fails or `path` is gone afterwards.
+fn run_post_command(command: &str, path: &Path) -> Result<(), SnapmeoError> {
+    let status = if cfg!(windows) {
+        Command::new("cmd")
+            .arg("/C")
+            .arg(format!("{} \"{}\"", command, path.display()))
+            .status()
+    } else {
+        // Passing the path as a positional parameter spares it from being parsed by the shell
+        Command::new("sh")
+            .arg("-c")
+            .arg(format!("{} \"$1\"", command))
+            .arg("snapmeo")
+            .arg(path)
+            .status()
+    }
+    .map_err(|err| SnapmeoError::Other(format!("Unable to run `{}`: {}", command, err)))?;
+
+    if !status.success() {
+        return Err(SnapmeoError::Other(match status.code() {
+            Some(code) => format!("`{}` failed with exit code {}", command, code),
+            None => format!("`{}` was killed before it finished", command),
+        }));
+    }
+    if !path.is_file() {
+        return Err(SnapmeoError::Other(format!(
+            "`{}` didn't leave a file at {}",
+            command,
+            path.display()
+        )));
+    }
+    Ok(())
+}
+
+/// Adds a bar to the bottom of an RGBA buffer with the time it was captured at and optionally the
+/// computer's hostname written in it.
+fn add_timestamp_bar(
+    buffer: &[u8],
+    width: usize,
+    height: usize,
+    captured_at: DateTime<Local>,
+    style: &TimestampBar,
+) -> (Vec<u8>, usize, usize) {
+    let mut text = captured_at.format("%Y-%m-%d %H:%M:%S %:z").to_string();
+    if let Some(hostname) = hostname().filter(|_| style.hostname) {
+        text = format!("{}  {}", text, hostname);
+    }
+
+    buffer::append_caption(
+        buffer,
+        width,
+        height,
+        &text,
+        style.font_scale,
+        style.background,
+        style.foreground,
+    )
+}
+
+/// Sets up the upload backend called `name`, as given to `--uploader` or `--broadcast`.
+fn make_uploader(name: &str, upload_url: &str) -> Result<Box<dyn Uploader>, SnapmeoError> {
+    Ok(match name {
+        "ameotrack" => Box::new(AmeoTrack {
+            upload_url: upload_url.into(),
+        }),
+        "s3" => Box::new(S3Uploader::from_env()?),
+        "sftp" => Box::new(SftpUploader::from_env()?),
+        other => {
+            return Err(SnapmeoError::BadArguments(format!(
+                "Unknown uploader `{}`; expected `ameotrack`, `s3`, or `sftp`",
+                other
+            )))
+        }
+    })
+}
+
+/// Placeholders that `--filename-template` can use
+const FILENAME_PLACEHOLDERS: [&str; 2] = ["date", "window"];
 
-const AMEOTRACK_UPLOAD_URL: &str = "https://ameo.link/u/upload";
+/// What screenshots are named when `--filename-template` isn't given
+const DEFAULT_FILENAME_TEMPLATE: &str = "Screenshot at {date}";
 
-fn get_capturer() -> Capturer {
-    let display = Display::primary().expect("Couldn't find primary display.");
-    Capturer::new(display).expect("Couldn't begin capture.")
+/// Checks that `filename_template` is a well formed template whose `%` codes strftime knows.
+fn validate_filename_template(filename_template: &str) -> Result<(), SnapmeoError> {
+    template::validate(filename_template, &FILENAME_PLACEHOLDERS)
+        .map_err(SnapmeoError::BadArguments)?;
+    if StrftimeItems::new(filename_template).any(|item| item == Item::Error) {
+        return Err(SnapmeoError::BadArguments(format!(
+            "Invalid `%` code in the filename template `{}`; write `%%` for a literal `%`",
+            filename_template
+        )));
+    }
+    Ok(())
+}
+
+/// Names a screenshot taken at `time` according to `filename_template`, without its extension.
+/// The `%` codes are filled in before the placeholders so that `%`s in window titles are left as
+/// they are.
+fn render_filename(
+    filename_template: &str,
+    time: &DateTime<Local>,
+    window_title: Option<&str>,
+) -> Result<String, SnapmeoError> {
+    let dated = time.format(filename_template).to_string();
+    template::render(&dated, |name| match name {
+        "date" => Some(time.format("%b %m %H-%M-%S").to_string()),
+        "window" => Some(
+            window_title
+                .map(str::trim)
+                .filter(|title| !title.is_empty())
+                .unwrap_or("Screenshot")
+                .to_owned(),
+        ),
+        _ => None,
+    })
+    .map_err(SnapmeoError::BadArguments)
+}
+
+/// Placeholders that `--clipboard-template` can use
+const CLIPBOARD_PLACEHOLDERS: [&str; 9] = [
+    "url", "id", "width", "height", "date", "path", "name", "text", "region",
+];
+
+/// Formats `region` as `X,Y,WIDTH,HEIGHT` like `--region` takes.
+fn format_region(region: &Region) -> String {
+    format!(
+        "{},{},{},{}",
+        region.x, region.y, region.width, region.height
+    )
+}
+
+/// Returns the last segment of the path of an uploaded image's `url` without its extension, which
+/// is how hosts usually identify images.
+fn upload_id(url: &str) -> Option<String> {
+    let path = url.split(['?', '#']).next()?.trim_end_matches('/');
+    let segment = path
+        .rsplit('/')
+        .next()
+        .filter(|segment| !segment.is_empty())?;
+    let id = match segment.rfind('.') {
+        Some(ix) if ix > 0 => &segment[..ix],
+        _ => segment,
+    };
+    Some(id.to_owned())
+}
+
+/// How long `--retry-queue` waits between attempts while the upload backend is unreachable
+const QUEUE_RETRY_INTERVAL: Duration = Duration::from_secs(30);
+
+/// Retries the uploads queued for `uploader`, reporting how each went. Returns a JSON object for
+/// each one that succeeded; in JSON mode, nothing is printed for those.
+fn flush_upload_queue(
+    uploader: &dyn Uploader,
+    json: bool,
+) -> Result<Vec<serde_json::Value>, SnapmeoError> {
+    let mut uploaded = Vec::new();
+    for (upload, result) in queue::flush(uploader)? {
+        match result {
+            Ok(url) => {
+                if !json {
+                    println!(
+                        "{} {} -> {}",
+                        "Queued upload succeeded:".green(),
+                        upload.path.display(),
+                        url
+                    );
+                }
+                uploaded.push(serde_json::json!({ "path": upload.path, "url": url }));
+            }
+            Err(err) if queue::is_unreachable(&err) => {
+                if !json {
+                    println!(
+                        "{} is still unreachable; keeping the remaining uploads queued",
+                        uploader.name()
+                    );
+                }
+            }
+            Err(err) => eprintln!(
+                "{} Dropped {} from the upload queue: {}",
+                "Error:".red(),
+                upload.path.display(),
+                err
+            ),
+        }
+    }
+
+    Ok(uploaded)
+}
+
+/// Describes a setting's effective value and which of `default`, `config`, `env`, or `cli` it came
+/// from, for `--json-config`.
+fn setting<T: serde::Serialize>(value: T, source: &str) -> serde_json::Value {
+    serde_json::json!({ "value": value, "source": source })
+}
+
+fn config_or_default<T>(value: &Option<T>) -> &'static str {
+    if value.is_some() {
+        "config"
+    } else {
+        "default"
+    }
+}
+
+fn hex_color(color: [u8; 4]) -> String {
+    format!(
+        "#{:02x}{:02x}{:02x}{:02x}",
+        color[0], color[1], color[2], color[3]
+    )
+}
+
+/// Returns the text on the clipboard, if there is any.
+fn clipboard_text() -> Option<String> {
+    ClipboardProvider::new()
+        .and_then(|mut ctx: ClipboardContext| ctx.get_contents())
+        .ok()
+}
+
+/// Waits for `delay` and then puts `previous` back on the clipboard, unless something other than
+/// the `copied` text snapmeo put there has been copied in the meantime.
+fn restore_clipboard(previous: String, copied: &str, delay: Duration) -> Result<(), SnapmeoError> {
+    thread::sleep(delay);
+    if clipboard_text().as_deref() != Some(copied) {
+        return Ok(());
+    }
+
+    let mut ctx: ClipboardContext =
+        ClipboardProvider::new().map_err(|err| SnapmeoError::Clipboard(err.to_string()))?;
+    ctx.set_contents(previous)
+        .map_err(|err| SnapmeoError::Clipboard(err.to_string()))
 }
 
-fn ameotrack_upload<P: AsRef<Path>>(
-    filename: P,
-    expiry: String,
-    secret: bool,
-    one_time: bool,
-) -> Result<String, Box<dyn Error>> {
-    let password = env::var("AMEOTRACK_PASSWORD")
-        .expect("The `AMEOTRACK_PASSWORD` environment variable must be set!");
+/// Selections end the same way whether they're cancelled or time out, so this tells the two apart
+/// by how long the selection that started at `start` went on for.
+fn selection_ended(start: Instant, timeout: Option<Duration>) -> SnapmeoError {
+    match timeout {
+        Some(timeout) if start.elapsed() >= timeout => SnapmeoError::SelectionTimedOut(timeout),
+        _ => SnapmeoError::Cancelled,
+    }
+}
 
-    let body = reqwest::blocking::multipart::Form::new()
-        .file("file", filename)?
-        .text("secret", if secret { "1" } else { "" })
-        .text("expiry", expiry)
-        .text("password", password)
-        .text("oneTime", if one_time { "1" } else { "" });
+/// The SDL window that selections, countdowns, and annotations are shown in. Scripted captures
+/// that don't need any of those skip creating it, so that they're quicker and work headless.
+#[cfg(feature = "interactive")]
+struct Overlay {
+    sdl_context: Sdl,
+    video_subsystem: VideoSubsystem,
+    canvas: Canvas<Window>,
+    event_pump: EventPump,
+}
 
-    let client = reqwest::blocking::Client::new();
-    let res = client.post(AMEOTRACK_UPLOAD_URL).multipart(body).send()?;
-    let status = res.status();
+/// Returns the overlay, or its frozen frame, for something that can only be reached when the
+/// overlay was created.
+#[cfg(feature = "interactive")]
+fn needed<T>(overlay: &mut Option<T>) -> Result<&mut T, SnapmeoError> {
+    overlay.as_mut().ok_or_else(|| {
+        SnapmeoError::Other("The overlay was skipped even though it's needed".into())
+    })
+}
 
-    let res_text = res
-        .text()
-        .expect("Unable to parse HTTP response into text!");
-    if !status.is_success() {
-        println!("Error uploading image to AmeoTrack: {:?}", res_text);
-        exit(1);
+/// Hides the overlay, if there is one, so that it doesn't get in the way of what comes next.
+#[cfg(feature = "interactive")]
+fn hide_overlay(overlay: &mut Option<Overlay>) {
+    if let Some(overlay) = overlay {
+        overlay.canvas.window_mut().hide();
     }
+}
 
-    Ok(res_text)
+/// SDL reports its errors as plain strings, none of which are the user's fault.
+#[cfg(feature = "interactive")]
+fn sdl_error<E: ToString>(err: E) -> SnapmeoError {
+    SnapmeoError::Other(err.to_string())
 }
 
+/// Builds without the `interactive` feature never have an overlay to hide.
+#[cfg(not(feature = "interactive"))]
+fn hide_overlay(_overlay: &mut Option<()>) {}
+
+/// The error for something that needs a window in a build without the `interactive` feature.
+#[cfg(not(feature = "interactive"))]
+fn not_interactive(what: &str) -> SnapmeoError {
+    SnapmeoError::BadArguments(format!(
+        "{} needs a window, but this build of snapmeo doesn't have the `interactive` feature. \
+         Rebuild it with the feature, or give the region to capture with `--region` or \
+         `--window`.",
+        what
+    ))
+}
+
+/// All exits go through here so that the exit code always reflects why snapmeo stopped.
 pub fn main() {
+    let code = match run() {
+        Ok(()) => EXIT_SUCCESS,
+        Err(err @ SnapmeoError::Cancelled) | Err(err @ SnapmeoError::SelectionTimedOut(_)) => {
+            println!("{}", err);
+            err.exit_code()
+        }
+        // Argument errors from clap already come formatted with their own prefix and usage
+        Err(err @ SnapmeoError::BadArguments(_)) => {
+            eprintln!("{}", err);
+            err.exit_code()
+        }
+        Err(err) => {
+            eprintln!("{} {}", "Error:".red(), err);
+            err.exit_code()
+        }
+    };
+    exit(code);
+}
+
+fn run() -> Result<(), SnapmeoError> {
     let matches = App::new("Snapmeo")
         .version("0.1.0")
         .author("Casey Primozic <me@ameo.link>")
@@ -73,8 +987,7 @@ pub fn main() {
             Arg::with_name("output_dir")
                 .short("o")
                 .long("output_dir")
-                .help("Directory into which screenshots will be saved")
-                .required(true)
+                .help("Directory into which screenshots will be saved. Required unless `output_dir` is set in the config file.")
                 .takes_value(true),
         )
         .arg(
@@ -88,152 +1001,2805 @@ pub fn main() {
             Arg::with_name("expiry")
                 .short("e")
                 .long("expiry")
-                .help("How long the image will be hosted before deletion, in days")
+                .help("How long the image will be hosted before deletion, such as `12h`, `7d`, or `never`. Plain numbers are a number of days.")
                 .takes_value(true),
         )
         .arg(
             Arg::with_name("one-time")
                 .short("b")
                 .long("one-time")
-                .help("If set, the image will be deleted as soon as it is viewed once.")
+                .help("If set, the image will be deleted as soon as it is viewed once. The selection always has to be confirmed like with `--confirm` before it's uploaded unless `--force` is given.")
                 .takes_value(false),
         )
-        .get_matches();
-
-    let local: DateTime<Local> = Local::now();
-    let date_string = local.format("%b %m %H-%M-%S").to_string();
-    let filename = format!("Screenshot at {}.png", date_string);
-    let filename = Path::new(matches.value_of("output_dir").unwrap()).join(filename);
-
-    // TODO: Parallelize with window creation + canvas setup
-    let mut capturer = get_capturer();
-    let one_second = Duration::new(1, 0);
-    let one_frame = one_second / 60;
-
-    let (width, height) = (capturer.width(), capturer.height());
-    println!("{:?}", (width, height));
-
-    loop {
-        let frame: Frame = match capturer.frame() {
-            Ok(buffer) => buffer,
-            Err(error) => {
-                if error.kind() == ErrorKind::WouldBlock {
-                    // Keep spinning.
-                    thread::sleep(one_frame);
-                    continue;
-                } else {
-                    panic!("Error: {}", error);
-                }
-            }
-        };
-        // println!("Captured screenshot frame!");
-
-        let sdl_context = sdl2::init().unwrap();
-        let video_subsystem = sdl_context.video().unwrap();
-
-        let window = video_subsystem
-            .window("rust-sdl2 demo: Video", width as u32, height as u32)
-            // .position_centered()
-            .opengl()
-            // .vulkan()
-            .allow_highdpi()
-            // .fullscreen_desktop()
-            .borderless()
-            .build()
-            .unwrap();
-
-        let mut canvas = window.into_canvas().build().unwrap();
-        let texture_creator = canvas.texture_creator();
-        // TODO: Pull this directly from the pixel buffer.  No reason not to.
-        // let texture = texture_creator.load_texture("output.png").unwrap();
-        let mut texture = texture_creator
-            .create_texture_static(Some(PixelFormatEnum::ARGB8888), width as u32, height as u32)
-            .expect("Unable to create texture!");
-        texture
-            .update(None, &*frame, width * 4)
-            .expect("Error updating texture with image data!");
-
-        // canvas.set_draw_color(Color::RGB(255, 0, 0));
-        canvas.clear();
-        canvas.copy(&texture, None, None).expect("Render failed");
-        canvas.present();
-        let mut event_pump = sdl_context.event_pump().unwrap();
-
-        let mut rect_corner_1: (i32, i32) = (0, 0);
-
-        let finish_screenshot = move |rect_corner_1: (i32, i32),
-                                      rect_corner_2: (i32, i32)|
-              -> Result<(), Box<dyn Error>> {
-            // println!("Corners: {:?}, {:?}", rect_corner_1, rect_corner_2);
-            let rect_width = (rect_corner_1.0 - rect_corner_2.0).abs() as usize;
-            let rect_height = (rect_corner_1.1 - rect_corner_2.1).abs() as usize;
-            let min_x = cmp::min(rect_corner_1.0, rect_corner_2.0) as usize;
-            let min_y = cmp::min(rect_corner_1.1, rect_corner_2.1) as usize;
-            let mut flip_buffer: Vec<u8> = Vec::with_capacity(rect_width * rect_height * 4);
-            let stride = width * 4;
-
-            for y in 0..rect_height {
-                for x in 0..rect_width {
-                    let i = (stride * (y + min_y)) + (4 * (x + min_x));
-                    flip_buffer.extend_from_slice(&[frame[i + 2], frame[i + 1], frame[i], 255]);
-                }
-            }
-
-            let file = File::create(filename.clone()).expect("Unable to create output file!");
-
-            repng::encode(file, rect_width as u32, rect_height as u32, &flip_buffer).unwrap();
-
-            let expiry = matches.value_of("expiry").unwrap_or("-1");
-            let secret = matches.is_present("secret");
-            let one_time = matches.is_present("one-time");
-
-            // Upload the image to AmeoTrack
-            println!("Image captured; starting upload to Ameotrack...");
-            let image_url = ameotrack_upload(filename, expiry.to_owned(), secret, one_time)?;
-
-            // Copy the URL to the clipboard and print to the console
-            let mut ctx: ClipboardContext =
-                ClipboardProvider::new().expect("Unable to create clipboard context!");
-            ctx.set_contents(image_url.clone())
-                .expect("Unable to set clipboard contents!");
-
-            println!("{} {}", "File successfully uploaded:".green(), image_url);
-            println!("Link has been copied to the clipboard.");
-
-            Ok(())
-        };
-
-        'running: loop {
-            for event in event_pump.poll_iter() {
-                match event {
-                    Event::Quit { .. }
-                    | Event::KeyDown {
-                        keycode: Some(Keycode::Escape),
-                        ..
-                    } => {
-                        break 'running;
-                    }
-                    Event::MouseButtonDown { x, y, .. } => {
-                        rect_corner_1 = (x, y);
-                    }
-                    Event::MouseButtonUp { x, y, .. } => {
-                        match finish_screenshot(rect_corner_1, (x, y)) {
-                            Ok(()) => (),
-                            Err(err) => {
-                                println!(
-                                    "An error occured during the screenshotting and uploading process: {:?}",
-                                    err
-                                );
-                            }
-                        };
-                        break 'running;
-                    }
-                    _ => {}
-                }
-            }
-            thread::sleep(one_frame);
-        }
-
-        break;
-    }
+        .arg(
+            Arg::with_name("delay")
+                .short("d")
+                .long("delay")
+                .help("Number of seconds to count down before freezing the screen for selection")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("at")
+                .long("at")
+                .help("Wait until the clock reads this time, given as `HH:MM` or `HH:MM:SS`, before doing anything else. Times that have already passed today mean tomorrow.")
+                .takes_value(true)
+                .value_name("TIME")
+                .conflicts_with_all(&["in", "from-clipboard", "listen"]),
+        )
+        .arg(
+            Arg::with_name("in")
+                .long("in")
+                .help("Wait this long, such as `90s`, `30m`, or `1h30m`, before doing anything else. Unlike `--delay`, nothing is shown on screen until the wait is over.")
+                .takes_value(true)
+                .value_name("DURATION")
+                .conflicts_with_all(&["from-clipboard", "listen"]),
+        )
+        .arg(
+            Arg::with_name("compare")
+                .long("compare")
+                .help("After capturing the region, wait for enter to be pressed in the terminal, then capture it again and combine the two to show what changed. With `side-by-side` (the default), the before and after captures are placed next to each other; with `diff`, the after capture is shown with the pixels that changed highlighted.")
+                .takes_value(true)
+                .min_values(0)
+                .value_name("MODE")
+                .conflicts_with_all(&["annotate", "scroll", "recapture", "from-clipboard"]),
+        )
+        .arg(
+            Arg::with_name("recapture")
+                .long("recapture")
+                .help("Count down the `--delay` after the region has been selected rather than before, then capture the same region again from the screen as it is then. Useful for capturing things like tooltips that only appear after a while.")
+                .requires("delay")
+                .conflicts_with_all(&["live", "scroll"])
+                .takes_value(false),
+        )
+        .arg(
+            Arg::with_name("scroll")
+                .long("scroll")
+                .help("After selecting a region, capture it repeatedly while scrolling its contents and stitch the captures into one tall image")
+                .takes_value(false),
+        )
+        .arg(
+            Arg::with_name("since-last")
+                .long("since-last")
+                .help("For timelapses taken by running snapmeo repeatedly, report how long it's been since the previous capture and since the first capture of the session, in the output, the file name, and `--json` output as `since_last_secs` and `session_secs`. A capture more than an hour after the previous one starts a new session.")
+                .takes_value(false),
+        )
+        .arg(
+            Arg::with_name("only-changed-region")
+                .long("only-changed-region")
+                .help("Only save and upload the part of the capture that changed since the previous capture of the same region, such as when capturing the same area repeatedly to monitor it. The whole region is used when there's no previous capture or when more than half of it changed. The part's position is in the `--json` output as `region`.")
+                .conflicts_with_all(&["scroll", "compare", "annotate", "record", "from-clipboard"])
+                .takes_value(false),
+        )
+        .arg(
+            Arg::with_name("record")
+                .long("record")
+                .value_name("DURATION")
+                .help("After selecting a region, record it for this long, such as `10s` or `1m`, and save and upload the recording as an animated PNG instead of a screenshot. Only works with the `png` format.")
+                .conflicts_with_all(&["scroll", "compare", "annotate", "recapture", "ocr", "from-clipboard"])
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("monitor-capture-fps")
+                .long("monitor-capture-fps")
+                .value_name("FPS")
+                .help("How many frames per second `--record` captures, which can be lowered to make recordings smaller and use less CPU. Defaults to 10.")
+                .requires("record")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("playback-fps")
+                .long("playback-fps")
+                .value_name("FPS")
+                .help("How many frames per second `--record` recordings play back at, if not in real time. Playing back faster than `--monitor-capture-fps` speeds the recording up, like a timelapse.")
+                .requires("record")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("annotate")
+                .long("annotate")
+                .help("After selecting a region, draw rectangles, arrows, highlights, and blurred areas over it before it's saved. Their colors can be set with `annotation_color` and `highlight_color` in the config file.")
+                .conflicts_with("scroll")
+                .takes_value(false),
+        )
+        .arg(
+            Arg::with_name("padding")
+                .long("padding")
+                .help("Number of pixels of background to add around every side of the captured image")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("background")
+                .long("background")
+                .help("Hex color such as `#ffffff` or `#00000080` to fill padding with. Defaults to transparent.")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("border")
+                .long("border")
+                .help("Draw a solid frame this many pixels wide around the captured image, outside of any padding")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("border-color")
+                .long("border-color")
+                .help("Hex color of the `--border` frame. Defaults to `#808080`.")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("shadow")
+                .long("shadow")
+                .help("Draw a drop shadow with this blur radius in pixels behind the captured image and its border. The image is extended with transparency to make room for it.")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("confirm")
+                .long("confirm")
+                .help("After selecting a region, wait for enter to upload it or `C` to copy the image to the clipboard without uploading")
+                .takes_value(false),
+        )
+        .arg(
+            Arg::with_name("format")
+                .short("f")
+                .long("format")
+                .help("Image format to save and upload the screenshot as: `png` (default), `bmp`, `ico`, `jpeg`, `avif`, or `svg`. ICO images are scaled to fit a standard icon size. SVG images embed the capture as a PNG with any `--annotate` rectangles, arrows, and highlights layered over it as editable shapes. AVIF requires building with the `avif` feature. Several formats can be given separated by commas, such as `png,jpeg`, to save a copy in each; only the `--upload-format` one is uploaded.")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("on-exists")
+                .long("on-exists")
+                .help("What to do when a file already exists where the screenshot would be saved: `rename` (default) to add a counter like ` (2)` to the name, `overwrite` to replace it (the default with `--force`), or `error` to fail without saving")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("auto-format")
+                .long("auto-format")
+                .help("Pick the format from what was captured: `jpeg` for photos and other content with lots of colors and gradients, where it's much smaller, or `png` for text, windows, and other flat content, where it's sharper. `--verbose` shows which was picked.")
+                .conflicts_with_all(&["format", "upload-format"])
+                .takes_value(false),
+        )
+        .arg(
+            Arg::with_name("work-area")
+                .long("work-area")
+                .help("Keep captures within the display's work area, which leaves out panels, docks, and taskbars, so that they never end up in the capture by accident. The selection can't be dragged past its edges, and `--region`s and windows are cropped to it. Falls back to the whole display when the platform doesn't report a work area.")
+                .takes_value(false),
+        )
+        .arg(
+            Arg::with_name("optimize-png")
+                .long("optimize-png")
+                .help("Recompress PNG captures with `oxipng` before uploading them, which usually makes them 10-30% smaller without changing a pixel at the cost of a second or so. Needs the `oxipng` command to be installed. `--verbose` shows how much was saved.")
+                .takes_value(false),
+        )
+        .arg(
+            Arg::with_name("upload-format")
+                .long("upload-format")
+                .help("Which format to upload, such as a small `jpeg` while saving a lossless `png` with `--format`. It's saved alongside the `--format`s if it isn't one of them. Defaults to the first `--format`, and `--format` defaults to it when only this is given.")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("subsampling")
+                .long("subsampling")
+                .help("Chroma subsampling of JPEG images: `4:4:4` (default), `4:2:2`, or `4:2:0`. 4:4:4 keeps text and thin colored lines crisp; 4:2:0 makes photos and gradients noticeably smaller at the cost of color fringing around sharp edges.")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("quality")
+                .short("q")
+                .long("quality")
+                .help("Quality of lossy formats from 0 to 100, overriding the config file's `jpeg_quality` or `avif_quality`. Defaults to 90 for JPEG and 80 for AVIF.")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("size")
+                .long("size")
+                .help("Capture a region of a fixed size such as `800x600` around where the screen is clicked rather than dragging out a selection")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("aspect")
+                .long("aspect")
+                .value_name("RATIO")
+                .help("Keep captures to an aspect ratio of width to height such as `16:9` or `1.91:1`, or one named under `[aspects]` in the config file or built in: `twitter-card`, `instagram-portrait`, `story`, and `youtube-thumbnail`. The selection keeps the ratio as it's dragged out, and `--region`s and windows are cropped around their middle to fit it.")
+                .conflicts_with("size")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("on-click")
+                .long("on-click")
+                .help("Don't show any selection UI; just capture the `--size` region around the next click")
+                .requires("size")
+                .takes_value(false),
+        )
+        .arg(
+            Arg::with_name("retina-downscale")
+                .long("retina-downscale")
+                .help("On HiDPI displays, scale captures down by the display's scale factor so that they're the size things appear on screen rather than their size in physical pixels. Does nothing on displays that aren't scaled.")
+                .takes_value(false),
+        )
+        .arg(
+            Arg::with_name("compress-upload")
+                .long("compress-upload")
+                .help("Gzip the upload request for large images if the server advertises support for it")
+                .takes_value(false),
+        )
+        .arg(
+            Arg::with_name("name")
+                .long("name")
+                .value_name("FILENAME")
+                .help("Name to give the upload on the backend instead of the local file's name, which some hosts use for the URL or the title shown. The image's extension is added if it's missing.")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("tag")
+                .long("tag")
+                .value_name("LABEL")
+                .help("Label the capture in the history so that it can be found again with `snapmeo history --tag`. Can be given more than once, or as a comma-separated list, to add several tags.")
+                .takes_value(true)
+                .multiple(true)
+                .number_of_values(1)
+                .use_delimiter(true),
+        )
+        .arg(
+            Arg::with_name("send-tags")
+                .long("send-tags")
+                .help("Also send the `--tag`s to the backend as a comma-separated `tags` field, for AmeoTrack servers that keep them")
+                .requires("tag")
+                .takes_value(false),
+        )
+        .arg(
+            Arg::with_name("pin")
+                .long("pin")
+                .help("After capturing, keep the image floating on top of other windows until it's closed with escape")
+                .takes_value(false),
+        )
+        .arg(
+            Arg::with_name("anchor")
+                .long("anchor")
+                .help("Which point of the `--size` region is placed at the click: `top-left`, `center` (default), or `bottom-right`")
+                .requires("size")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("max-upload-bytes")
+                .long("max-upload-bytes")
+                .help("Refuse to upload images that are larger than this many bytes once encoded. With `--force`, they're uploaded anyway after a warning.")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("auto-reduce")
+                .long("auto-reduce")
+                .help("Rather than refusing to upload images over `--max-upload-bytes`, scale them down until they fit. The saved copy is scaled down as well. Recordings can't be scaled down, so this can't be used with `--record`.")
+                .requires("max-upload-bytes")
+                .conflicts_with("record")
+                .takes_value(false),
+        )
+        .arg(
+            Arg::with_name("live")
+                .long("live")
+                .help("Select the region over the live screen rather than a frozen frame, capturing it when the mouse is released. Useful for animated content. Requires a compositor.")
+                .conflicts_with("size")
+                .takes_value(false),
+        )
+        .arg(
+            Arg::with_name("region")
+                .long("region")
+                .help("Capture the region `X,Y,WIDTH,HEIGHT` of the screen without showing any selection UI")
+                .conflicts_with("size")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("region-percent")
+                .long("region-percent")
+                .help("Like `--region`, but with the position and size given as percentages of the screen's size, such as `25,25,50,50` for the middle of the screen")
+                .conflicts_with_all(&[
+                    "size",
+                    "region",
+                    "window",
+                    "window-under-cursor",
+                    "active-window",
+                ])
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("preset")
+                .long("preset")
+                .help("Capture a region defined under `[presets]` in the config file, either with a `region` like `--region` takes or a `region_percent` like `--region-percent` takes")
+                .conflicts_with_all(&["size", "region", "region-percent", "selection-from-file"])
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("selection-from-file")
+                .long("selection-from-file")
+                .help("Like `--region`, but read the region from a file, or from stdin if the path is `-`. This allows using external region pickers such as `slurp`.")
+                .conflicts_with_all(&["size", "region", "region-percent"])
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("type-corners")
+                .long("type-corners")
+                .help("Like `--region`, but ask for two opposite corners of the region as `X,Y` lines on stdin, so that it can be captured without a pointing device.")
+                .conflicts_with_all(&["size", "region", "region-percent", "preset", "selection-from-file"])
+                .takes_value(false),
+        )
+        .arg(
+            Arg::with_name("window")
+                .long("window")
+                .help("Capture the topmost window whose title contains this text. With `--region` or `--selection-from-file`, the region is relative to the window's top-left corner instead.")
+                .conflicts_with("size")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("cursor")
+                .long("cursor")
+                .help("Draw the mouse cursor into the capture where it was when the screen was captured. The system's cursor is drawn where it can be read, which is only on X11, and a plain arrow is drawn elsewhere.")
+                .takes_value(false),
+        )
+        .arg(
+            Arg::with_name("cursor-image")
+                .long("cursor-image")
+                .value_name("PATH")
+                .help("Draw the PNG, BMP, or ICO image at PATH as the cursor instead of the system's, with its top left corner at the cursor's tip, such as to show the same cursor in every screenshot of a tutorial. Implies `--cursor`. It's scaled up to match HiDPI displays.")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("window-under-cursor")
+                .long("window-under-cursor")
+                .help("Capture the window under the mouse cursor without any clicking, treating it like `--window`. Combine with `--delay` to point at the window first.")
+                .conflicts_with_all(&["size", "window"])
+                .takes_value(false),
+        )
+        .arg(
+            Arg::with_name("transparent-window-capture")
+                .long("transparent-window-capture")
+                .value_name("BACKDROP")
+                .help("When capturing a window with `--window`, `--window-under-cursor`, or `--active-window`, read it straight from the window so that its transparent parts, such as rounded corners, show `checkerboard` (the default) or a hex color like `#ffffff` instead of what was behind it on screen. A transparent color like `#00000000` keeps the transparency in formats that support it. Only works on X11 for windows that are drawn with transparency.")
+                .min_values(0)
+                .max_values(1)
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("active-window")
+                .long("active-window")
+                .help("Capture the window that has input focus without any clicking, treating it like `--window`. Combine with `--delay` to focus the window first.")
+                .conflicts_with_all(&["size", "window", "window-under-cursor"])
+                .takes_value(false),
+        )
+        .arg(
+            Arg::with_name("decorations")
+                .long("decorations")
+                .value_name("include|exclude")
+                .help("Whether captures of a window with `--window`, `--window-under-cursor`, or `--active-window` include the title bar and borders around it (`include`, the default) or only its contents (`exclude`).")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("hold-to-select")
+                .long("hold-to-select")
+                .help("Only show the selection overlay while a modifier key is held: `ctrl`, `shift`, `alt`, or `super` (the default). Letting go of the key cancels, and releasing the mouse after dragging out a region captures it. Meant to be bound to a hotkey that includes the key. On X11 the overlay waits for the key to be pressed before appearing.")
+                .takes_value(true)
+                .min_values(0)
+                .value_name("KEY")
+                .conflicts_with("live"),
+        )
+        .arg(
+            Arg::with_name("select-timeout")
+                .long("select-timeout")
+                .help("Cancel the capture if nothing has been selected or confirmed within this many seconds, such as when a hotkey was pressed by accident")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("from-clipboard")
+                .long("from-clipboard")
+                .help("Upload the image on the clipboard, such as one copied from another tool, rather than capturing the screen")
+                .conflicts_with_all(&[
+                    "delay",
+                    "scroll",
+                    "annotate",
+                    "confirm",
+                    "size",
+                    "pin",
+                    "live",
+                    "region",
+                    "region-percent",
+                    "preset",
+                    "selection-from-file",
+                    "window",
+                    "window-under-cursor",
+                    "active-window",
+                ])
+                .takes_value(false),
+        )
+        .arg(
+            Arg::with_name("no-masks")
+                .long("no-masks")
+                .help("Don't pixelate the `masks` from the config file in this capture")
+                .takes_value(false),
+        )
+        .arg(
+            Arg::with_name("timestamp-bar")
+                .long("timestamp-bar")
+                .help("Add a bar to the bottom of the image showing when it was captured. Its look and whether it includes the hostname can be set with the `timestamp_bar_*` options in the config file.")
+                .takes_value(false),
+        )
+        .arg(
+            Arg::with_name("sound")
+                .long("sound")
+                .help("Play a camera shutter sound when the capture is taken")
+                .takes_value(false),
+        )
+        .arg(
+            Arg::with_name("sound-file")
+                .long("sound-file")
+                .help("WAV file to play instead of the built-in shutter sound")
+                .requires("sound")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("thumbnail")
+                .long("thumbnail")
+                .help("Also upload a thumbnail scaled down to fit within a size such as `320x240`")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("json")
+                .long("json")
+                .help("Print the results as a JSON object rather than as text")
+                .takes_value(false),
+        )
+        .arg(
+            Arg::with_name("interactive")
+                .short("i")
+                .long("interactive")
+                .help("After capturing, ask for the expiry and whether the upload should be secret or one-time, defaulting to the values of the other flags. Ignored with `--json` or when stdin isn't a terminal.")
+                .takes_value(false),
+        )
+        .arg(
+            Arg::with_name("force")
+                .short("y")
+                .long("force")
+                .alias("yes")
+                .help("Never wait on a decision, for unattended use: `--confirm` uploads the selection straight away and `--interactive` uses the other flags' values without asking. Captures that are entirely one color are kept without asking whether they're wanted, including the all-black ones that macOS hands out without permission to record the screen. Files in the way of the screenshot are overwritten unless `--on-exists rename` is given, even with `--on-exists error`, and images over `--max-upload-bytes` are uploaded anyway unless `--auto-reduce` is given.")
+                .takes_value(false),
+        )
+        .arg(
+            Arg::with_name("no-color")
+                .long("no-color")
+                .help("Don't color the output. Colors are also disabled when `NO_COLOR` is set or the output isn't a terminal.")
+                .takes_value(false),
+        )
+        .arg(
+            Arg::with_name("verbose")
+                .short("v")
+                .long("verbose")
+                .help("Print extra progress information")
+                .takes_value(false),
+        )
+        .arg(
+            Arg::with_name("uploader")
+                .long("uploader")
+                .help("Where to upload images: `ameotrack` (default), `s3`, or `sftp`. S3 uploads are configured with `SNAPMEO_S3_BUCKET`, `SNAPMEO_S3_REGION`, `SNAPMEO_S3_ENDPOINT`, `SNAPMEO_S3_PREFIX`, `SNAPMEO_S3_PUBLIC_URL`, `AWS_ACCESS_KEY_ID`, and `AWS_SECRET_ACCESS_KEY`. SFTP uploads are configured with `SNAPMEO_SFTP_HOST`, `SNAPMEO_SFTP_USER`, `SNAPMEO_SFTP_KEY`, `SNAPMEO_SFTP_DIR`, and `SNAPMEO_SFTP_URL`, a URL containing `{name}`; the server has to be in `~/.ssh/known_hosts` unless `SNAPMEO_SFTP_ACCEPT_NEW_HOST_KEY=1` is set.")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("broadcast")
+                .long("broadcast")
+                .help("Also upload each capture to these comma-separated backends, such as `s3,sftp`, at the same time as the `--uploader` one. Without a list, the `broadcast` list in the config file is used. Every URL is reported, and a backend failing doesn't stop the others; the main uploader's URL is the one copied to the clipboard.")
+                .takes_value(true)
+                .min_values(0)
+                .use_delimiter(true)
+                .value_name("BACKENDS"),
+        )
+        .arg(
+            Arg::with_name("notify")
+                .long("notify")
+                .help("Show a desktop notification once the capture has been uploaded or copied, previewing the image where the desktop supports it. Uses `notify-send` on Linux and `osascript` on macOS.")
+                .takes_value(false),
+        )
+        .arg(
+            Arg::with_name("post-command")
+                .long("post-command")
+                .help("Shell command to run on the saved screenshot before it's copied or uploaded, with its path added as the last argument, such as `optipng -o2`. Whatever the command leaves at that path is what gets uploaded.")
+                .takes_value(true)
+                .value_name("COMMAND"),
+        )
+        .arg(
+            Arg::with_name("ocr")
+                .long("ocr")
+                .help("Recognize the text in the capture with the `tesseract` command, which has to be installed. With `text` (the default), the text is copied to the clipboard instead of uploading the image; with `both`, the image is uploaded too and the text is copied after its URL. `--verbose` prints the text.")
+                .takes_value(true)
+                .min_values(0)
+                .value_name("MODE"),
+        )
+        .arg(
+            Arg::with_name("skip-unchanged")
+                .long("skip-unchanged")
+                .help("Don't upload the capture if it's identical to the last one uploaded to the same backend, such as when taking captures of an idle screen on a schedule. The previous upload's URL is reported and copied instead.")
+                .takes_value(false),
+        )
+        .arg(
+            Arg::with_name("upload-url")
+                .long("upload-url")
+                .help("URL of the AmeoTrack upload endpoint, for self-hosted instances")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("copy-region")
+                .long("copy-region")
+                .help("Print the captured region as `X,Y,WIDTH,HEIGHT` and copy it to the clipboard instead of the link, so that the same region can be captured again with `--region`. The region is always in the `--json` output and available to `--clipboard-template` as `{region}`.")
+                .conflicts_with_all(&["clipboard-template", "clipboard-flavor", "clipboard-image"])
+                .takes_value(false),
+        )
+        .arg(
+            Arg::with_name("clipboard-image")
+                .long("clipboard-image")
+                .help("The same as `--clipboard-flavor auto`")
+                .conflicts_with("clipboard-flavor")
+                .takes_value(false),
+        )
+        .arg(
+            Arg::with_name("clipboard-flavor")
+                .long("clipboard-flavor")
+                .value_name("FLAVOR")
+                .help("What to put on the clipboard after uploading: `url` (the default) for the link, or whatever `--clipboard-template` makes of it, `png` for the image itself, `file-uri` for a reference to the saved file that file managers paste as a copy, or `auto` for all three at once so that the app being pasted into picks the one it wants. `auto` works on macOS and Windows; elsewhere only the link is copied.")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("fast-paste")
+                .long("fast-paste")
+                .help("Copy the image to the clipboard as soon as it's saved so that it can be pasted straight away, then replace it with the link once the upload finishes, or with whatever `--clipboard-flavor` asks for. If the upload fails, the image is left on the clipboard.")
+                .takes_value(false),
+        )
+        .arg(
+            Arg::with_name("filename-template")
+                .long("filename-template")
+                .help("What to name saved screenshots, without the extension. `{window}` is the title of the `--window`, `--window-under-cursor`, or `--active-window` captured, or `Screenshot` when there isn't one, and `{date}` is the capture time like `Jan 01 12-30-00`. strftime codes like `%Y-%m-%d` are filled in with the capture time too; write `%%` for a literal `%`. Characters that aren't allowed in file names are replaced with `_`. Defaults to `Screenshot at {date}`.")
+                .takes_value(true)
+                .value_name("TEMPLATE"),
+        )
+        .arg(
+            Arg::with_name("clipboard-template")
+                .long("clipboard-template")
+                .help("What to copy to the clipboard after uploading, such as `[img]{url}[/img]` or `![]({url})`. The placeholders are `{url}`, `{id}` (the last part of the URL without its extension), `{width}`, `{height}`, `{date}`, `{path}`, `{name}` (the file's name), `{text}` (the `--ocr` text), and `{region}` (the captured region as `X,Y,WIDTH,HEIGHT`). Write `{{` and `}}` for literal braces. Defaults to `{url}`, followed by the text with `--ocr both`.")
+                .takes_value(true)
+                .value_name("TEMPLATE"),
+        )
+        .arg(
+            Arg::with_name("restore-clipboard-after")
+                .long("restore-clipboard-after")
+                .help("Put the text that was on the clipboard before the link was copied back this many seconds afterwards, unless something else has been copied since")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("queue-uploads")
+                .long("queue-uploads")
+                .help("If the upload backend can't be reached, save the screenshot to a queue on disk to be uploaded later instead of failing. Queued uploads are retried after the next successful upload. Every attempt at uploading a capture sends the same `idempotencyKey` form field, so an AmeoTrack server that remembers the keys it's seen won't store a capture twice when an earlier attempt went through but its response was lost.")
+                .takes_value(false),
+        )
+        .arg(
+            Arg::with_name("retry-queue")
+                .long("retry-queue")
+                .help("Don't capture anything; upload everything in the queue from `--queue-uploads`, retrying every 30 seconds while the backend is unreachable")
+                .takes_value(false),
+        )
+        .arg(
+            Arg::with_name("chain")
+                .long("chain")
+                .help("Take screenshots one after another, opening the selection overlay again as soon as each is uploaded, until a selection is cancelled with escape. The links of everything uploaded are listed at the end.")
+                .conflicts_with_all(&["listen", "region", "region-percent", "preset", "selection-from-file", "type-corners", "window", "window-under-cursor", "active-window", "from-clipboard", "retry-queue"])
+                .takes_value(false),
+        )
+        .arg(
+            Arg::with_name("listen")
+                .long("listen")
+                .help("Run as a service listening on a loopback address such as `127.0.0.1:7332` for capture requests from other programs instead of capturing right away. Each request is a line of JSON like `{\"args\": [\"--region\", \"0,0,800,600\"]}` with the flags to capture with, and is answered with a line of JSON holding the result. Requests have to include the token in `listen-token` in snapmeo's data directory as `\"token\"`, which is made the first time, and can only give flags that choose what to capture and how. `--output_dir`, `--config`, `--profile`, and `--uploader` are passed along to every capture.")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("cooldown")
+                .long("cooldown")
+                .value_name("MILLISECONDS")
+                .help("With `--listen`, ignore capture requests that arrive less than this long after the last capture finished, such as from a stuck hotkey")
+                .default_value("300")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("json-config")
+                .long("json-config")
+                .help("Print the settings that would be used after combining the defaults, the config file, environment variables, and the other flags as JSON along with where each came from, then exit without capturing anything")
+                .takes_value(false),
+        )
+        .arg(
+            Arg::with_name("config")
+                .long("config")
+                .help("Path of the TOML config file to read. Defaults to `snapmeo/config.toml` in the user's config directory.")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("env-file")
+                .long("env-file")
+                .value_name("PATH")
+                .help("Load environment variables such as `AMEOTRACK_PASSWORD` from this dotenv file of `KEY=VALUE` lines. Defaults to `.env` in the current directory if there is one. Variables that are already set take precedence.")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("profile")
+                .long("profile")
+                .help("Apply the overrides of a profile defined under `[profiles]` in the config file, such as `[profiles.work]`, on top of the rest of the config file. Flags still take precedence over both.")
+                .takes_value(true),
+        )
+        .subcommand(
+            SubCommand::with_name("benchmark")
+                .about("Repeatedly capture full frames without encoding or uploading them, then report the frame rate and frame grab latency achieved")
+                .arg(
+                    Arg::with_name("duration")
+                        .long("duration")
+                        .help("How many seconds to capture for. Defaults to 5.")
+                        .takes_value(true),
+                )
+                .arg(
+                    Arg::with_name("frames")
+                        .long("frames")
+                        .help("Capture this many frames instead of capturing for a fixed duration")
+                        .conflicts_with("duration")
+                        .takes_value(true),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("gallery")
+                .about("Show recent uploads as a grid of thumbnails, and copy the URL of the one that's clicked to the clipboard")
+        )
+        .subcommand(
+            SubCommand::with_name("history")
+                .about("List recent uploads, newest first")
+                .arg(
+                    Arg::with_name("tag")
+                        .long("tag")
+                        .value_name("LABEL")
+                        .help("Only list captures with this tag. Can be given more than once to list captures with all of the tags.")
+                        .takes_value(true)
+                        .multiple(true)
+                        .number_of_values(1),
+                )
+        )
+        .subcommand(
+            SubCommand::with_name("config")
+                .about("Open a window for editing the output directory, uploader, expiry, and format in the config file. Other settings in the file are kept, but comments are not.")
+        )
+        .get_matches_safe()
+        .map_err(|err| match err.kind {
+            // These aren't really errors, so let clap print them and exit successfully
+            clap::ErrorKind::HelpDisplayed | clap::ErrorKind::VersionDisplayed => err.exit(),
+            _ => SnapmeoError::BadArguments(err.message),
+        })?;
+
+    // Before anything reads the environment
+    match matches.value_of("env-file") {
+        Some(path) => dotenv::load(Path::new(path), true)?,
+        None => dotenv::load(Path::new(dotenv::DEFAULT_PATH), false)?,
+    }
+
+    let no_color_env = env::var_os("NO_COLOR").is_some_and(|value| !value.is_empty());
+    if matches.is_present("no-color") || no_color_env || !io::stdout().is_terminal() {
+        colored::control::set_override(false);
+    }
+
+    if let Some(benchmark_matches) = matches.subcommand_matches("benchmark") {
+        return benchmark(benchmark_matches, matches.is_present("json"));
+    }
+
+    if matches.subcommand_matches("config").is_some() {
+        #[cfg(feature = "interactive")]
+        let path = matches
+            .value_of("config")
+            .map(PathBuf::from)
+            .or_else(config::default_path)
+            .ok_or_else(|| {
+                SnapmeoError::Other("Unable to find a directory to keep the config file in".into())
+            })?;
+        #[cfg(feature = "interactive")]
+        return settings::edit(&path);
+        #[cfg(not(feature = "interactive"))]
+        return Err(not_interactive("`snapmeo config`"));
+    }
+
+    if matches.subcommand_matches("gallery").is_some() {
+        #[cfg(feature = "interactive")]
+        return gallery(matches.is_present("json"));
+        #[cfg(not(feature = "interactive"))]
+        return Err(not_interactive("`snapmeo gallery`"));
+    }
+
+    if let Some(history_matches) = matches.subcommand_matches("history") {
+        return list_history(history_matches, matches.is_present("json"));
+    }
+
+    if matches.is_present("chain") {
+        return chain::run(matches.is_present("json"));
+    }
+
+    if let Some(addr) = matches.value_of("listen") {
+        let addr = listen::parse_addr(addr)?;
+        let inherited_args = listen::INHERITED_FLAGS
+            .iter()
+            .filter_map(|&flag| {
+                matches
+                    .value_of(flag)
+                    .map(|value| (flag.to_owned(), value.to_owned()))
+            })
+            .collect();
+        let cooldown = matches
+            .value_of("cooldown")
+            .unwrap()
+            .parse()
+            .map(Duration::from_millis)
+            .map_err(|_| {
+                SnapmeoError::BadArguments("`--cooldown` must be a number of milliseconds".into())
+            })?;
+        return listen::serve(addr, inherited_args, cooldown);
+    }
+
+    let mut config = Config::load(matches.value_of("config").map(Path::new))?;
+    if let Some(profile) = matches.value_of("profile") {
+        config.apply_profile(profile)?;
+    }
+
+    let mut formats: Vec<Format> = Vec::new();
+    let format_list = matches
+        .value_of("format")
+        .or(config.format.as_deref())
+        .or_else(|| matches.value_of("upload-format"))
+        .unwrap_or("png");
+    for format in format_list.split(',') {
+        let format = format.trim().parse().map_err(SnapmeoError::BadArguments)?;
+        if !formats.contains(&format) {
+            formats.push(format);
+        }
+    }
+    let format: Format = match matches.value_of("upload-format") {
+        Some(upload_format) => {
+            let upload_format = upload_format.parse().map_err(SnapmeoError::BadArguments)?;
+            // It's encoded from the same capture as the others, and has to be on disk to upload
+            if !formats.contains(&upload_format) {
+                formats.push(upload_format);
+            }
+            upload_format
+        }
+        None => formats[0],
+    };
+
+    let force = matches.is_present("force");
+    let on_exists: Option<OnExists> = matches
+        .value_of("on-exists")
+        .map(str::parse)
+        .transpose()
+        .map_err(SnapmeoError::BadArguments)?;
+    // `--force` answers yes to replacing whatever's in the way
+    let on_exists = match (on_exists, force) {
+        (None, true) | (Some(OnExists::Error), true) => OnExists::Overwrite,
+        (Some(on_exists), _) => on_exists,
+        (None, false) => OnExists::Rename,
+    };
+
+    let subsampling: Subsampling = matches
+        .value_of("subsampling")
+        .unwrap_or("4:4:4")
+        .parse()
+        .map_err(SnapmeoError::BadArguments)?;
+    let cli_quality = matches
+        .value_of("quality")
+        .map(config::parse_quality)
+        .transpose()
+        .map_err(SnapmeoError::BadArguments)?;
+    let encode_options_for = |format: Format| EncodeOptions {
+        format,
+        subsampling,
+        quality: cli_quality.or_else(|| config.quality(format)),
+    };
+    let encode_options = encode_options_for(format);
+    let quality = encode_options.quality;
+    // Formats that are only saved locally alongside the uploaded one
+    let auto_format = matches.is_present("auto-format");
+    let optimize_png = matches.is_present("optimize-png");
+    let fast_paste = matches.is_present("fast-paste");
+    let auto_jpeg_options = encode_options_for(Format::Jpeg);
+    let extra_encode_options: Vec<EncodeOptions> = formats
+        .iter()
+        .filter(|&&other| other != format)
+        .map(|&other| encode_options_for(other))
+        .collect();
+
+    let record_duration = matches
+        .value_of("record")
+        .map(schedule::parse_duration)
+        .transpose()
+        .map_err(SnapmeoError::BadArguments)?;
+    let parse_fps = |name: &str| {
+        matches
+            .value_of(name)
+            .map(|fps| match fps.parse::<f64>() {
+                Ok(fps) if fps > 0. && fps.is_finite() => Ok(fps),
+                _ => Err(SnapmeoError::BadArguments(format!(
+                    "`--{}` must be a number of frames per second greater than zero",
+                    name
+                ))),
+            })
+            .transpose()
+    };
+    let capture_fps = parse_fps("monitor-capture-fps")?.unwrap_or(record::DEFAULT_CAPTURE_FPS);
+    let playback_fps = parse_fps("playback-fps")?;
+    if let Some(record_duration) = record_duration {
+        if record_duration.is_zero() {
+            return Err(SnapmeoError::BadArguments(
+                "`--record` needs a duration longer than zero".into(),
+            ));
+        }
+        if formats != [Format::Png] {
+            return Err(SnapmeoError::BadArguments(
+                "Recordings can only be saved as PNG, so `--record` can't be used with other formats"
+                    .into(),
+            ));
+        }
+    }
+
+    let delay = match matches.value_of("delay").map(str::parse) {
+        Some(Ok(secs)) => Some(Duration::from_secs(secs)),
+        Some(Err(_)) => {
+            return Err(SnapmeoError::BadArguments(
+                "The delay must be a whole number of seconds".into(),
+            ))
+        }
+        None => None,
+    };
+
+    // Worked out up front so that mistakes are reported before the wait rather than after it
+    let scheduled_at = match (matches.value_of("at"), matches.value_of("in")) {
+        (Some(time), _) => Some(
+            schedule::next_occurrence(time)
+                .map_err(SnapmeoError::BadArguments)?
+                .into(),
+        ),
+        (None, Some(duration)) => {
+            let delay = schedule::parse_duration(duration).map_err(SnapmeoError::BadArguments)?;
+            // Added as a chrono duration so that the deadline can always be shown as a date
+            let at = chrono::Duration::from_std(delay)
+                .ok()
+                .and_then(|delay| Local::now().checked_add_signed(delay))
+                .ok_or_else(|| {
+                    SnapmeoError::BadArguments(format!("`--in {}` is too far away", duration))
+                })?;
+            Some(at.into())
+        }
+        (None, None) => None,
+    };
+
+    let transparent_window_backdrop: Option<Backdrop> =
+        if matches.is_present("transparent-window-capture") {
+            if !["window", "window-under-cursor", "active-window"]
+                .iter()
+                .any(|name| matches.is_present(name))
+            {
+                return Err(SnapmeoError::BadArguments(
+                    "`--transparent-window-capture` needs a window to capture, given with \
+                     `--window`, `--window-under-cursor`, or `--active-window`"
+                        .into(),
+                ));
+            }
+            Some(
+                matches
+                    .value_of("transparent-window-capture")
+                    .unwrap_or("checkerboard")
+                    .parse()
+                    .map_err(SnapmeoError::BadArguments)?,
+            )
+        } else {
+            None
+        };
+
+    let compare: Option<CompareMode> = if matches.is_present("compare") {
+        if !io::stdin().is_terminal() {
+            return Err(SnapmeoError::BadArguments(
+                "`--compare` waits for enter to be pressed in a terminal, but there isn't one"
+                    .into(),
+            ));
+        }
+        Some(
+            matches
+                .value_of("compare")
+                .unwrap_or("side-by-side")
+                .parse()
+                .map_err(SnapmeoError::BadArguments)?,
+        )
+    } else {
+        None
+    };
+    let hold_key: Option<HoldKey> = matches
+        .value_of("hold-to-select")
+        .map(|key| key.parse().map_err(SnapmeoError::BadArguments))
+        .transpose()?;
+    let hold_key = hold_key.or_else(|| {
+        matches
+            .is_present("hold-to-select")
+            .then_some(HoldKey::Super)
+    });
+
+    let select_timeout = matches
+        .value_of("select-timeout")
+        .map(str::parse)
+        .transpose()
+        .map_err(|_| {
+            SnapmeoError::BadArguments(
+                "The selection timeout must be a whole number of seconds".into(),
+            )
+        })?
+        .map(Duration::from_secs);
+
+    let restore_clipboard_after = matches
+        .value_of("restore-clipboard-after")
+        .map(str::parse)
+        .transpose()
+        .map_err(|_| {
+            SnapmeoError::BadArguments(
+                "The clipboard restore delay must be a whole number of seconds".into(),
+            )
+        })?
+        .map(Duration::from_secs);
+
+    let verbose = matches.is_present("verbose");
+    let json = matches.is_present("json");
+    // Prompting would get in the way of anything consuming the output
+    let interactive =
+        matches.is_present("interactive") && !force && !json && io::stdin().is_terminal();
+    let thumbnail_size = matches
+        .value_of("thumbnail")
+        .map(buffer::parse_size)
+        .transpose()
+        .map_err(SnapmeoError::BadArguments)?;
+    let scroll = matches.is_present("scroll");
+    let only_changed_region = matches.is_present("only-changed-region");
+    #[cfg(feature = "interactive")]
+    let live = matches.is_present("live");
+    let annotate = matches.is_present("annotate");
+    let annotation_style = config.annotation_style()?;
+    #[cfg_attr(not(feature = "interactive"), allow(unused_variables))]
+    let preview_scale_quality = config.preview_scale_quality()?;
+    let timestamp_bar = if matches.is_present("timestamp-bar") {
+        Some(config.timestamp_bar()?)
+    } else {
+        None
+    };
+    let sound = matches.is_present("sound");
+    #[cfg(feature = "interactive")]
+    let sound_file = matches.value_of("sound-file").map(Path::new);
+    // One-time links are gone as soon as they're viewed, so an accidental upload of the wrong
+    // thing can't just be deleted and redone
+    let confirm = (matches.is_present("confirm") || matches.is_present("one-time")) && !force;
+    let pin = matches.is_present("pin");
+    let decorations: Decorations = matches
+        .value_of("decorations")
+        .unwrap_or("include")
+        .parse()
+        .map_err(SnapmeoError::BadArguments)?;
+    let clipboard_flavor = if matches.is_present("clipboard-image") {
+        Flavor::Auto
+    } else {
+        matches
+            .value_of("clipboard-flavor")
+            .map(str::parse)
+            .transpose()
+            .map_err(SnapmeoError::BadArguments)?
+            .unwrap_or(Flavor::Url)
+    };
+    #[cfg_attr(not(feature = "interactive"), allow(unused_variables))]
+    let fixed_size = matches
+        .value_of("size")
+        .map(buffer::parse_size)
+        .transpose()
+        .map_err(SnapmeoError::BadArguments)?;
+    let aspect = matches
+        .value_of("aspect")
+        .map(|aspect| config.aspect(aspect))
+        .transpose()?;
+    #[cfg_attr(not(feature = "interactive"), allow(unused_variables))]
+    let anchor: Anchor = matches
+        .value_of("anchor")
+        .unwrap_or("center")
+        .parse()
+        .map_err(SnapmeoError::BadArguments)?;
+    #[cfg(feature = "interactive")]
+    let on_click = matches.is_present("on-click");
+    let preset_region = match matches.value_of("selection-from-file") {
+        Some(path) => Some(read_selection_file(path)?),
+        None if matches.is_present("type-corners") => Some(
+            prompt::ask_corners()
+                .map_err(|err| SnapmeoError::BadArguments(format!("Invalid corners: {}", err)))?,
+        ),
+        None => matches
+            .value_of("region")
+            .map(buffer::parse_region)
+            .transpose()
+            .map_err(SnapmeoError::BadArguments)?,
+    };
+    let region_percent = matches
+        .value_of("region-percent")
+        .map(buffer::parse_region_percent)
+        .transpose()
+        .map_err(SnapmeoError::BadArguments)?;
+    let (preset_region, region_percent) = match matches.value_of("preset") {
+        Some(name) => match config.preset(name)? {
+            PresetRegion::Pixels(region) => (Some(region), None),
+            PresetRegion::Percent(percent) => (None, Some(percent)),
+        },
+        None => (preset_region, region_percent),
+    };
+
+    let padding: usize = matches
+        .value_of("padding")
+        .map(str::parse)
+        .transpose()
+        .map_err(|_| {
+            SnapmeoError::BadArguments("The padding must be a whole number of pixels".into())
+        })?
+        .unwrap_or(0);
+    let background = matches
+        .value_of("background")
+        .map(buffer::parse_color)
+        .transpose()
+        .map_err(SnapmeoError::BadArguments)?
+        .unwrap_or([0, 0, 0, 0]);
+
+    let border_width: usize = matches
+        .value_of("border")
+        .map(str::parse)
+        .transpose()
+        .map_err(|_| {
+            SnapmeoError::BadArguments("The border must be a whole number of pixels".into())
+        })?
+        .unwrap_or(0);
+    let shadow_blur: usize = matches
+        .value_of("shadow")
+        .map(str::parse)
+        .transpose()
+        .map_err(|_| {
+            SnapmeoError::BadArguments("The shadow blur must be a whole number of pixels".into())
+        })?
+        .unwrap_or(0);
+    let border_color = matches
+        .value_of("border-color")
+        .map(buffer::parse_color)
+        .transpose()
+        .map_err(SnapmeoError::BadArguments)?
+        .unwrap_or([128, 128, 128, 255]);
+    let border = Border {
+        width: border_width,
+        color: border_color,
+        shadow_blur,
+    };
+    let border = Some(border).filter(|border| border.width > 0 || border.shadow_blur > 0);
+    // Everything that goes around the captured image, which annotations have to be moved by
+    #[cfg_attr(not(feature = "interactive"), allow(unused_variables))]
+    let image_inset = padding + border.map_or(0, |border| border.inset());
+
+    let max_upload_bytes: Option<u64> = matches
+        .value_of("max-upload-bytes")
+        .map(str::parse)
+        .transpose()
+        .map_err(|_| {
+            SnapmeoError::BadArguments(
+                "The maximum upload size must be a whole number of bytes".into(),
+            )
+        })?;
+    let auto_reduce = matches.is_present("auto-reduce");
+    let skip_unchanged = matches.is_present("skip-unchanged");
+    let post_command = matches.value_of("post-command").map(str::to_owned);
+    let notify = matches.is_present("notify");
+    // Notifications can only preview the format that the uploaded file was saved in
+    let send_notification = move |summary: &str, body: &str, path: &Path| {
+        if let Err(err) = notify::notify(summary, body, Some((path, format))) {
+            eprintln!("{} {}", "Warning:".yellow(), err);
+        }
+    };
+    let ocr_mode: Option<OcrMode> = if matches.is_present("ocr") {
+        Some(
+            matches
+                .value_of("ocr")
+                .unwrap_or("text")
+                .parse()
+                .map_err(SnapmeoError::BadArguments)?,
+        )
+    } else {
+        None
+    };
+    let copy_region = matches.is_present("copy-region");
+    let clipboard_template = matches
+        .value_of("clipboard-template")
+        .or_else(|| copy_region.then_some("{region}"))
+        .or(config.clipboard_template.as_deref())
+        .map(str::to_owned)
+        .unwrap_or_else(|| match ocr_mode {
+            Some(OcrMode::Both) => "{url}\n\n{text}".into(),
+            _ => "{url}".into(),
+        });
+    template::validate(&clipboard_template, &CLIPBOARD_PLACEHOLDERS)
+        .map_err(SnapmeoError::BadArguments)?;
+    let filename_template = matches
+        .value_of("filename-template")
+        .or(config.filename_template.as_deref())
+        .unwrap_or(DEFAULT_FILENAME_TEMPLATE)
+        .to_owned();
+    validate_filename_template(&filename_template)?;
+
+    let upload_url = matches
+        .value_of("upload-url")
+        .or(config.upload_url.as_deref())
+        .unwrap_or(upload::AMEOTRACK_UPLOAD_URL);
+    let uploader_name = matches
+        .value_of("uploader")
+        .or(config.uploader.as_deref())
+        .unwrap_or("ameotrack");
+    let uploader = make_uploader(uploader_name, upload_url)?;
+    let broadcast_uploaders = if matches.is_present("broadcast") {
+        let names: Vec<String> = match matches.values_of("broadcast") {
+            Some(names) => names.map(str::to_owned).collect(),
+            None => config.broadcast.clone().unwrap_or_default(),
+        };
+        if names.is_empty() {
+            return Err(SnapmeoError::BadArguments(
+                "`--broadcast` needs a list of backends, either after it or as `broadcast` in the config file".into(),
+            ));
+        }
+        // Uploading the same file to the same place twice wouldn't achieve anything
+        let mut seen = vec![uploader_name];
+        let mut uploaders = Vec::new();
+        for name in names.iter().map(|name| name.trim()) {
+            if !seen.contains(&name) {
+                seen.push(name);
+                uploaders.push(make_uploader(name, upload_url)?);
+            }
+        }
+        uploaders
+    } else {
+        Vec::new()
+    };
+    let expiry: Expiry = matches
+        .value_of("expiry")
+        .or(config.expiry.as_deref())
+        .unwrap_or("never")
+        .parse()
+        .map_err(SnapmeoError::BadArguments)?;
+    // Make sure the backend can honor the expiry before anything is captured
+    uploader.translate_expiry(&expiry)?;
+    for broadcast_uploader in &broadcast_uploaders {
+        broadcast_uploader.translate_expiry(&expiry)?;
+    }
+    let remote_name = matches
+        .value_of("name")
+        .map(|name| upload::sanitize_file_name(name, format.extension()))
+        .transpose()
+        .map_err(SnapmeoError::BadArguments)?;
+    let tags = history::parse_tags(matches.values_of("tag").into_iter().flatten())
+        .map_err(SnapmeoError::BadArguments)?;
+    let upload_options = UploadOptions {
+        expiry,
+        secret: matches.is_present("secret"),
+        one_time: matches.is_present("one-time"),
+        content_type: format.content_type(),
+        compress: matches.is_present("compress-upload"),
+        remote_name,
+        tags: if matches.is_present("send-tags") {
+            tags.clone()
+        } else {
+            Vec::new()
+        },
+        idempotency_key: Some(upload::idempotency_key()),
+    };
+    #[cfg(feature = "interactive")]
+    let upload_one_time = upload_options.one_time;
+    let output_dir = matches
+        .value_of("output_dir")
+        .map(PathBuf::from)
+        .or_else(|| config.output_dir.clone());
+
+    if matches.is_present("json-config") {
+        let cli_or_default = |name: &str| {
+            if matches.occurrences_of(name) > 0 {
+                "cli"
+            } else {
+                "default"
+            }
+        };
+        let cli_config_or_default = |name: &str, in_config: bool| {
+            if matches.occurrences_of(name) > 0 {
+                "cli"
+            } else if in_config {
+                "config"
+            } else {
+                "default"
+            }
+        };
+        let quality_source = if matches.is_present("quality") {
+            "cli"
+        } else if config.quality(format).is_some() {
+            "config"
+        } else {
+            "default"
+        };
+        let color_source = if matches.is_present("no-color") {
+            "cli"
+        } else if no_color_env {
+            "env"
+        } else {
+            "default"
+        };
+        let timestamp_bar_style = config.timestamp_bar()?;
+
+        let effective = serde_json::json!({
+            "config_file": setting(
+                matches
+                    .value_of("config")
+                    .map(PathBuf::from)
+                    .or_else(config::default_path),
+                cli_or_default("config"),
+            ),
+            "profile": setting(matches.value_of("profile"), cli_or_default("profile")),
+            "output_dir": setting(
+                &output_dir,
+                cli_config_or_default("output_dir", config.output_dir.is_some()),
+            ),
+            "formats": setting(
+                formats.iter().map(|format| format.extension()).collect::<Vec<_>>(),
+                cli_config_or_default("format", config.format.is_some()),
+            ),
+            "on_exists": setting(
+                match on_exists {
+                    OnExists::Error => "error",
+                    OnExists::Overwrite => "overwrite",
+                    OnExists::Rename => "rename",
+                },
+                if force { "cli" } else { cli_or_default("on-exists") },
+            ),
+            "upload_format": setting(format.extension(), cli_or_default("upload-format")),
+            "quality": setting(quality.or(format.default_quality()), quality_source),
+            "subsampling": setting(
+                matches.value_of("subsampling").unwrap_or("4:4:4"),
+                cli_or_default("subsampling"),
+            ),
+            "uploader": setting(
+                uploader.name(),
+                cli_config_or_default("uploader", config.uploader.is_some()),
+            ),
+            "upload_url": setting(
+                upload_url,
+                cli_config_or_default("upload-url", config.upload_url.is_some()),
+            ),
+            "expiry": setting(
+                upload_options.expiry.to_string(),
+                cli_config_or_default("expiry", config.expiry.is_some()),
+            ),
+            "secret": setting(upload_options.secret, cli_or_default("secret")),
+            "one_time": setting(upload_options.one_time, cli_or_default("one-time")),
+            "padding": setting(padding, cli_or_default("padding")),
+            "background": setting(hex_color(background), cli_or_default("background")),
+            "border": setting(border_width, cli_or_default("border")),
+            "border_color": setting(hex_color(border_color), cli_or_default("border-color")),
+            "shadow": setting(shadow_blur, cli_or_default("shadow")),
+            "color": setting(!matches.is_present("no-color") && !no_color_env, color_source),
+            "annotation_color": setting(
+                hex_color(annotation_style.color),
+                config_or_default(&config.annotation_color),
+            ),
+            "highlight_color": setting(
+                hex_color(annotation_style.highlight_color),
+                config_or_default(&config.highlight_color),
+            ),
+            "timestamp_bar": setting(timestamp_bar.is_some(), cli_or_default("timestamp-bar")),
+            "timestamp_bar_color": setting(
+                hex_color(timestamp_bar_style.background),
+                config_or_default(&config.timestamp_bar_color),
+            ),
+            "timestamp_bar_text_color": setting(
+                hex_color(timestamp_bar_style.foreground),
+                config_or_default(&config.timestamp_bar_text_color),
+            ),
+            "timestamp_bar_font_scale": setting(
+                timestamp_bar_style.font_scale,
+                config_or_default(&config.timestamp_bar_font_scale),
+            ),
+            "timestamp_bar_hostname": setting(
+                timestamp_bar_style.hostname,
+                if config.timestamp_bar_hostname { "config" } else { "default" },
+            ),
+            "select_timeout": setting(
+                select_timeout.map(|timeout| timeout.as_secs()),
+                cli_or_default("select-timeout"),
+            ),
+            "clipboard_template": setting(
+                &clipboard_template,
+                cli_config_or_default(
+                    "clipboard-template",
+                    config.clipboard_template.is_some(),
+                ),
+            ),
+            "filename_template": setting(
+                &filename_template,
+                cli_config_or_default("filename-template", config.filename_template.is_some()),
+            ),
+            "frame_poll_interval_ms": setting(
+                config.frame_poll_interval_ms.unwrap_or(1000 / 60),
+                config_or_default(&config.frame_poll_interval_ms),
+            ),
+            "frame_timeout_ms": setting(
+                config
+                    .frame_timeout_ms
+                    .unwrap_or(capture::FRAME_TIMEOUT.as_millis() as u64),
+                config_or_default(&config.frame_timeout_ms),
+            ),
+            "settle_frames": setting(
+                config.settle_frames.unwrap_or(capture::SETTLE_FRAMES),
+                config_or_default(&config.settle_frames),
+            ),
+            "restore_clipboard_after": setting(
+                restore_clipboard_after.map(|delay| delay.as_secs()),
+                cli_or_default("restore-clipboard-after"),
+            ),
+            "presets": setting(
+                config.presets.keys().collect::<Vec<_>>(),
+                if config.presets.is_empty() { "default" } else { "config" },
+            ),
+            "masks": setting(
+                config.masks.keys().collect::<Vec<_>>(),
+                if config.masks.is_empty() { "default" } else { "config" },
+            ),
+        });
+        println!("{}", serde_json::to_string_pretty(&effective).unwrap());
+        return Ok(());
+    }
+
+    let queue_uploads = matches.is_present("queue-uploads");
+    if matches.is_present("retry-queue") {
+        let mut uploaded = Vec::new();
+        loop {
+            uploaded.extend(flush_upload_queue(uploader.as_ref(), json)?);
+            if !queue::has_pending(uploader.as_ref())? {
+                break;
+            }
+            thread::sleep(QUEUE_RETRY_INTERVAL);
+        }
+
+        if json {
+            println!("{}", serde_json::json!({ "uploaded_from_queue": uploaded }));
+        } else if uploaded.is_empty() {
+            println!("There are no queued uploads for {}.", uploader.name());
+        }
+        return Ok(());
+    }
+
+    let local: DateTime<Local> = Local::now();
+    let since_last = matches
+        .is_present("since-last")
+        .then(|| since_last::next(SystemTime::from(local)));
+    let output_dir = output_dir.ok_or_else(|| {
+        SnapmeoError::BadArguments(
+            "No output directory was given with `--output_dir` or `output_dir` in the config file"
+                .into(),
+        )
+    })?;
+    // Only named once the capture's been taken, since `{window}` depends on what was captured
+    let screenshot_path = move |window_title: Option<&str>| -> Result<PathBuf, SnapmeoError> {
+        let mut name = render_filename(&filename_template, &local, window_title)?;
+        // Window titles can be long enough to go past the file system's limit on names
+        upload::truncate_file_name(&mut name, upload::MAX_FILE_NAME_BYTES);
+        if let Some((_, timing)) = &since_last {
+            name.push_str(&format!(
+                " ({}{})",
+                since_last::format_duration(timing.session),
+                timing.since_last.map_or(String::new(), |since| format!(
+                    ", +{}",
+                    since_last::format_duration(since)
+                ))
+            ));
+        }
+        // Window titles and `%` codes can both bring in characters that aren't allowed in paths
+        let name = upload::sanitize_file_name(&name, format.extension()).map_err(|_| {
+            SnapmeoError::BadArguments(format!(
+                "The filename template made the empty file name `{}`",
+                name
+            ))
+        })?;
+        Ok(output_dir.join(name))
+    };
+
+    #[cfg(feature = "interactive")]
+    let saves_svg = formats.contains(&Format::Svg);
+    // Whether saving and uploading only needs the file, so that it can be encoded straight from
+    // the captured frame
+    let only_needs_file = formats == [Format::Png]
+        && !auto_format
+        && thumbnail_size.is_none()
+        && ocr_mode.is_none()
+        && max_upload_bytes.is_none();
+    // `vector_layer` is the background and SVG elements of annotations to save SVGs with instead
+    let finish_screenshot = move |buffer: &[u8],
+                                  rect_width: usize,
+                                  rect_height: usize,
+                                  vector_layer: Option<(&[u8], String)>,
+                                  action: ConfirmAction,
+                                  region: Option<Region>,
+                                  recording: Option<&Recording>,
+                                  source_frame: Option<(&[u8], usize, &Region)>,
+                                  window_title: Option<&str>|
+          -> Result<(), SnapmeoError> {
+        let filename = screenshot_path(window_title)?;
+        let region = region.as_ref().map(format_region);
+        if let Some(region) = region.as_ref().filter(|_| copy_region && !json) {
+            println!("Selected region: {}", region);
+        }
+        let add_details = |output: &mut serde_json::Value| {
+            if let Some(region) = &region {
+                output["region"] = serde_json::json!(region);
+            }
+            if let Some((_, timing)) = &since_last {
+                output["since_last_secs"] =
+                    serde_json::json!(timing.since_last.map(|since| since.as_secs_f64()));
+                output["session_secs"] = serde_json::json!(timing.session.as_secs_f64());
+            }
+        };
+        if let Some((session, timing)) = &since_last {
+            if !json {
+                match timing.since_last {
+                    Some(since) => println!(
+                        "Captured {} after the previous capture, {} into the session",
+                        since_last::format_duration(since),
+                        since_last::format_duration(timing.session)
+                    ),
+                    None => println!("Captured the first screenshot of a new session"),
+                }
+            }
+            since_last::save(session)?;
+        }
+        // Recordings are always animated PNGs
+        let (format, encode_options, filename) = if auto_format && recording.is_none() {
+            let format = if encode::looks_photographic(buffer) {
+                Format::Jpeg
+            } else {
+                Format::Png
+            };
+            if verbose {
+                eprintln!(
+                    "The capture looks like {}, so it's being saved as {}",
+                    if format == Format::Jpeg {
+                        "a photo"
+                    } else {
+                        "UI content"
+                    },
+                    format.extension()
+                );
+            }
+            let encode_options = match format {
+                Format::Jpeg => auto_jpeg_options,
+                _ => encode_options,
+            };
+            (
+                format,
+                encode_options,
+                filename.with_extension(format.extension()),
+            )
+        } else {
+            (format, encode_options, filename.clone())
+        };
+        let upload_options = UploadOptions {
+            content_type: format.content_type(),
+            ..upload_options.clone()
+        };
+        let filename = output_path(&filename, on_exists)?;
+        if verbose && formats.contains(&Format::Avif) {
+            eprintln!("Encoding AVIF; this can take a few seconds for large images...");
+        }
+        let save = |options: &EncodeOptions, path: &Path| match (&vector_layer, recording) {
+            (Some((background, elements)), _) if options.format == Format::Svg => {
+                encode::save_svg(background, rect_width, rect_height, elements, path)
+            }
+            (_, Some(recording)) => encode::save_apng(recording, path),
+            _ => match source_frame {
+                Some((frame, stride, region)) => encode::save_png_from_frame(
+                    frame,
+                    stride,
+                    (region.x, region.y, region.width, region.height),
+                    path,
+                ),
+                None => encode::save(buffer, rect_width, rect_height, options, path),
+            },
+        };
+        save(&encode_options, &filename)?;
+        // Checked before anything else sees the file, so that it's the shrunk image that's
+        // optimized, handed to the post-command and pasted
+        let uploading = action != ConfirmAction::CopyImage && ocr_mode != Some(OcrMode::Text);
+        if let Some(max_upload_bytes) = max_upload_bytes.filter(|_| uploading) {
+            check_upload_size(
+                buffer,
+                rect_width,
+                rect_height,
+                &encode_options,
+                &filename,
+                max_upload_bytes,
+                auto_reduce,
+                force,
+            )?;
+        }
+        // Animated PNGs are left alone since not every optimizer keeps their frames intact
+        if optimize_png && format == Format::Png && recording.is_none() {
+            let (before, after) = encode::optimize_png(&filename)?;
+            if verbose {
+                eprintln!(
+                    "Optimizing the PNG took it from {} to {} bytes, saving {:.1}%",
+                    before,
+                    after,
+                    before.saturating_sub(after) as f64 * 100. / before.max(1) as f64
+                );
+            }
+        }
+        if let Some(command) = &post_command {
+            if verbose {
+                eprintln!("Running `{}` on {}", command, filename.display());
+            }
+            run_post_command(command, &filename)?;
+            // The command's output can't be shrunk without throwing its changes away
+            if let Some(max_upload_bytes) = max_upload_bytes.filter(|_| uploading) {
+                let size = fs::metadata(&filename)?.len();
+                if size > max_upload_bytes && force {
+                    warn_oversized(size, max_upload_bytes);
+                } else if size > max_upload_bytes {
+                    return Err(SnapmeoError::Upload {
+                        status: None,
+                        message: format!(
+                            "`{}` left {} at {} bytes, which is over the limit of {} bytes",
+                            command,
+                            filename.display(),
+                            size,
+                            max_upload_bytes
+                        ),
+                    });
+                }
+            }
+        }
+
+        // Copied in the background so that it doesn't hold anything up. It's always waited for
+        // before anything else goes on the clipboard, so that the link replaces the image rather
+        // than the other way around.
+        let mut fast_paste_copy = (fast_paste && action != ConfirmAction::CopyImage).then(|| {
+            let (path, content_type) = (filename.clone(), format.content_type());
+            thread::spawn(move || image_clipboard::copy_image(&path, content_type))
+        });
+
+        let mut saved_paths = Vec::new();
+        for options in &extra_encode_options {
+            let path = output_path(
+                &filename.with_extension(options.format.extension()),
+                on_exists,
+            )?;
+            save(options, &path)?;
+            if !json {
+                println!("Saved {}", path.display());
+            }
+            saved_paths.push(path);
+        }
+
+        if action == ConfirmAction::CopyImage {
+            image_clipboard::copy_image(&filename, format.content_type())?;
+            if json {
+                let mut output = serde_json::json!({ "path": filename });
+                if !saved_paths.is_empty() {
+                    output["saved_paths"] = serde_json::json!(saved_paths);
+                }
+                add_details(&mut output);
+                println!("{}", output);
+            } else {
+                println!(
+                    "{} {}",
+                    "Image copied to the clipboard without uploading:".green(),
+                    filename.display()
+                );
+            }
+            if notify {
+                send_notification(
+                    "Screenshot copied",
+                    &filename.display().to_string(),
+                    &filename,
+                );
+            }
+            return Ok(());
+        }
+
+        let recognized_text = match ocr_mode {
+            Some(mode) => {
+                let text = ocr::recognize(buffer, rect_width, rect_height)?;
+                if text.is_empty() {
+                    eprintln!("{} No text was found in the capture", "Warning:".yellow());
+                } else if verbose {
+                    eprintln!("Recognized text:\n{}", text);
+                }
+
+                if mode == OcrMode::Text {
+                    finish_fast_paste(&mut fast_paste_copy);
+                    if !text.is_empty() {
+                        let mut ctx: ClipboardContext = ClipboardProvider::new()
+                            .map_err(|err| SnapmeoError::Clipboard(err.to_string()))?;
+                        ctx.set_contents(text.clone())
+                            .map_err(|err| SnapmeoError::Clipboard(err.to_string()))?;
+                    }
+                    if json {
+                        let mut output = serde_json::json!({ "path": filename, "text": text });
+                        if !saved_paths.is_empty() {
+                            output["saved_paths"] = serde_json::json!(saved_paths);
+                        }
+                        add_details(&mut output);
+                        println!("{}", output);
+                    } else if !text.is_empty() {
+                        println!(
+                            "{} {}",
+                            "Text copied to the clipboard without uploading:".green(),
+                            filename.display()
+                        );
+                    }
+                    return Ok(());
+                }
+                Some(text).filter(|text| !text.is_empty())
+            }
+            None => None,
+        };
+
+        // Asked before --skip-unchanged looks at the last upload, so it's compared with these settings
+        let upload_options = if interactive {
+            prompt::upload_options(&upload_options, uploader.as_ref()).map_err(|err| {
+                SnapmeoError::Other(format!("Unable to read upload settings: {}", err))
+            })?
+        } else {
+            upload_options.clone()
+        };
+
+        let file_hash = if skip_unchanged {
+            let file_hash = upload::dedup::hash(&fs::read(&filename)?);
+            if let Some(previous) =
+                upload::dedup::previous_upload(uploader.as_ref(), &file_hash, &upload_options)?
+            {
+                finish_fast_paste(&mut fast_paste_copy);
+                let mut ctx: ClipboardContext = ClipboardProvider::new()
+                    .map_err(|err| SnapmeoError::Clipboard(err.to_string()))?;
+                ctx.set_contents(previous.url.clone())
+                    .map_err(|err| SnapmeoError::Clipboard(err.to_string()))?;
+                chain::report_url(&previous.url)?;
+                if json {
+                    let mut output = serde_json::json!({
+                        "path": filename,
+                        "url": previous.url,
+                        "skipped_unchanged": true,
+                    });
+                    add_details(&mut output);
+                    println!("{}", output);
+                } else {
+                    println!(
+                        "{} {} is identical to the last upload to {}, which is at {}",
+                        "Skipped upload:".yellow(),
+                        filename.display(),
+                        uploader.name(),
+                        previous.url
+                    );
+                }
+                return Ok(());
+            }
+            Some(file_hash)
+        } else {
+            None
+        };
+
+        let uploaders: Vec<&dyn Uploader> = iter::once(uploader.as_ref())
+            .chain(broadcast_uploaders.iter().map(|uploader| uploader.as_ref()))
+            .collect();
+        if !json {
+            println!(
+                "Image captured; starting upload to {}...",
+                uploaders
+                    .iter()
+                    .map(|uploader| uploader.name())
+                    .collect::<Vec<_>>()
+                    .join(", ")
+            );
+        }
+        let mut results = upload::upload_all(&uploaders, &filename, &upload_options).into_iter();
+        let primary_result = results
+            .next()
+            .expect("The main uploader is always uploaded to");
+        let broadcast_results: Vec<_> = uploaders[1..].iter().zip(results).collect();
+        for (broadcast_uploader, result) in &broadcast_results {
+            match result {
+                Ok(url) if !json => println!(
+                    "{} {}",
+                    format!("Also uploaded to {}:", broadcast_uploader.name()).green(),
+                    url
+                ),
+                Ok(_) => {}
+                Err(err) => eprintln!(
+                    "{} Upload to {} failed: {}",
+                    "Warning:".yellow(),
+                    broadcast_uploader.name(),
+                    err
+                ),
+            }
+        }
+
+        let mut primary_result = primary_result;
+        if let Some(password_var) = uploader
+            .password_var()
+            .filter(|_| !json && io::stdin().is_terminal())
+        {
+            for _ in 0..PASSWORD_ATTEMPTS {
+                let err = match &primary_result {
+                    Err(err @ SnapmeoError::Auth(_)) => err,
+                    _ => break,
+                };
+                eprintln!("{}", err.to_string().red());
+                let password = match prompt::ask_password(&format!("{} password", uploader.name()))
+                {
+                    Ok(password) if !password.is_empty() => password,
+                    _ => break,
+                };
+                env::set_var(password_var, password);
+                primary_result = uploader.upload(&filename, &upload_options);
+                if primary_result.is_ok() {
+                    println!(
+                        "Set `{}` to the new password to avoid being asked again.",
+                        password_var
+                    );
+                }
+            }
+        }
+
+        if primary_result.is_err() && finish_fast_paste(&mut fast_paste_copy) && !json {
+            println!("The image is still on the clipboard to be pasted.");
+        }
+        let image_url = match primary_result {
+            Ok(image_url) => image_url,
+            Err(err) if queue_uploads && queue::is_unreachable(&err) => {
+                queue::push(QueuedUpload::new(
+                    &filename,
+                    uploader.as_ref(),
+                    &upload_options,
+                ))?;
+                if json {
+                    println!(
+                        "{}",
+                        serde_json::json!({ "path": filename, "queued": true })
+                    );
+                } else {
+                    println!(
+                        "{} {}; {} has been queued to be uploaded later.",
+                        "Upload failed:".yellow(),
+                        err,
+                        filename.display()
+                    );
+                }
+                return Ok(());
+            }
+            Err(err) => return Err(err),
+        };
+        if let Some(file_hash) = file_hash {
+            upload::dedup::record(
+                uploader.as_ref(),
+                file_hash,
+                image_url.clone(),
+                &upload_options,
+            )?;
+        }
+
+        let thumbnail = match thumbnail_size {
+            Some(bounds) => {
+                let (thumbnail_width, thumbnail_height) =
+                    encode::fit_within(rect_width, rect_height, bounds);
+                let thumbnail = encode::resize(
+                    buffer,
+                    rect_width,
+                    rect_height,
+                    thumbnail_width,
+                    thumbnail_height,
+                );
+                let thumbnail_path = output_path(&thumbnail_path(&filename), on_exists)?;
+                encode::save(
+                    &thumbnail,
+                    thumbnail_width,
+                    thumbnail_height,
+                    &encode_options,
+                    &thumbnail_path,
+                )?;
+
+                if !json {
+                    println!(
+                        "Uploading {}x{} thumbnail...",
+                        thumbnail_width, thumbnail_height
+                    );
+                }
+                let thumbnail_url =
+                    uploader.upload(&thumbnail_path, &upload_options.for_thumbnail())?;
+                Some((thumbnail_path, thumbnail_url))
+            }
+            None => None,
+        };
+
+        if let Err(err) = history::record(history::Entry {
+            url: image_url.clone(),
+            path: filename.clone(),
+            thumbnail: thumbnail
+                .as_ref()
+                .map(|(thumbnail_path, _)| thumbnail_path.clone()),
+            uploaded_at: Local::now().to_rfc3339(),
+            tags: tags.clone(),
+        }) {
+            eprintln!(
+                "{} Unable to add the upload to the history: {}",
+                "Warning:".yellow(),
+                err
+            );
+        }
+
+        // Copy the URL to the clipboard and print to the console
+        finish_fast_paste(&mut fast_paste_copy);
+        let mut ctx: ClipboardContext =
+            ClipboardProvider::new().map_err(|err| SnapmeoError::Clipboard(err.to_string()))?;
+        let copied = template::render(&clipboard_template, |name| match name {
+            "url" => Some(image_url.clone()),
+            "id" => upload_id(&image_url),
+            "width" => Some(rect_width.to_string()),
+            "height" => Some(rect_height.to_string()),
+            "date" => Some(Local::now().format("%Y-%m-%d").to_string()),
+            "path" => Some(filename.display().to_string()),
+            "name" => filename
+                .file_name()
+                .map(|name| name.to_string_lossy().into_owned()),
+            "text" => recognized_text.clone(),
+            "region" => region.clone(),
+            _ => None,
+        })
+        .map_err(SnapmeoError::BadArguments)?;
+        let copied_flavor = match clipboard_flavor {
+            Flavor::Url => Ok(()),
+            Flavor::Image => image_clipboard::copy_image(&filename, format.content_type()),
+            Flavor::FileUri => image_clipboard::copy_file(&filename),
+            Flavor::Auto => {
+                image_clipboard::copy_image_and_text(&filename, format.content_type(), &copied)
+            }
+        };
+        let copied_link = match copied_flavor {
+            Ok(()) => clipboard_flavor == Flavor::Url,
+            Err(err) => {
+                // Falling back is expected with `auto` on platforms that can't do it
+                if verbose || clipboard_flavor != Flavor::Auto {
+                    eprintln!("{} Only copying the link: {}", "Warning:".yellow(), err);
+                }
+                true
+            }
+        };
+        if copied_link {
+            ctx.set_contents(copied)
+                .map_err(|err| SnapmeoError::Clipboard(err.to_string()))?;
+        }
+
+        // The backend is evidently reachable again, so this is a good time to catch up
+        let uploaded_from_queue = if queue_uploads {
+            flush_upload_queue(uploader.as_ref(), json)?
+        } else {
+            Vec::new()
+        };
+
+        chain::report_url(&image_url)?;
+        if json {
+            let mut output = serde_json::json!({ "path": filename, "url": image_url });
+            if let Some((thumbnail_path, thumbnail_url)) = &thumbnail {
+                output["thumbnail_path"] = serde_json::json!(thumbnail_path);
+                output["thumbnail_url"] = serde_json::json!(thumbnail_url);
+            }
+            if !saved_paths.is_empty() {
+                output["saved_paths"] = serde_json::json!(saved_paths);
+            }
+            if let Some(text) = &recognized_text {
+                output["text"] = serde_json::json!(text);
+            }
+            if !uploaded_from_queue.is_empty() {
+                output["uploaded_from_queue"] = serde_json::json!(uploaded_from_queue);
+            }
+            if !broadcast_results.is_empty() {
+                output["broadcast"] = broadcast_results
+                    .iter()
+                    .map(|(broadcast_uploader, result)| match result {
+                        Ok(url) => serde_json::json!({
+                            "uploader": broadcast_uploader.name(),
+                            "url": url,
+                        }),
+                        Err(err) => serde_json::json!({
+                            "uploader": broadcast_uploader.name(),
+                            "error": err.to_string(),
+                        }),
+                    })
+                    .collect();
+            }
+            add_details(&mut output);
+            println!("{}", output);
+        } else {
+            println!("{} {}", "File successfully uploaded:".green(), image_url);
+            if let Some((_, thumbnail_url)) = &thumbnail {
+                println!("{} {}", "Thumbnail uploaded:".green(), thumbnail_url);
+            }
+            if copy_region {
+                println!("Region has been copied to the clipboard.");
+            } else if copied_link {
+                println!("Link has been copied to the clipboard.");
+            } else if clipboard_flavor == Flavor::Image {
+                println!("Image has been copied to the clipboard.");
+            } else if clipboard_flavor == Flavor::FileUri {
+                println!("File has been copied to the clipboard.");
+            } else {
+                println!("Image, file, and link have been copied to the clipboard.");
+            }
+        }
+        if notify {
+            send_notification("Screenshot uploaded", &image_url, &filename);
+        }
+
+        Ok(())
+    };
+
+    if matches.is_present("from-clipboard") {
+        let (buffer, image_width, image_height) = image_clipboard::paste_image()?;
+        let (buffer, image_width, image_height) = match &timestamp_bar {
+            Some(style) => {
+                add_timestamp_bar(&buffer, image_width, image_height, Local::now(), style)
+            }
+            None => (buffer, image_width, image_height),
+        };
+        let (buffer, image_width, image_height) = if padding > 0 {
+            buffer::pad(&buffer, image_width, image_height, padding, background)
+        } else {
+            (buffer, image_width, image_height)
+        };
+        let (buffer, image_width, image_height) = match &border {
+            Some(border) => buffer::add_border(&buffer, image_width, image_height, border),
+            None => (buffer, image_width, image_height),
+        };
+        return finish_screenshot(
+            &buffer,
+            image_width,
+            image_height,
+            None,
+            ConfirmAction::Upload,
+            None,
+            None,
+            None,
+            None,
+        );
+    }
+
+    if let Some(deadline) = scheduled_at {
+        schedule::wait_until(deadline);
+    }
+
+    // Scripted captures of a known region that don't show anything on screen don't need SDL at
+    // all. They always capture the primary display so that their coordinates don't depend on where
+    // the cursor happens to be.
+    let region_known = preset_region.is_some()
+        || region_percent.is_some()
+        || ["window", "window-under-cursor", "active-window"]
+            .iter()
+            .any(|&flag| matches.is_present(flag));
+    let needs_overlay = !region_known
+        || confirm
+        || annotate
+        || scroll
+        || pin
+        || sound
+        || delay.is_some()
+        || hold_key.is_some()
+        || matches.is_present("retina-downscale");
+    #[cfg(not(feature = "interactive"))]
+    if needs_overlay {
+        return Err(not_interactive(if region_known {
+            "`--confirm`, `--annotate`, `--scroll`, `--pin`, `--sound`, `--delay`, \
+             `--hold-to-select`, and `--retina-downscale` each"
+        } else {
+            "Selecting the region to capture"
+        }));
+    }
+    #[cfg(feature = "interactive")]
+    let sdl = if needs_overlay {
+        let sdl_context = sdl2::init().map_err(sdl_error)?;
+        let video_subsystem = sdl_context.video().map_err(sdl_error)?;
+        // This has to be set before any textures are created to affect them
+        sdl2::hint::set("SDL_RENDER_SCALE_QUALITY", preview_scale_quality);
+        Some((sdl_context, video_subsystem))
+    } else {
+        None
+    };
+
+    // TODO: Parallelize with window creation + canvas setup
+    #[cfg(feature = "interactive")]
+    let (cursor_display, mut capturer) = match sdl
+        .as_ref()
+        .and_then(|(_, video_subsystem)| cursor_display(video_subsystem))
+        .and_then(|(index, bounds)| {
+            // SDL and the capturer list displays independently, so the capturer's display is
+            // found by where it is instead. Anything ambiguous gets the primary display.
+            let capture_index = capture::find_display(
+                &capture::display_rects()?,
+                (
+                    bounds.x() as i64,
+                    bounds.y() as i64,
+                    bounds.width() as usize,
+                    bounds.height() as usize,
+                ),
+            )?;
+            let capturer = capture::open_display(capture_index).ok()?;
+            Some(((index, bounds, capture_index), capturer))
+        }) {
+        Some((display, capturer)) => (Some(display), capturer),
+        None => (None, capture::open_primary()?),
+    };
+    #[cfg(feature = "interactive")]
+    let (display_index, display_origin, capture_index) = (
+        cursor_display.map(|(index, _, _)| index),
+        cursor_display.map_or((0, 0), |(_, bounds, _)| {
+            (bounds.x() as i64, bounds.y() as i64)
+        }),
+        cursor_display.map(|(_, _, capture_index)| capture_index),
+    );
+    #[cfg(not(feature = "interactive"))]
+    let (display_origin, capture_index, mut capturer) =
+        ((0, 0), None::<usize>, capture::open_primary()?);
+    let open_capturer = || match capture_index {
+        Some(index) => capture::open_display(index),
+        None => capture::open_primary(),
+    };
+    let one_second = Duration::new(1, 0);
+    let one_frame = one_second / 60;
+    let frame_poll_interval = config
+        .frame_poll_interval_ms
+        .map_or(one_frame, Duration::from_millis);
+    let frame_timeout = config
+        .frame_timeout_ms
+        .map_or(capture::FRAME_TIMEOUT, Duration::from_millis);
+    capture::settle(
+        &mut capturer,
+        config.settle_frames.unwrap_or(capture::SETTLE_FRAMES),
+        frame_poll_interval,
+        frame_timeout,
+    )?;
+
+    let (width, height) = (capturer.width(), capturer.height());
+    let preset_region = match region_percent {
+        Some(percent) => Some(buffer::region_from_percent(percent, (width, height))),
+        None => preset_region,
+    };
+    let masks: Vec<(i64, i64, usize, usize)> = if matches.is_present("no-masks") {
+        Vec::new()
+    } else {
+        config
+            .masks()
+            .into_iter()
+            .map(|mask| match mask {
+                PresetRegion::Pixels(region) => region,
+                PresetRegion::Percent(percent) => {
+                    buffer::region_from_percent(percent, (width, height))
+                }
+            })
+            .collect()
+    };
+
+    #[cfg(feature = "interactive")]
+    let mut overlay = match sdl {
+        Some((sdl_context, video_subsystem)) => {
+            // The window is kept hidden until there's something to show so that it doesn't end up
+            // in the captured frame
+            let window = video_subsystem
+                .window("rust-sdl2 demo: Video", width as u32, height as u32)
+                .position(display_origin.0 as i32, display_origin.1 as i32)
+                // .position_centered()
+                .opengl()
+                // .vulkan()
+                .allow_highdpi()
+                // .fullscreen_desktop()
+                .borderless()
+                .hidden()
+                .build()
+                .map_err(sdl_error)?;
+            let canvas = window.into_canvas().build().map_err(sdl_error)?;
+            let event_pump = sdl_context.event_pump().map_err(sdl_error)?;
+            Some(Overlay {
+                sdl_context,
+                video_subsystem,
+                canvas,
+                event_pump,
+            })
+        }
+        None => None,
+    };
+    #[cfg(not(feature = "interactive"))]
+    let mut overlay = None;
+    #[cfg(feature = "interactive")]
+    let texture_creator = overlay
+        .as_ref()
+        .map(|overlay| overlay.canvas.texture_creator());
+    // Platforms differ in whether HiDPI scaling shows up in the display's bounds or only in the
+    // window's drawable size, so both are checked
+    #[cfg(feature = "interactive")]
+    let retina_factor = if matches.is_present("retina-downscale") {
+        let overlay = needed(&mut overlay)?;
+        let display_factor = overlay
+            .video_subsystem
+            .display_bounds(display_index.map_or(0, |index| index as i32))
+            .ok()
+            .filter(|bounds| bounds.width() > 0)
+            .map_or(1., |bounds| width as f64 / bounds.width() as f64);
+        Some(display_factor.max(selection::scale_factor(&overlay.canvas)))
+            .filter(|&factor| factor > 1.01)
+    } else {
+        None
+    };
+    #[cfg(not(feature = "interactive"))]
+    let retina_factor: Option<f64> = None;
+    let work_area = if matches.is_present("work-area") {
+        // Captures of known regions don't otherwise need SDL, so it's started just to ask
+        #[cfg(feature = "interactive")]
+        let area = {
+            let index = display_index.map(|index| index as i32);
+            match &overlay {
+                Some(overlay) => work_area(&overlay.video_subsystem, index, (width, height)),
+                None => sdl2::init()
+                    .and_then(|sdl_context| sdl_context.video())
+                    .ok()
+                    .and_then(|video_subsystem| {
+                        work_area(&video_subsystem, index, (width, height))
+                    }),
+            }
+        };
+        #[cfg(not(feature = "interactive"))]
+        let area = None;
+        Some(match area {
+            Some(area) => area,
+            None => {
+                eprintln!(
+                    "{} Unable to find the display's work area, so the whole display can be \
+                     captured",
+                    "Warning:".yellow()
+                );
+                Region {
+                    x: 0,
+                    y: 0,
+                    width,
+                    height,
+                }
+            }
+        })
+    } else {
+        None
+    };
+    if let (true, Some(area)) = (verbose, &work_area) {
+        eprintln!(
+            "Keeping captures within the display's work area of {}x{} at ({}, {})",
+            area.width, area.height, area.x, area.y
+        );
+    }
+    // Cropped to the aspect ratio last so that cropping to the work area can't throw it off
+    let constrain_region = |region: Region| {
+        let region = match &work_area {
+            Some(area) => region.within(area).ok_or_else(|| {
+                SnapmeoError::BadArguments(
+                    "The selected region is entirely outside the display's work area".into(),
+                )
+            })?,
+            None => region,
+        };
+        Ok::<_, SnapmeoError>(match aspect {
+            Some(aspect) => region.crop_to_aspect(aspect),
+            None => region,
+        })
+    };
+    let cursor_image = if matches.is_present("cursor") || matches.is_present("cursor-image") {
+        // The system cursor is already the right size for the display, but the others are drawn
+        // at their own sizes
+        #[cfg(feature = "interactive")]
+        let hidpi_factor = overlay
+            .as_ref()
+            .and_then(|overlay| {
+                overlay
+                    .video_subsystem
+                    .display_bounds(display_index.map_or(0, |index| index as i32))
+                    .ok()
+            })
+            .filter(|bounds| bounds.width() > 0)
+            .map_or(1., |bounds| width as f64 / bounds.width() as f64);
+        #[cfg(not(feature = "interactive"))]
+        let hidpi_factor = 1.;
+        Some(match matches.value_of("cursor-image") {
+            Some(path) => CursorImage::load(Path::new(path))
+                .map_err(SnapmeoError::BadArguments)?
+                .scaled(hidpi_factor),
+            None => cursor::system_cursor()
+                .unwrap_or_else(|_| CursorImage::arrow().scaled(hidpi_factor)),
+        })
+    } else {
+        None
+    };
+    if let (true, Some(factor)) = (verbose, retina_factor) {
+        eprintln!(
+            "Scaling captures down by the display's scale factor of {}",
+            factor
+        );
+    }
+
+    #[cfg(feature = "interactive")]
+    let recapture = matches.is_present("recapture");
+    #[cfg(feature = "interactive")]
+    if let Some(delay) = delay.filter(|_| !recapture) {
+        let Overlay {
+            canvas, event_pump, ..
+        } = needed(&mut overlay)?;
+        if !count_down(canvas, event_pump, delay, one_frame) {
+            return Err(SnapmeoError::Cancelled);
+        }
+    }
+
+    #[cfg(feature = "interactive")]
+    let hold_watcher = hold_key.map(HoldWatcher::new);
+    #[cfg(feature = "interactive")]
+    if let Some(hold_watcher) = hold_watcher.as_ref().filter(|watcher| watcher.is_global()) {
+        // The frame is only grabbed once the key is held so that it's current
+        let event_pump = &mut needed(&mut overlay)?.event_pump;
+        let start = Instant::now();
+        while !hold_watcher.is_held(event_pump) {
+            if select_timeout.is_some_and(|timeout| start.elapsed() >= timeout) {
+                return Err(selection_ended(start, select_timeout));
+            }
+            event_pump.pump_events();
+            thread::sleep(one_frame);
+        }
+    }
+
+    // The window is looked up as late as possible so that it can be moved during the delay
+    let target_window = match matches.value_of("window") {
+        Some(query) => Some(window::find_window(query)),
+        None if matches.is_present("window-under-cursor") => Some(window::window_under_cursor()),
+        None if matches.is_present("active-window") => Some(window::active_window()),
+        None => None,
+    }
+    .transpose()
+    .map_err(|err| SnapmeoError::Capture(err.to_string()))?
+    // Windows are positioned relative to the whole desktop rather than the captured display
+    .map(|mut window| {
+        window.x -= display_origin.0;
+        window.y -= display_origin.1;
+        window
+    });
+    let preset_region = match &target_window {
+        Some(window) => {
+            let (window_x, window_y, window_width, window_height) = window.bounds(decorations);
+            Some(match preset_region {
+                Some((x, y, region_width, region_height)) => {
+                    (window_x + x, window_y + y, region_width, region_height)
+                }
+                None => (window_x, window_y, window_width, window_height),
+            })
+        }
+        None => preset_region,
+    };
+
+    #[cfg(feature = "interactive")]
+    let live_region = if live && preset_region.is_none() {
+        let start = Instant::now();
+        let Overlay {
+            canvas, event_pump, ..
+        } = needed(&mut overlay)?;
+        let region = selection::select_region_live(canvas, event_pump, select_timeout, one_frame)
+            .map_err(SnapmeoError::Other)?;
+        Some(region.ok_or_else(|| selection_ended(start, select_timeout))?)
+    } else {
+        None
+    };
+    #[cfg(not(feature = "interactive"))]
+    let live_region: Option<Region> = None;
+
+    thread::sleep(
+        config
+            .settle_delay_ms
+            .map_or(capture::SETTLE_DELAY, Duration::from_millis),
+    );
+    let mut frame = capture::grab_frame(
+        &mut capturer,
+        open_capturer,
+        frame_poll_interval,
+        frame_timeout,
+    )?;
+    // Black screens are sometimes what's really there, which `--force` vouches for
+    if !force {
+        if let Err(err) = capture::check_permission(&frame) {
+            if !json
+                && io::stdin().is_terminal()
+                && prompt::ask_to_continue(
+                    "The screen came back entirely black, which probably means snapmeo isn't \
+                     allowed to record it. Open System Settings to allow it?",
+                )?
+            {
+                capture::open_permission_settings();
+            }
+            return Err(err);
+        }
+    }
+    // Read straight away, before the overlay can move the cursor or change what it looks like
+    let cursor = if let Some(image) = cursor_image {
+        match window::cursor_position() {
+            Ok((x, y)) => Some((image, (x - display_origin.0, y - display_origin.1))),
+            Err(err) => {
+                eprintln!(
+                    "{} Unable to find the mouse cursor, so it isn't drawn: {}",
+                    "Warning:".yellow(),
+                    err
+                );
+                None
+            }
+        }
+    } else {
+        None
+    };
+    #[cfg_attr(not(feature = "interactive"), allow(unused_mut))]
+    let mut captured_at = Local::now();
+    // println!("Captured screenshot frame!");
+
+    // TODO: Pull this directly from the pixel buffer.  No reason not to.
+    // let texture = texture_creator.load_texture("output.png").unwrap();
+    #[cfg(feature = "interactive")]
+    let mut texture = texture_creator.as_ref().map(|texture_creator| {
+        let mut texture = texture_creator
+            .create_texture_static(Some(PixelFormatEnum::ARGB8888), width as u32, height as u32)
+            .expect("Unable to create texture!");
+        texture
+            .update(None, &frame, width * 4)
+            .expect("Error updating texture with image data!");
+        texture
+    });
+
+    // Live selections are captured as soon as the mouse is released, so unless they're confirmed
+    // there's no frozen frame to flash them over
+    #[cfg(feature = "interactive")]
+    let selected_interactively = confirm || (live_region.is_none() && preset_region.is_none());
+    #[cfg(feature = "interactive")]
+    let mut selected_window = false;
+    #[cfg(feature = "interactive")]
+    let edges = selected_interactively.then(|| snap::EdgeMap::new(&frame, width, height));
+    let region = match (live_region, preset_region) {
+        (Some(region), _) => region,
+        (None, Some((x, y, region_width, region_height))) => {
+            Region::clamped(x, y, region_width, region_height, (width, height)).ok_or_else(
+                || SnapmeoError::BadArguments("The selected region is entirely off screen".into()),
+            )?
+        }
+        #[cfg(not(feature = "interactive"))]
+        (None, None) => unreachable!("Headless builds only capture known regions"),
+        #[cfg(feature = "interactive")]
+        (None, None) => {
+            let Overlay {
+                canvas, event_pump, ..
+            } = needed(&mut overlay)?;
+            let texture = needed(&mut texture)?;
+            show_frozen_frame(canvas, texture);
+            let start = Instant::now();
+            let selection = selection::select_region(
+                canvas,
+                texture,
+                event_pump,
+                (width, height),
+                work_area.as_ref(),
+                aspect,
+                display_origin,
+                fixed_size.map(|size| (size, anchor)),
+                !on_click,
+                edges.as_ref(),
+                hold_watcher.as_ref(),
+                select_timeout,
+                one_frame,
+            )
+            .ok_or_else(|| selection_ended(start, select_timeout))?;
+            selected_window = selection.from_window;
+            selection.region
+        }
+    };
+    let region = constrain_region(region)?;
+    // println!("Selected region: {:?}", region);
+    let confirm_start = Instant::now();
+    // A double-clicked window's bounds can still be adjusted before they're captured
+    #[cfg(feature = "interactive")]
+    let (action, region) = if confirm || selected_window {
+        let Overlay {
+            canvas, event_pump, ..
+        } = needed(&mut overlay)?;
+        let texture = needed(&mut texture)?;
+        show_frozen_frame(canvas, texture);
+        selection::confirm_selection(
+            canvas,
+            texture,
+            event_pump,
+            region,
+            (width, height),
+            edges.as_ref(),
+            upload_one_time,
+            select_timeout,
+            one_frame,
+        )
+    } else {
+        (ConfirmAction::Upload, region)
+    };
+    #[cfg(not(feature = "interactive"))]
+    let action = ConfirmAction::Upload;
+    if action == ConfirmAction::Cancel {
+        return Err(selection_ended(confirm_start, select_timeout));
+    }
+    let region = constrain_region(region)?;
+    // The frozen frame only decided where to capture; what's captured is the screen after the delay
+    #[cfg(feature = "interactive")]
+    if let Some(delay) = delay.filter(|_| recapture) {
+        let Overlay {
+            canvas, event_pump, ..
+        } = needed(&mut overlay)?;
+        if !count_down(canvas, event_pump, delay, one_frame) {
+            return Err(SnapmeoError::Cancelled);
+        }
+        frame = capture::grab_frame(
+            &mut capturer,
+            open_capturer,
+            frame_poll_interval,
+            frame_timeout,
+        )?;
+        captured_at = Local::now();
+        let texture = needed(&mut texture)?;
+        texture
+            .update(None, &frame, width * 4)
+            .expect("Error updating texture with image data!");
+        if selected_interactively {
+            show_frozen_frame(canvas, texture);
+        }
+    }
+    // Kept around until the end so that the sound isn't cut off
+    #[cfg(feature = "interactive")]
+    let _shutter_sound = if sound {
+        sound::play_shutter(&needed(&mut overlay)?.sdl_context, sound_file)
+            .map_err(|err| {
+                eprintln!(
+                    "{} Unable to play the shutter sound: {}",
+                    "Warning:".yellow(),
+                    err
+                )
+            })
+            .ok()
+    } else {
+        None
+    };
+    #[cfg(feature = "interactive")]
+    if selected_interactively {
+        let canvas = &mut needed(&mut overlay)?.canvas;
+        selection::flash_selection(canvas, needed(&mut texture)?, &region, one_frame);
+    }
+
+    if let Some((image, position)) = &cursor {
+        image.draw(&mut frame, width, height, *position);
+    }
+    let stride = width * 4;
+    if let Some([r, g, b]) = buffer::solid_color(&frame, stride, &region).filter(|_| !force) {
+        eprintln!(
+            "{} The capture is entirely #{:02x}{:02x}{:02x}, which usually means that capturing \
+             failed or that the region was empty or offscreen. Pass `--force` to skip this check.",
+            "Warning:".yellow(),
+            r,
+            g,
+            b
+        );
+        if !json && io::stdin().is_terminal() {
+            hide_overlay(&mut overlay);
+            if !prompt::ask_to_continue("Save and upload it anyway?")? {
+                return Err(SnapmeoError::Cancelled);
+            }
+        }
+    }
+    // Huge captures that are saved just as they were captured are encoded straight from the frame
+    // rather than being copied out into a buffer of their own first, leaving `buffer` empty. Every
+    // step below that changes the capture must be listed here.
+    let stream_from_frame = only_needs_file
+        && region.width * region.height * 4 > encode::STREAMING_THRESHOLD
+        && masks.is_empty()
+        && !annotate
+        && !scroll
+        && !pin
+        && !only_changed_region
+        && compare.is_none()
+        && record_duration.is_none()
+        && retina_factor.is_none()
+        && timestamp_bar.is_none()
+        && padding == 0
+        && border.is_none()
+        && transparent_window_backdrop.is_none();
+    let mut buffer = if stream_from_frame {
+        Vec::new()
+    } else {
+        buffer::crop(&frame, stride, &region)
+    };
+    if let (Some(backdrop), Some(window)) = (transparent_window_backdrop, &target_window) {
+        match window::capture_with_alpha(window) {
+            Ok(pixels) => apply_window_alpha(&mut buffer, &region, window, &pixels, backdrop),
+            Err(err) => eprintln!(
+                "{} Unable to capture the window's transparency, so it's shown as it was on \
+                 screen: {}",
+                "Warning:".yellow(),
+                err
+            ),
+        }
+    }
+    apply_masks(&mut buffer, &region, &masks);
+    let changed_part = if only_changed_region {
+        changes::changed_part(&region, &buffer)?
+    } else {
+        None
+    };
+    let region = match changed_part {
+        Some(part) => {
+            buffer = buffer::crop_rgba(&buffer, region.width, &part);
+            let changed = Region {
+                x: region.x + part.x,
+                y: region.y + part.y,
+                ..part
+            };
+            if verbose {
+                eprintln!(
+                    "Only {} changed since the previous capture",
+                    format_region(&changed)
+                );
+            }
+            changed
+        }
+        None => region,
+    };
+    let recording = match record_duration {
+        Some(duration) => {
+            hide_overlay(&mut overlay);
+            // Give the window manager time to actually get the overlay off of the screen
+            thread::sleep(one_frame * 4);
+            if !json {
+                println!("Recording {}x{}...", region.width, region.height);
+            }
+            let mut recording = record::record(
+                || {
+                    capture::grab_frame(
+                        &mut capturer,
+                        open_capturer,
+                        frame_poll_interval,
+                        frame_timeout,
+                    )
+                    .map(|frame| {
+                        let mut buffer = buffer::crop(&frame, stride, &region);
+                        apply_masks(&mut buffer, &region, &masks);
+                        buffer
+                    })
+                },
+                region.width,
+                region.height,
+                duration,
+                Duration::from_secs_f64(1. / capture_fps),
+            )?;
+            // The first frame stands in for the recording wherever a still image is needed
+            buffer = recording.frames[0].0.clone();
+            if let Some(playback_fps) = playback_fps {
+                recording.scale_delays(capture_fps / playback_fps);
+            }
+            Some(recording)
+        }
+        None => None,
+    };
+    let mut captured_width = region.width;
+    if let Some(mode) = compare {
+        hide_overlay(&mut overlay);
+        eprintln!("Captured the before image; make your changes and press enter to capture the after image.");
+        io::stdin().read_line(&mut String::new())?;
+        let frame = capture::grab_frame(
+            &mut capturer,
+            open_capturer,
+            frame_poll_interval,
+            frame_timeout,
+        )?;
+        let mut after = buffer::crop(&frame, stride, &region);
+        apply_masks(&mut after, &region, &masks);
+        buffer = match mode {
+            CompareMode::SideBySide => {
+                let (combined, combined_width, _) = buffer::side_by_side(
+                    &buffer,
+                    &after,
+                    region.width,
+                    region.height,
+                    COMPARE_GAP,
+                    background,
+                );
+                captured_width = combined_width;
+                combined
+            }
+            CompareMode::Diff => buffer::highlight_diff(&buffer, &after, COMPARE_HIGHLIGHT),
+        };
+    }
+    #[cfg(feature = "interactive")]
+    let (buffer, vector_annotations) = if annotate {
+        let Overlay {
+            canvas, event_pump, ..
+        } = needed(&mut overlay)?;
+        let texture = needed(&mut texture)?;
+        show_frozen_frame(canvas, texture);
+        let annotated = annotate::annotate(
+            canvas,
+            texture,
+            event_pump,
+            buffer,
+            &region,
+            &annotation_style,
+            one_frame,
+        )
+        .ok_or(SnapmeoError::Cancelled)?;
+        let elements = match retina_factor {
+            // Shapes are drawn in physical pixels, so they're scaled down along with the image
+            Some(factor) => format!(
+                "<g transform=\"translate({inset} {inset}) scale({})\">\n{}\n</g>",
+                1. / factor,
+                annotated.svg_elements((0, 0)),
+                inset = image_inset
+            ),
+            None => annotated.svg_elements((image_inset as i32, image_inset as i32)),
+        };
+        let vector_annotations = if saves_svg {
+            Some((annotated.background, elements))
+        } else {
+            None
+        };
+        (annotated.buffer, vector_annotations)
+    } else {
+        (buffer, None)
+    };
+    #[cfg(not(feature = "interactive"))]
+    let (buffer, vector_annotations): (_, Option<(Vec<u8>, String)>) = (buffer, None);
+
+    #[cfg(feature = "interactive")]
+    let captured = if scroll {
+        hide_overlay(&mut overlay);
+        let Overlay {
+            video_subsystem,
+            event_pump,
+            ..
+        } = needed(&mut overlay)?;
+        scroll::capture(
+            video_subsystem,
+            event_pump,
+            || {
+                capture::grab_frame(
+                    &mut capturer,
+                    open_capturer,
+                    frame_poll_interval,
+                    frame_timeout,
+                )
+                .map(|frame| {
+                    let mut buffer = buffer::crop(&frame, stride, &region);
+                    apply_masks(&mut buffer, &region, &masks);
+                    buffer
+                })
+            },
+            buffer,
+            region.width,
+            region.height,
+            one_frame,
+        )?
+    } else {
+        Some((buffer, region.height))
+    };
+    #[cfg(not(feature = "interactive"))]
+    let captured = Some((buffer, region.height));
+
+    let (buffer, rect_height) = captured.ok_or(SnapmeoError::Cancelled)?;
+    let downscale = |buffer: Vec<u8>, width: usize, height: usize| match retina_factor {
+        Some(factor) => {
+            let scaled_width = cmp::max((width as f64 / factor).round() as usize, 1);
+            let scaled_height = cmp::max((height as f64 / factor).round() as usize, 1);
+            (
+                encode::resize(&buffer, width, height, scaled_width, scaled_height),
+                scaled_width,
+                scaled_height,
+            )
+        }
+        None => (buffer, width, height),
+    };
+    let decorate = |buffer: Vec<u8>, width: usize, height: usize| {
+        let (buffer, width, height) = match &timestamp_bar {
+            Some(style) => add_timestamp_bar(&buffer, width, height, captured_at, style),
+            None => (buffer, width, height),
+        };
+        let (buffer, width, height) = if padding > 0 {
+            buffer::pad(&buffer, width, height, padding, background)
+        } else {
+            (buffer, width, height)
+        };
+        // Last of all so that the border goes around everything else
+        match &border {
+            Some(border) => buffer::add_border(&buffer, width, height, border),
+            None => (buffer, width, height),
+        }
+    };
+    let (buffer, rect_width, rect_height) = downscale(buffer, captured_width, rect_height);
+    let (buffer, rect_width, rect_height) = decorate(buffer, rect_width, rect_height);
+    let recording = recording.map(|recording| {
+        recording.map_frames(|frame, width, height| {
+            let (frame, width, height) = downscale(frame, width, height);
+            decorate(frame, width, height)
+        })
+    });
+    let vector_layer = vector_annotations.map(|(background, elements)| {
+        let (background, width, height) = downscale(background, region.width, region.height);
+        (decorate(background, width, height).0, elements)
+    });
+
+    if interactive && action == ConfirmAction::Upload {
+        // Get out of the way of the terminal
+        hide_overlay(&mut overlay);
+    }
+    // Read before anything is copied so that it can be put back afterwards
+    let previous_clipboard = restore_clipboard_after.and_then(|_| clipboard_text());
+    // Anything that changes the capture has to turn off `stream_from_frame`, or the change is lost
+    debug_assert!(
+        !stream_from_frame
+            || (buffer.is_empty() && (rect_width, rect_height) == (region.width, region.height)),
+        "A capture that's encoded straight from the frame was changed"
+    );
+    let result = finish_screenshot(
+        &buffer,
+        rect_width,
+        rect_height,
+        vector_layer
+            .as_ref()
+            .map(|(background, elements)| (background.as_slice(), elements.clone())),
+        action,
+        Some(region),
+        recording.as_ref(),
+        stream_from_frame.then_some((frame.as_slice(), stride, &region)),
+        target_window.as_ref().map(|window| window.title.as_str()),
+    );
+    let copied = previous_clipboard
+        .as_ref()
+        .filter(|_| result.is_ok())
+        .and_then(|_| clipboard_text());
+    let copied_at = Instant::now();
+
+    #[cfg(feature = "interactive")]
+    if pin && !buffer.is_empty() {
+        hide_overlay(&mut overlay);
+        let Overlay {
+            video_subsystem,
+            event_pump,
+            ..
+        } = needed(&mut overlay)?;
+        pin::pin(
+            video_subsystem,
+            event_pump,
+            &buffer,
+            rect_width,
+            rect_height,
+            (width, height),
+            one_frame,
+        );
+    }
+
+    if let (Some(delay), Some(previous), Some(copied)) =
+        (restore_clipboard_after, previous_clipboard, copied)
+    {
+        let remaining = delay.saturating_sub(copied_at.elapsed());
+        if !json && !remaining.is_zero() {
+            println!(
+                "Restoring the previous clipboard contents in {} seconds...",
+                remaining.as_secs_f32().ceil()
+            );
+        }
+        restore_clipboard(previous, &copied, remaining)?;
+    }
+
+    result
 }
@@ -0,0 +1,54 @@
+//! `--notify`, which shows a desktop notification with a preview of the capture once it's done.
+//!
+//! Notifications are sent with the `notify-send` command on freedesktop desktops, where the capture
+//! is shown as the notification's image, and with `osascript` on macOS, where notifications can't
+//! have custom images and so are text only.
+
+use std::path::Path;
+use std::process::Command;
+
+use crate::encode::Format;
+
+/// Shows a notification with `summary` and `body`, previewing the image at `image` if the platform
+/// supports it and it's in a format notification servers can show. Returns a description of the
+/// problem if it couldn't be shown.
+pub fn notify(summary: &str, body: &str, image: Option<(&Path, Format)>) -> Result<(), String> {
+    let image = image
+        .filter(|(_, format)| matches!(format, Format::Png | Format::Jpeg | Format::Bmp))
+        .map(|(path, _)| path);
+    let status = if cfg!(target_os = "macos") {
+        let script = format!(
+            "display notification {} with title {}",
+            apple_script_string(body),
+            apple_script_string(summary)
+        );
+        Command::new("osascript").arg("-e").arg(script).status()
+    } else if cfg!(unix) {
+        let mut command = Command::new("notify-send");
+        command.arg("--app-name=snapmeo");
+        if let Some(image) = image {
+            // The icon is what most servers show; the hint lets those that can show a full image
+            // show it bigger
+            command
+                .arg("--icon")
+                .arg(image)
+                .arg("--hint")
+                .arg(format!("string:image-path:{}", image.display()));
+        }
+        command.arg(summary).arg(body).status()
+    } else {
+        return Err("Desktop notifications aren't supported on this platform".into());
+    }
+    .map_err(|err| format!("Unable to send a notification: {}", err))?;
+
+    if status.success() {
+        Ok(())
+    } else {
+        Err("The notification couldn't be shown".into())
+    }
+}
+
+/// Quotes `s` as an AppleScript string literal.
+fn apple_script_string(s: &str) -> String {
+    format!("\"{}\"", s.replace('\\', "\\\\").replace('"', "\\\""))
+}
@@ -0,0 +1,73 @@
+//! `--ocr`, which recognizes the text in a capture so that it can be pasted as text.
+//!
+//! Recognition is done by the `tesseract` command rather than by linking against Tesseract, so
+//! that snapmeo builds without it and only needs it installed when `--ocr` is actually used. The
+//! image is piped in as a PNG and the text is read back from its output.
+
+use std::io::{ErrorKind, Write};
+use std::process::{Command, Stdio};
+use std::str::FromStr;
+
+use crate::error::SnapmeoError;
+
+/// What to do with the recognized text
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum OcrMode {
+    /// Copy the text instead of uploading the image
+    Text,
+    /// Upload the image as usual and copy the text along with its URL
+    Both,
+}
+
+impl FromStr for OcrMode {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "text" => Ok(OcrMode::Text),
+            "both" => Ok(OcrMode::Both),
+            _ => Err(format!(
+                "Invalid `--ocr` mode `{}`; expected `text` or `both`",
+                s
+            )),
+        }
+    }
+}
+
+/// Returns the text in the RGBA `buffer`, which is empty if none could be found.
+pub fn recognize(buffer: &[u8], width: usize, height: usize) -> Result<String, SnapmeoError> {
+    if width == 0 || height == 0 {
+        return Ok(String::new());
+    }
+    let mut png = Vec::new();
+    repng::encode(&mut png, width as u32, height as u32, buffer)?;
+
+    let mut child = Command::new("tesseract")
+        .args(["stdin", "stdout"])
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .map_err(|err| match err.kind() {
+            ErrorKind::NotFound => SnapmeoError::Other(
+                "`--ocr` needs the `tesseract` command, which isn't installed or isn't on the PATH"
+                    .into(),
+            ),
+            _ => SnapmeoError::Other(format!("Unable to run `tesseract`: {}", err)),
+        })?;
+    // Taken so that it's closed once written, which is how Tesseract knows the image is complete
+    child
+        .stdin
+        .take()
+        .expect("stdin is piped")
+        .write_all(&png)?;
+
+    let output = child.wait_with_output()?;
+    if !output.status.success() {
+        return Err(SnapmeoError::Other(format!(
+            "Text recognition failed: {}",
+            String::from_utf8_lossy(&output.stderr).trim()
+        )));
+    }
+    Ok(String::from_utf8_lossy(&output.stdout).trim().to_owned())
+}
@@ -0,0 +1,93 @@
+//! Pinning a capture to the screen in a small floating window.
+
+use std::thread;
+use std::time::Duration;
+
+use sdl2::event::Event;
+use sdl2::keyboard::Keycode;
+use sdl2::mouse::MouseButton;
+use sdl2::pixels::PixelFormatEnum;
+use sdl2::sys::SDL_WindowFlags;
+use sdl2::video::WindowPos;
+use sdl2::{EventPump, VideoSubsystem};
+
+/// Largest fraction of the display's width or height that a pinned image will take up
+const MAX_PINNED_FRACTION: f32 = 0.5;
+
+/// Shows the RGBA `buffer` in a borderless, always-on-top window until the user closes it with
+/// escape. The window can be moved around by dragging it, and images too large to comfortably
+/// keep on screen are scaled down.
+pub fn pin(
+    video_subsystem: &VideoSubsystem,
+    event_pump: &mut EventPump,
+    buffer: &[u8],
+    width: usize,
+    height: usize,
+    display_size: (usize, usize),
+    one_frame: Duration,
+) {
+    let scale = (MAX_PINNED_FRACTION * display_size.0 as f32 / width as f32)
+        .min(MAX_PINNED_FRACTION * display_size.1 as f32 / height as f32)
+        .min(1.);
+    let window_width = ((width as f32 * scale).round() as u32).max(1);
+    let window_height = ((height as f32 * scale).round() as u32).max(1);
+
+    let mut window_builder = video_subsystem.window("Snapmeo", window_width, window_height);
+    let flags = window_builder.window_flags() | SDL_WindowFlags::SDL_WINDOW_ALWAYS_ON_TOP as u32;
+    let window = window_builder
+        .set_window_flags(flags)
+        .borderless()
+        .build()
+        .expect("Unable to create pinned window!");
+    let mut canvas = window.into_canvas().build().unwrap();
+    let texture_creator = canvas.texture_creator();
+    let mut texture = texture_creator
+        .create_texture_static(Some(PixelFormatEnum::RGBA32), width as u32, height as u32)
+        .expect("Unable to create texture!");
+    texture
+        .update(None, buffer, width * 4)
+        .expect("Error updating texture with image data!");
+
+    println!("Image pinned to the screen; press escape to close it.");
+    // Where in the window the user grabbed it when dragging it around
+    let mut grab_point: Option<(i32, i32)> = None;
+
+    loop {
+        for event in event_pump.poll_iter() {
+            match event {
+                Event::Quit { .. }
+                | Event::KeyDown {
+                    keycode: Some(Keycode::Escape),
+                    ..
+                } => return,
+                Event::MouseButtonDown {
+                    mouse_btn: MouseButton::Left,
+                    x,
+                    y,
+                    ..
+                } => grab_point = Some((x, y)),
+                Event::MouseButtonUp {
+                    mouse_btn: MouseButton::Left,
+                    ..
+                } => grab_point = None,
+                Event::MouseMotion { x, y, .. } => {
+                    if let Some((grab_x, grab_y)) = grab_point {
+                        let window = canvas.window_mut();
+                        let (window_x, window_y) = window.position();
+                        window.set_position(
+                            WindowPos::Positioned(window_x + x - grab_x),
+                            WindowPos::Positioned(window_y + y - grab_y),
+                        );
+                    }
+                }
+                _ => {}
+            }
+        }
+
+        canvas.clear();
+        canvas.copy(&texture, None, None).expect("Render failed");
+        canvas.present();
+
+        thread::sleep(one_frame);
+    }
+}
@@ -0,0 +1,155 @@
+//! Asking the user for upload settings on the console.
+
+use std::io::{self, BufRead, IsTerminal, Write};
+#[cfg(unix)]
+use std::process::{Command, Stdio};
+
+use crate::buffer;
+use crate::upload::{Expiry, UploadOptions, Uploader};
+
+/// Asks `question` and returns the trimmed answer, or `default` if the answer is empty.
+fn ask(question: &str, default: &str) -> io::Result<String> {
+    print!("{} [{}]: ", question, default);
+    io::stdout().flush()?;
+
+    let mut answer = String::new();
+    if io::stdin().lock().read_line(&mut answer)? == 0 {
+        return Err(io::Error::new(
+            io::ErrorKind::UnexpectedEof,
+            "stdin was closed",
+        ));
+    }
+    let answer = answer.trim();
+    Ok(if answer.is_empty() { default } else { answer }.to_owned())
+}
+
+/// Asks for a password without echoing what's typed. Only supported where `stty` can turn echoing
+/// off, since showing the password isn't an acceptable fallback.
+#[cfg(unix)]
+pub fn ask_password(question: &str) -> io::Result<String> {
+    let set_echo = |setting: &str| {
+        Command::new("stty")
+            .arg(setting)
+            .stdin(Stdio::inherit())
+            .status()
+            .and_then(|status| {
+                if status.success() {
+                    Ok(())
+                } else {
+                    Err(io::Error::other(
+                        "Unable to hide the password as it's typed",
+                    ))
+                }
+            })
+    };
+
+    print!("{}: ", question);
+    io::stdout().flush()?;
+    set_echo("-echo")?;
+    let mut answer = String::new();
+    let read = io::stdin().lock().read_line(&mut answer);
+    set_echo("echo")?;
+    // The newline that was typed wasn't echoed either
+    println!();
+
+    if read? == 0 {
+        return Err(io::Error::new(
+            io::ErrorKind::UnexpectedEof,
+            "stdin was closed",
+        ));
+    }
+    Ok(answer.trim_end_matches(['\r', '\n']).to_owned())
+}
+
+#[cfg(not(unix))]
+pub fn ask_password(_question: &str) -> io::Result<String> {
+    Err(io::Error::new(
+        io::ErrorKind::Unsupported,
+        "Asking for a password isn't supported on this platform",
+    ))
+}
+
+fn ask_bool(question: &str, default: bool) -> io::Result<bool> {
+    loop {
+        let answer = ask(question, if default { "y" } else { "n" })?;
+        match answer.to_lowercase().as_str() {
+            "y" | "yes" => return Ok(true),
+            "n" | "no" => return Ok(false),
+            _ => println!("Please answer `y` or `n`."),
+        }
+    }
+}
+
+/// Asks whether to carry on after something looks wrong, defaulting to no.
+pub fn ask_to_continue(question: &str) -> io::Result<bool> {
+    ask_bool(question, false)
+}
+
+/// Asks for `question` until the answer is something `parse` accepts. Only asks again when stdin
+/// is a terminal; otherwise a bad answer is an error, since there's nobody to correct it.
+fn ask_until<T>(question: &str, parse: impl Fn(&str) -> Result<T, String>) -> io::Result<T> {
+    let terminal = io::stdin().is_terminal();
+    loop {
+        if terminal {
+            print!("{}: ", question);
+            io::stdout().flush()?;
+        }
+        let mut answer = String::new();
+        if io::stdin().lock().read_line(&mut answer)? == 0 {
+            return Err(io::Error::new(
+                io::ErrorKind::UnexpectedEof,
+                "stdin was closed",
+            ));
+        }
+        match parse(answer.trim()) {
+            Ok(value) => return Ok(value),
+            Err(err) if terminal => println!("{}", err),
+            Err(err) => return Err(io::Error::new(io::ErrorKind::InvalidData, err)),
+        }
+    }
+}
+
+/// Asks for two opposite corners of a region as `X,Y` lines on stdin, so that a region can be
+/// captured without a pointing device, and returns the region between them.
+pub fn ask_corners() -> io::Result<(i64, i64, usize, usize)> {
+    loop {
+        let first = ask_until("First corner (X,Y)", buffer::parse_point)?;
+        let second = ask_until("Opposite corner (X,Y)", buffer::parse_point)?;
+        match buffer::region_between(first, second) {
+            Ok(region) => return Ok(region),
+            Err(err) if io::stdin().is_terminal() => println!("{}", err),
+            Err(err) => return Err(io::Error::new(io::ErrorKind::InvalidData, err)),
+        }
+    }
+}
+
+/// Prompts for each of the settings in `defaults` that the user is likely to want to change per
+/// screenshot, asking again until the expiry is one that `uploader` supports.
+pub fn upload_options(
+    defaults: &UploadOptions,
+    uploader: &dyn Uploader,
+) -> io::Result<UploadOptions> {
+    let expiry = loop {
+        let answer = ask("Expiry", &defaults.expiry.to_string())?;
+        let expiry = answer
+            .parse::<Expiry>()
+            .map_err(|err| err.to_string())
+            .and_then(|expiry| {
+                uploader
+                    .translate_expiry(&expiry)
+                    .map(|_| expiry)
+                    .map_err(|err| err.to_string())
+            });
+        match expiry {
+            Ok(expiry) => break expiry,
+            Err(err) => println!("{}", err),
+        }
+    };
+
+    Ok(UploadOptions {
+        expiry,
+        secret: ask_bool("Obfuscate the file name?", defaults.secret)?,
+        one_time: ask_bool("Delete after one view?", defaults.one_time)?,
+        ..defaults.clone()
+    })
+}
@@ -0,0 +1,97 @@
+//! `--record`, which captures the selected region over and over for a while and saves it as an
+//! animated PNG, which unlike GIF keeps every color and any transparency.
+//!
+//! Frames are kept in memory until recording ends since APNG needs the number of frames up front.
+//! A frame that's identical to the one before it isn't kept at all; the one before is just shown
+//! for longer, which keeps recordings of mostly still screens small.
+
+use std::iter;
+use std::thread;
+use std::time::{Duration, Instant};
+
+use crate::error::SnapmeoError;
+
+/// How many frames are captured per second unless configured otherwise
+pub const DEFAULT_CAPTURE_FPS: f64 = 10.;
+
+/// A recorded animation
+pub struct Recording {
+    pub width: usize,
+    pub height: usize,
+    /// RGBA frames along with how long each is shown for
+    pub frames: Vec<(Vec<u8>, Duration)>,
+}
+
+impl Recording {
+    /// Makes every frame show for `factor` times as long, so that the recording plays back slower
+    /// if it's more than 1 or faster if it's less.
+    pub fn scale_delays(&mut self, factor: f64) {
+        for (_, delay) in &mut self.frames {
+            *delay = delay.mul_f64(factor);
+        }
+    }
+
+    /// Applies `f`, which has to produce frames of the same size as each other, to every frame.
+    pub fn map_frames(
+        self,
+        mut f: impl FnMut(Vec<u8>, usize, usize) -> (Vec<u8>, usize, usize),
+    ) -> Recording {
+        let (old_width, old_height) = (self.width, self.height);
+        let (mut width, mut height) = (old_width, old_height);
+        let frames = self
+            .frames
+            .into_iter()
+            .map(|(frame, delay)| {
+                let (frame, new_width, new_height) = f(frame, old_width, old_height);
+                width = new_width;
+                height = new_height;
+                (frame, delay)
+            })
+            .collect();
+        Recording {
+            width,
+            height,
+            frames,
+        }
+    }
+}
+
+/// Records frames of size `width` x `height` from `grab` for `duration`, grabbing one every
+/// `frame_interval`.
+pub fn record(
+    mut grab: impl FnMut() -> Result<Vec<u8>, SnapmeoError>,
+    width: usize,
+    height: usize,
+    duration: Duration,
+    frame_interval: Duration,
+) -> Result<Recording, SnapmeoError> {
+    let end = Instant::now()
+        .checked_add(duration)
+        .ok_or_else(|| SnapmeoError::BadArguments("The recording is too long".into()))?;
+    let mut frames: Vec<(Vec<u8>, Instant)> = Vec::new();
+    while Instant::now() < end {
+        let grabbed_at = Instant::now();
+        let frame = grab()?;
+        if frames.last().is_none_or(|(last, _)| *last != frame) {
+            frames.push((frame, grabbed_at));
+        }
+        thread::sleep(frame_interval.saturating_sub(grabbed_at.elapsed()));
+    }
+
+    // Each frame is shown until the next one that's different, and the last until the end
+    let shown_until: Vec<Instant> = frames
+        .iter()
+        .skip(1)
+        .map(|&(_, grabbed_at)| grabbed_at)
+        .chain(iter::once(Instant::now()))
+        .collect();
+    Ok(Recording {
+        width,
+        height,
+        frames: frames
+            .into_iter()
+            .zip(shown_until)
+            .map(|((frame, grabbed_at), until)| (frame, until - grabbed_at))
+            .collect(),
+    })
+}
@@ -0,0 +1,124 @@
+//! `--at` and `--in`, which hold off on capturing until a later time.
+//!
+//! The deadline is kept as a wall-clock time and checked in short steps rather than slept towards
+//! in one go. A monotonic timer doesn't advance while the process is stopped with Ctrl+Z or the
+//! computer is asleep, which would push the capture back by however long that lasted; this way the
+//! capture happens as soon as the process is running again past the deadline.
+
+use std::thread;
+use std::time::{Duration, SystemTime};
+
+use chrono::prelude::*;
+
+/// Longest stretch slept at a time while waiting
+const CHECK_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Parses durations like `90`, `45s`, `30m`, `2h`, or `1h30m`. Plain numbers are seconds.
+pub fn parse_duration(s: &str) -> Result<Duration, String> {
+    parse_duration_in(s, 1)
+}
+
+/// Parses durations like `parse_duration`, but with plain numbers counting `plain_unit_secs`
+/// seconds each. Also takes `w` for weeks. Durations too long to count in seconds are errors.
+pub fn parse_duration_in(s: &str, plain_unit_secs: u64) -> Result<Duration, String> {
+    let invalid = || format!("Invalid duration `{}`; expected something like `30m`", s);
+    let too_long = || format!("The duration `{}` is too long", s);
+    let s = s.trim().to_lowercase();
+    if let Ok(count) = s.parse::<u64>() {
+        return count
+            .checked_mul(plain_unit_secs)
+            .map(Duration::from_secs)
+            .ok_or_else(too_long);
+    }
+
+    let mut total_secs: u64 = 0;
+    let mut rest = s.as_str();
+    while !rest.is_empty() {
+        let unit_ix = rest
+            .find(|c: char| !c.is_ascii_digit())
+            .ok_or_else(invalid)?;
+        let (count, after) = rest.split_at(unit_ix);
+        let count: u64 = count.parse().map_err(|_| invalid())?;
+        let unit = after.chars().next().ok_or_else(invalid)?;
+        let unit_secs = match unit {
+            's' => 1,
+            'm' => 60,
+            'h' => 60 * 60,
+            'd' => 24 * 60 * 60,
+            'w' => 7 * 24 * 60 * 60,
+            unit => {
+                return Err(format!(
+                    "Invalid duration unit `{}`; expected one of `s`, `m`, `h`, `d`, or `w`",
+                    unit
+                ))
+            }
+        };
+        total_secs = count
+            .checked_mul(unit_secs)
+            .and_then(|secs| total_secs.checked_add(secs))
+            .ok_or_else(too_long)?;
+        rest = &after[unit.len_utf8()..];
+    }
+    Ok(Duration::from_secs(total_secs))
+}
+
+/// Returns the next time the local clock reads `time`, given as `HH:MM` or `HH:MM:SS`. Times that
+/// have already passed today are taken to mean tomorrow.
+pub fn next_occurrence(time: &str) -> Result<DateTime<Local>, String> {
+    let time = NaiveTime::parse_from_str(time.trim(), "%H:%M:%S")
+        .or_else(|_| NaiveTime::parse_from_str(time.trim(), "%H:%M"))
+        .map_err(|_| format!("Invalid time `{}`; expected something like `14:30`", time))?;
+
+    let now = Local::now();
+    let mut date = now.date_naive();
+    loop {
+        // Times skipped over by a daylight saving change don't exist on that day
+        if let Some(at) = Local
+            .from_local_datetime(&date.and_time(time))
+            .earliest()
+            .filter(|at| *at > now)
+        {
+            return Ok(at);
+        }
+        date = date
+            .succ_opt()
+            .ok_or_else(|| "The date is out of range".to_string())?;
+    }
+}
+
+/// Formats `duration` like `1h 20m 5s`, leaving out any units that are zero.
+fn describe(duration: Duration) -> String {
+    let secs = duration.as_secs();
+    let parts: Vec<String> = [(secs / 3600, "h"), (secs / 60 % 60, "m"), (secs % 60, "s")]
+        .iter()
+        .filter(|(count, _)| *count > 0)
+        .map(|(count, unit)| format!("{}{}", count, unit))
+        .collect();
+    if parts.is_empty() {
+        "0s".into()
+    } else {
+        parts.join(" ")
+    }
+}
+
+/// Blocks until the wall clock passes `deadline`, saying what it's waiting for first.
+pub fn wait_until(deadline: SystemTime) {
+    let remaining = match deadline.duration_since(SystemTime::now()) {
+        Ok(remaining) => remaining,
+        Err(_) => return,
+    };
+    let local_deadline: DateTime<Local> = deadline.into();
+    eprintln!(
+        "Waiting until {} (in {}) to capture...",
+        local_deadline.format("%Y-%m-%d %H:%M:%S"),
+        describe(remaining)
+    );
+
+    // `duration_since` fails once the deadline has passed
+    while let Ok(remaining) = deadline.duration_since(SystemTime::now()) {
+        if remaining.is_zero() {
+            break;
+        }
+        thread::sleep(remaining.min(CHECK_INTERVAL));
+    }
+}
@@ -0,0 +1,220 @@
+//! Scroll-capture: stitching repeated captures of the same region into one tall image while the
+//! user scrolls the content beneath it.
+//!
+//! Consecutive frames are lined up by hashing every row and finding the vertical offset at which
+//! the rows of the new frame best match the rows of the previous one. Only scrolling downwards is
+//! supported.
+
+use std::collections::hash_map::DefaultHasher;
+use std::hash::{Hash, Hasher};
+use std::thread;
+use std::time::Duration;
+
+use sdl2::event::Event;
+use sdl2::keyboard::Keycode;
+use sdl2::pixels::Color;
+use sdl2::{EventPump, VideoSubsystem};
+
+use crate::error::SnapmeoError;
+use crate::font;
+
+/// Minimum number of rows that must be shared by two frames for an offset to be considered
+const MIN_OVERLAP_ROWS: usize = 8;
+/// Fraction of the informative overlapping rows that must be identical for an offset to be
+/// accepted as the scroll distance between two frames
+const MIN_MATCH_RATIO: f32 = 0.9;
+const CONTROL_WINDOW_SCALE: u32 = 2;
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+struct RowHash {
+    hash: u64,
+    /// Rows that are a single solid color match at almost any offset, so they aren't used as
+    /// evidence for or against an offset.
+    uniform: bool,
+}
+
+fn row_hashes(buffer: &[u8], width: usize) -> Vec<RowHash> {
+    buffer
+        .chunks(width * 4)
+        .map(|row| {
+            let mut hasher = DefaultHasher::new();
+            row.hash(&mut hasher);
+            RowHash {
+                hash: hasher.finish(),
+                uniform: row.chunks(4).all(|pixel| pixel == &row[..4]),
+            }
+        })
+        .collect()
+}
+
+/// Returns the number of rows by which the content of `next` has been scrolled up relative to
+/// `prev`, or `None` if no offset lines the two frames up convincingly.
+fn find_scroll_offset(prev: &[RowHash], next: &[RowHash]) -> Option<usize> {
+    let height = prev.len();
+    let mut best: Option<(usize, f32)> = None;
+
+    for offset in 0..height.saturating_sub(MIN_OVERLAP_ROWS) {
+        let mut considered = 0;
+        let mut matched = 0;
+        for (prev_row, next_row) in prev[offset..].iter().zip(next) {
+            if prev_row.uniform && next_row.uniform {
+                continue;
+            }
+
+            considered += 1;
+            if prev_row == next_row {
+                matched += 1;
+            }
+        }
+
+        if considered < MIN_OVERLAP_ROWS {
+            continue;
+        }
+
+        let ratio = matched as f32 / considered as f32;
+        let is_best = match best {
+            Some((_, best_ratio)) => ratio > best_ratio,
+            None => true,
+        };
+        if ratio >= MIN_MATCH_RATIO && is_best {
+            best = Some((offset, ratio));
+        }
+    }
+
+    best.map(|(offset, _)| offset)
+}
+
+/// Accumulates frames of a scrolling region into one tall RGBA buffer.
+pub struct Stitcher {
+    width: usize,
+    height: usize,
+    buffer: Vec<u8>,
+    last_hashes: Vec<RowHash>,
+}
+
+impl Stitcher {
+    pub fn new(first_frame: Vec<u8>, width: usize, height: usize) -> Self {
+        Stitcher {
+            width,
+            height,
+            last_hashes: row_hashes(&first_frame, width),
+            buffer: first_frame,
+        }
+    }
+
+    /// Appends the part of `frame` that wasn't visible in the previous frame, returning the
+    /// number of new rows.
+    pub fn push(&mut self, frame: &[u8]) -> usize {
+        let hashes = row_hashes(frame, self.width);
+        let new_rows = match find_scroll_offset(&self.last_hashes, &hashes) {
+            Some(offset) => offset,
+            None => {
+                eprintln!("Couldn't find the overlap with the previous frame; appending it whole.");
+                self.height
+            }
+        };
+
+        let row_size = self.width * 4;
+        self.buffer
+            .extend_from_slice(&frame[(self.height - new_rows) * row_size..]);
+        self.last_hashes = hashes;
+
+        new_rows
+    }
+
+    pub fn total_height(&self) -> usize {
+        self.buffer.len() / (self.width * 4)
+    }
+
+    pub fn into_buffer(self) -> Vec<u8> {
+        self.buffer
+    }
+}
+
+/// Shows a small control window while the user scrolls the selected region, calling `grab` to
+/// capture the region each time they press space. Returns the stitched buffer and its height once
+/// they press enter, `None` if they cancel with escape, or the first error returned by `grab`.
+pub fn capture(
+    video_subsystem: &VideoSubsystem,
+    event_pump: &mut EventPump,
+    mut grab: impl FnMut() -> Result<Vec<u8>, SnapmeoError>,
+    first_frame: Vec<u8>,
+    width: usize,
+    height: usize,
+    one_frame: Duration,
+) -> Result<Option<(Vec<u8>, usize)>, SnapmeoError> {
+    let lines = ["SPACE: CAPTURE FRAME", "ENTER: FINISH", "ESC: CANCEL"];
+    let line_height = (font::GLYPH_HEIGHT + 3) * CONTROL_WINDOW_SCALE;
+    let (text_width, _) = font::text_size(lines[0], CONTROL_WINDOW_SCALE);
+    let window = video_subsystem
+        .window(
+            "Snapmeo scroll capture",
+            text_width + 20,
+            line_height * (lines.len() as u32 + 1) + 20,
+        )
+        .build()
+        .expect("Unable to create scroll capture window!");
+    let mut canvas = window.into_canvas().build().unwrap();
+
+    eprintln!("Scroll the selected region, pressing space after each scroll to capture it and enter to finish.");
+    let mut stitcher = Stitcher::new(first_frame, width, height);
+    let mut frame_count = 1;
+
+    loop {
+        for event in event_pump.poll_iter() {
+            match event {
+                Event::Quit { .. }
+                | Event::KeyDown {
+                    keycode: Some(Keycode::Escape),
+                    ..
+                } => return Ok(None),
+                Event::KeyDown {
+                    keycode: Some(Keycode::Return),
+                    ..
+                } => {
+                    let total_height = stitcher.total_height();
+                    return Ok(Some((stitcher.into_buffer(), total_height)));
+                }
+                Event::KeyDown {
+                    keycode: Some(Keycode::Space),
+                    ..
+                } => {
+                    // Keep the control window out of the captured frame in case it overlaps the
+                    // region being captured
+                    canvas.window_mut().hide();
+                    thread::sleep(one_frame * 4);
+                    let frame = grab();
+                    canvas.window_mut().show();
+                    let frame = frame?;
+
+                    frame_count += 1;
+                    let new_rows = stitcher.push(&frame);
+                    eprintln!("Captured frame {} ({} new rows)", frame_count, new_rows);
+                }
+                _ => {}
+            }
+        }
+
+        canvas.set_draw_color(Color::RGB(32, 32, 32));
+        canvas.clear();
+        canvas.set_draw_color(Color::RGB(255, 255, 255));
+        let status = format!(
+            "FRAMES: {}  HEIGHT: {}",
+            frame_count,
+            stitcher.total_height()
+        );
+        for (i, line) in lines.iter().chain(&[status.as_str()]).enumerate() {
+            font::draw_text(
+                &mut canvas,
+                line,
+                10,
+                10 + (i as u32 * line_height) as i32,
+                CONTROL_WINDOW_SCALE,
+            )
+            .expect("Render failed");
+        }
+        canvas.present();
+
+        thread::sleep(one_frame);
+    }
+}
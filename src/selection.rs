@@ -0,0 +1,859 @@
+//! The interactive overlay used to select a region of the frozen frame.
+
+use std::cmp;
+use std::thread;
+use std::time::{Duration, Instant};
+
+use sdl2::event::Event;
+use sdl2::keyboard::{Keycode, Mod};
+use sdl2::pixels::Color;
+use sdl2::rect::Rect;
+use sdl2::render::{BlendMode, Canvas, Texture};
+use sdl2::video::Window;
+use sdl2::EventPump;
+
+use crate::buffer::{self, Anchor, Region};
+use crate::font;
+use crate::hold::HoldWatcher;
+use crate::snap::EdgeMap;
+use crate::window;
+use crate::ConfirmAction;
+
+/// Side length of the square handles drawn on the selection's corners and edges
+const HANDLE_SIZE: u32 = 8;
+/// How close to a handle, in pixels, a click has to be to grab it
+const HANDLE_GRAB_DISTANCE: i32 = 10;
+/// How many pixels the mouse has to move while pressed for it to count as a drag rather than a
+/// click, so that a click with a shaky hand doesn't select a tiny region
+const DRAG_THRESHOLD: i32 = 4;
+const HINT_SCALE: u32 = 2;
+/// Opacity of the overlay shown over the live screen so that the screen can be seen through it
+const LIVE_OVERLAY_OPACITY: f32 = 0.3;
+/// How long the committed selection flashes for
+const FLASH_DURATION: Duration = Duration::from_millis(200);
+/// Width of the outline drawn around the selection while it flashes
+const FLASH_OUTLINE_WIDTH: i32 = 3;
+/// Space in pixels between the text of a numeric entry field and its border
+const FIELD_PADDING: u32 = 4;
+/// Space in pixels between neighboring numeric entry fields
+const FIELD_SPACING: u32 = 16;
+/// Labels of the numeric entry fields, in the order that `Tab` moves through them
+const FIELD_LABELS: [&str; 4] = ["X", "Y", "W", "H"];
+/// Most digits that can be typed into a numeric entry field
+const FIELD_MAX_DIGITS: usize = 5;
+
+/// Snaps points being dragged to the edges in `edges` while enabled, which the `S` key toggles.
+struct Snapper<'a> {
+    edges: Option<&'a EdgeMap>,
+    enabled: bool,
+}
+
+impl<'a> Snapper<'a> {
+    fn new(edges: Option<&'a EdgeMap>) -> Self {
+        Snapper {
+            edges,
+            enabled: true,
+        }
+    }
+
+    fn toggle(&mut self) {
+        self.enabled = !self.enabled;
+    }
+
+    fn snap(&self, point: (i32, i32)) -> (i32, i32) {
+        match self.edges {
+            Some(edges) if self.enabled => edges.snap(point),
+            _ => point,
+        }
+    }
+}
+
+/// Returns how many physical pixels there are per SDL point in the canvas's window, which is more
+/// than one on HiDPI displays.
+pub fn scale_factor(canvas: &Canvas<Window>) -> f64 {
+    let (output_width, _) = canvas.output_size().expect("Unable to get window size!");
+    let (window_width, _) = canvas.window().size();
+    if window_width == 0 {
+        return 1.;
+    }
+    output_width as f64 / window_width as f64
+}
+
+/// Converts the positions of mouse events from SDL points into physical pixels within `bounds`,
+/// leaving other events as they are.
+pub fn to_physical_event(mut event: Event, scale_factor: f64, bounds: (usize, usize)) -> Event {
+    match &mut event {
+        Event::MouseButtonDown { x, y, .. }
+        | Event::MouseButtonUp { x, y, .. }
+        | Event::MouseMotion { x, y, .. } => {
+            let (physical_x, physical_y) = buffer::to_physical((*x, *y), scale_factor, bounds);
+            *x = physical_x as i32;
+            *y = physical_y as i32;
+        }
+        _ => {}
+    }
+    event
+}
+
+/// Fields for typing in the position and size of the selection exactly, opened with `Tab`
+struct NumericEntry {
+    /// The digits typed into each field, in the order of `FIELD_LABELS`
+    values: [String; 4],
+    /// Index of the field being typed into
+    active: usize,
+}
+
+impl NumericEntry {
+    fn new(region: &Region) -> Self {
+        NumericEntry {
+            values: [region.x, region.y, region.width, region.height]
+                .map(|value| value.to_string()),
+            active: 0,
+        }
+    }
+
+    /// Makes the fields show `region`, keeping the same field active.
+    fn sync(&mut self, region: &Region) {
+        self.values = NumericEntry::new(region).values;
+    }
+
+    fn next_field(&mut self, backwards: bool) {
+        let field_count = FIELD_LABELS.len();
+        self.active = if backwards {
+            (self.active + field_count - 1) % field_count
+        } else {
+            (self.active + 1) % field_count
+        };
+    }
+
+    fn push_digit(&mut self, digit: char) {
+        let value = &mut self.values[self.active];
+        if value == "0" {
+            value.clear();
+        }
+        if value.len() < FIELD_MAX_DIGITS {
+            value.push(digit);
+        }
+    }
+
+    fn pop_digit(&mut self) {
+        self.values[self.active].pop();
+    }
+
+    /// Returns the region the fields describe, cut down to fit within a display of size `bounds`,
+    /// or `None` if any field is empty or it wouldn't contain any pixels.
+    fn region(&self, bounds: (usize, usize)) -> Option<Region> {
+        let mut values = [0; 4];
+        for (value, text) in values.iter_mut().zip(&self.values) {
+            *value = text.parse().ok()?;
+        }
+        let [x, y, width, height] = values;
+        if x >= bounds.0 || y >= bounds.1 || width == 0 || height == 0 {
+            return None;
+        }
+
+        Some(Region {
+            x,
+            y,
+            width: cmp::min(width, bounds.0 - x),
+            height: cmp::min(height, bounds.1 - y),
+        })
+    }
+}
+
+/// Returns the digit typed by `keycode`, if it's a number key.
+fn typed_digit(keycode: Keycode) -> Option<char> {
+    let digit = match keycode {
+        Keycode::Num0 | Keycode::Kp0 => 0,
+        Keycode::Num1 | Keycode::Kp1 => 1,
+        Keycode::Num2 | Keycode::Kp2 => 2,
+        Keycode::Num3 | Keycode::Kp3 => 3,
+        Keycode::Num4 | Keycode::Kp4 => 4,
+        Keycode::Num5 | Keycode::Kp5 => 5,
+        Keycode::Num6 | Keycode::Kp6 => 6,
+        Keycode::Num7 | Keycode::Kp7 => 7,
+        Keycode::Num8 | Keycode::Kp8 => 8,
+        Keycode::Num9 | Keycode::Kp9 => 9,
+        _ => return None,
+    };
+    std::char::from_digit(digit, 10)
+}
+
+/// Returns whether the mouse moving from `press` to `position` counts as a drag.
+fn is_drag(press: (i32, i32), position: (i32, i32)) -> bool {
+    (press.0 - position.0).abs() > DRAG_THRESHOLD || (press.1 - position.1).abs() > DRAG_THRESHOLD
+}
+
+/// Returns whether more than `timeout` has passed since `start`.
+fn timed_out(start: Instant, timeout: Option<Duration>) -> bool {
+    timeout.is_some_and(|timeout| start.elapsed() >= timeout)
+}
+
+pub fn region_rect(region: &Region) -> Rect {
+    Rect::new(
+        region.x as i32,
+        region.y as i32,
+        cmp::max(region.width as u32, 1),
+        cmp::max(region.height as u32, 1),
+    )
+}
+
+/// A region chosen with `select_region`
+pub struct Selection {
+    pub region: Region,
+    /// Whether the region is the bounds of a double-clicked window rather than one dragged out,
+    /// which is worth confirming before it's captured since it's only a guess at what was wanted
+    pub from_window: bool,
+}
+
+/// Grabbable points on the selection used to resize it
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Handle {
+    TopLeft,
+    Top,
+    TopRight,
+    Right,
+    BottomRight,
+    Bottom,
+    BottomLeft,
+    Left,
+}
+
+const HANDLES: [Handle; 8] = [
+    Handle::TopLeft,
+    Handle::Top,
+    Handle::TopRight,
+    Handle::Right,
+    Handle::BottomRight,
+    Handle::Bottom,
+    Handle::BottomLeft,
+    Handle::Left,
+];
+
+impl Handle {
+    fn position(self, region: &Region) -> (i32, i32) {
+        let (left, top) = (region.x as i32, region.y as i32);
+        let (right, bottom) = (left + region.width as i32, top + region.height as i32);
+        let (center_x, center_y) = ((left + right) / 2, (top + bottom) / 2);
+
+        match self {
+            Handle::TopLeft => (left, top),
+            Handle::Top => (center_x, top),
+            Handle::TopRight => (right, top),
+            Handle::Right => (right, center_y),
+            Handle::BottomRight => (right, bottom),
+            Handle::Bottom => (center_x, bottom),
+            Handle::BottomLeft => (left, bottom),
+            Handle::Left => (left, center_y),
+        }
+    }
+
+    /// Returns the handle of `region` closest to `point` if any is within grabbing distance.
+    fn hit_test(region: &Region, point: (i32, i32)) -> Option<Handle> {
+        HANDLES
+            .iter()
+            .map(|&handle| {
+                let (x, y) = handle.position(region);
+                (handle, cmp::max((x - point.0).abs(), (y - point.1).abs()))
+            })
+            .filter(|&(_, distance)| distance <= HANDLE_GRAB_DISTANCE)
+            .min_by_key(|&(_, distance)| distance)
+            .map(|(handle, _)| handle)
+    }
+
+    /// Returns `region` with the edges controlled by this handle moved to `point`. Dragging a
+    /// handle past the opposite edge flips the selection rather than collapsing it.
+    fn drag(self, region: &Region, point: (i32, i32), bounds: (usize, usize)) -> Region {
+        let x = cmp::max(cmp::min(point.0, bounds.0 as i32), 0);
+        let y = cmp::max(cmp::min(point.1, bounds.1 as i32), 0);
+        let (mut left, mut top) = (region.x as i32, region.y as i32);
+        let (mut right, mut bottom) = (left + region.width as i32, top + region.height as i32);
+
+        match self {
+            Handle::TopLeft | Handle::Left | Handle::BottomLeft => left = x,
+            Handle::TopRight | Handle::Right | Handle::BottomRight => right = x,
+            Handle::Top | Handle::Bottom => (),
+        }
+        match self {
+            Handle::TopLeft | Handle::Top | Handle::TopRight => top = y,
+            Handle::BottomLeft | Handle::Bottom | Handle::BottomRight => bottom = y,
+            Handle::Left | Handle::Right => (),
+        }
+
+        Region::from_corners((left, top), (right, bottom))
+    }
+}
+
+/// Draws labelled boxes showing the values of `entry`'s fields in a row centered horizontally on
+/// a canvas `width` pixels wide, with their tops at `y`.
+fn draw_numeric_entry(canvas: &mut Canvas<Window>, entry: &NumericEntry, width: u32, y: i32) {
+    let (label_width, text_height) = font::text_size("X", HINT_SCALE);
+    let label_width = label_width + FIELD_PADDING;
+    let field_width =
+        font::text_size(&"0".repeat(FIELD_MAX_DIGITS + 1), HINT_SCALE).0 + FIELD_PADDING * 2;
+    let field_height = text_height + FIELD_PADDING * 2;
+    let total_width =
+        FIELD_LABELS.len() as u32 * (label_width + field_width + FIELD_SPACING) - FIELD_SPACING;
+
+    let mut x = (width as i32 - total_width as i32) / 2;
+    for (ix, (label, value)) in FIELD_LABELS.iter().zip(&entry.values).enumerate() {
+        let text_y = y + FIELD_PADDING as i32;
+        let field_x = x + label_width as i32;
+        let field_rect = Rect::new(field_x, y, field_width, field_height);
+        let text = if ix == entry.active {
+            format!("{}_", value)
+        } else {
+            value.clone()
+        };
+
+        canvas.set_draw_color(Color::RGB(0, 0, 0));
+        canvas.fill_rect(field_rect).expect("Render failed");
+        canvas.set_draw_color(Color::RGB(255, 255, 255));
+        font::draw_text(canvas, label, x, text_y, HINT_SCALE).expect("Render failed");
+        font::draw_text(
+            canvas,
+            &text,
+            field_x + FIELD_PADDING as i32,
+            text_y,
+            HINT_SCALE,
+        )
+        .expect("Render failed");
+        canvas.draw_rect(field_rect).expect("Render failed");
+        if ix == entry.active {
+            let inner_rect = Rect::new(field_x + 1, y + 1, field_width - 2, field_height - 2);
+            canvas.draw_rect(inner_rect).expect("Render failed");
+        }
+
+        x += (label_width + field_width + FIELD_SPACING) as i32;
+    }
+}
+
+/// Draws the frozen frame with everything outside of `region` dimmed, optionally with resize
+/// handles on the selection, a line of instructions at the top of the screen, and the fields of a
+/// numeric entry below them.
+fn draw_selection(
+    canvas: &mut Canvas<Window>,
+    texture: &Texture,
+    region: &Region,
+    show_handles: bool,
+    hint: Option<&str>,
+    entry: Option<&NumericEntry>,
+) {
+    let (width, height) = canvas.output_size().expect("Unable to get window size!");
+    let selection_rect = region_rect(region);
+    let dimmed_rects = [
+        Rect::new(0, 0, width, region.y as u32),
+        Rect::new(
+            0,
+            selection_rect.y(),
+            region.x as u32,
+            selection_rect.height(),
+        ),
+        Rect::new(
+            selection_rect.right(),
+            selection_rect.y(),
+            width.saturating_sub(selection_rect.right() as u32),
+            selection_rect.height(),
+        ),
+        Rect::new(
+            0,
+            selection_rect.bottom(),
+            width,
+            height.saturating_sub(selection_rect.bottom() as u32),
+        ),
+    ];
+
+    canvas.copy(texture, None, None).expect("Render failed");
+    canvas.set_blend_mode(BlendMode::Blend);
+    canvas.set_draw_color(Color::RGBA(0, 0, 0, 128));
+    canvas.fill_rects(&dimmed_rects).expect("Render failed");
+    canvas.set_blend_mode(BlendMode::None);
+    canvas.set_draw_color(Color::RGB(255, 255, 255));
+    canvas.draw_rect(selection_rect).expect("Render failed");
+
+    if show_handles {
+        let handle_rects: Vec<Rect> = HANDLES
+            .iter()
+            .map(|handle| {
+                let (x, y) = handle.position(region);
+                Rect::from_center((x, y), HANDLE_SIZE, HANDLE_SIZE)
+            })
+            .collect();
+        canvas.fill_rects(&handle_rects).expect("Render failed");
+    }
+
+    if let Some(hint) = hint {
+        let (hint_width, hint_height) = font::text_size(hint, HINT_SCALE);
+        font::draw_text(
+            canvas,
+            hint,
+            (width as i32 - hint_width as i32) / 2,
+            hint_height as i32,
+            HINT_SCALE,
+        )
+        .expect("Render failed");
+    }
+
+    if let Some(entry) = entry {
+        let (_, hint_height) = font::text_size("X", HINT_SCALE);
+        draw_numeric_entry(canvas, entry, width, hint_height as i32 * 3);
+    }
+
+    canvas.present();
+}
+
+/// Lets the user drag out a region of the frozen frame, returning it once they release the mouse
+/// or `None` if they cancel with escape.
+///
+/// If `fixed_size` is set, a single click selects a region of that size positioned relative to
+/// the click by its anchor instead. The region that would be selected follows the cursor unless
+/// `show_preview` is unset.
+///
+/// If `edges` is given, the corners being dragged snap to nearby edges in the frame unless
+/// snapping is toggled off with `S`. If `work_area` is given, the mouse is confined to it so that
+/// panels and taskbars outside of it can't be dragged over, and if `aspect` is, the region being
+/// dragged out keeps that ratio of width to height.
+///
+/// Pressing `Tab` opens fields for typing in the region's position and size exactly instead, which
+/// follow any region being dragged out. `Tab` moves between the fields and `Enter` selects the
+/// region they describe.
+///
+/// Double-clicking selects the bounds of the window under the cursor, clamped to the display, as a
+/// shortcut for capturing a whole window. A single click doesn't select anything so that it can
+/// become a double-click, even if the mouse moved by a few pixels while it was pressed.
+///
+/// Also returns `None` if nothing has been selected once `timeout` has passed, or if `hold` is
+/// given and its key is let go of after having been held.
+#[allow(clippy::too_many_arguments)]
+pub fn select_region(
+    canvas: &mut Canvas<Window>,
+    texture: &Texture,
+    event_pump: &mut EventPump,
+    bounds: (usize, usize),
+    work_area: Option<&Region>,
+    aspect: Option<f64>,
+    origin: (i64, i64),
+    fixed_size: Option<((usize, usize), Anchor)>,
+    show_preview: bool,
+    edges: Option<&EdgeMap>,
+    hold: Option<&HoldWatcher>,
+    timeout: Option<Duration>,
+    one_frame: Duration,
+) -> Option<Selection> {
+    let dragged = |region| Selection {
+        region,
+        from_window: false,
+    };
+    let start = Instant::now();
+    let mut snapper = Snapper::new(edges);
+    let keep_aspect = |drag_start, corner| match aspect {
+        Some(aspect) => buffer::constrain_to_aspect(drag_start, corner, aspect),
+        None => corner,
+    };
+    let scale_factor = scale_factor(canvas);
+    let mut drag_start: Option<(i32, i32)> = None;
+    // Where the mouse was pressed before snapping, which is what dragging is measured from
+    let mut press: Option<(i32, i32)> = None;
+    let mut cursor_position: Option<(i32, i32)> = None;
+    let mut entry: Option<NumericEntry> = None;
+    // Where the key can only be seen once the overlay has focus, it may not register as held at
+    // first even though it is
+    let mut held = false;
+
+    loop {
+        if timed_out(start, timeout) {
+            return None;
+        }
+        if let Some(hold) = hold {
+            match (hold.is_held(event_pump), held) {
+                (true, _) => held = true,
+                (false, true) => return None,
+                (false, false) => {}
+            }
+        }
+
+        let preview = match (fixed_size, drag_start, cursor_position) {
+            (Some((size, anchor)), _, Some(cursor_position)) => {
+                Some(Region::anchored(cursor_position, size, anchor, bounds))
+            }
+            (None, Some(drag_start), Some(cursor_position))
+                if press.is_some_and(|press| is_drag(press, cursor_position)) =>
+            {
+                Some(Region::from_corners(
+                    drag_start,
+                    keep_aspect(drag_start, snapper.snap(cursor_position)),
+                ))
+            }
+            _ => None,
+        };
+
+        for event in event_pump.poll_iter() {
+            let mut event = to_physical_event(event, scale_factor, bounds);
+            // The mouse can still be moved over panels, but nothing beyond the edge of the work
+            // area can be selected
+            if let (
+                Some(work_area),
+                Event::MouseButtonDown { x, y, .. }
+                | Event::MouseButtonUp { x, y, .. }
+                | Event::MouseMotion { x, y, .. },
+            ) = (work_area, &mut event)
+            {
+                (*x, *y) = work_area.confine((*x, *y));
+            }
+            match event {
+                Event::Quit { .. } => return None,
+                // Escape closes the numeric entry before it cancels the selection
+                Event::KeyDown {
+                    keycode: Some(Keycode::Escape),
+                    ..
+                } if entry.is_none() => return None,
+                Event::KeyDown {
+                    keycode: Some(Keycode::Escape),
+                    ..
+                } => entry = None,
+                Event::KeyDown {
+                    keycode: Some(Keycode::S),
+                    ..
+                } => snapper.toggle(),
+                Event::KeyDown {
+                    keycode: Some(Keycode::Tab),
+                    keymod,
+                    ..
+                } => match &mut entry {
+                    Some(entry) => {
+                        entry.next_field(keymod.intersects(Mod::LSHIFTMOD | Mod::RSHIFTMOD))
+                    }
+                    None => {
+                        let initial = preview.or_else(|| {
+                            fixed_size.map(|(size, _)| Region {
+                                x: 0,
+                                y: 0,
+                                width: size.0,
+                                height: size.1,
+                            })
+                        });
+                        entry = Some(NumericEntry::new(&initial.unwrap_or(Region {
+                            x: 0,
+                            y: 0,
+                            width: bounds.0,
+                            height: bounds.1,
+                        })));
+                    }
+                },
+                Event::KeyDown {
+                    keycode: Some(Keycode::Backspace),
+                    ..
+                } => {
+                    if let Some(entry) = &mut entry {
+                        entry.pop_digit();
+                    }
+                }
+                Event::KeyDown {
+                    keycode: Some(Keycode::Return),
+                    ..
+                }
+                | Event::KeyDown {
+                    keycode: Some(Keycode::KpEnter),
+                    ..
+                } => {
+                    if let Some(region) = entry.as_ref().and_then(|entry| entry.region(bounds)) {
+                        return Some(dragged(region));
+                    }
+                }
+                Event::KeyDown {
+                    keycode: Some(keycode),
+                    ..
+                } => {
+                    if let (Some(entry), Some(digit)) = (&mut entry, typed_digit(keycode)) {
+                        entry.push_digit(digit);
+                    }
+                }
+                Event::MouseButtonDown { x, y, clicks, .. } => {
+                    if let Some((size, anchor)) = fixed_size {
+                        return Some(dragged(Region::anchored((x, y), size, anchor, bounds)));
+                    }
+                    if clicks >= 2 {
+                        let window_region =
+                            window::window_at(x as i64 + origin.0, y as i64 + origin.1)
+                                .ok()
+                                .and_then(|window| {
+                                    Region::clamped(
+                                        window.x - origin.0,
+                                        window.y - origin.1,
+                                        window.width,
+                                        window.height,
+                                        bounds,
+                                    )
+                                });
+                        if let Some(region) = window_region {
+                            return Some(Selection {
+                                region,
+                                from_window: true,
+                            });
+                        }
+                    }
+                    drag_start = Some(snapper.snap((x, y)));
+                    press = Some((x, y));
+                }
+                Event::MouseButtonUp { x, y, .. } => {
+                    // Just a click, which might be the first half of a double-click
+                    if press.is_some_and(|press| !is_drag(press, (x, y))) {
+                        drag_start = None;
+                        press = None;
+                        continue;
+                    }
+                    let end = snapper.snap((x, y));
+                    let drag_start = drag_start.unwrap_or(end);
+                    return Some(dragged(Region::from_corners(
+                        drag_start,
+                        keep_aspect(drag_start, end),
+                    )));
+                }
+                Event::MouseMotion { x, y, .. } => {
+                    cursor_position = Some((x, y));
+                }
+                _ => {}
+            }
+        }
+
+        if let Some(entry) = &mut entry {
+            // Dragging out a region fills the fields in with it
+            if let (Some(preview), Some(_)) = (preview, drag_start) {
+                entry.sync(&preview);
+            }
+            let region = entry.region(bounds).unwrap_or(Region {
+                x: 0,
+                y: 0,
+                width: 0,
+                height: 0,
+            });
+            draw_selection(
+                canvas,
+                texture,
+                &region,
+                false,
+                Some("TAB: NEXT FIELD  ENTER: SELECT  ESC: BACK TO DRAGGING"),
+                Some(entry),
+            );
+        } else if show_preview {
+            if let Some(preview) = preview {
+                draw_selection(canvas, texture, &preview, false, None, None);
+            }
+        }
+
+        thread::sleep(one_frame);
+    }
+}
+
+/// Like `select_region`, but shows a translucent overlay over the live screen rather than a frozen
+/// frame so that animated content can be captured at the moment the mouse is released. The
+/// overlay is hidden again before returning, so the screen can be captured straight away.
+///
+/// Returns an error if the overlay can't be made translucent, such as without a compositor.
+pub fn select_region_live(
+    canvas: &mut Canvas<Window>,
+    event_pump: &mut EventPump,
+    timeout: Option<Duration>,
+    one_frame: Duration,
+) -> Result<Option<Region>, String> {
+    canvas.window_mut().show();
+    if let Err(err) = canvas.window_mut().set_opacity(LIVE_OVERLAY_OPACITY) {
+        canvas.window_mut().hide();
+        return Err(format!(
+            "Unable to make the selection overlay translucent: {}",
+            err
+        ));
+    }
+
+    let scale_factor = scale_factor(canvas);
+    let (width, height) = canvas.output_size().expect("Unable to get window size!");
+    let bounds = (width as usize, height as usize);
+    let mut drag_start: Option<(i32, i32)> = None;
+    let mut cursor_position: Option<(i32, i32)> = None;
+    let start = Instant::now();
+    let selection = 'select: loop {
+        if timed_out(start, timeout) {
+            break 'select None;
+        }
+
+        for event in event_pump.poll_iter() {
+            let event = to_physical_event(event, scale_factor, bounds);
+            match event {
+                Event::Quit { .. }
+                | Event::KeyDown {
+                    keycode: Some(Keycode::Escape),
+                    ..
+                } => break 'select None,
+                Event::MouseButtonDown { x, y, .. } => drag_start = Some((x, y)),
+                Event::MouseButtonUp { x, y, .. } => match drag_start {
+                    Some(start) if !is_drag(start, (x, y)) => drag_start = None,
+                    _ => {
+                        break 'select Some(Region::from_corners(
+                            drag_start.unwrap_or((x, y)),
+                            (x, y),
+                        ));
+                    }
+                },
+                Event::MouseMotion { x, y, .. } => cursor_position = Some((x, y)),
+                _ => {}
+            }
+        }
+
+        canvas.set_draw_color(Color::RGB(0, 0, 0));
+        canvas.clear();
+        if let (Some(drag_start), Some(cursor_position)) = (drag_start, cursor_position) {
+            canvas.set_draw_color(Color::RGB(255, 255, 255));
+            canvas
+                .draw_rect(region_rect(&Region::from_corners(
+                    drag_start,
+                    cursor_position,
+                )))
+                .expect("Render failed");
+        }
+        canvas.present();
+
+        thread::sleep(one_frame);
+    };
+
+    canvas.window_mut().hide();
+    let _ = canvas.window_mut().set_opacity(1.0);
+    // Give the window manager time to actually get the overlay off of the screen
+    thread::sleep(one_frame * 4);
+
+    Ok(selection)
+}
+
+/// Flashes the outline of the committed `region` over the frozen frame, fading it out like a
+/// camera shutter so that it's clear what was captured. Only the overlay is drawn on; the
+/// captured pixels are untouched.
+pub fn flash_selection(
+    canvas: &mut Canvas<Window>,
+    texture: &Texture,
+    region: &Region,
+    one_frame: Duration,
+) {
+    let start = Instant::now();
+    while let Some(remaining) = FLASH_DURATION.checked_sub(start.elapsed()) {
+        let alpha = (255. * remaining.as_secs_f32() / FLASH_DURATION.as_secs_f32()) as u8;
+        let selection_rect = region_rect(region);
+        let outline_rects: Vec<Rect> = (0..FLASH_OUTLINE_WIDTH)
+            .map(|offset| {
+                Rect::new(
+                    selection_rect.x() - offset,
+                    selection_rect.y() - offset,
+                    selection_rect.width() + offset as u32 * 2,
+                    selection_rect.height() + offset as u32 * 2,
+                )
+            })
+            .collect();
+
+        canvas.copy(texture, None, None).expect("Render failed");
+        canvas.set_blend_mode(BlendMode::Blend);
+        canvas.set_draw_color(Color::RGBA(255, 255, 255, alpha / 4));
+        canvas.fill_rect(selection_rect).expect("Render failed");
+        canvas.set_draw_color(Color::RGBA(255, 255, 255, alpha));
+        canvas.draw_rects(&outline_rects).expect("Render failed");
+        canvas.set_blend_mode(BlendMode::None);
+        canvas.present();
+
+        thread::sleep(one_frame);
+    }
+}
+
+/// Shows the selected region highlighted on top of the frozen frame and waits for the user to
+/// choose what to do with it: enter uploads it, `C` copies it to the clipboard without uploading,
+/// and escape cancels. Until then, the selection can be fine-tuned by dragging the handles on its
+/// corners and edges or replaced entirely by dragging out a new one.
+///
+/// Dragged handles and corners snap to `edges` like in `select_region`. Cancels if no choice has
+/// been made once `timeout` has passed.
+///
+/// If `one_time` is set, the instructions warn that uploading creates a link that stops working
+/// after it's first viewed.
+#[allow(clippy::too_many_arguments)]
+pub fn confirm_selection(
+    canvas: &mut Canvas<Window>,
+    texture: &Texture,
+    event_pump: &mut EventPump,
+    region: Region,
+    bounds: (usize, usize),
+    edges: Option<&EdgeMap>,
+    one_time: bool,
+    timeout: Option<Duration>,
+    one_frame: Duration,
+) -> (ConfirmAction, Region) {
+    let mut snapper = Snapper::new(edges);
+    let scale_factor = scale_factor(canvas);
+    let mut region = region;
+    let mut grabbed_handle: Option<Handle> = None;
+    // Where the mouse was pressed outside of the handles, before and after snapping
+    let mut new_selection_start: Option<((i32, i32), (i32, i32))> = None;
+    let start = Instant::now();
+
+    loop {
+        if timed_out(start, timeout) {
+            return (ConfirmAction::Cancel, region);
+        }
+
+        for event in event_pump.poll_iter() {
+            let event = to_physical_event(event, scale_factor, bounds);
+            match event {
+                Event::Quit { .. }
+                | Event::KeyDown {
+                    keycode: Some(Keycode::Escape),
+                    ..
+                } => return (ConfirmAction::Cancel, region),
+                Event::KeyDown {
+                    keycode: Some(Keycode::Return),
+                    ..
+                }
+                | Event::KeyDown {
+                    keycode: Some(Keycode::KpEnter),
+                    ..
+                } => return (ConfirmAction::Upload, region),
+                Event::KeyDown {
+                    keycode: Some(Keycode::C),
+                    ..
+                } => return (ConfirmAction::CopyImage, region),
+                Event::KeyDown {
+                    keycode: Some(Keycode::S),
+                    ..
+                } => snapper.toggle(),
+                Event::MouseButtonDown { x, y, .. } => {
+                    grabbed_handle = Handle::hit_test(&region, (x, y));
+                    if grabbed_handle.is_none() {
+                        new_selection_start = Some(((x, y), snapper.snap((x, y))));
+                    }
+                }
+                Event::MouseMotion { x, y, .. } => {
+                    if let Some(handle) = grabbed_handle {
+                        region = handle.drag(&region, snapper.snap((x, y)), bounds);
+                        // The handle being dragged changes when the selection flips
+                        grabbed_handle = Handle::hit_test(&region, (x, y)).or(grabbed_handle);
+                    } else if let Some((press, start)) = new_selection_start {
+                        if is_drag(press, (x, y)) {
+                            region = Region::from_corners(start, snapper.snap((x, y)));
+                        }
+                    }
+                }
+                Event::MouseButtonUp { .. } => {
+                    grabbed_handle = None;
+                    new_selection_start = None;
+                }
+                _ => {}
+            }
+        }
+
+        let hint = format!(
+            "ENTER: {}  C: COPY WITHOUT UPLOADING  S: SNAPPING {}  ESC: CANCEL",
+            if one_time {
+                "UPLOAD ONE-TIME LINK (DELETED AFTER FIRST VIEW)"
+            } else {
+                "UPLOAD"
+            },
+            if snapper.enabled { "ON" } else { "OFF" }
+        );
+        draw_selection(canvas, texture, &region, true, Some(&hint), None);
+        thread::sleep(one_frame);
+    }
+}
@@ -0,0 +1,307 @@
+//! The `config` subcommand, a small window for editing the most common settings in the config file
+//! without having to write TOML by hand.
+//!
+//! Only the edited keys are changed; everything else in the file is kept. The file is rewritten
+//! from its parsed contents when saving though, so any comments in it are lost.
+
+use std::fs;
+use std::io::ErrorKind;
+use std::path::Path;
+use std::thread;
+use std::time::Duration;
+
+use sdl2::event::Event;
+use sdl2::keyboard::{Keycode, Mod};
+use sdl2::mouse::MouseButton;
+use sdl2::pixels::Color;
+use sdl2::rect::Rect;
+use sdl2::render::Canvas;
+use sdl2::video::Window;
+
+use crate::encode::Format;
+use crate::error::SnapmeoError;
+use crate::font;
+use crate::upload::Expiry;
+
+const TEXT_SCALE: u32 = 2;
+/// Space in pixels around the edges of the window and between its rows
+const MARGIN: u32 = 12;
+/// Width in characters that values are given room for
+const VALUE_CHARS: u32 = 48;
+const UPLOADERS: [&str; 3] = ["ameotrack", "s3", "sftp"];
+
+/// How a setting is edited
+#[derive(Clone, Copy)]
+enum FieldKind {
+    /// Typed in freely
+    Text,
+    /// Picked from a fixed list with the left and right arrow keys
+    Choice(&'static [&'static str]),
+}
+
+/// One of the settings that the window edits, along with its value as shown
+struct Field {
+    key: &'static str,
+    label: &'static str,
+    kind: FieldKind,
+    value: String,
+}
+
+impl Field {
+    /// Moves to the next or previous choice of a `Choice` field, wrapping around.
+    fn cycle(&mut self, backwards: bool) {
+        if let FieldKind::Choice(choices) = self.kind {
+            let ix = choices.iter().position(|&choice| choice == self.value);
+            let next = match (ix, backwards) {
+                (Some(ix), false) => (ix + 1) % choices.len(),
+                (Some(ix), true) => (ix + choices.len() - 1) % choices.len(),
+                (None, _) => 0,
+            };
+            self.value = choices[next].into();
+        }
+    }
+
+    /// Returns an error describing what's wrong with the field's value, if anything.
+    fn validate(&self) -> Result<(), String> {
+        match self.key {
+            "expiry" if !self.value.is_empty() => self.value.parse::<Expiry>().map(|_| ()),
+            "format" if !self.value.is_empty() => self
+                .value
+                .split(',')
+                .try_for_each(|format| format.trim().parse::<Format>().map(|_| ())),
+            _ => Ok(()),
+        }
+    }
+}
+
+/// Reads the top level table of the config file at `path`, which doesn't have to exist yet.
+fn read_table(path: &Path) -> Result<toml::value::Table, SnapmeoError> {
+    let contents = match fs::read_to_string(path) {
+        Ok(contents) => contents,
+        Err(err) if err.kind() == ErrorKind::NotFound => return Ok(Default::default()),
+        Err(err) => return Err(err.into()),
+    };
+    toml::from_str(&contents).map_err(|err| {
+        SnapmeoError::BadArguments(format!("Invalid config file `{}`: {}", path.display(), err))
+    })
+}
+
+/// Writes `fields` into the config file at `path`, removing the keys of any that are empty.
+fn save(path: &Path, fields: &[Field]) -> Result<(), String> {
+    for field in fields {
+        field
+            .validate()
+            .map_err(|err| format!("{}: {}", field.label, err))?;
+    }
+
+    let mut table = read_table(path).map_err(|err| err.to_string())?;
+    for field in fields {
+        if field.value.is_empty() {
+            table.remove(field.key);
+        } else {
+            table.insert(field.key.into(), toml::Value::String(field.value.clone()));
+        }
+    }
+
+    let contents = toml::to_string(&toml::Value::Table(table)).map_err(|err| err.to_string())?;
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir).map_err(|err| err.to_string())?;
+    }
+    fs::write(path, contents).map_err(|err| err.to_string())
+}
+
+fn row_height() -> u32 {
+    font::GLYPH_HEIGHT * TEXT_SCALE + MARGIN
+}
+
+fn draw(canvas: &mut Canvas<Window>, fields: &[Field], selected: usize, status: &str) {
+    let label_width = font::text_size("OUTPUT DIRECTORY ", TEXT_SCALE).0;
+    canvas.set_draw_color(Color::RGB(32, 32, 32));
+    canvas.clear();
+
+    for (ix, field) in fields.iter().enumerate() {
+        let y = (MARGIN + ix as u32 * row_height()) as i32;
+        let value_x = (MARGIN + label_width) as i32;
+        if ix == selected {
+            let (width, _) = canvas.output_size().expect("Unable to get window size!");
+            canvas.set_draw_color(Color::RGB(64, 64, 96));
+            canvas
+                .fill_rect(Rect::new(0, y - MARGIN as i32 / 2, width, row_height()))
+                .expect("Render failed");
+        }
+
+        let value = match (field.kind, ix == selected) {
+            (FieldKind::Text, true) => format!("{}_", field.value),
+            (FieldKind::Choice(_), true) => format!("< {} >", field.value),
+            (_, false) if field.value.is_empty() => "(DEFAULT)".into(),
+            (_, false) => field.value.clone(),
+        };
+        // Show the end of long values, which is usually the interesting part of a path
+        let value: String = {
+            let chars: Vec<char> = value.chars().collect();
+            let start = chars.len().saturating_sub(VALUE_CHARS as usize);
+            chars[start..].iter().collect()
+        };
+
+        canvas.set_draw_color(Color::RGB(255, 255, 255));
+        font::draw_text(canvas, field.label, MARGIN as i32, y, TEXT_SCALE).expect("Render failed");
+        font::draw_text(canvas, &value, value_x, y, TEXT_SCALE).expect("Render failed");
+    }
+
+    let hint_y = (MARGIN + fields.len() as u32 * row_height()) as i32;
+    canvas.set_draw_color(Color::RGB(160, 160, 160));
+    font::draw_text(
+        canvas,
+        "UP/DOWN: FIELD  LEFT/RIGHT: CHANGE  CTRL+S: SAVE  ESC: CLOSE",
+        MARGIN as i32,
+        hint_y,
+        TEXT_SCALE,
+    )
+    .expect("Render failed");
+    canvas.set_draw_color(Color::RGB(255, 255, 255));
+    font::draw_text(
+        canvas,
+        status,
+        MARGIN as i32,
+        hint_y + row_height() as i32,
+        TEXT_SCALE,
+    )
+    .expect("Render failed");
+    canvas.present();
+}
+
+/// Opens the settings window for the config file at `path`, returning once it's closed.
+pub fn edit(path: &Path) -> Result<(), SnapmeoError> {
+    let table = read_table(path)?;
+    let value_of = |key: &str| {
+        table
+            .get(key)
+            .and_then(|value| value.as_str())
+            .unwrap_or_default()
+            .to_owned()
+    };
+    let mut fields = vec![
+        Field {
+            key: "output_dir",
+            label: "OUTPUT DIRECTORY",
+            kind: FieldKind::Text,
+            value: value_of("output_dir"),
+        },
+        Field {
+            key: "uploader",
+            label: "UPLOADER",
+            kind: FieldKind::Choice(&UPLOADERS),
+            value: value_of("uploader"),
+        },
+        Field {
+            key: "expiry",
+            label: "EXPIRY",
+            kind: FieldKind::Text,
+            value: value_of("expiry"),
+        },
+        Field {
+            key: "format",
+            label: "FORMAT",
+            kind: FieldKind::Text,
+            value: value_of("format"),
+        },
+    ];
+
+    let sdl_context = sdl2::init().map_err(SnapmeoError::Other)?;
+    let video_subsystem = sdl_context.video().map_err(SnapmeoError::Other)?;
+    let label_width = font::text_size("OUTPUT DIRECTORY ", TEXT_SCALE).0;
+    let value_width = font::text_size(&"0".repeat(VALUE_CHARS as usize), TEXT_SCALE).0;
+    let window = video_subsystem
+        .window(
+            "Snapmeo settings",
+            MARGIN * 2 + label_width + value_width,
+            MARGIN * 2 + (fields.len() as u32 + 2) * row_height(),
+        )
+        .position_centered()
+        .build()
+        .map_err(|err| SnapmeoError::Other(err.to_string()))?;
+    let mut canvas = window
+        .into_canvas()
+        .build()
+        .map_err(|err| SnapmeoError::Other(err.to_string()))?;
+    let mut event_pump = sdl_context.event_pump().map_err(SnapmeoError::Other)?;
+    video_subsystem.text_input().start();
+
+    let mut selected = 0;
+    let mut status = format!("EDITING {}", path.display());
+    loop {
+        for event in event_pump.poll_iter() {
+            match event {
+                Event::Quit { .. }
+                | Event::KeyDown {
+                    keycode: Some(Keycode::Escape),
+                    ..
+                } => return Ok(()),
+                Event::KeyDown {
+                    keycode: Some(Keycode::S),
+                    keymod,
+                    ..
+                } if keymod.intersects(Mod::LCTRLMOD | Mod::RCTRLMOD) => {
+                    status = match save(path, &fields) {
+                        Ok(()) => format!("SAVED TO {}", path.display()),
+                        Err(err) => format!("NOT SAVED: {}", err),
+                    };
+                }
+                Event::KeyDown {
+                    keycode: Some(Keycode::Up),
+                    ..
+                } => selected = (selected + fields.len() - 1) % fields.len(),
+                Event::KeyDown {
+                    keycode: Some(Keycode::Down),
+                    ..
+                }
+                | Event::KeyDown {
+                    keycode: Some(Keycode::Tab),
+                    ..
+                } => selected = (selected + 1) % fields.len(),
+                Event::KeyDown {
+                    keycode: Some(Keycode::Left),
+                    ..
+                } => fields[selected].cycle(true),
+                Event::KeyDown {
+                    keycode: Some(Keycode::Right),
+                    ..
+                } => fields[selected].cycle(false),
+                Event::KeyDown {
+                    keycode: Some(Keycode::Backspace),
+                    ..
+                } => match fields[selected].kind {
+                    FieldKind::Text => {
+                        fields[selected].value.pop();
+                    }
+                    // Going back to the default
+                    FieldKind::Choice(_) => fields[selected].value.clear(),
+                },
+                // Holding Ctrl is for shortcuts, not typing
+                Event::TextInput { text, .. } => {
+                    let ctrl = sdl_context
+                        .keyboard()
+                        .mod_state()
+                        .intersects(Mod::LCTRLMOD | Mod::RCTRLMOD);
+                    if let (FieldKind::Text, false) = (fields[selected].kind, ctrl) {
+                        fields[selected].value.push_str(&text);
+                    }
+                }
+                Event::MouseButtonDown {
+                    mouse_btn: MouseButton::Left,
+                    y,
+                    ..
+                } => {
+                    let row = (y - MARGIN as i32 / 2).max(0) as u32 / row_height();
+                    if (row as usize) < fields.len() {
+                        selected = row as usize;
+                    }
+                }
+                _ => {}
+            }
+        }
+
+        draw(&mut canvas, &fields, selected, &status);
+        thread::sleep(Duration::from_secs(1) / 60);
+    }
+}
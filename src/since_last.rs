@@ -0,0 +1,148 @@
+//! `--since-last`, which reports how long it's been since the previous capture and since the
+//! session of captures began, so that progress can be followed through a long timelapse.
+//!
+//! Timelapses are taken by separate runs, so the times are kept in `since-last.json` in the same
+//! directory as the upload queue. A capture more than `SESSION_GAP` after the previous one starts
+//! a new session.
+
+use std::fs;
+use std::path::PathBuf;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use serde::{Deserialize, Serialize};
+
+use crate::error::SnapmeoError;
+use crate::upload;
+
+/// How long after the previous capture a new session begins
+const SESSION_GAP: Duration = Duration::from_secs(60 * 60);
+
+/// When the current session began and when its latest capture was taken, in seconds since the Unix
+/// epoch
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Session {
+    started_at: f64,
+    previous_at: f64,
+}
+
+/// How a capture fits into its session
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Timing {
+    /// Time since the previous capture, or `None` if this capture started the session
+    pub since_last: Option<Duration>,
+    /// Time since the session's first capture
+    pub session: Duration,
+}
+
+fn session_path() -> Result<PathBuf, SnapmeoError> {
+    upload::data_dir()
+        .map(|dir| dir.join("since-last.json"))
+        .ok_or_else(|| {
+            SnapmeoError::Other(
+                "Unable to find a directory to remember the previous capture time in".into(),
+            )
+        })
+}
+
+/// Returns the session after a capture at `now`, given the session so far, along with how the
+/// capture fits into it.
+fn advance(session: Option<Session>, now: f64) -> (Session, Timing) {
+    match session.filter(|session| {
+        now >= session.previous_at && now - session.previous_at <= SESSION_GAP.as_secs_f64()
+    }) {
+        Some(session) => (
+            Session {
+                previous_at: now,
+                ..session
+            },
+            Timing {
+                since_last: Some(Duration::from_secs_f64(now - session.previous_at)),
+                session: Duration::from_secs_f64(now - session.started_at),
+            },
+        ),
+        None => (
+            Session {
+                started_at: now,
+                previous_at: now,
+            },
+            Timing {
+                since_last: None,
+                session: Duration::ZERO,
+            },
+        ),
+    }
+}
+
+/// Works out how a capture at `now` fits into the current session. The session is only updated
+/// once the returned `Session` is passed to `save`, so that cancelled captures don't count.
+pub fn next(now: SystemTime) -> (Session, Timing) {
+    // This is only for reporting, so a missing or damaged file just starts a new session
+    let session = session_path()
+        .ok()
+        .and_then(|path| fs::read_to_string(path).ok())
+        .and_then(|contents| serde_json::from_str(&contents).ok());
+    let now = now
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs_f64();
+    advance(session, now)
+}
+
+/// Remembers `session` for the next capture.
+pub fn save(session: &Session) -> Result<(), SnapmeoError> {
+    let path = session_path()?;
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir)?;
+    }
+    let contents =
+        serde_json::to_string(session).map_err(|err| SnapmeoError::Other(err.to_string()))?;
+    fs::write(path, contents)?;
+    Ok(())
+}
+
+/// Formats `duration` to the second like `1h03m05s`, which is also safe to put in file names.
+pub fn format_duration(duration: Duration) -> String {
+    let secs = duration.as_secs();
+    let (hours, minutes, secs) = (secs / 3600, secs / 60 % 60, secs % 60);
+    if hours > 0 {
+        format!("{}h{:02}m{:02}s", hours, minutes, secs)
+    } else if minutes > 0 {
+        format!("{}m{:02}s", minutes, secs)
+    } else {
+        format!("{}s", secs)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn advance_continues_recent_sessions() {
+        let (session, timing) = advance(None, 1000.);
+        assert_eq!(timing.since_last, None);
+        assert_eq!(timing.session, Duration::ZERO);
+
+        let (session, timing) = advance(Some(session), 1030.);
+        assert_eq!(timing.since_last, Some(Duration::from_secs(30)));
+        assert_eq!(timing.session, Duration::from_secs(30));
+
+        let (_, timing) = advance(Some(session), 1090.);
+        assert_eq!(timing.since_last, Some(Duration::from_secs(60)));
+        assert_eq!(timing.session, Duration::from_secs(90));
+    }
+
+    #[test]
+    fn advance_starts_a_new_session_after_a_gap() {
+        let (session, _) = advance(None, 1000.);
+        let (_, timing) = advance(Some(session), 1000. + SESSION_GAP.as_secs_f64() + 1.);
+        assert_eq!(timing.since_last, None);
+    }
+
+    #[test]
+    fn format_duration_uses_the_largest_unit_needed() {
+        assert_eq!(format_duration(Duration::from_secs(5)), "5s");
+        assert_eq!(format_duration(Duration::from_secs(302)), "5m02s");
+        assert_eq!(format_duration(Duration::from_secs(3785)), "1h03m05s");
+    }
+}
@@ -0,0 +1,104 @@
+//! Snapping selections to the edges of windows, panels, and other UI elements in the frozen frame.
+//!
+//! Edges are found once per frame as the points where the luminance changes sharply between
+//! neighboring pixels. A point being dragged snaps to an edge if there's a long enough run of
+//! edge pixels near it, so that noise like text doesn't attract the selection.
+
+/// Smallest difference in luminance from 0 to 255 between neighboring pixels that counts as an edge
+const EDGE_THRESHOLD: u8 = 40;
+/// How far in pixels a point can be from an edge to snap to it
+const SNAP_DISTANCE: i32 = 6;
+/// Half of the length of the span along an edge that's checked for edge pixels around a point
+const EDGE_SPAN_RADIUS: i32 = 12;
+/// How many pixels of that span have to be edge pixels for it to count as a real edge
+const MIN_EDGE_PIXELS: usize = 16;
+
+/// The edges found in a frame
+pub struct EdgeMap {
+    width: usize,
+    height: usize,
+    /// Whether each pixel is on the left side of a vertical edge with the pixel to its left
+    vertical: Vec<bool>,
+    /// Whether each pixel is on the top side of a horizontal edge with the pixel above it
+    horizontal: Vec<bool>,
+}
+
+impl EdgeMap {
+    /// Finds the edges in a captured BGRA `frame` whose rows are `width * 4` bytes apart.
+    pub fn new(frame: &[u8], width: usize, height: usize) -> Self {
+        let luminance: Vec<u8> = frame
+            .chunks(4)
+            .take(width * height)
+            .map(|pixel| {
+                let (b, g, r) = (pixel[0] as u32, pixel[1] as u32, pixel[2] as u32);
+                ((r * 299 + g * 587 + b * 114) / 1000) as u8
+            })
+            .collect();
+        let is_edge = |a: usize, b: usize| luminance[a].abs_diff(luminance[b]) >= EDGE_THRESHOLD;
+
+        let mut vertical = vec![false; width * height];
+        let mut horizontal = vec![false; width * height];
+        for y in 0..height {
+            for x in 0..width {
+                let ix = y * width + x;
+                vertical[ix] = x > 0 && is_edge(ix, ix - 1);
+                horizontal[ix] = y > 0 && is_edge(ix, ix - width);
+            }
+        }
+
+        EdgeMap {
+            width,
+            height,
+            vertical,
+            horizontal,
+        }
+    }
+
+    /// Counts the edge pixels of `edges` in the column `x` (or row, if `transposed`) within
+    /// `EDGE_SPAN_RADIUS` of `along`.
+    fn edge_strength(&self, edges: &[bool], x: i32, along: i32, transposed: bool) -> usize {
+        let (limit, other_limit) = if transposed {
+            (self.height, self.width)
+        } else {
+            (self.width, self.height)
+        };
+        if x < 0 || x as usize >= limit {
+            return 0;
+        }
+
+        let start = (along - EDGE_SPAN_RADIUS).max(0);
+        let end = (along + EDGE_SPAN_RADIUS).min(other_limit as i32 - 1);
+        (start..=end)
+            .filter(|&along| {
+                let ix = if transposed {
+                    x as usize * self.width + along as usize
+                } else {
+                    along as usize * self.width + x as usize
+                };
+                edges[ix]
+            })
+            .count()
+    }
+
+    /// Returns the coordinate within `SNAP_DISTANCE` of `x` with the strongest edge of `edges`
+    /// nearby, or `x` itself if there isn't one.
+    fn snap_coordinate(&self, edges: &[bool], x: i32, along: i32, transposed: bool) -> i32 {
+        (x - SNAP_DISTANCE..=x + SNAP_DISTANCE)
+            .map(|candidate| {
+                let strength = self.edge_strength(edges, candidate, along, transposed);
+                (candidate, strength)
+            })
+            .filter(|&(_, strength)| strength >= MIN_EDGE_PIXELS)
+            // Prefer stronger edges, then closer ones
+            .max_by_key(|&(candidate, strength)| (strength, -(candidate - x).abs()))
+            .map_or(x, |(candidate, _)| candidate)
+    }
+
+    /// Moves `point` onto any strong edges close to it, horizontally and vertically independently.
+    pub fn snap(&self, point: (i32, i32)) -> (i32, i32) {
+        (
+            self.snap_coordinate(&self.vertical, point.0, point.1, false),
+            self.snap_coordinate(&self.horizontal, point.1, point.0, true),
+        )
+    }
+}
@@ -0,0 +1,83 @@
+//! Playing a camera shutter sound when a capture is taken.
+//!
+//! The default sound is synthesized rather than shipped as an audio file: two short bursts of
+//! decaying noise, like a shutter opening and closing.
+
+use std::path::Path;
+
+use sdl2::audio::{AudioCVT, AudioFormat, AudioQueue, AudioSpecDesired, AudioSpecWAV};
+use sdl2::Sdl;
+
+const SAMPLE_RATE: i32 = 44_100;
+/// Offsets in seconds of each of the clicks that make up the default sound
+const CLICK_OFFSETS: [f32; 2] = [0., 0.07];
+const CLICK_DURATION: f32 = 0.04;
+const CLICK_VOLUME: f32 = 0.5;
+
+/// Returns the samples of the built-in shutter sound as mono 16-bit audio at `SAMPLE_RATE`.
+fn default_shutter() -> Vec<i16> {
+    let total_secs = CLICK_OFFSETS[CLICK_OFFSETS.len() - 1] + CLICK_DURATION;
+    let sample_count = (total_secs * SAMPLE_RATE as f32) as usize;
+    // A tiny LCG is plenty for noise and keeps the sound the same every time
+    let mut seed: u32 = 0x2545_f491;
+    let mut noise = move || {
+        seed = seed.wrapping_mul(1_664_525).wrapping_add(1_013_904_223);
+        (seed >> 16) as f32 / 32_768. - 1.
+    };
+
+    (0..sample_count)
+        .map(|ix| {
+            let t = ix as f32 / SAMPLE_RATE as f32;
+            let envelope: f32 = CLICK_OFFSETS
+                .iter()
+                .filter(|&&offset| t >= offset && t < offset + CLICK_DURATION)
+                .map(|&offset| (-(t - offset) / (CLICK_DURATION / 6.)).exp())
+                .sum();
+            (noise() * envelope.min(1.) * CLICK_VOLUME * i16::MAX as f32) as i16
+        })
+        .collect()
+}
+
+/// Loads the WAV file at `path` converted to mono 16-bit audio at `SAMPLE_RATE`.
+fn load_wav(path: &Path) -> Result<Vec<i16>, String> {
+    let wav = AudioSpecWAV::load_wav(path)
+        .map_err(|err| format!("Unable to load `{}`: {}", path.display(), err))?;
+    let converter = AudioCVT::new(
+        wav.format,
+        wav.channels,
+        wav.freq,
+        AudioFormat::s16_sys(),
+        1,
+        SAMPLE_RATE,
+    )?;
+    let converted = converter.convert(wav.buffer().to_vec());
+
+    Ok(converted
+        .chunks_exact(2)
+        .map(|sample| i16::from_ne_bytes([sample[0], sample[1]]))
+        .collect())
+}
+
+/// Starts playing the shutter sound, or the WAV file at `path` instead if it's given. Playback
+/// happens in the background and continues for as long as the returned queue is kept alive.
+pub fn play_shutter(sdl_context: &Sdl, path: Option<&Path>) -> Result<AudioQueue<i16>, String> {
+    let audio_subsystem = sdl_context.audio()?;
+    let desired_spec = AudioSpecDesired {
+        freq: Some(SAMPLE_RATE),
+        channels: Some(1),
+        samples: None,
+    };
+    // SDL converts to whatever the device actually plays itself
+    let queue: AudioQueue<i16> = audio_subsystem.open_queue(None, &desired_spec)?;
+
+    let samples = match path {
+        Some(path) => load_wav(path)?,
+        None => default_shutter(),
+    };
+    if !queue.queue(&samples) {
+        return Err("Unable to queue the shutter sound".into());
+    }
+    queue.resume();
+
+    Ok(queue)
+}
@@ -0,0 +1,136 @@
+//! Templates like `[img]{url}[/img]` that have `{name}` placeholders filled in with details of a
+//! capture. Literal braces are written as `{{` and `}}`.
+
+/// A piece of a parsed template
+enum Piece<'a> {
+    Literal(&'a str),
+    Placeholder(&'a str),
+}
+
+fn parse(template: &str) -> Result<Vec<Piece<'_>>, String> {
+    let mut pieces = Vec::new();
+    let mut rest = template;
+    while let Some(ix) = rest.find(['{', '}']) {
+        if ix > 0 {
+            pieces.push(Piece::Literal(&rest[..ix]));
+        }
+        let after = &rest[ix + 1..];
+        match (&rest[ix..ix + 1], after.chars().next()) {
+            ("{", Some('{')) => {
+                pieces.push(Piece::Literal("{"));
+                rest = &after[1..];
+            }
+            ("}", Some('}')) => {
+                pieces.push(Piece::Literal("}"));
+                rest = &after[1..];
+            }
+            ("{", _) => {
+                let end = after
+                    .find('}')
+                    .ok_or_else(|| format!("Unclosed `{{` in template `{}`", template))?;
+                pieces.push(Piece::Placeholder(&after[..end]));
+                rest = &after[end + 1..];
+            }
+            _ => {
+                return Err(format!(
+                    "Unmatched `}}` in template `{}`; write `}}}}` for a literal brace",
+                    template
+                ))
+            }
+        }
+    }
+    if !rest.is_empty() {
+        pieces.push(Piece::Literal(rest));
+    }
+    Ok(pieces)
+}
+
+/// Checks that `template` is well formed and only uses the placeholders in `names`, so that
+/// mistakes are caught before anything is captured.
+pub fn validate(template: &str, names: &[&str]) -> Result<(), String> {
+    for piece in parse(template)? {
+        if let Piece::Placeholder(name) = piece {
+            if !names.contains(&name) {
+                return Err(format!(
+                    "Unknown placeholder `{{{}}}` in template `{}`; expected one of {}",
+                    name,
+                    template,
+                    names
+                        .iter()
+                        .map(|name| format!("`{{{}}}`", name))
+                        .collect::<Vec<_>>()
+                        .join(", ")
+                ));
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Fills in the placeholders in `template` with the values `value` returns for their names.
+/// Placeholders it has no value for are left empty.
+pub fn render(template: &str, value: impl Fn(&str) -> Option<String>) -> Result<String, String> {
+    Ok(parse(template)?
+        .into_iter()
+        .map(|piece| match piece {
+            Piece::Literal(text) => text.to_owned(),
+            Piece::Placeholder(name) => value(name).unwrap_or_default(),
+        })
+        .collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const NAMES: &[&str] = &["url", "width", "height"];
+
+    fn value(name: &str) -> Option<String> {
+        match name {
+            "url" => Some("https://ameo.link/u/abc.png".into()),
+            "width" => Some("800".into()),
+            _ => None,
+        }
+    }
+
+    #[test]
+    fn render_fills_in_placeholders() {
+        assert_eq!(
+            render("[img]{url}[/img]", value).unwrap(),
+            "[img]https://ameo.link/u/abc.png[/img]"
+        );
+        assert_eq!(render("{width}x{height}", value).unwrap(), "800x");
+        assert_eq!(render("no placeholders", value).unwrap(), "no placeholders");
+        assert_eq!(render("", value).unwrap(), "");
+    }
+
+    #[test]
+    fn doubled_braces_are_literal() {
+        assert_eq!(render("{{url}}", value).unwrap(), "{url}");
+        assert_eq!(
+            render("{{\"src\": \"{url}\"}}", value).unwrap(),
+            "{\"src\": \"https://ameo.link/u/abc.png\"}"
+        );
+        assert!(validate("{{unknown}}", NAMES).is_ok());
+    }
+
+    #[test]
+    fn unclosed_and_stray_braces_are_errors() {
+        let err = render("[img]{url[/img]", value).unwrap_err();
+        assert!(err.contains("Unclosed `{`"), "{}", err);
+        assert!(validate("{url", NAMES).is_err());
+
+        let err = render("url}", value).unwrap_err();
+        assert!(err.contains("Unmatched `}`"), "{}", err);
+        assert!(validate("{url}}", NAMES).is_err());
+    }
+
+    #[test]
+    fn validate_rejects_unknown_placeholders() {
+        assert!(validate("{url} {width}x{height}", NAMES).is_ok());
+        let err = validate("{url} {size}", NAMES).unwrap_err();
+        assert!(err.contains("`{size}`"), "{}", err);
+        assert!(err.contains("`{url}`, `{width}`, `{height}`"), "{}", err);
+        assert!(validate("{}", NAMES).is_err());
+    }
+}
@@ -0,0 +1,914 @@
+//! Backends that captured images can be uploaded to.
+
+use std::collections::hash_map::RandomState;
+use std::convert::TryFrom;
+use std::env;
+use std::fmt;
+use std::fs;
+use std::hash::{BuildHasher, Hasher};
+use std::path::{Path, PathBuf};
+use std::process;
+use std::str::FromStr;
+use std::thread;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use reqwest::blocking::multipart::{Form, Part};
+use reqwest::blocking::Client;
+use reqwest::header::{ACCEPT_ENCODING, CONTENT_ENCODING, CONTENT_TYPE};
+use reqwest::{Method, StatusCode};
+
+pub use snapmeo::ameotrack::AMEOTRACK_UPLOAD_URL;
+use snapmeo::ameotrack::{
+    ameotrack_upload, gzip, multipart_body, multipart_boundary, response_url, AmeoTrackOptions,
+    MIN_COMPRESSED_UPLOAD_BYTES,
+};
+
+use crate::error::SnapmeoError;
+use crate::schedule;
+
+pub mod dedup;
+pub mod queue;
+pub mod s3;
+pub mod sftp;
+
+const SECONDS_PER_MINUTE: u64 = 60;
+const SECONDS_PER_HOUR: u64 = 60 * SECONDS_PER_MINUTE;
+const SECONDS_PER_DAY: u64 = 24 * SECONDS_PER_HOUR;
+const SECONDS_PER_WEEK: u64 = 7 * SECONDS_PER_DAY;
+
+/// How long an uploaded image should be hosted before it's deleted
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Expiry {
+    Never,
+    After(Duration),
+}
+
+impl FromStr for Expiry {
+    type Err = String;
+
+    /// Parses expiries like `30m`, `12h`, `7d`, `2w`, or `never`. Plain numbers are treated as a
+    /// number of days and `-1` as never for compatibility with AmeoTrack's own format.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let s = s.trim().to_lowercase();
+        if s == "never" || s == "-1" {
+            return Ok(Expiry::Never);
+        }
+
+        let duration = schedule::parse_duration_in(&s, SECONDS_PER_DAY)?;
+        if duration.is_zero() {
+            return Err("The expiry must be greater than zero".into());
+        }
+        Ok(Expiry::After(duration))
+    }
+}
+
+impl fmt::Display for Expiry {
+    /// Formats the expiry in the largest unit that represents it exactly, such as `12h`.
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let secs = match self {
+            Expiry::Never => return write!(f, "never"),
+            Expiry::After(duration) => duration.as_secs(),
+        };
+        let (unit_secs, unit) = [
+            (SECONDS_PER_WEEK, "w"),
+            (SECONDS_PER_DAY, "d"),
+            (SECONDS_PER_HOUR, "h"),
+            (SECONDS_PER_MINUTE, "m"),
+        ]
+        .iter()
+        .find(|(unit_secs, _)| secs % unit_secs == 0)
+        .cloned()
+        .unwrap_or((1, "s"));
+        write!(f, "{}{}", secs / unit_secs, unit)
+    }
+}
+
+/// Settings controlling how an image is hosted, independent of the backend it's uploaded to
+#[derive(Clone)]
+pub struct UploadOptions {
+    pub expiry: Expiry,
+    pub secret: bool,
+    pub one_time: bool,
+    /// MIME type of the file being uploaded
+    pub content_type: &'static str,
+    /// Whether to gzip the request body if the backend supports it
+    pub compress: bool,
+    /// Name to give the file on the backend instead of the local file's name, already passed
+    /// through `sanitize_file_name`
+    pub remote_name: Option<String>,
+    /// Labels to send along with the upload for `--send-tags`, for backends that keep them
+    pub tags: Vec<String>,
+    /// Sent with every attempt at uploading the same capture, including the uncompressed retry
+    /// and retries from the queue, so that a backend that remembers the keys it's seen can ignore
+    /// an attempt whose earlier response was lost rather than storing the capture twice. Backends
+    /// that don't support them just ignore the field.
+    pub idempotency_key: Option<String>,
+}
+
+impl UploadOptions {
+    /// Returns the name the file at `path` should be given on the backend.
+    pub fn remote_name(&self, path: &Path) -> String {
+        self.remote_name.clone().unwrap_or_else(|| {
+            path.file_name()
+                .map(|name| name.to_string_lossy().into_owned())
+                .unwrap_or_default()
+        })
+    }
+
+    /// Returns the options for uploading a thumbnail of the capture these are for. The thumbnail
+    /// is a different file, so it gets an idempotency key of its own; a backend that remembers
+    /// keys would otherwise take it for a retry of the capture and never store it.
+    pub fn for_thumbnail(&self) -> UploadOptions {
+        UploadOptions {
+            idempotency_key: Some(idempotency_key()),
+            ..self.clone()
+        }
+    }
+}
+
+/// Generates a new random idempotency key for a capture, formatted as a version 4 UUID. The
+/// standard library's randomly seeded hasher is used as the source of randomness, which is plenty
+/// for telling captures apart.
+pub fn idempotency_key() -> String {
+    let nanos = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_nanos();
+    let random_half = |salt: u64| {
+        let mut hasher = RandomState::new().build_hasher();
+        hasher.write_u128(nanos);
+        hasher.write_u32(process::id());
+        hasher.write_u64(salt);
+        hasher.finish()
+    };
+    let mut bits = (u128::from(random_half(0)) << 64) | u128::from(random_half(1));
+    // The version and variant bits are what make it a version 4 UUID
+    bits = (bits & !(0xf << 76)) | (0x4 << 76);
+    bits = (bits & !(0x3 << 62)) | (0x2 << 62);
+    let hex = format!("{:032x}", bits);
+    format!(
+        "{}-{}-{}-{}-{}",
+        &hex[..8],
+        &hex[8..12],
+        &hex[12..16],
+        &hex[16..20],
+        &hex[20..]
+    )
+}
+
+/// How long a generated file name can get before its suffixes and extension are added. File
+/// systems generally allow 255 bytes, which leaves plenty of room for those.
+pub const MAX_FILE_NAME_BYTES: usize = 200;
+
+/// Shortens `name` to at most `max_bytes` bytes, cutting it between characters.
+pub fn truncate_file_name(name: &mut String, max_bytes: usize) {
+    if name.len() > max_bytes {
+        let end = (0..=max_bytes)
+            .rev()
+            .find(|&ix| name.is_char_boundary(ix))
+            .unwrap_or(0);
+        name.truncate(end);
+    }
+}
+
+/// Makes a user provided `--name` safe to send as a file name: path separators and characters
+/// that aren't allowed in file names on some platforms are replaced with `_`, and leading dots are
+/// removed so that it can't name a hidden file or a parent directory. `extension` is added if the
+/// name doesn't already end with it, since many hosts pick the content type from it.
+pub fn sanitize_file_name(name: &str, extension: &str) -> Result<String, String> {
+    let sanitized: String = name
+        .trim()
+        .chars()
+        .map(|c| match c {
+            '/' | '\\' | ':' | '*' | '?' | '"' | '<' | '>' | '|' => '_',
+            c if c.is_control() => '_',
+            c => c,
+        })
+        .collect();
+    let sanitized = sanitized.trim_start_matches('.').trim_end();
+    if sanitized.is_empty() {
+        return Err(format!("`{}` can't be used as an upload file name", name));
+    }
+
+    let suffix = format!(".{}", extension);
+    if sanitized.to_lowercase().ends_with(&suffix.to_lowercase()) {
+        Ok(sanitized.to_owned())
+    } else {
+        Ok(format!("{}{}", sanitized, suffix))
+    }
+}
+
+/// A backend that screenshots can be uploaded to. Backends have to be shareable between threads
+/// so that `--broadcast` can upload to several of them at once.
+pub trait Uploader: Sync {
+    /// Name of the backend used in messages to the user
+    fn name(&self) -> &'static str;
+
+    /// Converts `expiry` into the value this backend expects, returning an error if the backend
+    /// can't honor it rather than letting the setting be silently ignored.
+    fn translate_expiry(&self, expiry: &Expiry) -> Result<String, SnapmeoError>;
+
+    /// Uploads the file at `path`, returning the URL at which it can be viewed.
+    fn upload(&self, path: &Path, options: &UploadOptions) -> Result<String, SnapmeoError>;
+
+    /// Environment variable the backend reads its password from, if it uses one, so that the
+    /// password can be asked for again when it's rejected.
+    fn password_var(&self) -> Option<&'static str> {
+        None
+    }
+}
+
+pub struct AmeoTrack {
+    /// Endpoint that upload forms are posted to
+    pub upload_url: String,
+}
+
+impl AmeoTrack {
+    /// The library's options for uploading with `options`, with the password from
+    /// `AMEOTRACK_PASSWORD`
+    fn ameotrack_options(&self, options: &UploadOptions) -> Result<AmeoTrackOptions, SnapmeoError> {
+        let password = env::var("AMEOTRACK_PASSWORD").map_err(|_| {
+            SnapmeoError::Auth("The `AMEOTRACK_PASSWORD` environment variable must be set".into())
+        })?;
+        // Makes sure that the expiry is a whole number of days
+        self.translate_expiry(&options.expiry)?;
+        let expiry_days = match &options.expiry {
+            Expiry::Never => None,
+            Expiry::After(duration) => Some(
+                u32::try_from(duration.as_secs() / SECONDS_PER_DAY).map_err(|_| {
+                    SnapmeoError::BadArguments("That expiry is too far away for AmeoTrack".into())
+                })?,
+            ),
+        };
+
+        Ok(AmeoTrackOptions {
+            upload_url: self.upload_url.clone(),
+            password,
+            secret: options.secret,
+            one_time: options.one_time,
+            expiry_days,
+            idempotency_key: options.idempotency_key.clone(),
+            tags: options.tags.clone(),
+            compress: options.compress,
+        })
+    }
+}
+
+impl Default for AmeoTrack {
+    fn default() -> Self {
+        AmeoTrack {
+            upload_url: AMEOTRACK_UPLOAD_URL.into(),
+        }
+    }
+}
+
+/// The `multipart/form-data` form that an upload is posted as
+#[cfg_attr(not(test), allow(dead_code))]
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct UploadForm {
+    /// Name of the field the file goes in
+    pub file_field: &'static str,
+    /// Text fields sent along with the file
+    pub fields: Vec<(&'static str, String)>,
+}
+
+/// A backend that takes uploads as HTTP form posts and responds with the uploaded image's URL.
+/// Each one lays its form out however its API expects. AmeoTrack uploads through
+/// `snapmeo::ameotrack` instead, so this is for hosts that have no client of their own.
+#[cfg_attr(not(test), allow(dead_code))]
+pub trait FormUploader: Uploader {
+    /// Endpoint that upload forms are posted to
+    fn upload_url(&self) -> &str;
+
+    /// Builds the form for uploading a file with `options`.
+    fn form(&self, options: &UploadOptions) -> Result<UploadForm, SnapmeoError>;
+
+    /// Converts an unsuccessful response to an upload into an error.
+    fn response_error(&self, status: StatusCode, body: String) -> SnapmeoError {
+        SnapmeoError::Upload {
+            status: Some(status.as_u16()),
+            message: format!("{:?}", body),
+        }
+    }
+}
+
+impl Uploader for AmeoTrack {
+    fn name(&self) -> &'static str {
+        "AmeoTrack"
+    }
+
+    /// AmeoTrack takes expiries as a whole number of days, with `-1` meaning never.
+    fn translate_expiry(&self, expiry: &Expiry) -> Result<String, SnapmeoError> {
+        match expiry {
+            Expiry::Never => Ok("-1".into()),
+            Expiry::After(duration) if duration.as_secs() % SECONDS_PER_DAY == 0 => {
+                Ok((duration.as_secs() / SECONDS_PER_DAY).to_string())
+            }
+            Expiry::After(_) => Err(SnapmeoError::BadArguments(
+                "AmeoTrack only supports expiries of a whole number of days".into(),
+            )),
+        }
+    }
+
+    fn upload(&self, path: &Path, options: &UploadOptions) -> Result<String, SnapmeoError> {
+        let ameotrack_options = self.ameotrack_options(options)?;
+        let file = fs::read(path)?;
+        ameotrack_upload(
+            file,
+            &options.remote_name(path),
+            options.content_type,
+            &ameotrack_options,
+        )
+        .map_err(|err| match err {
+            SnapmeoError::Auth(message) => {
+                SnapmeoError::Auth(format!("{}; check `AMEOTRACK_PASSWORD`", message))
+            }
+            err => err,
+        })
+    }
+
+    fn password_var(&self) -> Option<&'static str> {
+        Some("AMEOTRACK_PASSWORD")
+    }
+}
+
+/// Uploads the file at `path` to a form based backend, trying a compressed upload first if
+/// `options` asks for one.
+#[cfg_attr(not(test), allow(dead_code))]
+pub fn upload_form<U: FormUploader + ?Sized>(
+    uploader: &U,
+    path: &Path,
+    options: &UploadOptions,
+) -> Result<String, SnapmeoError> {
+    let form = uploader.form(options)?;
+    if options.compress {
+        match upload_form_gzipped(uploader, &form, path, options) {
+            Ok(Some(url)) => return Ok(url),
+            Ok(None) => (),
+            // Retrying won't help if the credentials are wrong
+            Err(err @ SnapmeoError::Auth(_)) => return Err(err),
+            Err(err) => eprintln!(
+                "Compressed upload failed ({}); retrying uncompressed...",
+                err
+            ),
+        }
+    }
+
+    let file = Part::file(path)?
+        .file_name(options.remote_name(path))
+        .mime_str(options.content_type)?;
+    let body = form.fields.into_iter().fold(
+        Form::new().part(form.file_field, file),
+        |body, (name, value)| body.text(name, value),
+    );
+
+    let client = reqwest::blocking::Client::new();
+    let res = client.post(uploader.upload_url()).multipart(body).send()?;
+    let status = res.status();
+
+    let res_text = res.text()?;
+    if !status.is_success() {
+        return Err(uploader.response_error(status, res_text));
+    }
+
+    response_url(status, &res_text)
+}
+
+/// Returns whether the server at `url` advertises that it accepts gzip-encoded request bodies via
+/// the `Accept-Encoding` header of its response to an `OPTIONS` request (RFC 7694).
+#[cfg_attr(not(test), allow(dead_code))]
+fn accepts_gzip_requests(client: &Client, url: &str) -> Result<bool, SnapmeoError> {
+    let res = client.request(Method::OPTIONS, url).send()?;
+    let accepts_gzip = res
+        .headers()
+        .get_all(ACCEPT_ENCODING)
+        .iter()
+        .filter_map(|value| value.to_str().ok())
+        .flat_map(|value| value.split(','))
+        .any(|encoding| encoding.trim().starts_with("gzip"));
+
+    Ok(accepts_gzip)
+}
+
+/// Uploads the file as `form` with a gzip-encoded request body. Returns `None` without uploading
+/// anything if the file is too small to be worth compressing, the backend doesn't advertise
+/// support for compressed requests, or compression wouldn't make the request smaller.
+#[cfg_attr(not(test), allow(dead_code))]
+fn upload_form_gzipped<U: FormUploader + ?Sized>(
+    uploader: &U,
+    form: &UploadForm,
+    path: &Path,
+    options: &UploadOptions,
+) -> Result<Option<String>, SnapmeoError> {
+    let file = fs::read(path)?;
+    if file.len() < MIN_COMPRESSED_UPLOAD_BYTES {
+        return Ok(None);
+    }
+
+    let url = uploader.upload_url();
+    let client = Client::new();
+    if !accepts_gzip_requests(&client, url)? {
+        eprintln!(
+            "{} doesn't advertise support for compressed uploads; uploading uncompressed.",
+            uploader.name()
+        );
+        return Ok(None);
+    }
+
+    let boundary = multipart_boundary()?;
+    let file_name = options.remote_name(path);
+    let body = multipart_body(
+        &boundary,
+        &form.fields,
+        form.file_field,
+        &file_name,
+        options.content_type,
+        &file,
+    );
+
+    let compressed = match gzip(&body)? {
+        Some(compressed) => compressed,
+        None => {
+            eprintln!("Compression wouldn't make the upload any smaller; uploading uncompressed.");
+            return Ok(None);
+        }
+    };
+    eprintln!(
+        "Compressed upload from {} to {} bytes ({:.1}% smaller)",
+        body.len(),
+        compressed.len(),
+        100. * (1. - compressed.len() as f64 / body.len() as f64)
+    );
+
+    let res = client
+        .post(url)
+        .header(
+            CONTENT_TYPE,
+            format!("multipart/form-data; boundary={}", boundary),
+        )
+        .header(CONTENT_ENCODING, "gzip")
+        .body(compressed)
+        .send()?;
+    let status = res.status();
+    let res_text = res.text()?;
+    if !status.is_success() {
+        return Err(uploader.response_error(status, res_text));
+    }
+
+    response_url(status, &res_text).map(Some)
+}
+
+/// Returns the directory that state such as queued uploads is kept in between runs:
+/// `$XDG_DATA_HOME/snapmeo` (`~/.local/share/snapmeo` if that's unset), or
+/// `%LOCALAPPDATA%\snapmeo` on Windows.
+pub fn data_dir() -> Option<PathBuf> {
+    let data_dir = if cfg!(windows) {
+        env::var_os("LOCALAPPDATA").map(PathBuf::from)
+    } else {
+        env::var_os("XDG_DATA_HOME")
+            .filter(|dir| !dir.is_empty())
+            .map(PathBuf::from)
+            .or_else(|| env::var_os("HOME").map(|home| Path::new(&home).join(".local/share")))
+    };
+    data_dir.map(|dir| dir.join("snapmeo"))
+}
+
+/// Uploads the file at `path` to every one of `uploaders` at the same time, returning how each
+/// upload went in the same order. One backend failing doesn't stop the others.
+pub fn upload_all(
+    uploaders: &[&dyn Uploader],
+    path: &Path,
+    options: &UploadOptions,
+) -> Vec<Result<String, SnapmeoError>> {
+    thread::scope(|scope| {
+        let uploads: Vec<_> = uploaders
+            .iter()
+            .map(|uploader| scope.spawn(move || uploader.upload(path, options)))
+            .collect();
+        uploads
+            .into_iter()
+            .map(|upload| {
+                upload.join().unwrap_or_else(|_| {
+                    Err(SnapmeoError::Other("The upload thread panicked".into()))
+                })
+            })
+            .collect()
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+    use std::io::{BufRead, BufReader, Read, Write};
+    use std::net::TcpListener;
+    use std::thread::{self, JoinHandle};
+
+    use flate2::read::GzDecoder;
+    use snapmeo::ameotrack::upload_async;
+
+    use super::*;
+
+    const PASSWORD: &str = "hunter2";
+
+    struct Request {
+        method: String,
+        headers: HashMap<String, String>,
+        body: Vec<u8>,
+    }
+
+    /// Starts an HTTP server on a free local port that answers one request per connection with
+    /// each of `responses` in turn, returning its URL and a handle yielding the requests it got.
+    fn mock_server(responses: Vec<String>) -> (String, JoinHandle<Vec<Request>>) {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}/u/upload", listener.local_addr().unwrap());
+
+        let handle = thread::spawn(move || {
+            responses
+                .into_iter()
+                .map(|response| {
+                    let (mut stream, _) = listener.accept().unwrap();
+                    let mut reader = BufReader::new(stream.try_clone().unwrap());
+
+                    let mut request_line = String::new();
+                    reader.read_line(&mut request_line).unwrap();
+                    let mut headers = HashMap::new();
+                    loop {
+                        let mut line = String::new();
+                        reader.read_line(&mut line).unwrap();
+                        let line = line.trim_end();
+                        if line.is_empty() {
+                            break;
+                        }
+                        let (name, value) = line.split_at(line.find(':').unwrap());
+                        headers.insert(name.to_lowercase(), value[1..].trim().to_owned());
+                    }
+                    let content_length = headers
+                        .get("content-length")
+                        .map(|length| length.parse().unwrap())
+                        .unwrap_or(0);
+                    let mut body = vec![0; content_length];
+                    reader.read_exact(&mut body).unwrap();
+
+                    stream.write_all(response.as_bytes()).unwrap();
+                    Request {
+                        method: request_line.split(' ').next().unwrap().to_owned(),
+                        headers,
+                        body,
+                    }
+                })
+                .collect()
+        });
+
+        (url, handle)
+    }
+
+    fn response(status: &str, headers: &[(&str, &str)], body: &str) -> String {
+        let headers: String = headers
+            .iter()
+            .map(|(name, value)| format!("{}: {}\r\n", name, value))
+            .collect();
+        format!(
+            "HTTP/1.1 {}\r\n{}Content-Length: {}\r\nConnection: close\r\n\r\n{}",
+            status,
+            headers,
+            body.len(),
+            body
+        )
+    }
+
+    /// Splits a `multipart/form-data` body into its fields' values, keyed by field name.
+    fn form_fields(request: &Request, body: &[u8]) -> HashMap<String, Vec<u8>> {
+        let content_type = &request.headers["content-type"];
+        let boundary = content_type.split("boundary=").nth(1).unwrap();
+        let delimiter = format!("--{}", boundary);
+        let body = String::from_utf8_lossy(body).into_owned();
+
+        body.split(delimiter.as_str())
+            .filter_map(|part| {
+                let (headers, value) = part.split_at(part.find("\r\n\r\n")?);
+                let name = headers.split("name=\"").nth(1)?.split('"').next()?;
+                let value = value[4..].strip_suffix("\r\n").unwrap_or(&value[4..]);
+                Some((name.to_owned(), value.as_bytes().to_vec()))
+            })
+            .collect()
+    }
+
+    fn write_temp_file(name: &str, contents: &[u8]) -> std::path::PathBuf {
+        let path = env::temp_dir().join(format!("snapmeo-test-{}-{}", std::process::id(), name));
+        fs::write(&path, contents).unwrap();
+        path
+    }
+
+    fn options(compress: bool) -> UploadOptions {
+        UploadOptions {
+            expiry: Expiry::After(Duration::from_secs(7 * SECONDS_PER_DAY)),
+            secret: true,
+            one_time: false,
+            content_type: "image/png",
+            compress,
+            remote_name: None,
+            tags: Vec::new(),
+            idempotency_key: None,
+        }
+    }
+
+    #[test]
+    fn idempotency_keys_are_unique_uuids() {
+        let key = idempotency_key();
+        let groups: Vec<&str> = key.split('-').collect();
+        assert_eq!(
+            groups.iter().map(|group| group.len()).collect::<Vec<_>>(),
+            [8, 4, 4, 4, 12]
+        );
+        assert!(groups[2].starts_with('4'));
+        assert!(groups[3].starts_with(['8', '9', 'a', 'b']));
+        assert_ne!(key, idempotency_key());
+    }
+
+    #[test]
+    fn expiries_parse_without_overflowing() {
+        assert_eq!(
+            "2w".parse(),
+            Ok(Expiry::After(Duration::from_secs(2 * SECONDS_PER_WEEK)))
+        );
+        assert_eq!(
+            "3".parse(),
+            Ok(Expiry::After(Duration::from_secs(3 * SECONDS_PER_DAY)))
+        );
+        assert_eq!("-1".parse(), Ok(Expiry::Never));
+        assert!("0d".parse::<Expiry>().is_err());
+        assert!("7y".parse::<Expiry>().is_err());
+        assert!("99999999999999999999999w".parse::<Expiry>().is_err());
+        assert!(format!("{}", u64::MAX / 2).parse::<Expiry>().is_err());
+        assert!(format!("{}w", u64::MAX / 2).parse::<Expiry>().is_err());
+        assert!(format!("{}s1s", u64::MAX).parse::<Expiry>().is_err());
+    }
+
+    #[test]
+    fn truncate_file_name_cuts_between_characters() {
+        let mut name = "Screenshot".to_owned();
+        truncate_file_name(&mut name, MAX_FILE_NAME_BYTES);
+        assert_eq!(name, "Screenshot");
+
+        let mut name = "a".repeat(300);
+        truncate_file_name(&mut name, MAX_FILE_NAME_BYTES);
+        assert_eq!(name.len(), MAX_FILE_NAME_BYTES);
+
+        // Each `é` is two bytes, so the one that would be split is left out
+        let mut name = format!("a{}", "é".repeat(150));
+        truncate_file_name(&mut name, MAX_FILE_NAME_BYTES);
+        assert_eq!(name, format!("a{}", "é".repeat(99)));
+    }
+
+    #[test]
+    fn thumbnails_get_their_own_idempotency_key() {
+        let options = UploadOptions {
+            idempotency_key: Some(idempotency_key()),
+            ..options(false)
+        };
+        let thumbnail_options = options.for_thumbnail();
+        assert!(thumbnail_options.idempotency_key.is_some());
+        assert_ne!(thumbnail_options.idempotency_key, options.idempotency_key);
+    }
+
+    #[test]
+    fn upload_sends_form_fields() {
+        env::set_var("AMEOTRACK_PASSWORD", PASSWORD);
+        let path = write_temp_file("fields.png", b"not really a png");
+        let (url, server) = mock_server(vec![response(
+            "200 OK",
+            &[],
+            "https://ameo.link/u/abc.png\n",
+        )]);
+
+        let uploader = AmeoTrack { upload_url: url };
+        let options = UploadOptions {
+            idempotency_key: Some("8c1e6a2e-5f0b-4d3a-9c8e-2b7f1d4a6e90".into()),
+            ..options(false)
+        };
+        let image_url = uploader.upload(&path, &options).unwrap();
+        assert_eq!(image_url, "https://ameo.link/u/abc.png");
+
+        let requests = server.join().unwrap();
+        assert_eq!(requests.len(), 1);
+        assert_eq!(requests[0].method, "POST");
+        let fields = form_fields(&requests[0], &requests[0].body);
+        assert_eq!(fields["file"], b"not really a png");
+        assert_eq!(fields["secret"], b"1");
+        assert_eq!(fields["expiry"], b"7");
+        assert_eq!(fields["password"], PASSWORD.as_bytes());
+        assert_eq!(fields["oneTime"], b"");
+        assert_eq!(
+            fields["idempotencyKey"],
+            b"8c1e6a2e-5f0b-4d3a-9c8e-2b7f1d4a6e90"
+        );
+
+        fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn upload_fails_on_error_status() {
+        env::set_var("AMEOTRACK_PASSWORD", PASSWORD);
+        let path = write_temp_file("error.png", b"not really a png");
+        let (url, server) = mock_server(vec![response("403 Forbidden", &[], "Invalid password")]);
+
+        let uploader = AmeoTrack { upload_url: url };
+        let err = uploader.upload(&path, &options(false)).unwrap_err();
+        assert!(matches!(err, SnapmeoError::Auth(_)));
+        assert!(err.to_string().contains("Invalid password"));
+
+        server.join().unwrap();
+        fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn upload_fails_when_the_response_is_not_a_url() {
+        env::set_var("AMEOTRACK_PASSWORD", PASSWORD);
+        let path = write_temp_file("not-a-url.png", b"not really a png");
+        let (url, server) = mock_server(vec![response("200 OK", &[], "Error: disk full\n")]);
+
+        let uploader = AmeoTrack { upload_url: url };
+        let err = uploader.upload(&path, &options(false)).unwrap_err();
+        assert!(matches!(
+            err,
+            SnapmeoError::Upload {
+                status: Some(200),
+                ..
+            }
+        ));
+        assert!(err.to_string().contains("Error: disk full"));
+
+        server.join().unwrap();
+        fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn ameotrack_form_uses_ameotrack_field_names() {
+        env::set_var("AMEOTRACK_PASSWORD", PASSWORD);
+        let uploader = AmeoTrack::default();
+        let mut options = options(false);
+        options.secret = false;
+        options.one_time = true;
+        options.tags = vec!["work".into(), "bugs".into()];
+
+        let ameotrack_options = uploader.ameotrack_options(&options).unwrap();
+        assert_eq!(
+            ameotrack_options.fields(),
+            vec![
+                ("secret", String::new()),
+                ("expiry", "7".to_owned()),
+                ("password", PASSWORD.to_owned()),
+                ("oneTime", "1".to_owned()),
+                ("tags", "work,bugs".to_owned()),
+            ]
+        );
+    }
+
+    #[test]
+    fn upload_async_posts_the_form() {
+        let (url, server) = mock_server(vec![response(
+            "200 OK",
+            &[],
+            "https://ameo.link/u/async.png",
+        )]);
+        let options = AmeoTrackOptions {
+            upload_url: url,
+            idempotency_key: Some("key".into()),
+            ..AmeoTrackOptions::new(PASSWORD)
+        };
+
+        let mut runtime = tokio::runtime::Builder::new()
+            .basic_scheduler()
+            .enable_all()
+            .build()
+            .unwrap();
+        let image_url = runtime
+            .block_on(upload_async(
+                b"not really a png".to_vec(),
+                "async.png",
+                "image/png",
+                &options,
+            ))
+            .unwrap();
+        assert_eq!(image_url, "https://ameo.link/u/async.png");
+
+        let requests = server.join().unwrap();
+        assert_eq!(requests[0].method, "POST");
+        let fields = form_fields(&requests[0], &requests[0].body);
+        assert_eq!(fields["file"], b"not really a png");
+        assert_eq!(fields["expiry"], b"-1");
+        assert_eq!(fields["password"], PASSWORD.as_bytes());
+        assert_eq!(fields["idempotencyKey"], b"key");
+    }
+
+    /// A form based backend whose API looks nothing like AmeoTrack's
+    struct OtherHost {
+        upload_url: String,
+    }
+
+    impl Uploader for OtherHost {
+        fn name(&self) -> &'static str {
+            "OtherHost"
+        }
+
+        fn translate_expiry(&self, expiry: &Expiry) -> Result<String, SnapmeoError> {
+            Ok(expiry.to_string())
+        }
+
+        fn upload(&self, path: &Path, options: &UploadOptions) -> Result<String, SnapmeoError> {
+            upload_form(self, path, options)
+        }
+    }
+
+    impl FormUploader for OtherHost {
+        fn upload_url(&self) -> &str {
+            &self.upload_url
+        }
+
+        fn form(&self, options: &UploadOptions) -> Result<UploadForm, SnapmeoError> {
+            Ok(UploadForm {
+                file_field: "image",
+                fields: vec![
+                    ("api_key", "abc123".into()),
+                    ("ttl", self.translate_expiry(&options.expiry)?),
+                ],
+            })
+        }
+    }
+
+    #[test]
+    fn form_uploads_use_the_backend_field_names() {
+        let path = write_temp_file("other-host.png", b"not really a png");
+        let (url, server) = mock_server(vec![response(
+            "200 OK",
+            &[],
+            "https://other.example/i/xyz.png",
+        )]);
+
+        let uploader = OtherHost { upload_url: url };
+        let image_url = uploader.upload(&path, &options(false)).unwrap();
+        assert_eq!(image_url, "https://other.example/i/xyz.png");
+
+        let requests = server.join().unwrap();
+        let fields = form_fields(&requests[0], &requests[0].body);
+        assert_eq!(fields["image"], b"not really a png");
+        assert_eq!(fields["api_key"], b"abc123");
+        assert_eq!(fields["ttl"], b"1w");
+        assert!(!fields.contains_key("file"));
+        assert!(!fields.contains_key("password"));
+
+        fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn multipart_body_puts_the_file_in_the_form_file_field() {
+        let fields = [("name", "shot".to_owned())];
+        let body = multipart_body(
+            "BOUNDARY",
+            &fields,
+            "upload",
+            "shot.png",
+            "image/png",
+            b"pixels",
+        );
+        let body = String::from_utf8(body).unwrap();
+        assert_eq!(
+            body,
+            "--BOUNDARY\r\nContent-Disposition: form-data; name=\"name\"\r\n\r\nshot\r\n\
+             --BOUNDARY\r\nContent-Disposition: form-data; name=\"upload\"; filename=\"shot.png\"\r\nContent-Type: image/png\r\n\r\n\
+             pixels\r\n--BOUNDARY--\r\n"
+        );
+    }
+
+    #[test]
+    fn compressed_upload_sends_gzipped_form() {
+        env::set_var("AMEOTRACK_PASSWORD", PASSWORD);
+        let file = vec![b'a'; MIN_COMPRESSED_UPLOAD_BYTES * 2];
+        let path = write_temp_file("compressed.png", &file);
+        let (url, server) = mock_server(vec![
+            response("204 No Content", &[("Accept-Encoding", "gzip")], ""),
+            response("200 OK", &[], "https://ameo.link/u/big.png"),
+        ]);
+
+        let uploader = AmeoTrack { upload_url: url };
+        let image_url = uploader.upload(&path, &options(true)).unwrap();
+        assert_eq!(image_url, "https://ameo.link/u/big.png");
+
+        let requests = server.join().unwrap();
+        assert_eq!(requests[0].method, "OPTIONS");
+        let upload = &requests[1];
+        assert_eq!(upload.method, "POST");
+        assert_eq!(upload.headers["content-encoding"], "gzip");
+        assert!(upload.body.len() < file.len());
+
+        let mut body = Vec::new();
+        GzDecoder::new(upload.body.as_slice())
+            .read_to_end(&mut body)
+            .unwrap();
+        let fields = form_fields(upload, &body);
+        assert_eq!(fields["file"], file);
+        assert_eq!(fields["password"], PASSWORD.as_bytes());
+
+        fs::remove_file(path).unwrap();
+    }
+}
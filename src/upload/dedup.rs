@@ -0,0 +1,204 @@
+//! `--skip-unchanged`, which avoids uploading a capture that's byte-for-byte the same as the
+//! previous one uploaded to the same backend, such as when the screen is idle during a timelapse.
+//!
+//! Captures are usually taken by separate runs, so the hash and URL of the last upload to each
+//! backend are kept in `last-uploads.json` next to the upload queue. SHA-256 is used for the hash
+//! since it's already needed for S3 and is stable across builds, unlike the standard library's
+//! hasher; it's far quicker than the upload it saves regardless.
+//!
+//! A link is only handed out again if it was uploaded with the same settings and is still up, so
+//! one-time links, expired links, and public links standing in for secret ones never are.
+
+use std::collections::BTreeMap;
+use std::fs;
+use std::io::ErrorKind;
+use std::path::PathBuf;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+
+use super::{data_dir, Expiry, UploadOptions, Uploader};
+use crate::error::SnapmeoError;
+
+/// The settings an upload was hosted with that decide whether its link can stand in for another
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Hosting {
+    pub secret: bool,
+    pub one_time: bool,
+    /// How long the upload was kept for in seconds, or `None` for forever
+    pub expiry_secs: Option<u64>,
+}
+
+impl Hosting {
+    pub fn of(options: &UploadOptions) -> Self {
+        Hosting {
+            secret: options.secret,
+            one_time: options.one_time,
+            expiry_secs: match options.expiry {
+                Expiry::Never => None,
+                Expiry::After(duration) => Some(duration.as_secs()),
+            },
+        }
+    }
+}
+
+/// The most recent upload to a backend
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct LastUpload {
+    /// Hex SHA-256 of the uploaded file's contents
+    pub hash: String,
+    pub url: String,
+    /// Missing from uploads recorded before it was, whose links are never reused
+    #[serde(default)]
+    pub hosting: Option<Hosting>,
+    /// Unix time in seconds at which the upload expires, if it does
+    #[serde(default)]
+    pub expires_at: Option<u64>,
+}
+
+impl LastUpload {
+    /// Whether this upload's link can be handed out for a file with `hash` that's to be uploaded
+    /// with `options`, at Unix time `now`.
+    fn reusable_for(&self, hash: &str, options: &UploadOptions, now: u64) -> bool {
+        self.hash == hash
+            && self.hosting.as_ref() == Some(&Hosting::of(options))
+            && !options.one_time
+            && self.expires_at.is_none_or(|expires_at| now < expires_at)
+    }
+}
+
+fn unix_now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |since_epoch| since_epoch.as_secs())
+}
+
+fn last_uploads_path() -> Result<PathBuf, SnapmeoError> {
+    data_dir()
+        .map(|dir| dir.join("last-uploads.json"))
+        .ok_or_else(|| {
+            SnapmeoError::Other("Unable to find a directory to remember the last upload in".into())
+        })
+}
+
+fn load() -> Result<BTreeMap<String, LastUpload>, SnapmeoError> {
+    let path = last_uploads_path()?;
+    let contents = match fs::read_to_string(&path) {
+        Ok(contents) => contents,
+        Err(err) if err.kind() == ErrorKind::NotFound => return Ok(BTreeMap::new()),
+        Err(err) => return Err(err.into()),
+    };
+    // This is only an optimization, so a damaged file just means uploading again
+    Ok(serde_json::from_str(&contents).unwrap_or_default())
+}
+
+/// Returns the hash of `contents` that uploads are compared by.
+pub fn hash(contents: &[u8]) -> String {
+    Sha256::digest(contents)
+        .iter()
+        .map(|byte| format!("{:02x}", byte))
+        .collect()
+}
+
+/// Returns the last upload to `uploader` if it had the same `hash` and its link can stand in for
+/// an upload with `options`.
+pub fn previous_upload(
+    uploader: &dyn Uploader,
+    hash: &str,
+    options: &UploadOptions,
+) -> Result<Option<LastUpload>, SnapmeoError> {
+    let now = unix_now();
+    Ok(load()?
+        .remove(uploader.name())
+        .filter(|last| last.reusable_for(hash, options, now)))
+}
+
+/// Records that a file with `hash` was just uploaded to `uploader` at `url` with `options`.
+pub fn record(
+    uploader: &dyn Uploader,
+    hash: String,
+    url: String,
+    options: &UploadOptions,
+) -> Result<(), SnapmeoError> {
+    let hosting = Hosting::of(options);
+    let expires_at = hosting
+        .expiry_secs
+        .map(|secs| unix_now().saturating_add(secs));
+    let mut last_uploads = load()?;
+    last_uploads.insert(
+        uploader.name().into(),
+        LastUpload {
+            hash,
+            url,
+            hosting: Some(hosting),
+            expires_at,
+        },
+    );
+
+    let path = last_uploads_path()?;
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir)?;
+    }
+    let contents = serde_json::to_string_pretty(&last_uploads)
+        .map_err(|err| SnapmeoError::Other(err.to_string()))?;
+    fs::write(path, contents)?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::*;
+
+    fn options(secret: bool, one_time: bool, expiry: Expiry) -> UploadOptions {
+        UploadOptions {
+            expiry,
+            secret,
+            one_time,
+            content_type: "image/png",
+            compress: false,
+            remote_name: None,
+            tags: Vec::new(),
+            idempotency_key: None,
+        }
+    }
+
+    fn last_upload(options: &UploadOptions, expires_at: Option<u64>) -> LastUpload {
+        LastUpload {
+            hash: "abc".into(),
+            url: "https://example.com/abc.png".into(),
+            hosting: Some(Hosting::of(options)),
+            expires_at,
+        }
+    }
+
+    #[test]
+    fn only_matching_live_links_are_reused() {
+        let week = Expiry::After(Duration::from_secs(7 * 24 * 60 * 60));
+        let public = options(false, false, Expiry::Never);
+        assert!(last_upload(&public, None).reusable_for("abc", &public, 100));
+        assert!(!last_upload(&public, None).reusable_for("def", &public, 100));
+        // A public link can't stand in for a secret one
+        assert!(!last_upload(&public, None).reusable_for(
+            "abc",
+            &options(true, false, Expiry::Never),
+            100
+        ));
+        assert!(!last_upload(&public, None).reusable_for("abc", &options(false, false, week), 100));
+
+        let expiring = options(false, false, week);
+        assert!(last_upload(&expiring, Some(200)).reusable_for("abc", &expiring, 100));
+        assert!(!last_upload(&expiring, Some(200)).reusable_for("abc", &expiring, 200));
+
+        let one_time = options(false, true, Expiry::Never);
+        assert!(!last_upload(&one_time, None).reusable_for("abc", &one_time, 100));
+
+        let unrecorded = LastUpload {
+            hosting: None,
+            ..last_upload(&public, None)
+        };
+        assert!(!unrecorded.reusable_for("abc", &public, 100));
+    }
+}
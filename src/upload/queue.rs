@@ -0,0 +1,160 @@
+//! Uploads that couldn't be made because the upload backend was unreachable, kept on disk so that
+//! they can be retried once the connection is back, even by a later run.
+//!
+//! The queue lives at `$XDG_DATA_HOME/snapmeo/upload-queue.json` (`~/.local/share/snapmeo` if that's
+//! unset), or in `%LOCALAPPDATA%\snapmeo` on Windows.
+
+use std::fs;
+use std::io::ErrorKind;
+use std::path::{Path, PathBuf};
+
+use serde::{Deserialize, Serialize};
+
+use super::{data_dir, UploadOptions, Uploader};
+use crate::encode::Format;
+use crate::error::SnapmeoError;
+
+/// A saved screenshot waiting to be uploaded
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct QueuedUpload {
+    pub path: PathBuf,
+    /// Name of the backend it was going to be uploaded to. Only that backend will retry it.
+    pub uploader: String,
+    pub expiry: String,
+    pub secret: bool,
+    pub one_time: bool,
+    pub compress: bool,
+    #[serde(default)]
+    pub remote_name: Option<String>,
+    #[serde(default)]
+    pub tags: Vec<String>,
+    /// Kept so that retrying doesn't upload it twice if the first attempt actually went through
+    #[serde(default)]
+    pub idempotency_key: Option<String>,
+}
+
+impl QueuedUpload {
+    pub fn new(path: &Path, uploader: &dyn Uploader, options: &UploadOptions) -> Self {
+        QueuedUpload {
+            path: path.to_owned(),
+            uploader: uploader.name().into(),
+            expiry: options.expiry.to_string(),
+            secret: options.secret,
+            one_time: options.one_time,
+            compress: options.compress,
+            remote_name: options.remote_name.clone(),
+            tags: options.tags.clone(),
+            idempotency_key: options.idempotency_key.clone(),
+        }
+    }
+
+    fn options(&self) -> Result<UploadOptions, SnapmeoError> {
+        let format: Format = self
+            .path
+            .extension()
+            .map(|extension| extension.to_string_lossy().into_owned())
+            .unwrap_or_default()
+            .parse()
+            .map_err(SnapmeoError::Other)?;
+
+        Ok(UploadOptions {
+            expiry: self.expiry.parse().map_err(SnapmeoError::Other)?,
+            secret: self.secret,
+            one_time: self.one_time,
+            content_type: format.content_type(),
+            compress: self.compress,
+            remote_name: self.remote_name.clone(),
+            tags: self.tags.clone(),
+            idempotency_key: self.idempotency_key.clone(),
+        })
+    }
+}
+
+/// A queued upload that was retried and how it went
+pub type Outcome = (QueuedUpload, Result<String, SnapmeoError>);
+
+/// Returns whether an upload failed because the backend couldn't be reached at all, as opposed to
+/// it rejecting the upload, which retrying wouldn't help with.
+pub fn is_unreachable(err: &SnapmeoError) -> bool {
+    matches!(err, SnapmeoError::Upload { status: None, .. })
+}
+
+fn queue_path() -> Option<PathBuf> {
+    data_dir().map(|dir| dir.join("upload-queue.json"))
+}
+
+fn no_queue_path() -> SnapmeoError {
+    SnapmeoError::Other("Unable to find a directory to keep the upload queue in".into())
+}
+
+/// Returns every queued upload, oldest first.
+pub fn load() -> Result<Vec<QueuedUpload>, SnapmeoError> {
+    let path = queue_path().ok_or_else(no_queue_path)?;
+    let contents = match fs::read_to_string(&path) {
+        Ok(contents) => contents,
+        Err(err) if err.kind() == ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(err) => return Err(err.into()),
+    };
+
+    serde_json::from_str(&contents).map_err(|err| {
+        SnapmeoError::Other(format!(
+            "The upload queue at `{}` is corrupt: {}",
+            path.display(),
+            err
+        ))
+    })
+}
+
+fn save(queue: &[QueuedUpload]) -> Result<(), SnapmeoError> {
+    let path = queue_path().ok_or_else(no_queue_path)?;
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir)?;
+    }
+
+    let contents =
+        serde_json::to_string_pretty(queue).map_err(|err| SnapmeoError::Other(err.to_string()))?;
+    fs::write(path, contents)?;
+    Ok(())
+}
+
+/// Adds an upload to the end of the queue.
+pub fn push(upload: QueuedUpload) -> Result<(), SnapmeoError> {
+    let mut queue = load()?;
+    queue.push(upload);
+    save(&queue)
+}
+
+/// Tries each upload queued for `uploader` in order, stopping at the first one that fails because
+/// the backend is still unreachable. Returns the outcome of each one that was tried; those that
+/// were tried are removed from the queue unless they hit the backend being unreachable.
+pub fn flush(uploader: &dyn Uploader) -> Result<Vec<Outcome>, SnapmeoError> {
+    let mut remaining = Vec::new();
+    let mut outcomes = Vec::new();
+    let mut unreachable = false;
+
+    for upload in load()? {
+        if unreachable || upload.uploader != uploader.name() {
+            remaining.push(upload);
+            continue;
+        }
+
+        let result = upload
+            .options()
+            .and_then(|options| uploader.upload(&upload.path, &options));
+        if result.as_ref().is_err_and(is_unreachable) {
+            unreachable = true;
+            remaining.push(upload.clone());
+        }
+        outcomes.push((upload, result));
+    }
+
+    save(&remaining)?;
+    Ok(outcomes)
+}
+
+/// Returns whether any uploads are queued for `uploader`.
+pub fn has_pending(uploader: &dyn Uploader) -> Result<bool, SnapmeoError> {
+    Ok(load()?
+        .iter()
+        .any(|upload| upload.uploader == uploader.name()))
+}
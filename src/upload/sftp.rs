@@ -0,0 +1,251 @@
+//! Uploading to a directory on a remote server over SFTP, such as one served by a web server.
+
+use std::env;
+use std::fs;
+use std::io::Write;
+use std::net::TcpStream;
+use std::path::{Path, PathBuf};
+
+use ssh2::{CheckResult, ErrorCode, KnownHostFileKind, OpenFlags, OpenType, Session};
+
+use super::{idempotency_key, Expiry, UploadOptions, Uploader};
+use crate::error::SnapmeoError;
+
+const DEFAULT_SSH_PORT: u16 = 22;
+
+/// libssh2's `LIBSSH2_ERROR_AUTHENTICATION_FAILED` and `LIBSSH2_ERROR_PUBLICKEY_UNVERIFIED`
+const AUTH_ERRORS: [i32; 2] = [-18, -19];
+/// SFTP statuses that trying again won't change, from `LIBSSH2_FX_NO_SUCH_FILE` through
+/// `LIBSSH2_FX_INVALID_FILENAME`: missing directories, permissions, full disks, and the like.
+/// The others are about the connection.
+const PERMANENT_SFTP_ERRORS: [i32; 10] = [2, 3, 8, 10, 11, 12, 14, 15, 19, 20];
+
+/// Copies images into a directory on a remote server.
+///
+/// Configured with the environment variables `SNAPMEO_SFTP_HOST` (optionally with a `:port`),
+/// `SNAPMEO_SFTP_USER`, `SNAPMEO_SFTP_DIR` for the remote directory, and `SNAPMEO_SFTP_URL` for the
+/// URL template that `{name}` is replaced with the uploaded file's name in, such as
+/// `https://example.com/screenshots/{name}`. `SNAPMEO_SFTP_KEY` can point at a private key;
+/// otherwise the SSH agent is used.
+///
+/// The server's host key has to already be in `~/.ssh/known_hosts`, which connecting with `ssh`
+/// once takes care of. Setting `SNAPMEO_SFTP_ACCEPT_NEW_HOST_KEY=1` adds the key of a server that
+/// isn't there yet instead, but a key that differs from the one on record is always refused.
+pub struct SftpUploader {
+    pub host: String,
+    pub port: u16,
+    pub user: String,
+    pub key_path: Option<PathBuf>,
+    pub remote_dir: String,
+    pub url_template: String,
+    /// Whether to trust and remember the host key of a server that isn't in `known_hosts`
+    pub accept_new_host_key: bool,
+}
+
+fn required_var(name: &str) -> Result<String, SnapmeoError> {
+    env::var(name).map_err(|_| {
+        SnapmeoError::BadArguments(format!(
+            "The `{}` environment variable must be set to upload over SFTP",
+            name
+        ))
+    })
+}
+
+/// Converts `err` into an error that's only retried from the upload queue if it could be down to
+/// the connection.
+fn ssh_error(err: ssh2::Error) -> SnapmeoError {
+    match err.code() {
+        ErrorCode::Session(code) if AUTH_ERRORS.contains(&code) => {
+            SnapmeoError::Auth(err.to_string())
+        }
+        ErrorCode::SFTP(code) if PERMANENT_SFTP_ERRORS.contains(&code) => {
+            SnapmeoError::Other(format!("The SFTP server refused the upload: {}", err))
+        }
+        _ => SnapmeoError::Upload {
+            status: None,
+            message: err.to_string(),
+        },
+    }
+}
+
+impl SftpUploader {
+    pub fn from_env() -> Result<Self, SnapmeoError> {
+        let host = required_var("SNAPMEO_SFTP_HOST")?;
+        let (host, port) = match host.rsplit_once(':') {
+            Some((host, port)) => (
+                host.to_owned(),
+                port.parse().map_err(|_| {
+                    SnapmeoError::BadArguments(format!("Invalid SFTP port `{}`", port))
+                })?,
+            ),
+            None => (host, DEFAULT_SSH_PORT),
+        };
+        let url_template = required_var("SNAPMEO_SFTP_URL")?;
+        if !url_template.contains("{name}") {
+            return Err(SnapmeoError::BadArguments(
+                "`SNAPMEO_SFTP_URL` must contain `{name}`".into(),
+            ));
+        }
+
+        Ok(SftpUploader {
+            host,
+            port,
+            user: required_var("SNAPMEO_SFTP_USER")?,
+            key_path: env::var_os("SNAPMEO_SFTP_KEY").map(PathBuf::from),
+            remote_dir: required_var("SNAPMEO_SFTP_DIR")?,
+            url_template,
+            accept_new_host_key: env::var("SNAPMEO_SFTP_ACCEPT_NEW_HOST_KEY").as_deref() == Ok("1"),
+        })
+    }
+
+    /// Makes sure that the server `session` is connected to is the one in `known_hosts` rather
+    /// than something in between us and it that would get to see the upload.
+    fn check_host_key(&self, session: &Session) -> Result<(), SnapmeoError> {
+        let known_hosts_path = env::var_os("HOME")
+            .map(|home| Path::new(&home).join(".ssh/known_hosts"))
+            .ok_or_else(|| {
+                SnapmeoError::Other("`HOME` must be set to find `~/.ssh/known_hosts`".into())
+            })?;
+        let mut known_hosts = session.known_hosts().map_err(ssh_error)?;
+        if known_hosts_path.exists() {
+            known_hosts
+                .read_file(&known_hosts_path, KnownHostFileKind::OpenSSH)
+                .map_err(ssh_error)?;
+        }
+
+        let (key, key_type) = session
+            .host_key()
+            .ok_or_else(|| SnapmeoError::Auth(format!("{} didn't send a host key", self.host)))?;
+        match known_hosts.check_port(&self.host, self.port, key) {
+            CheckResult::Match => Ok(()),
+            CheckResult::Mismatch => Err(SnapmeoError::Auth(format!(
+                "{}'s host key doesn't match the one in {}, so someone may be intercepting the \
+                 connection. If the server's key was changed on purpose, remove the old one with \
+                 `ssh-keygen -R {}`.",
+                self.host,
+                known_hosts_path.display(),
+                self.host
+            ))),
+            CheckResult::NotFound if self.accept_new_host_key => {
+                // Entries for ports other than the default are written like `[host]:port`
+                let host = if self.port == DEFAULT_SSH_PORT {
+                    self.host.clone()
+                } else {
+                    format!("[{}]:{}", self.host, self.port)
+                };
+                known_hosts
+                    .add(&host, key, "added by snapmeo", key_type.into())
+                    .map_err(ssh_error)?;
+                if let Some(ssh_dir) = known_hosts_path.parent() {
+                    fs::create_dir_all(ssh_dir)?;
+                }
+                known_hosts
+                    .write_file(&known_hosts_path, KnownHostFileKind::OpenSSH)
+                    .map_err(ssh_error)?;
+                eprintln!(
+                    "Added {}'s host key to {}",
+                    host,
+                    known_hosts_path.display()
+                );
+                Ok(())
+            }
+            CheckResult::NotFound => Err(SnapmeoError::Auth(format!(
+                "{} isn't in {}. Connect to it with `ssh` once to check and save its host key, or \
+                 set `SNAPMEO_SFTP_ACCEPT_NEW_HOST_KEY=1` to trust it on first use.",
+                self.host,
+                known_hosts_path.display()
+            ))),
+            CheckResult::Failure => Err(SnapmeoError::Auth(format!(
+                "Unable to check {}'s host key against {}",
+                self.host,
+                known_hosts_path.display()
+            ))),
+        }
+    }
+
+    fn connect(&self) -> Result<Session, SnapmeoError> {
+        let stream = TcpStream::connect((self.host.as_str(), self.port))?;
+        let mut session = Session::new().map_err(ssh_error)?;
+        session.set_tcp_stream(stream);
+        session.handshake().map_err(ssh_error)?;
+        self.check_host_key(&session)?;
+
+        let auth_result = match &self.key_path {
+            Some(key_path) => session.userauth_pubkey_file(&self.user, None, key_path, None),
+            None => session.userauth_agent(&self.user),
+        };
+        if let Err(err) = auth_result {
+            let method = match &self.key_path {
+                Some(key_path) => format!("the key at {}", key_path.display()),
+                None => "the SSH agent's keys".into(),
+            };
+            return Err(SnapmeoError::Auth(format!(
+                "{}@{} didn't accept {}: {}",
+                self.user, self.host, method, err
+            )));
+        }
+
+        Ok(session)
+    }
+}
+
+impl Uploader for SftpUploader {
+    fn name(&self) -> &'static str {
+        "SFTP"
+    }
+
+    /// Files stay on the server until they're deleted there.
+    fn translate_expiry(&self, expiry: &Expiry) -> Result<String, SnapmeoError> {
+        match expiry {
+            Expiry::Never => Ok(String::new()),
+            Expiry::After(_) => Err(SnapmeoError::BadArguments(
+                "SFTP uploads can't expire".into(),
+            )),
+        }
+    }
+
+    fn upload(&self, path: &Path, options: &UploadOptions) -> Result<String, SnapmeoError> {
+        self.translate_expiry(&options.expiry)?;
+        if options.one_time {
+            return Err(SnapmeoError::BadArguments(
+                "SFTP doesn't support one-time uploads".into(),
+            ));
+        }
+
+        let file = fs::read(path)?;
+        let extension = path
+            .extension()
+            .map(|extension| format!(".{}", extension.to_string_lossy()))
+            .unwrap_or_default();
+        let file_name = if options.secret {
+            // Random rather than anything to do with the capture, so that it can't be guessed
+            let name: String = idempotency_key().chars().filter(|&c| c != '-').collect();
+            format!("{}{}", name, extension)
+        } else {
+            // Spaces are a pain in URLs
+            options.remote_name(path).replace(' ', "_")
+        };
+
+        let session = self.connect()?;
+        let sftp = session.sftp().map_err(ssh_error)?;
+        let remote_path = Path::new(&self.remote_dir).join(&file_name);
+        if sftp.stat(&remote_path).is_ok() {
+            return Err(SnapmeoError::Other(format!(
+                "{} already exists on the SFTP server; pick another name with `--name`",
+                remote_path.display()
+            )));
+        }
+        // Exclusive in case something else puts a file there in the meantime
+        let mut remote_file = sftp
+            .open_mode(
+                &remote_path,
+                OpenFlags::WRITE | OpenFlags::CREATE | OpenFlags::EXCLUSIVE,
+                0o644,
+                OpenType::File,
+            )
+            .map_err(ssh_error)?;
+        remote_file.write_all(&file)?;
+
+        Ok(self.url_template.replace("{name}", &file_name))
+    }
+}
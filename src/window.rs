@@ -0,0 +1,301 @@
+//! Finding the positions of other applications' windows on the screen.
+//!
+//! Only X11 is supported. Wayland compositors don't let clients see each other's windows, though
+//! XWayland windows are still visible through the X11 path.
+
+use std::error::Error;
+#[cfg(feature = "interactive")]
+use std::process;
+use std::str::FromStr;
+
+/// A top-level window belonging to some application, positioned in display pixels. Its bounds are
+/// those of its content, without the title bar and borders the window manager adds around it.
+#[derive(Clone, Debug)]
+pub struct WindowInfo {
+    /// The X11 window ID
+    pub id: u32,
+    pub title: String,
+    pub x: i64,
+    pub y: i64,
+    pub width: usize,
+    pub height: usize,
+    /// Process that owns the window, if it says
+    #[cfg_attr(not(feature = "interactive"), allow(dead_code))]
+    pub pid: Option<u32>,
+    /// How far the window manager's decorations extend past the content on the left, right, top,
+    /// and bottom
+    pub frame_extents: [usize; 4],
+}
+
+/// Whether captures of a window include its title bar and borders
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Decorations {
+    Include,
+    Exclude,
+}
+
+impl FromStr for Decorations {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "include" | "yes" => Ok(Decorations::Include),
+            "exclude" | "no" => Ok(Decorations::Exclude),
+            _ => Err(format!(
+                "Invalid `--decorations` `{}`; expected `include` or `exclude`",
+                s
+            )),
+        }
+    }
+}
+
+/// Returns the topmost window whose title contains `query`, ignoring case.
+pub fn find_window(query: &str) -> Result<WindowInfo, Box<dyn Error>> {
+    let lowercase_query = query.to_lowercase();
+    platform::list_windows()?
+        .into_iter()
+        .find(|window| window.title.to_lowercase().contains(&lowercase_query))
+        .ok_or_else(|| format!("No window with a title containing `{}` was found", query).into())
+}
+
+/// Returns the window that currently has input focus.
+pub fn active_window() -> Result<WindowInfo, Box<dyn Error>> {
+    platform::active_window()
+}
+
+/// Returns where the mouse cursor is on the screen.
+pub fn cursor_position() -> Result<(i64, i64), Box<dyn Error>> {
+    platform::cursor_position()
+}
+
+/// Returns the topmost window containing the mouse cursor.
+pub fn window_under_cursor() -> Result<WindowInfo, Box<dyn Error>> {
+    let (x, y) = platform::cursor_position()?;
+    platform::list_windows()?
+        .into_iter()
+        .find(|window| window.contains(x, y))
+        .ok_or_else(|| "There's no window under the cursor".into())
+}
+
+/// Returns the topmost window containing the point `(x, y)` other than snapmeo's own, which is
+/// what's under the selection overlay.
+#[cfg(feature = "interactive")]
+pub fn window_at(x: i64, y: i64) -> Result<WindowInfo, Box<dyn Error>> {
+    let own_pid = process::id();
+    platform::list_windows()?
+        .into_iter()
+        .find(|window| window.pid != Some(own_pid) && window.contains(x, y))
+        .ok_or_else(|| "There's no window there".into())
+}
+
+/// Reads the contents of `window` straight from the window rather than from the screen, so that
+/// its transparent parts, such as rounded corners, keep their transparency instead of showing
+/// what's behind them. Returns RGBA with premultiplied alpha, or an error if the window isn't
+/// transparent.
+pub fn capture_with_alpha(window: &WindowInfo) -> Result<Vec<u8>, Box<dyn Error>> {
+    platform::capture_with_alpha(window)
+}
+
+impl WindowInfo {
+    /// Returns the window's bounds as `(x, y, width, height)`, with or without its decorations.
+    pub fn bounds(&self, decorations: Decorations) -> (i64, i64, usize, usize) {
+        match decorations {
+            Decorations::Include => {
+                let [left, right, top, bottom] = self.frame_extents;
+                (
+                    self.x - left as i64,
+                    self.y - top as i64,
+                    self.width + left + right,
+                    self.height + top + bottom,
+                )
+            }
+            Decorations::Exclude => (self.x, self.y, self.width, self.height),
+        }
+    }
+
+    fn contains(&self, x: i64, y: i64) -> bool {
+        x >= self.x
+            && y >= self.y
+            && x < self.x + self.width as i64
+            && y < self.y + self.height as i64
+    }
+}
+
+#[cfg(target_os = "linux")]
+mod platform {
+    use std::error::Error;
+
+    use x11rb::connection::Connection;
+    use x11rb::protocol::xproto::{Atom, AtomEnum, ConnectionExt, ImageFormat, ImageOrder, Window};
+    use x11rb::rust_connection::RustConnection;
+
+    use super::WindowInfo;
+
+    fn intern_atom(conn: &RustConnection, name: &str) -> Result<Atom, Box<dyn Error>> {
+        Ok(conn.intern_atom(false, name.as_bytes())?.reply()?.atom)
+    }
+
+    fn window_title(conn: &RustConnection, window: Window) -> Result<String, Box<dyn Error>> {
+        let net_wm_name = intern_atom(conn, "_NET_WM_NAME")?;
+        let utf8_string = intern_atom(conn, "UTF8_STRING")?;
+        let title = conn
+            .get_property(false, window, net_wm_name, utf8_string, 0, u32::MAX)?
+            .reply()?
+            .value;
+        if !title.is_empty() {
+            return Ok(String::from_utf8_lossy(&title).into_owned());
+        }
+
+        // Fall back to the legacy Latin-1 title for applications that don't set the EWMH one
+        let title = conn
+            .get_property(false, window, AtomEnum::WM_NAME, AtomEnum::ANY, 0, u32::MAX)?
+            .reply()?
+            .value;
+        Ok(title.iter().map(|&c| c as char).collect())
+    }
+
+    fn window_pid(conn: &RustConnection, window: Window) -> Result<Option<u32>, Box<dyn Error>> {
+        let net_wm_pid = intern_atom(conn, "_NET_WM_PID")?;
+        Ok(conn
+            .get_property(false, window, net_wm_pid, AtomEnum::CARDINAL, 0, 1)?
+            .reply()?
+            .value32()
+            .and_then(|mut pids| pids.next()))
+    }
+
+    /// Returns the `_NET_FRAME_EXTENTS` of `window`, which are all zero if the window manager
+    /// doesn't report them, such as for windows that draw their own decorations.
+    fn frame_extents(conn: &RustConnection, window: Window) -> Result<[usize; 4], Box<dyn Error>> {
+        let net_frame_extents = intern_atom(conn, "_NET_FRAME_EXTENTS")?;
+        let reply = conn
+            .get_property(false, window, net_frame_extents, AtomEnum::CARDINAL, 0, 4)?
+            .reply()?;
+        let mut extents = [0; 4];
+        if let Some(values) = reply.value32() {
+            for (extent, value) in extents.iter_mut().zip(values) {
+                *extent = value as usize;
+            }
+        }
+        Ok(extents)
+    }
+
+    fn window_info(
+        conn: &RustConnection,
+        root: Window,
+        window: Window,
+    ) -> Result<WindowInfo, Box<dyn Error>> {
+        let geometry = conn.get_geometry(window)?.reply()?;
+        let position = conn.translate_coordinates(window, root, 0, 0)?.reply()?;
+        Ok(WindowInfo {
+            id: window,
+            title: window_title(conn, window)?,
+            x: position.dst_x as i64,
+            y: position.dst_y as i64,
+            width: geometry.width as usize,
+            height: geometry.height as usize,
+            pid: window_pid(conn, window)?,
+            frame_extents: frame_extents(conn, window)?,
+        })
+    }
+
+    pub fn active_window() -> Result<WindowInfo, Box<dyn Error>> {
+        let (conn, screen_num) = x11rb::connect(None)?;
+        let root = conn.setup().roots[screen_num].root;
+        let net_active_window = intern_atom(&conn, "_NET_ACTIVE_WINDOW")?;
+        let window = conn
+            .get_property(false, root, net_active_window, AtomEnum::WINDOW, 0, 1)?
+            .reply()?
+            .value32()
+            .and_then(|mut windows| windows.next())
+            .ok_or("The window manager doesn't report which window is focused")?;
+        // Nothing is focused, such as when the desktop itself was clicked last
+        if window == x11rb::NONE {
+            return Err("No window has focus".into());
+        }
+
+        window_info(&conn, root, window)
+    }
+
+    pub fn cursor_position() -> Result<(i64, i64), Box<dyn Error>> {
+        let (conn, screen_num) = x11rb::connect(None)?;
+        let root = conn.setup().roots[screen_num].root;
+        let pointer = conn.query_pointer(root)?.reply()?;
+        Ok((pointer.root_x as i64, pointer.root_y as i64))
+    }
+
+    /// Lists the managed top-level windows from topmost to bottommost.
+    pub fn list_windows() -> Result<Vec<WindowInfo>, Box<dyn Error>> {
+        let (conn, screen_num) = x11rb::connect(None)?;
+        let root = conn.setup().roots[screen_num].root;
+        let client_list = intern_atom(&conn, "_NET_CLIENT_LIST_STACKING")?;
+        let clients: Vec<Window> = conn
+            .get_property(false, root, client_list, AtomEnum::WINDOW, 0, u32::MAX)?
+            .reply()?
+            .value32()
+            .ok_or("The window manager doesn't provide a list of windows")?
+            .collect();
+
+        clients
+            .into_iter()
+            .rev()
+            .map(|window| window_info(&conn, root, window))
+            .collect()
+    }
+
+    pub fn capture_with_alpha(window: &WindowInfo) -> Result<Vec<u8>, Box<dyn Error>> {
+        let (conn, _) = x11rb::connect(None)?;
+        let image = conn
+            .get_image(
+                ImageFormat::Z_PIXMAP,
+                window.id,
+                0,
+                0,
+                window.width as u16,
+                window.height as u16,
+                u32::MAX,
+            )?
+            .reply()?;
+        // Only windows with a 32 bit visual have an alpha channel; the fourth byte of others is
+        // just padding
+        if image.depth != 32 {
+            return Err(format!("`{}` doesn't have any transparency", window.title).into());
+        }
+
+        let msb_first = conn.setup().image_byte_order == ImageOrder::MSB_FIRST;
+        Ok(image
+            .data
+            .chunks_exact(4)
+            .flat_map(|pixel| {
+                let (b, g, r, a) = if msb_first {
+                    (pixel[3], pixel[2], pixel[1], pixel[0])
+                } else {
+                    (pixel[0], pixel[1], pixel[2], pixel[3])
+                };
+                [r, g, b, a]
+            })
+            .collect())
+    }
+}
+
+#[cfg(not(target_os = "linux"))]
+mod platform {
+    use std::error::Error;
+
+    use super::WindowInfo;
+
+    pub fn active_window() -> Result<WindowInfo, Box<dyn Error>> {
+        Err("Finding windows isn't supported on this platform".into())
+    }
+
+    pub fn cursor_position() -> Result<(i64, i64), Box<dyn Error>> {
+        Err("Finding windows isn't supported on this platform".into())
+    }
+
+    pub fn list_windows() -> Result<Vec<WindowInfo>, Box<dyn Error>> {
+        Err("Finding windows isn't supported on this platform".into())
+    }
+
+    pub fn capture_with_alpha(_window: &WindowInfo) -> Result<Vec<u8>, Box<dyn Error>> {
+        Err("Capturing windows with transparency isn't supported on this platform".into())
+    }
+}
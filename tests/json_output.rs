@@ -0,0 +1,102 @@
+//! Runs the `snapmeo` binary to check that everything it prints along the way with `--json`
+//! stays off of stdout, which has to hold nothing but the one JSON document it ends with.
+
+use std::env;
+use std::fs;
+use std::io::{BufRead, BufReader, Read, Write};
+use std::net::TcpListener;
+use std::process::Command;
+use std::thread;
+
+/// Big enough for the upload to be compressed, so that the compression progress is printed
+const FILE_BYTES: usize = 1024 * 1024;
+
+/// Starts an HTTP server that answers one request per connection with each of `responses` in
+/// turn, returning its URL.
+fn mock_server(responses: Vec<String>) -> String {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let url = format!("http://{}/u/upload", listener.local_addr().unwrap());
+
+    thread::spawn(move || {
+        for response in responses {
+            let (mut stream, _) = listener.accept().unwrap();
+            let mut reader = BufReader::new(stream.try_clone().unwrap());
+            let mut content_length = 0;
+            loop {
+                let mut line = String::new();
+                reader.read_line(&mut line).unwrap();
+                let line = line.trim_end().to_lowercase();
+                if line.is_empty() {
+                    break;
+                }
+                if let Some(length) = line.strip_prefix("content-length:") {
+                    content_length = length.trim().parse().unwrap();
+                }
+            }
+            let mut body = vec![0; content_length];
+            reader.read_exact(&mut body).unwrap();
+            stream.write_all(response.as_bytes()).unwrap();
+        }
+    });
+
+    url
+}
+
+fn response(status: &str, headers: &str, body: &str) -> String {
+    format!(
+        "HTTP/1.1 {}\r\n{}Content-Length: {}\r\nConnection: close\r\n\r\n{}",
+        status,
+        headers,
+        body.len(),
+        body
+    )
+}
+
+#[test]
+fn json_output_is_a_single_document() {
+    let home = env::temp_dir().join(format!("snapmeo-test-{}-json-home", std::process::id()));
+    let data_dir = home.join(".local/share/snapmeo");
+    fs::create_dir_all(&data_dir).unwrap();
+    let path = home.join("queued.png");
+    fs::write(&path, vec![b'a'; FILE_BYTES]).unwrap();
+    fs::write(
+        data_dir.join("upload-queue.json"),
+        serde_json::json!([{
+            "path": path,
+            "uploader": "AmeoTrack",
+            "expiry": "1w",
+            "secret": false,
+            "one_time": false,
+            "compress": true,
+        }])
+        .to_string(),
+    )
+    .unwrap();
+
+    let url = mock_server(vec![
+        response("204 No Content", "Accept-Encoding: gzip\r\n", ""),
+        response("200 OK", "", "https://ameo.link/u/queued.png"),
+    ]);
+    let output = Command::new(env!("CARGO_BIN_EXE_snapmeo"))
+        .args(["--retry-queue", "--json", "--upload-url", &url])
+        .env("HOME", &home)
+        .env_remove("XDG_DATA_HOME")
+        .env_remove("XDG_CONFIG_HOME")
+        .env("AMEOTRACK_PASSWORD", "hunter2")
+        .output()
+        .unwrap();
+    fs::remove_dir_all(&home).unwrap();
+
+    assert!(
+        output.status.success(),
+        "{}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    let document: serde_json::Value = serde_json::from_str(&stdout)
+        .unwrap_or_else(|err| panic!("{:?} isn't one JSON document: {}", stdout, err));
+    assert_eq!(
+        document["uploaded_from_queue"][0]["url"],
+        "https://ameo.link/u/queued.png"
+    );
+}